@@ -4,12 +4,26 @@
 //! VTable analysis, and memory scanning to extract meaningful information about
 //! binary structures, class hierarchies, and code patterns.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::ops::Range;
+use std::path::Path;
 
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{AnalysisCache, ModuleAnalysis, ModuleFingerprint, ProcessAnalysis, SectionAnalysis};
+use crate::callconv::{self, FunctionSignature};
+use crate::cfg::ControlFlowGraph;
+use crate::diff::FunctionFeatures;
+use crate::disasm;
 use crate::errors::Error;
 use crate::memory::{MemoryScanner, ComprehensiveScanResult};
-use crate::vtable::{VTable, VTableScanner, ClassHierarchy, VTableAnalyzer};
+use crate::pe::{self, PeImage};
+use crate::strings::{self, StringScanConfig};
+use crate::structure::{self, AccessCollector, ObjectLayout};
+use crate::types::BitField;
+use crate::vtable::{VTableScanner, ClassHierarchy, VTableAnalyzer};
+use crate::xref::{self, Xref, XrefIndex, XrefKind};
 
 /// Configuration for analysis operations.
 #[derive(Debug, Clone)]
@@ -24,6 +38,13 @@ pub struct AnalysisConfig {
     pub priority_patterns: Vec<String>,
     /// Whether to perform cross-reference analysis.
     pub enable_cross_reference: bool,
+    /// Maximum number of virtual functions disassembled per class during structure recovery.
+    pub max_structure_functions: usize,
+    /// Base addresses of the modules whose exception directory provides function
+    /// boundaries. When empty, the main executable image is used.
+    pub modules: Vec<usize>,
+    /// Encodings and length limits for string extraction.
+    pub string_scan: StringScanConfig,
 }
 
 impl Default for AnalysisConfig {
@@ -34,12 +55,15 @@ impl Default for AnalysisConfig {
             include_low_confidence: false,
             priority_patterns: Vec::new(),
             enable_cross_reference: true,
+            max_structure_functions: 32,
+            modules: Vec::new(),
+            string_scan: StringScanConfig::default(),
         }
     }
 }
 
 /// Represents a discovered function in the binary.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiscoveredFunction {
     pub address: usize,
     pub size: Option<usize>,
@@ -51,10 +75,13 @@ pub struct DiscoveredFunction {
     pub references: Vec<usize>,
     pub xrefs_to: Vec<usize>,
     pub xrefs_from: Vec<usize>,
+    /// Structural features used to match the function across builds.
+    #[serde(default)]
+    pub features: Option<FunctionFeatures>,
 }
 
 /// Calling conventions for functions.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CallingConvention {
     Unknown,
     Cdecl,
@@ -65,15 +92,17 @@ pub enum CallingConvention {
 }
 
 /// Function parameter information.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Parameter {
     pub index: usize,
     pub data_type: DataType,
     pub location: ParameterLocation,
+    /// Confidence that the parameter exists with this type (0.0 - 1.0).
+    pub confidence: f32,
 }
 
 /// Parameter location (register or stack).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ParameterLocation {
     Register(String),
     Stack(i32),
@@ -81,7 +110,7 @@ pub enum ParameterLocation {
 }
 
 /// Data types discovered during analysis.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DataType {
     Unknown,
     Void,
@@ -100,10 +129,12 @@ pub enum DataType {
     Array(Box<DataType>, usize),
     Struct(String),
     Class(String),
+    Union(String),
+    Enum(String),
 }
 
 /// Represents a discovered structure or class.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiscoveredStruct {
     pub name: String,
     pub size: usize,
@@ -114,16 +145,19 @@ pub struct DiscoveredStruct {
 }
 
 /// Structure field information.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StructField {
     pub name: String,
     pub offset: usize,
     pub data_type: DataType,
     pub size: usize,
+    /// Bit range within the storage unit at `offset`, for bitfields.
+    #[serde(default)]
+    pub bit_field: Option<BitField>,
 }
 
 /// Results from comprehensive binary analysis.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct AnalysisResult {
     pub functions: Vec<DiscoveredFunction>,
     pub structures: Vec<DiscoveredStruct>,
@@ -132,11 +166,33 @@ pub struct AnalysisResult {
     pub import_table: Vec<ImportEntry>,
     pub export_table: Vec<ExportEntry>,
     pub code_patterns: Vec<CodePattern>,
+    pub cross_references: XrefIndex,
     pub statistics: AnalysisStatistics,
 }
 
+impl AnalysisResult {
+    /// Returns the function containing `address`, if its bounds are known.
+    pub fn function_containing(&self, address: usize) -> Option<&DiscoveredFunction> {
+        self.functions.iter().find(|f| {
+            f.address == address || f.size.is_some_and(|size| (f.address..f.address + size).contains(&address))
+        })
+    }
+
+    /// Returns the functions referencing a string whose value equals `text`.
+    pub fn functions_referencing_string(&self, text: &str) -> Vec<&DiscoveredFunction> {
+        let mut functions: Vec<&DiscoveredFunction> = self.string_references.iter()
+            .filter(|string| string.value == text)
+            .flat_map(|string| string.references.iter())
+            .filter_map(|&reference| self.function_containing(reference))
+            .collect();
+        functions.sort_by_key(|f| f.address);
+        functions.dedup_by_key(|f| f.address);
+        functions
+    }
+}
+
 /// String reference found in the binary.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StringReference {
     pub address: usize,
     pub value: String,
@@ -144,15 +200,16 @@ pub struct StringReference {
     pub references: Vec<usize>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StringEncoding {
     Ascii,
+    /// UTF-16LE.
     Unicode,
     Utf8,
 }
 
 /// Import table entry.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImportEntry {
     pub module_name: String,
     pub function_name: String,
@@ -161,7 +218,7 @@ pub struct ImportEntry {
 }
 
 /// Export table entry.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportEntry {
     pub name: String,
     pub address: usize,
@@ -169,7 +226,7 @@ pub struct ExportEntry {
 }
 
 /// Discovered code pattern.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CodePattern {
     pub pattern_type: PatternType,
     pub addresses: Vec<usize>,
@@ -177,7 +234,7 @@ pub struct CodePattern {
     pub confidence: f32,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PatternType {
     FunctionPrologue,
     FunctionEpilogue,
@@ -190,7 +247,7 @@ pub enum PatternType {
 }
 
 /// Analysis statistics.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnalysisStatistics {
     pub total_functions: usize,
     pub high_confidence_functions: usize,
@@ -215,12 +272,42 @@ impl fmt::Display for AnalysisStatistics {
     }
 }
 
+/// Stage of a running analysis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalysisStage {
+    /// Functions, cross-references and strings of each target module.
+    Modules,
+    /// Pattern, vtable and structure scans over the whole process.
+    Process,
+    Finished,
+}
+
+/// Progress of a running analysis, reported through [`AnalysisEngine::with_progress`].
+///
+/// Every target module counts as one step and the process-wide scans as one more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnalysisProgress {
+    pub stage: AnalysisStage,
+    pub completed: usize,
+    pub total: usize,
+}
+
+impl AnalysisProgress {
+    /// Returns the completed fraction between 0.0 and 1.0.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 { 0.0 } else { self.completed as f32 / self.total as f32 }
+    }
+}
+
+type ProgressCallback = Box<dyn Fn(AnalysisProgress) + Send + Sync>;
+
 /// Main analysis engine that coordinates all analysis tasks.
 pub struct AnalysisEngine {
     config: AnalysisConfig,
     memory_scanner: MemoryScanner,
     vtable_scanner: VTableScanner,
     pattern_database: PatternDatabase,
+    progress: Option<ProgressCallback>,
 }
 
 impl AnalysisEngine {
@@ -231,6 +318,7 @@ impl AnalysisEngine {
             memory_scanner,
             vtable_scanner: VTableScanner::new(),
             pattern_database: PatternDatabase::new(),
+            progress: None,
         }
     }
 
@@ -241,32 +329,93 @@ impl AnalysisEngine {
             memory_scanner,
             vtable_scanner: VTableScanner::new(),
             pattern_database: PatternDatabase::new(),
+            progress: None,
+        }
+    }
+
+    /// Calls `callback` as the analysis moves through its stages.
+    pub fn with_progress(mut self, callback: impl Fn(AnalysisProgress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    fn report(&self, stage: AnalysisStage, completed: usize, total: usize) {
+        if let Some(callback) = &self.progress {
+            callback(AnalysisProgress { stage, completed, total });
         }
     }
 
     /// Performs comprehensive analysis of the target binary.
     pub fn analyze(&self) -> Result<AnalysisResult, Error> {
-        let start_time = std::time::Instant::now();
+        self.analyze_incremental(None).map(|(result, _)| result)
+    }
 
-        // Perform initial scans
-        let scan_result = self.comprehensive_scan()?;
-        
-        // Analyze functions
-        let functions = self.analyze_functions(&scan_result)?;
-        
-        // Analyze structures and classes
-        let structures = self.analyze_structures(&scan_result.vtables)?;
-        let class_hierarchy = VTableAnalyzer::reconstruct_hierarchy(&scan_result.vtables);
-        
-        // Find string references
-        let string_references = self.find_string_references(&scan_result)?;
-        
-        // Analyze imports/exports (simplified - would need PE parsing)
-        let import_table = self.analyze_imports()?;
-        let export_table = self.analyze_exports()?;
-        
-        // Detect code patterns
-        let code_patterns = self.detect_code_patterns(&scan_result)?;
+    /// Performs the analysis, reusing what is still valid in the cache at `path`.
+    ///
+    /// The updated cache is written back. A missing, corrupt or outdated cache
+    /// file only means everything is analyzed again.
+    pub fn analyze_cached(&self, path: &Path) -> Result<AnalysisResult, Error> {
+        let previous = match AnalysisCache::load(path) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                log::info!("analysis cache at {} not used: {e}", path.display());
+                None
+            }
+        };
+        let (result, cache) = self.analyze_incremental(previous.as_ref())?;
+        cache.save(path)?;
+        Ok(result)
+    }
+
+    fn analyze_incremental(&self, previous: Option<&AnalysisCache>) -> Result<(AnalysisResult, AnalysisCache), Error> {
+        let start_time = std::time::Instant::now();
+        let mut cache = AnalysisCache::new(self.config.string_scan.clone());
+        let reuse_strings = previous.is_some_and(|p| p.string_scan == self.config.string_scan);
+
+        // Functions, cross-references and strings of each target module
+        let modules = self.target_modules()?;
+        let total = modules.len() + 1;
+        // Kept for the structure recovery of the process-wide scans.
+        let mut images = Vec::new();
+        for (position, base) in modules.into_iter().enumerate() {
+            self.report(AnalysisStage::Modules, position, total);
+            let analysis = self.memory_scanner.read_image(base)
+                .and_then(|image| Ok((self.analyze_module(base, &image, previous, reuse_strings)?, image)));
+            match analysis {
+                Ok((module, image)) => {
+                    cache.modules.push(module);
+                    images.push(image);
+                }
+                Err(e) => log::warn!("analysis of module at 0x{base:X} failed: {e}"),
+            }
+        }
+        self.report(AnalysisStage::Process, total - 1, total);
+
+        let mut functions: Vec<DiscoveredFunction> = cache.modules.iter()
+            .flat_map(|module| module.functions.iter().cloned())
+            .collect();
+        let cross_references = XrefIndex::from_xrefs(cache.modules.iter().flat_map(|module| module.xrefs()));
+        let mut string_references: Vec<StringReference> = cache.modules.iter()
+            .flat_map(|module| module.strings().cloned())
+            .collect();
+        strings::link_references(&mut string_references, &cross_references);
+
+        // Process-wide scans, reused while every module is unchanged
+        let process = match previous.and_then(|p| p.process.as_ref()) {
+            Some(process) if process.is_valid_for(&cache.modules) => process.clone(),
+            _ => {
+                let known = functions.iter().map(|f| f.address).collect();
+                self.analyze_process(&cache.modules, &images, known)?
+            }
+        };
+        functions.extend(process.functions.iter().cloned());
+        let virtual_functions: HashSet<usize> = process.virtual_functions.iter().copied().collect();
+        for function in &mut functions {
+            if virtual_functions.contains(&function.address) {
+                function.calling_convention = CallingConvention::Thiscall;
+            }
+        }
+        self.link_cross_references(&mut functions, &cross_references);
 
         let analysis_time = start_time.elapsed().as_millis() as u64;
         
@@ -275,22 +424,134 @@ impl AnalysisEngine {
             high_confidence_functions: functions.iter()
                 .filter(|f| f.confidence >= self.config.confidence_threshold)
                 .count(),
-            total_structures: structures.len(),
-            vtables_analyzed: scan_result.vtables.len(),
+            total_structures: process.structures.len(),
+            vtables_analyzed: process.vtables_analyzed,
             string_references: string_references.len(),
-            code_patterns: code_patterns.len(),
+            code_patterns: process.code_patterns.len(),
             analysis_time_ms: analysis_time,
         };
 
-        Ok(AnalysisResult {
+        let result = AnalysisResult {
             functions,
-            structures,
-            class_hierarchy,
+            structures: process.structures.clone(),
+            class_hierarchy: process.class_hierarchy.clone(),
             string_references,
-            import_table,
-            export_table,
-            code_patterns,
+            import_table: process.import_table.clone(),
+            export_table: process.export_table.clone(),
+            code_patterns: process.code_patterns.clone(),
+            cross_references,
             statistics,
+        };
+        cache.process = Some(process);
+        self.report(AnalysisStage::Finished, total, total);
+        Ok((result, cache))
+    }
+
+    /// Analyzes the target module mapped at `base` from its `image`, reusing the
+    /// unchanged parts of `cached`.
+    fn analyze_module(&self, base: usize, image: &[u8], previous: Option<&AnalysisCache>, reuse_strings: bool) -> Result<ModuleAnalysis, Error> {
+        let pe = PeImage::parse(image, base)?;
+        let fingerprint = ModuleFingerprint::compute(&pe);
+        let xrefs_enabled = self.config.enable_cross_reference;
+        let cached = previous
+            .and_then(|previous| previous.module_for(base, &fingerprint))
+            .filter(|module| module.cross_references || !xrefs_enabled)
+            .map(|module| {
+                let mut module = module.clone();
+                module.rebase(base);
+                module
+            });
+
+        let (functions, code_xrefs) = match &cached {
+            Some(module) if module.fingerprint.same_code(&fingerprint) => {
+                (module.functions.clone(), module.code_xrefs.clone())
+            }
+            _ => {
+                let code_xrefs = if xrefs_enabled { xref::code_xrefs(&pe) } else { Vec::new() };
+                (self.module_functions(&pe), code_xrefs)
+            }
+        };
+
+        let mut sections = Vec::new();
+        for (section, section_fingerprint) in pe.sections().iter().zip(&fingerprint.sections) {
+            if section.is_executable() {
+                continue;
+            }
+            let reusable = cached.as_ref()
+                .filter(|_| reuse_strings)
+                .and_then(|module| module.reusable_section(section_fingerprint));
+            if let Some(analysis) = reusable {
+                sections.push(analysis.clone());
+                continue;
+            }
+            let start = section.virtual_address as usize;
+            let end = (start + section.virtual_size as usize).min(image.len());
+            sections.push(SectionAnalysis {
+                virtual_address: section.virtual_address,
+                hash: section_fingerprint.hash,
+                strings: if start < end { self.extract_strings(&image[start..end], base + start) } else { Vec::new() },
+                data_xrefs: if xrefs_enabled { xref::section_data_xrefs(&pe, section) } else { Vec::new() },
+            });
+        }
+
+        Ok(ModuleAnalysis {
+            base,
+            fingerprint,
+            functions,
+            code_xrefs,
+            sections,
+            cross_references: xrefs_enabled,
+        })
+    }
+
+    /// Runs the scans over the whole process: patterns, vtables and structures.
+    ///
+    /// Functions found by pattern or vtable that are not in `known` are
+    /// analyzed individually; virtual functions are recorded so they can be
+    /// marked `thiscall` wherever they came from. `images[i]` is the image
+    /// `modules[i]` was analyzed from.
+    fn analyze_process(&self, modules: &[ModuleAnalysis], images: &[Vec<u8>], mut known: HashSet<usize>) -> Result<ProcessAnalysis, Error> {
+        let scan_result = self.comprehensive_scan()?;
+        let mut functions = Vec::new();
+        let mut virtual_functions = Vec::new();
+
+        // Extract functions from pattern matches
+        for result in &scan_result.pattern_matches {
+            if known.contains(&result.address) {
+                continue;
+            }
+            if let Ok(function) = self.analyze_function(result.address) {
+                known.insert(function.address);
+                functions.push(function);
+            }
+        }
+
+        // Extract functions from VTables
+        for vtable in &scan_result.vtables {
+            for virtual_func in &vtable.functions {
+                virtual_functions.push(virtual_func.address);
+                if known.contains(&virtual_func.address) {
+                    continue;
+                }
+                if let Ok(mut function) = self.analyze_function(virtual_func.address) {
+                    function.calling_convention = CallingConvention::Thiscall;
+                    function.confidence += 0.1; // Higher confidence for vtable functions
+                    known.insert(function.address);
+                    functions.push(function);
+                }
+            }
+        }
+
+        Ok(ProcessAnalysis {
+            modules: modules.iter().map(|m| (m.base, m.fingerprint.hash())).collect(),
+            functions,
+            virtual_functions,
+            structures: self.analyze_structures(&scan_result, modules, images)?,
+            class_hierarchy: VTableAnalyzer::reconstruct_hierarchy(&scan_result.vtables),
+            code_patterns: self.detect_code_patterns(&scan_result)?,
+            import_table: self.analyze_imports()?,
+            export_table: self.analyze_exports()?,
+            vtables_analyzed: scan_result.vtables.len(),
         })
     }
 
@@ -312,27 +573,27 @@ impl AnalysisEngine {
         let data = self.memory_scanner.read_memory(address, 512)
             .map_err(|e| Error::MemoryError(e.to_string()))?;
 
+        let signature = self.infer_signature(&data, address, None);
         let mut function = DiscoveredFunction {
             address,
             size: None,
             name: None,
-            calling_convention: self.detect_calling_convention(&data),
-            parameters: Vec::new(),
-            return_type: None,
+            calling_convention: signature.calling_convention,
+            parameters: signature.parameters,
+            return_type: signature.return_type,
             confidence: 0.5,
             references: Vec::new(),
             xrefs_to: Vec::new(),
             xrefs_from: Vec::new(),
+            features: None,
         };
 
         // Analyze function prologue/epilogue for size estimation
-        if let Some(size) = self.estimate_function_size(&data) {
+        if let Some(size) = self.estimate_function_size(&data, address) {
             function.size = Some(size);
             function.confidence += 0.2;
         }
 
-        // Detect parameters
-        function.parameters = self.analyze_function_parameters(&data);
         if !function.parameters.is_empty() {
             function.confidence += 0.1;
         }
@@ -340,79 +601,287 @@ impl AnalysisEngine {
         Ok(function)
     }
 
+    /// Builds the control-flow graph of the function at `address`.
+    ///
+    /// When the function lies in a target module, the whole image is used so
+    /// jump tables resolve, and the `.pdata` bounds separate internal jumps
+    /// from tail calls. Otherwise the code following `address` is decoded
+    /// without bounds.
+    pub fn build_control_flow_graph(&self, address: usize) -> Result<ControlFlowGraph, Error> {
+        for base in self.target_modules()? {
+            let Ok(header) = self.memory_scanner.read_memory(base, 0x1000) else {
+                continue;
+            };
+            let Ok(pe) = PeImage::parse(&header, base) else {
+                continue;
+            };
+            if !(base..base + pe.size_of_image() as usize).contains(&address) {
+                continue;
+            }
+
+            let image = self.memory_scanner.read_image(base)?;
+            let pe = PeImage::parse(&image, base)?;
+            let bounds = pe.function_entries()
+                .into_iter()
+                .find(|entry| entry.address == address)
+                .map(|entry| entry.range());
+            return Ok(ControlFlowGraph::build(&image, base, address, bounds));
+        }
+
+        let data = self.memory_scanner.read_memory(address, 0x1000)
+            .or_else(|_| self.memory_scanner.read_memory(address, 512))
+            .map_err(|e| Error::MemoryError(e.to_string()))?;
+        Ok(ControlFlowGraph::build(&data, address, address, None))
+    }
+
     fn comprehensive_scan(&self) -> Result<ComprehensiveScanResult, Error> {
         let patterns = self.pattern_database.get_common_patterns();
         self.memory_scanner.comprehensive_scan(&patterns)
             .map_err(|e| Error::ScanError(e.to_string()))
     }
 
-    fn analyze_functions(&self, scan_result: &ComprehensiveScanResult) -> Result<Vec<DiscoveredFunction>, Error> {
-        let mut functions = Vec::new();
-        
-        // Extract functions from pattern matches
-        for result in &scan_result.pattern_matches {
-            if let Ok(function) = self.analyze_function(result.address) {
-                functions.push(function);
+    /// Discovers the functions of a loaded module from its exception directory.
+    ///
+    /// Every primary `.pdata` entry yields a function with exact bounds and
+    /// confidence 1.0; chained fragments are folded into their parent. Leaf
+    /// functions, which carry no unwind data, are found by recursive descent
+    /// from the known entries and sized by the code they reach.
+    pub fn analyze_module_functions(&self, module_base: usize) -> Result<Vec<DiscoveredFunction>, Error> {
+        let image = self.memory_scanner.read_image(module_base)?;
+        let pe = PeImage::parse(&image, module_base)?;
+        Ok(self.module_functions(&pe))
+    }
+
+    fn module_functions(&self, pe: &PeImage) -> Vec<DiscoveredFunction> {
+        let image = pe.data();
+        let module_base = pe.base();
+        let module = module_base..module_base + pe.size_of_image() as usize;
+        let entries = pe.function_entries();
+        let leaves = pe.find_leaf_functions(&entries);
+
+        let primary = entries.iter()
+            .filter(|entry| entry.parent.is_none())
+            .map(|entry| (entry.address, entry.size, 1.0));
+        let leaf = leaves.iter().map(|leaf| (leaf.address, leaf.size, 0.9));
+
+        primary.chain(leaf)
+            .map(|(address, size, confidence)| {
+                // The whole image lets jump tables in .rdata resolve.
+                let graph = ControlFlowGraph::build(image, module_base, address, Some(address..address + size));
+                let signature = callconv::infer_signature(&graph);
+                DiscoveredFunction {
+                    address,
+                    size: Some(size),
+                    name: None,
+                    calling_convention: signature.calling_convention,
+                    parameters: signature.parameters,
+                    return_type: signature.return_type,
+                    confidence,
+                    references: Vec::new(),
+                    xrefs_to: Vec::new(),
+                    xrefs_from: Vec::new(),
+                    features: Some(FunctionFeatures::extract(&graph, module.clone())),
+                }
+            })
+            .collect()
+    }
+
+    /// Builds the cross-reference index of the target modules.
+    pub fn build_cross_references(&self) -> Result<XrefIndex, Error> {
+        let mut index = XrefIndex::new();
+        for base in self.target_modules()? {
+            let Ok(image) = self.memory_scanner.read_image(base) else {
+                continue;
+            };
+            if let Ok(pe) = PeImage::parse(&image, base) {
+                index.extend(XrefIndex::build(&pe).iter().copied());
             }
         }
+        Ok(index)
+    }
 
-        // Extract functions from VTables
-        for vtable in &scan_result.vtables {
-            for virtual_func in &vtable.functions {
-                if let Ok(mut function) = self.analyze_function(virtual_func.address) {
-                    function.calling_convention = CallingConvention::Thiscall;
-                    function.confidence += 0.1; // Higher confidence for vtable functions
-                    functions.push(function);
+    /// Fills the reference fields of each function from the index.
+    ///
+    /// `xrefs_to` lists the sources referencing the function start,
+    /// `xrefs_from` the calls and jumps leaving the function body, and
+    /// `references` the data the body reads, writes or takes the address of.
+    fn link_cross_references(&self, functions: &mut [DiscoveredFunction], index: &XrefIndex) {
+        for function in functions {
+            let body = function.address..function.address + function.size.unwrap_or(1);
+
+            function.xrefs_to = index.xrefs_to(function.address).map(|xref| xref.from).collect();
+            function.xrefs_to.sort_unstable();
+            function.xrefs_to.dedup();
+
+            let (code, data): (Vec<&Xref>, Vec<&Xref>) = index.xrefs_in(body.clone())
+                .iter()
+                .partition(|xref| xref.kind.is_code());
+            function.xrefs_from = code.iter()
+                .map(|xref| xref.to)
+                .filter(|to| !body.contains(to))
+                .collect();
+            function.xrefs_from.sort_unstable();
+            function.xrefs_from.dedup();
+            function.references = data.iter().map(|xref| xref.to).collect();
+            function.references.sort_unstable();
+            function.references.dedup();
+        }
+    }
+
+    fn target_modules(&self) -> Result<Vec<usize>, Error> {
+        if !self.config.modules.is_empty() {
+            return Ok(self.config.modules.clone());
+        }
+        let mut executables = Vec::new();
+        for base in self.memory_scanner.find_modules()? {
+            let Ok(header) = self.memory_scanner.read_memory(base, 0x1000) else {
+                continue;
+            };
+            if PeImage::parse(&header, base).is_ok_and(|pe| !pe.is_dll()) {
+                executables.push(base);
+            }
+        }
+        Ok(executables)
+    }
+
+    /// Recovers object layouts for the classes owning the given vtables.
+    ///
+    /// Constructors are the functions of the analyzed `modules` taking the
+    /// address of each vtable in their code cross-references, allocation sizes
+    /// come from the `operator new` call preceding calls to them, and field
+    /// accesses from disassembling constructors and virtual member functions.
+    /// `images[i]` is the image `modules[i]` was analyzed from; code outside
+    /// them is read one function at a time.
+    pub fn recover_layouts(&self, scan_result: &ComprehensiveScanResult, modules: &[ModuleAnalysis], images: &[Vec<u8>]) -> Vec<ObjectLayout> {
+        let mut layouts: Vec<ObjectLayout> = scan_result.vtables.iter()
+            .map(|vtable| ObjectLayout::new(vtable.base_address))
+            .collect();
+
+        for (module, image) in modules.iter().zip(images) {
+            let index = XrefIndex::from_xrefs(module.code_xrefs.iter().cloned());
+            let containing_function = |address: usize| {
+                module.functions.iter()
+                    .find(|f| f.size.is_some_and(|size| (f.address..f.address + size).contains(&address)))
+                    .map(|f| f.address)
+            };
+            for layout in &mut layouts {
+                let references = index.xrefs_to(layout.vtable_address).filter(|x| x.kind == XrefKind::AddressOf);
+                for reference in references {
+                    let Some(ctor) = containing_function(reference.from) else {
+                        continue;
+                    };
+                    if layout.constructors.contains(&ctor) {
+                        continue;
+                    }
+                    layout.constructors.push(ctor);
+                    if layout.allocation_size.is_none() {
+                        layout.allocation_size = index.callers(ctor).into_iter()
+                            .find_map(|call| structure::find_allocation_size(image, call - module.base, 0x40));
+                    }
                 }
             }
         }
 
-        Ok(functions)
+        let collector = AccessCollector::new();
+        let collect = |address: usize| {
+            let module = modules.iter().zip(images)
+                .find(|(module, image)| (module.base..module.base + image.len()).contains(&address));
+            if let Some((module, image)) = module {
+                return collector.collect(&image[address - module.base..], address).accesses;
+            }
+            let region = scan_result.memory_regions.iter()
+                .filter(|r| r.is_executable() && r.is_readable())
+                .find(|r| (r.base_address..r.base_address + r.size).contains(&address));
+            let Some(region) = region else {
+                return Vec::new();
+            };
+            let length = collector.max_bytes.min(region.base_address + region.size - address);
+            self.memory_scanner.read_memory(address, length)
+                .map(|code| collector.collect(&code, address).accesses)
+                .unwrap_or_default()
+        };
+
+        for (vtable, layout) in scan_result.vtables.iter().zip(&mut layouts) {
+            for &ctor in &layout.constructors {
+                layout.accesses.extend(collect(ctor));
+            }
+
+            for virtual_func in vtable.functions.iter().take(self.config.max_structure_functions) {
+                layout.accesses.extend(collect(virtual_func.address));
+            }
+        }
+
+        layouts
     }
 
-    fn analyze_structures(&self, vtables: &[VTable]) -> Result<Vec<DiscoveredStruct>, Error> {
+    fn analyze_structures(&self, scan_result: &ComprehensiveScanResult, modules: &[ModuleAnalysis], images: &[Vec<u8>]) -> Result<Vec<DiscoveredStruct>, Error> {
+        let layouts = self.recover_layouts(scan_result, modules, images);
+        let sizes = VTableAnalyzer::estimate_object_sizes(&scan_result.vtables, &layouts);
         let mut structures = Vec::new();
-        
-        for vtable in vtables {
-            let mut structure = DiscoveredStruct {
+
+        for (vtable, layout) in scan_result.vtables.iter().zip(&layouts) {
+            let mut fields = layout.fields();
+            if fields.first().is_none_or(|f| f.offset != 0) {
+                fields.insert(0, StructField {
+                    name: "vtable".to_string(),
+                    offset: 0,
+                    data_type: DataType::Pointer(Box::new(DataType::Void)),
+                    size: std::mem::size_of::<usize>(),
+                    bit_field: None,
+                });
+            }
+
+            let mut confidence = 0.6;
+            if !layout.constructors.is_empty() {
+                confidence += 0.1;
+            }
+            if layout.allocation_size.is_some() {
+                confidence += 0.2;
+            }
+
+            structures.push(DiscoveredStruct {
                 name: vtable.estimated_class_name().unwrap_or_else(|| {
                     format!("Struct_{:X}", vtable.base_address)
                 }),
-                size: std::mem::size_of::<usize>(), // At least vtable pointer
-                fields: Vec::new(),
+                size: sizes.get(&vtable.base_address).copied().unwrap_or(std::mem::size_of::<usize>()),
+                fields,
                 vtable_offset: Some(0), // Typically at offset 0
                 base_classes: Vec::new(),
-                confidence: 0.6,
-            };
-
-            // Add vtable pointer field
-            structure.fields.push(StructField {
-                name: "vtable".to_string(),
-                offset: 0,
-                data_type: DataType::Pointer(Box::new(DataType::Void)),
-                size: std::mem::size_of::<usize>(),
+                confidence,
             });
-
-            structures.push(structure);
         }
         
         Ok(structures)
     }
 
-    fn find_string_references(&self, scan_result: &ComprehensiveScanResult) -> Result<Vec<StringReference>, Error> {
-        let mut string_refs = Vec::new();
-        
-        // Look for ASCII strings in readable regions
-        for region in &scan_result.memory_regions {
-            if region.is_readable() {
-                if let Ok(data) = self.memory_scanner.read_memory(region.base_address, region.size) {
-                    let strings = self.extract_strings(&data, region.base_address);
-                    string_refs.extend(strings);
+    /// Finds the functions that load or take the address of the string `text`.
+    ///
+    /// The target modules are searched for `text` in every configured encoding
+    /// and the referencing instructions are mapped to the `.pdata` or leaf
+    /// function containing them. This does not require a full analysis pass.
+    pub fn find_functions_referencing_string(&self, text: &str) -> Result<Vec<usize>, Error> {
+        let mut functions = Vec::new();
+        for base in self.target_modules()? {
+            let image = self.memory_scanner.read_image(base)?;
+            let pe = PeImage::parse(&image, base)?;
+            let addresses = strings::find_in_image(&pe, text, &self.config.string_scan.encodings);
+            if addresses.is_empty() {
+                continue;
+            }
+
+            let entries = pe.function_entries();
+            let leaves = pe.find_leaf_functions(&entries);
+            let index = XrefIndex::from_xrefs(xref::code_xrefs(&pe));
+            for address in addresses {
+                for reference in index.xrefs_to(address).filter(|x| strings::is_string_use(x.kind)) {
+                    functions.extend(pe::containing_function(&entries, &leaves, reference.from));
                 }
             }
         }
-        
-        Ok(string_refs)
+
+        functions.sort_unstable();
+        functions.dedup();
+        Ok(functions)
     }
 
     fn analyze_imports(&self) -> Result<Vec<ImportEntry>, Error> {
@@ -476,75 +945,20 @@ impl AnalysisEngine {
         })
     }
 
-    fn detect_calling_convention(&self, data: &[u8]) -> CallingConvention {
-        // Simplified detection based on common patterns
-        if data.len() >= 4 {
-            match &data[0..4] {
-                [0x55, 0x48, 0x89, 0xE5] => CallingConvention::Cdecl, // push rbp; mov rbp, rsp
-                [0x48, 0x89, 0x4C, 0x24] => CallingConvention::Fastcall, // mov [rsp+8], rcx
-                _ => CallingConvention::Unknown,
-            }
-        } else {
-            CallingConvention::Unknown
-        }
-    }
-
-    fn estimate_function_size(&self, data: &[u8]) -> Option<usize> {
-        // Look for function epilogue patterns
-        for (i, window) in data.windows(3).enumerate() {
-            if matches!(window, [0x48, 0x83, 0xC4] | [0x5D, 0xC3, _] | [0xC3, _, _]) {
-                return Some(i + 1);
-            }
-        }
-        None
+    /// Infers the signature of the function whose code starts at `address`
+    /// from the argument registers it reads before writing them.
+    fn infer_signature(&self, data: &[u8], address: usize, bounds: Option<Range<usize>>) -> FunctionSignature {
+        callconv::infer_signature(&ControlFlowGraph::build(data, address, address, bounds))
     }
 
-    fn analyze_function_parameters(&self, data: &[u8]) -> Vec<Parameter> {
-        let mut parameters = Vec::new();
-        
-        // Simplified parameter detection
-        // Look for register usage patterns in first few instructions
-        if data.len() >= 8 {
-            // Check for fastcall pattern (RCX, RDX, R8, R9)
-            if matches!(&data[0..4], [0x48, 0x89, 0x4C, 0x24]) {
-                parameters.push(Parameter {
-                    index: 0,
-                    data_type: DataType::Unknown,
-                    location: ParameterLocation::Register("RCX".to_string()),
-                });
-            }
-        }
-        
-        parameters
+    fn estimate_function_size(&self, data: &[u8], address: usize) -> Option<usize> {
+        ControlFlowGraph::build(data, address, address, None)
+            .size()
+            .or_else(|| disasm::function_size(data, address))
     }
 
     fn extract_strings(&self, data: &[u8], base_address: usize) -> Vec<StringReference> {
-        let mut strings = Vec::new();
-        let mut current_string = Vec::new();
-        let mut start_offset = 0;
-
-        for (i, &byte) in data.iter().enumerate() {
-            if byte.is_ascii_graphic() || byte == b' ' {
-                if current_string.is_empty() {
-                    start_offset = i;
-                }
-                current_string.push(byte);
-            } else {
-                if current_string.len() >= 4 { // Minimum string length
-                    if let Ok(string) = String::from_utf8(current_string.clone()) {
-                        strings.push(StringReference {
-                            address: base_address + start_offset,
-                            value: string,
-                            encoding: StringEncoding::Ascii,
-                            references: Vec::new(),
-                        });
-                    }
-                }
-                current_string.clear();
-            }
-        }
-
-        strings
+        strings::extract_strings(data, base_address, &self.config.string_scan)
     }
 
     fn is_data_pointer(&self, data: &[u8]) -> bool {
@@ -654,11 +1068,36 @@ mod tests {
     fn test_calling_convention_detection() {
         let engine = AnalysisEngine::new(MemoryScanner::new().unwrap());
         
-        let prologue1 = [0x55, 0x48, 0x89, 0xE5]; // push rbp; mov rbp, rsp
-        assert_eq!(engine.detect_calling_convention(&prologue1), CallingConvention::Cdecl);
-        
-        let prologue2 = [0x48, 0x89, 0x4C, 0x24]; // mov [rsp+8], rcx
-        assert_eq!(engine.detect_calling_convention(&prologue2), CallingConvention::Fastcall);
+        let registers = |code: &[u8]| {
+            let signature = engine.infer_signature(code, 0, None);
+            let locations = signature.parameters.iter()
+                .map(|p| match &p.location {
+                    ParameterLocation::Register(register) => register.clone(),
+                    other => format!("{other:?}"),
+                })
+                .collect::<Vec<_>>();
+            (signature.calling_convention, locations)
+        };
+
+        // mov rax, rcx; add rax, rdx; imul rax, r8; sub rax, r9; ret
+        let integers = [0x48, 0x89, 0xC8, 0x48, 0x01, 0xD0, 0x49, 0x0F, 0xAF, 0xC0, 0x4C, 0x29, 0xC8, 0xC3];
+        assert_eq!(registers(&integers), (CallingConvention::Fastcall, vec!["RCX".into(), "RDX".into(), "R8".into(), "R9".into()]));
+
+        // addss xmm0, xmm1; ret
+        let floats = [0xF3, 0x0F, 0x58, 0xC1, 0xC3];
+        assert_eq!(registers(&floats), (CallingConvention::Fastcall, vec!["XMM0".into(), "XMM1".into()]));
+
+        // addps xmm0, xmm4; ret
+        let vectors = [0x0F, 0x58, 0xC4, 0xC3];
+        assert_eq!(registers(&vectors).0, CallingConvention::Vectorcall);
+
+        // mov eax, [rcx+8]; ret
+        let member = [0x8B, 0x41, 0x08, 0xC3];
+        assert_eq!(registers(&member), (CallingConvention::Thiscall, vec!["RCX".into()]));
+
+        // push rbp; mov rbp, rsp; xor eax, eax; pop rbp; ret reads no arguments
+        let frame = [0x55, 0x48, 0x89, 0xE5, 0x31, 0xC0, 0x5D, 0xC3];
+        assert_eq!(registers(&frame), (CallingConvention::Unknown, Vec::new()));
     }
 
     #[test]
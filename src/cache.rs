@@ -0,0 +1,427 @@
+//! On-disk cache of analysis results.
+//!
+//! Each target module is fingerprinted by its PE timestamp and a hash of
+//! every section. Aligned pointers into the image are hashed as RVAs, so a
+//! module loaded at a different base produces the same fingerprint. On the
+//! next run the parts whose inputs are unchanged are reused:
+//!
+//! - functions and code references, while the executable sections and the
+//!   exception directory hash the same;
+//! - strings and data references of a read-only section, while that section
+//!   hashes the same. Writable sections are always rescanned;
+//! - the process-wide scans (vtables, structures, pattern matches), while
+//!   every module is unchanged and loaded at the same base.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{
+    CodePattern, DiscoveredFunction, DiscoveredStruct, ExportEntry, ImportEntry, StringReference,
+};
+use crate::errors::Error;
+use crate::pe::{self, PeImage, Section};
+use crate::strings::StringScanConfig;
+use crate::vtable::ClassHierarchy;
+use crate::xref::Xref;
+
+/// Version of the cache format; caches written by other versions are ignored.
+pub const CACHE_VERSION: u32 = 1;
+
+pub(crate) const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// Hash of one section of a module.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionFingerprint {
+    pub name: String,
+    pub virtual_address: u32,
+    pub hash: u64,
+    pub executable: bool,
+    pub writable: bool,
+}
+
+/// Identity and content hashes of a loaded module.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleFingerprint {
+    pub timestamp: u32,
+    pub size_of_image: u32,
+    pub sections: Vec<SectionFingerprint>,
+    /// Hash over the executable sections and the section holding `.pdata`.
+    pub code_hash: u64,
+}
+
+impl ModuleFingerprint {
+    /// Fingerprints a module image.
+    pub fn compute(image: &PeImage) -> Self {
+        let exception = image
+            .data_directory(pe::DIRECTORY_EXCEPTION)
+            .and_then(|directory| image.section_for_rva(directory.rva));
+        let mut code_hash = FNV_OFFSET;
+        let sections: Vec<SectionFingerprint> = image
+            .sections()
+            .iter()
+            .map(|section| {
+                let hash = section_hash(image, section);
+                if section.is_executable() || exception.as_ref() == Some(section) {
+                    code_hash = fnv(code_hash, &hash.to_le_bytes());
+                }
+                SectionFingerprint {
+                    name: section.name.clone(),
+                    virtual_address: section.virtual_address,
+                    hash,
+                    executable: section.is_executable(),
+                    writable: section.is_writable(),
+                }
+            })
+            .collect();
+        Self {
+            timestamp: image.timestamp(),
+            size_of_image: image.size_of_image(),
+            sections,
+            code_hash,
+        }
+    }
+
+    /// Hash over the header fields and every read-only section.
+    pub fn hash(&self) -> u64 {
+        let mut hash = fnv(FNV_OFFSET, &self.timestamp.to_le_bytes());
+        hash = fnv(hash, &self.size_of_image.to_le_bytes());
+        for section in self.sections.iter().filter(|s| !s.writable) {
+            hash = fnv(hash, &section.hash.to_le_bytes());
+        }
+        hash
+    }
+
+    /// Returns true if the code, and thus the functions, are unchanged.
+    pub fn same_code(&self, other: &ModuleFingerprint) -> bool {
+        self.code_hash == other.code_hash && self.size_of_image == other.size_of_image
+    }
+}
+
+pub(crate) fn fnv(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Hashes a section, replacing aligned pointers into the image with their RVA.
+fn section_hash(image: &PeImage, section: &Section) -> u64 {
+    let data = image.data();
+    let bounds = image.base()..image.base() + image.size_of_image() as usize;
+    let range = section.rva_range();
+    let end = (range.end as usize).min(data.len());
+    let mut hash = FNV_OFFSET;
+    let mut offset = range.start as usize;
+    while offset < end {
+        if offset.is_multiple_of(8) && offset + 8 <= end {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&data[offset..offset + 8]);
+            let value = u64::from_le_bytes(bytes) as usize;
+            if bounds.contains(&value) {
+                hash = fnv(hash, &((value - image.base()) as u64).to_le_bytes());
+                offset += 8;
+                continue;
+            }
+        }
+        hash = fnv(hash, &data[offset..offset + 1]);
+        offset += 1;
+    }
+    hash
+}
+
+/// Strings and data references found in one non-executable section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SectionAnalysis {
+    pub virtual_address: u32,
+    pub hash: u64,
+    pub strings: Vec<StringReference>,
+    pub data_xrefs: Vec<Xref>,
+}
+
+/// Analysis of one target module.
+///
+/// Addresses are absolute for `base`; [`ModuleAnalysis::rebase`] moves them
+/// when the module is loaded elsewhere. Function and string reference lists
+/// are linked after loading and are not meaningful in the cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleAnalysis {
+    pub base: usize,
+    pub fingerprint: ModuleFingerprint,
+    pub functions: Vec<DiscoveredFunction>,
+    pub code_xrefs: Vec<Xref>,
+    pub sections: Vec<SectionAnalysis>,
+    /// Whether cross-references were collected.
+    pub cross_references: bool,
+}
+
+impl ModuleAnalysis {
+    /// Moves every address to a module loaded at `base`.
+    pub fn rebase(&mut self, base: usize) {
+        let delta = base.wrapping_sub(self.base);
+        if delta == 0 {
+            return;
+        }
+        let shift = |address: &mut usize| *address = address.wrapping_add(delta);
+        for function in &mut self.functions {
+            shift(&mut function.address);
+            function.references.iter_mut().for_each(shift);
+            function.xrefs_to.iter_mut().for_each(shift);
+            function.xrefs_from.iter_mut().for_each(shift);
+            if let Some(features) = &mut function.features {
+                features.calls.iter_mut().for_each(shift);
+            }
+        }
+        let xrefs = self.code_xrefs.iter_mut().chain(
+            self.sections
+                .iter_mut()
+                .flat_map(|s| s.data_xrefs.iter_mut()),
+        );
+        for xref in xrefs {
+            shift(&mut xref.from);
+            shift(&mut xref.to);
+        }
+        for string in self.sections.iter_mut().flat_map(|s| s.strings.iter_mut()) {
+            shift(&mut string.address);
+            string.references.iter_mut().for_each(shift);
+        }
+        self.base = base;
+    }
+
+    /// Returns the cached analysis of a section if its contents are unchanged.
+    ///
+    /// Writable sections change at run time and are never reused.
+    pub fn reusable_section(&self, fingerprint: &SectionFingerprint) -> Option<&SectionAnalysis> {
+        if fingerprint.writable {
+            return None;
+        }
+        self.sections.iter().find(|section| {
+            section.virtual_address == fingerprint.virtual_address
+                && section.hash == fingerprint.hash
+        })
+    }
+
+    /// Iterates over the code and data references of the module.
+    pub fn xrefs(&self) -> impl Iterator<Item = Xref> + '_ {
+        self.code_xrefs
+            .iter()
+            .chain(self.sections.iter().flat_map(|s| s.data_xrefs.iter()))
+            .copied()
+    }
+
+    /// Iterates over the strings of the module.
+    pub fn strings(&self) -> impl Iterator<Item = &StringReference> {
+        self.sections.iter().flat_map(|s| s.strings.iter())
+    }
+}
+
+/// Results of the scans over the whole process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessAnalysis {
+    /// Base and fingerprint hash of each module when the scans ran.
+    pub modules: Vec<(usize, u64)>,
+    /// Functions found by pattern and vtable scans outside the module functions.
+    pub functions: Vec<DiscoveredFunction>,
+    /// Addresses of virtual functions, which use `thiscall`.
+    pub virtual_functions: Vec<usize>,
+    pub structures: Vec<DiscoveredStruct>,
+    pub class_hierarchy: ClassHierarchy,
+    pub code_patterns: Vec<CodePattern>,
+    pub import_table: Vec<ImportEntry>,
+    pub export_table: Vec<ExportEntry>,
+    pub vtables_analyzed: usize,
+}
+
+impl ProcessAnalysis {
+    /// Returns true if the scans were made against exactly these modules.
+    pub fn is_valid_for(&self, modules: &[ModuleAnalysis]) -> bool {
+        self.modules.len() == modules.len()
+            && self
+                .modules
+                .iter()
+                .zip(modules)
+                .all(|(&(base, hash), module)| {
+                    base == module.base && hash == module.fingerprint.hash()
+                })
+    }
+}
+
+/// Everything persisted between runs.
+///
+/// Modules are matched to the current target modules by base address, or,
+/// since bases change between runs, by their code fingerprint; see
+/// [`AnalysisCache::module_for`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisCache {
+    pub version: u32,
+    /// String extraction settings the cached strings were made with.
+    pub string_scan: StringScanConfig,
+    pub modules: Vec<ModuleAnalysis>,
+    pub process: Option<ProcessAnalysis>,
+}
+
+impl AnalysisCache {
+    /// Creates an empty cache.
+    pub fn new(string_scan: StringScanConfig) -> Self {
+        Self {
+            version: CACHE_VERSION,
+            string_scan,
+            modules: Vec::new(),
+            process: None,
+        }
+    }
+
+    /// Returns the cached analysis of the module loaded at `base` with
+    /// `fingerprint`: the entry for the same base if there is one, otherwise
+    /// one whose code is unchanged.
+    pub fn module_for(
+        &self,
+        base: usize,
+        fingerprint: &ModuleFingerprint,
+    ) -> Option<&ModuleAnalysis> {
+        self.modules
+            .iter()
+            .find(|module| module.base == base)
+            .or_else(|| {
+                self.modules
+                    .iter()
+                    .find(|module| module.fingerprint.same_code(fingerprint))
+            })
+    }
+
+    /// Reads a cache file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let data = std::fs::read(path)?;
+        let cache: Self =
+            serde_json::from_slice(&data).map_err(|e| Error::InvalidCache(e.to_string()))?;
+        if cache.version != CACHE_VERSION {
+            return Err(Error::InvalidCache(format!(
+                "version {} is not {CACHE_VERSION}",
+                cache.version
+            )));
+        }
+        Ok(cache)
+    }
+
+    /// Writes the cache file, replacing it only once fully written.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let data = serde_json::to_vec(self).map_err(|e| Error::InvalidCache(e.to_string()))?;
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, data)?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::StringEncoding;
+    use crate::pe::fixtures::{BASE, DATA, IMAGE_SIZE, TEXT, build_image, put_u64};
+    use crate::xref::XrefKind;
+
+    fn image_at(base: usize, code: &[u8]) -> Vec<u8> {
+        let mut image = build_image(code, &[], &[]);
+        image[DATA + 0x10..DATA + 0x15].copy_from_slice(b"Tick\0");
+        put_u64(&mut image, DATA + 0x20, (base + TEXT) as u64);
+        image
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_base() {
+        let code = [0x31, 0xC0, 0xC3];
+        let other = BASE + 0x1000_0000;
+        let first = image_at(BASE, &code);
+        let second = image_at(other, &code);
+        let a = ModuleFingerprint::compute(&PeImage::parse(&first, BASE).unwrap());
+        let b = ModuleFingerprint::compute(&PeImage::parse(&second, other).unwrap());
+        assert_eq!(a, b);
+
+        let mut patched = image_at(BASE, &[0x33, 0xC0, 0xC3]);
+        let c = ModuleFingerprint::compute(&PeImage::parse(&patched, BASE).unwrap());
+        assert!(!a.same_code(&c));
+        assert_eq!(a.sections[2].hash, c.sections[2].hash);
+
+        // The fixture's .data is writable, so it does not count towards the module hash.
+        patched[DATA + 0x10] = b't';
+        let d = ModuleFingerprint::compute(&PeImage::parse(&patched, BASE).unwrap());
+        assert!(c.same_code(&d));
+        assert_ne!(c.sections[2].hash, d.sections[2].hash);
+        assert_eq!(c.hash(), d.hash());
+    }
+
+    #[test]
+    fn test_rebase_and_round_trip() {
+        let mut module = ModuleAnalysis {
+            base: BASE,
+            fingerprint: ModuleFingerprint {
+                timestamp: 1,
+                size_of_image: IMAGE_SIZE as u32,
+                sections: Vec::new(),
+                code_hash: 2,
+            },
+            functions: Vec::new(),
+            code_xrefs: vec![Xref {
+                from: BASE + TEXT,
+                to: BASE + DATA,
+                kind: XrefKind::AddressOf,
+            }],
+            sections: vec![SectionAnalysis {
+                virtual_address: DATA as u32,
+                hash: 3,
+                strings: vec![StringReference {
+                    address: BASE + DATA,
+                    value: "Tick".to_string(),
+                    encoding: StringEncoding::Ascii,
+                    references: Vec::new(),
+                }],
+                data_xrefs: Vec::new(),
+            }],
+            cross_references: true,
+        };
+        let mut cache = AnalysisCache::new(StringScanConfig::default());
+        cache.modules.push(module.clone());
+
+        let path = std::env::temp_dir().join(format!("analysis_cache_{}.json", std::process::id()));
+        cache.save(&path).unwrap();
+        let loaded = AnalysisCache::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.modules[0].fingerprint, module.fingerprint);
+        assert_eq!(loaded.modules[0].code_xrefs, module.code_xrefs);
+        assert_eq!(loaded.string_scan, StringScanConfig::default());
+
+        let moved = 0x7FF7_0000_0000;
+        let changed = ModuleFingerprint {
+            code_hash: 4,
+            ..module.fingerprint.clone()
+        };
+        assert!(cache.module_for(BASE, &changed).is_some());
+        assert!(cache.module_for(moved, &module.fingerprint).is_some());
+        assert!(cache.module_for(moved, &changed).is_none());
+
+        module.rebase(0x7FF6_0000_0000);
+        assert_eq!(module.xrefs().next().unwrap().to, 0x7FF6_0000_0000 + DATA);
+        assert_eq!(
+            module.strings().next().unwrap().address,
+            0x7FF6_0000_0000 + DATA
+        );
+        let read_only = SectionFingerprint {
+            name: ".rdata".to_string(),
+            virtual_address: DATA as u32,
+            hash: 3,
+            executable: false,
+            writable: false,
+        };
+        assert!(module.reusable_section(&read_only).is_some());
+        let writable = SectionFingerprint {
+            writable: true,
+            ..read_only
+        };
+        assert!(module.reusable_section(&writable).is_none());
+    }
+}
@@ -0,0 +1,778 @@
+//! Calling convention and parameter inference via register liveness.
+//!
+//! Under the Microsoft x64 convention the first four arguments arrive in
+//! RCX/RDX/R8/R9 or XMM0–XMM3 by position, further arguments at `[rsp+0x28]`
+//! and up, and results leave in RAX or XMM0. A forward dataflow over the
+//! control-flow graph tracks which registers are definitely written on every
+//! path; argument registers read before that point are parameters, typed by
+//! the width and kind of their first use. Stack parameters are found by
+//! following RSP through the prolog, and the return type comes from the last
+//! write to RAX or XMM0 before each `ret`.
+
+use std::collections::HashMap;
+
+use crate::analysis::{CallingConvention, DataType, Parameter, ParameterLocation};
+use crate::cfg::{BlockExit, ControlFlowGraph};
+use crate::disasm::{Flow, Instruction, Mnemonic, OpcodeMap, Register};
+use crate::structure;
+
+/// Offset of the first stack argument from RSP at entry.
+const FIRST_STACK_ARGUMENT: i64 = 0x28;
+/// Number of stack parameters reported at most.
+const MAX_STACK_PARAMETERS: i64 = 16;
+
+const ARGUMENT_GPRS: [u8; 4] = [1, 2, 8, 9];
+const VOLATILE_GPRS: [u8; 7] = [0, 1, 2, 8, 9, 10, 11];
+const VOLATILE_XMMS: u8 = 6;
+
+/// Inferred prototype of a function.
+#[derive(Debug, Clone)]
+pub struct FunctionSignature {
+    pub calling_convention: CallingConvention,
+    pub parameters: Vec<Parameter>,
+    /// `None` when no return path was analyzed or the value comes from a call.
+    pub return_type: Option<DataType>,
+    /// Returns true if RCX is dereferenced as an object pointer.
+    pub uses_this: bool,
+}
+
+/// Infers the calling convention, parameters and return type of `graph`.
+pub fn infer_signature(graph: &ControlFlowGraph) -> FunctionSignature {
+    let order = graph.reverse_postorder();
+    let mut states: HashMap<usize, State> = HashMap::new();
+    let mut inputs: Vec<Input> = Vec::new();
+    let mut stack: HashMap<i64, DataType> = HashMap::new();
+    let mut returns: Vec<Returned> = Vec::new();
+    let mut uses_this = false;
+
+    for &start in &order {
+        let block = &graph.blocks[&start];
+        let mut state = if start == graph.entry {
+            State::default()
+        } else {
+            block
+                .predecessors
+                .iter()
+                .filter_map(|p| states.get(p))
+                .fold(None, |acc: Option<State>, s| {
+                    Some(match acc {
+                        Some(acc) => acc.merge(s),
+                        None => s.clone(),
+                    })
+                })
+                .unwrap_or_default()
+        };
+
+        for insn in &block.instructions {
+            let effects = effects(insn);
+            for &(register, ref data_type) in &effects.reads {
+                if is_argument(register) && !state.is_defined(register) {
+                    record_input(&mut inputs, register, data_type.clone());
+                }
+            }
+            if effects.addressed.contains(&1) && !state.is_defined(Register::RCX) {
+                uses_this = true;
+                record_input(
+                    &mut inputs,
+                    Register::RCX,
+                    DataType::Pointer(Box::new(DataType::Unknown)),
+                );
+            }
+            if let Some(offset) = state.stack_offset(insn)
+                && offset >= FIRST_STACK_ARGUMENT
+                && !structure::is_memory_write(insn)
+            {
+                let slot = (offset - FIRST_STACK_ARGUMENT) / 8;
+                if slot < MAX_STACK_PARAMETERS {
+                    stack.entry(slot).or_insert(effects.value_type.clone());
+                }
+            }
+            state.apply(insn, &effects);
+        }
+
+        if block.exit == Some(BlockExit::Return) {
+            returns.push(state.returned.clone());
+        }
+        states.insert(start, state);
+    }
+
+    let parameters = build_parameters(&inputs, &stack);
+    let vector = inputs
+        .iter()
+        .any(|input| matches!(input.register, Register::Xmm(4 | 5)));
+    let calling_convention = if uses_this {
+        CallingConvention::Thiscall
+    } else if vector {
+        CallingConvention::Vectorcall
+    } else if !parameters.is_empty() {
+        CallingConvention::Fastcall
+    } else {
+        CallingConvention::Unknown
+    };
+
+    FunctionSignature {
+        calling_convention,
+        parameters,
+        return_type: return_type(&returns),
+        uses_this,
+    }
+}
+
+/// An argument register read before it is written.
+struct Input {
+    register: Register,
+    data_type: DataType,
+}
+
+/// What RAX/XMM0 hold at a point in the function.
+#[derive(Debug, Clone, PartialEq)]
+enum Returned {
+    /// Neither register has been written.
+    Nothing,
+    /// Written by the function itself with a value of this type.
+    Value(DataType),
+    /// Clobbered by a call, so the value is the callee's result.
+    Call,
+}
+
+/// Dataflow state at a program point.
+#[derive(Debug, Clone)]
+struct State {
+    /// Registers written on every path: bits 0–15 GPRs, 16–31 XMM registers.
+    defined: u32,
+    /// RSP relative to its value at entry.
+    rsp: i64,
+    /// RBP relative to the entry RSP when it was set up as a frame pointer.
+    rbp: Option<i64>,
+    returned: Returned,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            defined: 0,
+            rsp: 0,
+            rbp: None,
+            returned: Returned::Nothing,
+        }
+    }
+}
+
+impl State {
+    fn is_defined(&self, register: Register) -> bool {
+        self.defined & bit(register) != 0
+    }
+
+    fn merge(self, other: &State) -> State {
+        State {
+            defined: self.defined & other.defined,
+            ..self
+        }
+    }
+
+    /// Offset of the instruction's stack operand from the entry RSP.
+    fn stack_offset(&self, insn: &Instruction) -> Option<i64> {
+        let memory = insn.memory.filter(|m| m.index.is_none())?;
+        let frame = match memory.base? {
+            4 => self.rsp,
+            5 => self.rbp?,
+            _ => return None,
+        };
+        Some(frame + memory.displacement)
+    }
+
+    fn apply(&mut self, insn: &Instruction, effects: &Effects) {
+        let immediate = insn.immediate.unwrap_or(0);
+        match insn.mnemonic {
+            Mnemonic::Push => self.rsp -= 8,
+            Mnemonic::Pop => self.rsp += 8,
+            Mnemonic::Sub if insn.rm_register() == Some(4) => self.rsp -= immediate,
+            Mnemonic::Add if insn.rm_register() == Some(4) => self.rsp += immediate,
+            Mnemonic::Mov
+                if insn.opcode == 0x8B
+                    && insn.reg_field() == Some(5)
+                    && insn.rm_register() == Some(4) =>
+            {
+                self.rbp = Some(self.rsp);
+            }
+            Mnemonic::Mov
+                if insn.opcode == 0x89
+                    && insn.rm_register() == Some(5)
+                    && insn.reg_field() == Some(4) =>
+            {
+                self.rbp = Some(self.rsp);
+            }
+            Mnemonic::Lea if insn.reg_field() == Some(5) => {
+                self.rbp = self.stack_offset(insn);
+            }
+            _ => {}
+        }
+
+        for &register in &effects.writes {
+            self.defined |= bit(register);
+            if register == Register::RAX || register == Register::Xmm(0) {
+                self.returned = Returned::Value(effects.value_type.clone());
+            }
+        }
+        if matches!(insn.flow(), Flow::Call | Flow::IndirectCall) {
+            for register in VOLATILE_GPRS {
+                self.defined |= bit(Register::Gpr(register));
+            }
+            for register in 0..VOLATILE_XMMS {
+                self.defined |= bit(Register::Xmm(register));
+            }
+            self.returned = Returned::Call;
+        }
+    }
+}
+
+/// Registers read and written by one instruction.
+#[derive(Debug)]
+struct Effects {
+    /// Registers read, with the type implied by the read.
+    reads: Vec<(Register, DataType)>,
+    writes: Vec<Register>,
+    /// GPRs used as the base of a memory operand.
+    addressed: Vec<u8>,
+    /// Type of the value moved or computed by the instruction.
+    value_type: DataType,
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Self {
+            reads: Vec::new(),
+            writes: Vec::new(),
+            addressed: Vec::new(),
+            value_type: DataType::Unknown,
+        }
+    }
+}
+
+fn bit(register: Register) -> u32 {
+    match register {
+        Register::Gpr(n) => 1 << (n & 15),
+        Register::Xmm(n) => 1 << (16 + (n & 15)),
+    }
+}
+
+fn is_argument(register: Register) -> bool {
+    match register {
+        Register::Gpr(n) => ARGUMENT_GPRS.contains(&n),
+        Register::Xmm(n) => n < VOLATILE_XMMS,
+    }
+}
+
+/// Positional parameter index of an argument register.
+fn argument_index(register: Register) -> usize {
+    match register {
+        Register::Gpr(n) => ARGUMENT_GPRS.iter().position(|&r| r == n).unwrap_or(0),
+        Register::Xmm(n) => n as usize,
+    }
+}
+
+fn record_input(inputs: &mut Vec<Input>, register: Register, data_type: DataType) {
+    match inputs.iter_mut().find(|input| input.register == register) {
+        Some(input) => {
+            if input.data_type == DataType::Unknown || matches!(data_type, DataType::Pointer(_)) {
+                input.data_type = data_type;
+            }
+        }
+        None => inputs.push(Input {
+            register,
+            data_type,
+        }),
+    }
+}
+
+fn integer_type(size: u8) -> DataType {
+    match size {
+        1 => DataType::Int8,
+        2 => DataType::Int16,
+        4 => DataType::Int32,
+        _ => DataType::Int64,
+    }
+}
+
+fn build_parameters(inputs: &[Input], stack: &HashMap<i64, DataType>) -> Vec<Parameter> {
+    let mut parameters: Vec<Parameter> = Vec::new();
+    for input in inputs {
+        let index = argument_index(input.register);
+        if parameters.iter().any(|p| p.index == index) {
+            continue;
+        }
+        let confidence = if input.data_type == DataType::Unknown {
+            0.7
+        } else {
+            0.9
+        };
+        parameters.push(Parameter {
+            index,
+            data_type: input.data_type.clone(),
+            location: ParameterLocation::Register(input.register.to_string()),
+            confidence,
+        });
+    }
+    for (&slot, data_type) in stack {
+        parameters.push(Parameter {
+            index: 4 + slot as usize,
+            data_type: data_type.clone(),
+            location: ParameterLocation::Stack((FIRST_STACK_ARGUMENT + slot * 8) as i32),
+            confidence: 0.7,
+        });
+    }
+
+    // Arguments are positional, so every index below a used one is a parameter too.
+    let count = parameters.iter().map(|p| p.index + 1).max().unwrap_or(0);
+    for index in 0..count {
+        if parameters.iter().all(|p| p.index != index) {
+            let location = match ARGUMENT_GPRS.get(index) {
+                Some(&register) => ParameterLocation::Register(Register::Gpr(register).to_string()),
+                None => {
+                    ParameterLocation::Stack((FIRST_STACK_ARGUMENT + (index as i64 - 4) * 8) as i32)
+                }
+            };
+            parameters.push(Parameter {
+                index,
+                data_type: DataType::Unknown,
+                location,
+                confidence: 0.4,
+            });
+        }
+    }
+    parameters.sort_by_key(|p| p.index);
+    parameters
+}
+
+fn return_type(returns: &[Returned]) -> Option<DataType> {
+    if returns.is_empty() {
+        return None;
+    }
+    if returns.iter().all(|r| *r == Returned::Nothing) {
+        return Some(DataType::Void);
+    }
+    returns.iter().find_map(|r| match r {
+        Returned::Value(data_type) if *data_type != DataType::Unknown => Some(data_type.clone()),
+        _ => None,
+    })
+}
+
+/// Computes the register effects of an instruction.
+///
+/// Unmodelled instructions only contribute the registers of their memory
+/// operand, which errs towards missing a parameter rather than inventing one.
+fn effects(insn: &Instruction) -> Effects {
+    let mut effects = Effects::default();
+    if let Some(memory) = insn.memory {
+        if let Some(base) = memory.base {
+            effects.addressed.push(base);
+            effects.reads.push((
+                Register::Gpr(base),
+                DataType::Pointer(Box::new(DataType::Unknown)),
+            ));
+        }
+        if let Some(index) = memory.index {
+            effects.reads.push((Register::Gpr(index), DataType::Int64));
+        }
+    }
+
+    let op = insn.opcode;
+    let primary = insn.map == OpcodeMap::Primary;
+    let byte_form = primary
+        && ((op < 0x40 && op & 1 == 0)
+            || matches!(op, 0x80 | 0x84 | 0x86 | 0x88 | 0x8A | 0xC6 | 0xF6 | 0xFE));
+    let size = if byte_form { 1 } else { insn.operand_size() };
+    let gpr_type = integer_type(size);
+    let reg = insn.reg_field().map(Register::Gpr);
+    let rm = insn.rm_register().map(Register::Gpr);
+    let xmm_reg = insn.reg_field().map(Register::Xmm);
+    let xmm_rm = insn.rm_register().map(Register::Xmm);
+    let same_register = insn.modrm.is_some_and(|m| m.mode == 3 && m.reg == m.rm);
+    let sse_prefix = match insn.vex {
+        Some(v) => [None, Some(0x66), Some(0xF3), Some(0xF2)][v.pp as usize],
+        None => insn.prefixes.mandatory,
+    };
+    let float_type = match sse_prefix {
+        Some(0xF2) | Some(0x66) => DataType::Float64,
+        _ => DataType::Float32,
+    };
+
+    let mut read = |register: Option<Register>, data_type: &DataType| {
+        if let Some(register) = register {
+            effects.reads.push((register, data_type.clone()));
+        }
+    };
+    let mut writes: Vec<Option<Register>> = Vec::new();
+    let mut value_type = gpr_type.clone();
+
+    match insn.mnemonic {
+        Mnemonic::Mov if primary => match op {
+            0x88 | 0x89 => {
+                read(reg, &gpr_type);
+                writes.push(rm);
+            }
+            0x8A | 0x8B => {
+                read(rm, &gpr_type);
+                writes.push(reg);
+            }
+            0xC6 | 0xC7 => writes.push(rm),
+            0xB0..=0xBF => {
+                let register = (op & 7) | ((insn.rex & 1) << 3);
+                value_type = if op < 0xB8 {
+                    DataType::Int8
+                } else {
+                    gpr_type.clone()
+                };
+                writes.push(Some(Register::Gpr(register)));
+            }
+            _ => {}
+        },
+        Mnemonic::Lea => {
+            value_type = DataType::Pointer(Box::new(DataType::Unknown));
+            writes.push(reg);
+        }
+        Mnemonic::Movzx | Mnemonic::Movsx | Mnemonic::Movsxd => {
+            let source = match op {
+                0xB6 | 0xBE => 1,
+                0xB7 | 0xBF => 2,
+                _ => 4,
+            };
+            read(rm, &integer_type(source));
+            writes.push(reg);
+        }
+        Mnemonic::Xor | Mnemonic::Sub if same_register => writes.push(rm),
+        Mnemonic::Add
+        | Mnemonic::Or
+        | Mnemonic::Adc
+        | Mnemonic::Sbb
+        | Mnemonic::And
+        | Mnemonic::Sub
+        | Mnemonic::Xor
+        | Mnemonic::Cmp => {
+            let compare = insn.mnemonic == Mnemonic::Cmp;
+            if insn.modrm.is_none() {
+                read(Some(Register::RAX), &gpr_type);
+                if !compare {
+                    writes.push(Some(Register::RAX));
+                }
+            } else if op < 0x40 {
+                read(reg, &gpr_type);
+                read(rm, &gpr_type);
+                if !compare {
+                    writes.push(if op & 2 != 0 { reg } else { rm });
+                }
+            } else {
+                read(rm, &gpr_type);
+                if !compare {
+                    writes.push(rm);
+                }
+            }
+        }
+        Mnemonic::Test => {
+            if insn.modrm.is_none() {
+                read(Some(Register::RAX), &gpr_type);
+            } else {
+                read(rm, &gpr_type);
+                if matches!(op, 0x84 | 0x85) {
+                    read(reg, &gpr_type);
+                }
+            }
+        }
+        Mnemonic::Inc
+        | Mnemonic::Dec
+        | Mnemonic::Neg
+        | Mnemonic::Not
+        | Mnemonic::Rol
+        | Mnemonic::Ror
+        | Mnemonic::Rcl
+        | Mnemonic::Rcr
+        | Mnemonic::Shl
+        | Mnemonic::Shr
+        | Mnemonic::Sar => {
+            read(rm, &gpr_type);
+            if matches!(op, 0xD2 | 0xD3) {
+                read(Some(Register::RCX), &DataType::Int8);
+            }
+            writes.push(rm);
+        }
+        Mnemonic::Mul | Mnemonic::Div | Mnemonic::Idiv => {
+            read(rm, &gpr_type);
+            read(Some(Register::RAX), &gpr_type);
+            if insn.mnemonic != Mnemonic::Mul {
+                read(Some(Register::RDX), &gpr_type);
+            }
+            writes.extend([Some(Register::RAX), Some(Register::RDX)]);
+        }
+        Mnemonic::Imul => match op {
+            0xF6 | 0xF7 => {
+                read(rm, &gpr_type);
+                read(Some(Register::RAX), &gpr_type);
+                writes.extend([Some(Register::RAX), Some(Register::RDX)]);
+            }
+            0xAF => {
+                read(reg, &gpr_type);
+                read(rm, &gpr_type);
+                writes.push(reg);
+            }
+            _ => {
+                read(rm, &gpr_type);
+                writes.push(reg);
+            }
+        },
+        Mnemonic::Cmov => {
+            read(reg, &gpr_type);
+            read(rm, &gpr_type);
+            writes.push(reg);
+        }
+        Mnemonic::Set => {
+            value_type = DataType::Bool;
+            writes.push(rm);
+        }
+        Mnemonic::Xchg => {
+            read(reg, &gpr_type);
+            read(rm, &gpr_type);
+            writes.extend([reg, rm]);
+        }
+        Mnemonic::Push if primary && (0x50..=0x57).contains(&op) => {
+            read(
+                Some(Register::Gpr((op & 7) | ((insn.rex & 1) << 3))),
+                &DataType::Int64,
+            );
+        }
+        Mnemonic::Push => read(rm, &DataType::Int64),
+        Mnemonic::Pop if primary && (0x58..=0x5F).contains(&op) => {
+            writes.push(Some(Register::Gpr((op & 7) | ((insn.rex & 1) << 3))));
+        }
+        Mnemonic::Cdqe => {
+            read(Some(Register::RAX), &DataType::Int32);
+            value_type = DataType::Int64;
+            writes.push(Some(Register::RAX));
+        }
+        Mnemonic::Cqo => {
+            read(Some(Register::RAX), &gpr_type);
+            writes.push(Some(Register::RDX));
+        }
+        Mnemonic::Movs | Mnemonic::Stos | Mnemonic::Lods | Mnemonic::Cmps | Mnemonic::Scas => {
+            let pointer = DataType::Pointer(Box::new(DataType::Unknown));
+            if matches!(
+                insn.mnemonic,
+                Mnemonic::Movs | Mnemonic::Cmps | Mnemonic::Lods
+            ) {
+                read(Some(Register::RSI), &pointer);
+            }
+            if insn.mnemonic != Mnemonic::Lods {
+                read(Some(Register::RDI), &pointer);
+            }
+            if matches!(insn.mnemonic, Mnemonic::Stos | Mnemonic::Scas) {
+                read(Some(Register::RAX), &gpr_type);
+            }
+            if insn.prefixes.rep || insn.prefixes.repne {
+                read(Some(Register::RCX), &DataType::Int64);
+                writes.push(Some(Register::RCX));
+            }
+        }
+        Mnemonic::Jmp if insn.relative.is_none() => read(rm, &DataType::Int64),
+        Mnemonic::Call if insn.relative.is_none() => read(rm, &DataType::Int64),
+        Mnemonic::Movss
+        | Mnemonic::Movsd
+        | Mnemonic::Movups
+        | Mnemonic::Movupd
+        | Mnemonic::Movaps
+        | Mnemonic::Movapd
+        | Mnemonic::Movdqa
+        | Mnemonic::Movdqu => {
+            value_type = match insn.mnemonic {
+                Mnemonic::Movss => DataType::Float32,
+                Mnemonic::Movsd => DataType::Float64,
+                _ => DataType::Unknown,
+            };
+            if matches!(op, 0x11 | 0x29 | 0x7F) {
+                read(xmm_reg, &value_type);
+                writes.push(xmm_rm);
+            } else {
+                read(xmm_rm, &value_type);
+                writes.push(xmm_reg);
+            }
+        }
+        Mnemonic::Movd | Mnemonic::Movq => {
+            value_type = integer_type(if insn.mnemonic == Mnemonic::Movd {
+                4
+            } else {
+                8
+            });
+            match op {
+                0x6E => {
+                    read(rm, &value_type);
+                    writes.push(xmm_reg);
+                }
+                0x7E if sse_prefix == Some(0xF3) => {
+                    read(xmm_rm, &DataType::Unknown);
+                    writes.push(xmm_reg);
+                }
+                0x7E => {
+                    read(xmm_reg, &DataType::Unknown);
+                    writes.push(rm);
+                }
+                0xD6 => {
+                    read(xmm_reg, &DataType::Unknown);
+                    writes.push(xmm_rm);
+                }
+                _ => {}
+            }
+        }
+        Mnemonic::SseXor | Mnemonic::Pxor if same_register && insn.vex.is_none() => {
+            value_type = DataType::Unknown;
+            writes.push(xmm_reg);
+        }
+        Mnemonic::SseAdd
+        | Mnemonic::SseSub
+        | Mnemonic::SseMul
+        | Mnemonic::SseDiv
+        | Mnemonic::SseMin
+        | Mnemonic::SseMax
+        | Mnemonic::SseAnd
+        | Mnemonic::SseAndn
+        | Mnemonic::SseOr
+        | Mnemonic::SseXor
+        | Mnemonic::SseSqrt
+        | Mnemonic::Pxor => {
+            value_type = float_type.clone();
+            read(xmm_rm, &float_type);
+            match insn.vex {
+                Some(vex)
+                    if insn.mnemonic != Mnemonic::SseSqrt
+                        || sse_prefix.is_some_and(|p| p >= 0xF2) =>
+                {
+                    read(Some(Register::Xmm(vex.vvvv)), &float_type);
+                }
+                None if insn.mnemonic != Mnemonic::SseSqrt => read(xmm_reg, &float_type),
+                _ => {}
+            }
+            writes.push(xmm_reg);
+        }
+        Mnemonic::SseConvert => match op {
+            0x2A => {
+                value_type = float_type.clone();
+                read(rm, &integer_type(insn.operand_size()));
+                writes.push(xmm_reg);
+            }
+            0x2C | 0x2D => {
+                let source = if sse_prefix == Some(0xF2) {
+                    DataType::Float64
+                } else {
+                    DataType::Float32
+                };
+                read(xmm_rm, &source);
+                writes.push(reg);
+            }
+            _ => {
+                value_type = DataType::Unknown;
+                read(xmm_rm, &DataType::Unknown);
+                writes.push(xmm_reg);
+            }
+        },
+        Mnemonic::SseCompare => {
+            read(xmm_reg, &float_type);
+            read(xmm_rm, &float_type);
+            if op == 0xC2 {
+                writes.push(xmm_reg);
+            }
+        }
+        _ => {}
+    }
+
+    effects.writes = writes.into_iter().flatten().collect();
+    effects.value_type = value_type;
+    effects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(code: &[u8]) -> FunctionSignature {
+        infer_signature(&ControlFlowGraph::build(code, 0x1000, 0x1000, None))
+    }
+
+    #[test]
+    fn test_register_parameters() {
+        let code = [
+            0x48, 0x89, 0x5C, 0x24, 0x08, // mov [rsp+8], rbx
+            0x8B, 0x41, 0x10, // mov eax, [rcx+0x10]
+            0x01, 0xD0, // add eax, edx
+            0xF3, 0x0F, 0x59, 0xCA, // mulss xmm1, xmm2
+            0x45, 0x31, 0xC9, // xor r9d, r9d
+            0x45, 0x01, 0xC8, // add r8d, r9d
+            0xC3, // ret
+        ];
+        let sig = signature(&code);
+        assert!(sig.uses_this);
+        assert_eq!(sig.calling_convention, CallingConvention::Thiscall);
+        let types: Vec<(usize, DataType, String)> = sig
+            .parameters
+            .iter()
+            .map(|p| match &p.location {
+                ParameterLocation::Register(r) => (p.index, p.data_type.clone(), r.clone()),
+                other => panic!("unexpected location {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                (
+                    0,
+                    DataType::Pointer(Box::new(DataType::Unknown)),
+                    "RCX".to_string()
+                ),
+                (1, DataType::Int32, "RDX".to_string()),
+                (2, DataType::Float32, "XMM2".to_string()),
+            ]
+        );
+        assert_eq!(sig.return_type, Some(DataType::Int32));
+    }
+
+    #[test]
+    fn test_stack_parameters_and_gaps() {
+        let code = [
+            0x53, // push rbx
+            0x48, 0x83, 0xEC, 0x20, // sub rsp, 0x20
+            0x8B, 0x44, 0x24, 0x50, // mov eax, [rsp+0x50] (fifth argument)
+            0x01, 0xC1, // add ecx, eax
+            0xF2, 0x0F, 0x10, 0x44, 0x24, 0x58, // movsd xmm0, [rsp+0x58] (sixth)
+            0x48, 0x83, 0xC4, 0x20, // add rsp, 0x20
+            0x5B, // pop rbx
+            0xC3, // ret
+        ];
+        let sig = signature(&code);
+        assert_eq!(sig.calling_convention, CallingConvention::Fastcall);
+        let indices: Vec<usize> = sig.parameters.iter().map(|p| p.index).collect();
+        assert_eq!(indices, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(sig.parameters[1].confidence, 0.4);
+        assert!(matches!(
+            sig.parameters[4].location,
+            ParameterLocation::Stack(0x28)
+        ));
+        assert_eq!(sig.parameters[4].data_type, DataType::Int32);
+        assert_eq!(sig.parameters[5].data_type, DataType::Float64);
+        assert_eq!(sig.return_type, Some(DataType::Float64));
+    }
+
+    #[test]
+    fn test_return_types() {
+        // sub rsp, 0x28; call f; add rsp, 0x28; ret
+        let call = [
+            0x48, 0x83, 0xEC, 0x28, 0xE8, 0x00, 0x00, 0x00, 0x00, 0x48, 0x83, 0xC4, 0x28, 0xC3,
+        ];
+        assert_eq!(signature(&call).return_type, None);
+        assert_eq!(signature(&[0xC3]).return_type, Some(DataType::Void));
+        // test ecx, ecx; setne al; ret
+        let boolean = [0x85, 0xC9, 0x0F, 0x95, 0xC0, 0xC3];
+        let sig = signature(&boolean);
+        assert_eq!(sig.return_type, Some(DataType::Bool));
+        assert_eq!(sig.parameters[0].data_type, DataType::Int32);
+        assert_eq!(
+            signature(&[0xC3]).calling_convention,
+            CallingConvention::Unknown
+        );
+    }
+}
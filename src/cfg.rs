@@ -0,0 +1,693 @@
+//! Control-flow graph construction for discovered functions.
+//!
+//! A function is decoded by recursive descent from its entry and split into
+//! basic blocks at branch targets and after branches. Edges cover
+//! fall-through, conditional and unconditional jumps, and switch dispatch
+//! through jump tables recovered from the `jmp [reg*8+table]` and
+//! `mov reg, [base+reg*4+table]; add reg, base; jmp reg` idioms. On top of the
+//! graph, dominators, natural loops and exit blocks are computed, and the
+//! graph can be exported to Graphviz DOT for review.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::ops::Range;
+
+use crate::disasm::{self, Flow, Instruction, Mnemonic};
+
+/// Maximum number of instructions decoded per function.
+const MAX_INSTRUCTIONS: usize = 0x4000;
+/// Maximum number of jump table entries followed when the bound is unknown.
+const MAX_TABLE_ENTRIES: usize = 512;
+/// Maximum number of instructions searched back from a jump for its table.
+const MAX_PRECEDING: usize = 16;
+
+/// Kind of a control-flow edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// Execution continues with the next instruction.
+    FallThrough,
+    /// Taken side of a conditional jump.
+    Conditional,
+    /// Unconditional direct jump.
+    Jump,
+    /// Switch dispatch through a jump table.
+    JumpTable,
+}
+
+/// A directed edge between two basic blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// How control leaves the function at the end of an exit block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockExit {
+    Return,
+    /// Direct jump to another function.
+    TailCall(usize),
+    /// Indirect jump that is not a recognized jump table.
+    IndirectJump,
+    /// `int3`, `ud2` or `hlt`.
+    Trap,
+}
+
+/// A straight-line sequence of instructions with a single entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+    pub instructions: Vec<Instruction>,
+    pub successors: Vec<usize>,
+    pub predecessors: Vec<usize>,
+    /// Set for blocks that leave the function.
+    pub exit: Option<BlockExit>,
+}
+
+/// A natural loop identified by its back edges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: usize,
+    /// Blocks with a back edge to the header.
+    pub latches: Vec<usize>,
+    /// Every block in the loop body, including the header.
+    pub blocks: BTreeSet<usize>,
+}
+
+/// Control-flow graph of a single function.
+#[derive(Debug, Clone, Default)]
+pub struct ControlFlowGraph {
+    pub entry: usize,
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    pub edges: Vec<Edge>,
+    /// True if decoding ran off the end of the supplied code or failed.
+    pub truncated: bool,
+}
+
+impl ControlFlowGraph {
+    /// Builds the graph of the function at `entry`.
+    ///
+    /// `code` holds the bytes mapped at `base`; jump tables are only resolved
+    /// when they lie inside it, so passing a whole module image gives the best
+    /// results. `bounds` is the function's address range when known (e.g.
+    /// from `.pdata`); jumps leaving it are tail calls. Without bounds, long
+    /// jumps that land past the code decoded so far or before the entry are
+    /// treated as tail calls.
+    pub fn build(code: &[u8], base: usize, entry: usize, bounds: Option<Range<usize>>) -> Self {
+        let mut graph = Self {
+            entry,
+            ..Self::default()
+        };
+        let code_range = base..base + code.len();
+        let decode = |address: usize| {
+            if !code_range.contains(&address) {
+                return None;
+            }
+            disasm::decode(&code[address - base..], address).ok()
+        };
+        let internal = |target: usize, short: bool, furthest: usize| match &bounds {
+            Some(range) => range.contains(&target),
+            None => target >= entry && (short || target < furthest),
+        };
+
+        // Decode every reachable instruction and collect block leaders.
+        let mut instructions: BTreeMap<usize, Instruction> = BTreeMap::new();
+        let mut leaders = BTreeSet::from([entry]);
+        let mut pending = vec![entry];
+        let mut targets: HashMap<usize, Vec<(usize, EdgeKind)>> = HashMap::new();
+        // Instructions control reaches each instruction from.
+        let mut sources: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut exits: HashMap<usize, BlockExit> = HashMap::new();
+        let mut furthest = entry;
+        // Indirect jumps are resolved once the code leading to them is known.
+        let mut indirect = Vec::new();
+
+        loop {
+            while let Some(start) = pending.pop() {
+                let mut address = start;
+                while !instructions.contains_key(&address) {
+                    if instructions.len() >= MAX_INSTRUCTIONS {
+                        graph.truncated = true;
+                        break;
+                    }
+                    let Some(insn) = decode(address) else {
+                        graph.truncated = true;
+                        break;
+                    };
+                    furthest = furthest.max(insn.next_address());
+                    let next = insn.next_address();
+                    let mut branch =
+                        |target: usize, kind: EdgeKind, leaders: &mut BTreeSet<usize>| {
+                            targets.entry(address).or_default().push((target, kind));
+                            sources.entry(target).or_default().push(address);
+                            if leaders.insert(target) {
+                                pending.push(target);
+                            }
+                        };
+
+                    let ends = match insn.flow() {
+                        Flow::ConditionalJump => {
+                            if let Some(target) = insn.branch_target() {
+                                branch(target, EdgeKind::Conditional, &mut leaders);
+                            }
+                            leaders.insert(next);
+                            false
+                        }
+                        Flow::Jump => {
+                            let target = insn.branch_target().unwrap_or(next);
+                            if internal(target, insn.length == 2, furthest) {
+                                branch(target, EdgeKind::Jump, &mut leaders);
+                            } else {
+                                exits.insert(address, BlockExit::TailCall(target));
+                            }
+                            true
+                        }
+                        Flow::IndirectJump => {
+                            indirect.push(address);
+                            true
+                        }
+                        Flow::Return => {
+                            exits.insert(address, BlockExit::Return);
+                            true
+                        }
+                        Flow::Trap => {
+                            exits.insert(address, BlockExit::Trap);
+                            true
+                        }
+                        _ => false,
+                    };
+                    instructions.insert(address, insn);
+                    if ends {
+                        break;
+                    }
+                    sources.entry(next).or_default().push(address);
+                    address = next;
+                }
+            }
+
+            let Some(address) = indirect.pop() else {
+                break;
+            };
+            let previous = preceding(&instructions, &sources, address);
+            match resolve_jump_table(&instructions[&address], &previous, code, base) {
+                Some(table) => {
+                    for target in table.into_iter().filter(|&t| decode(t).is_some()) {
+                        targets
+                            .entry(address)
+                            .or_default()
+                            .push((target, EdgeKind::JumpTable));
+                        sources.entry(target).or_default().push(address);
+                        if leaders.insert(target) {
+                            pending.push(target);
+                        }
+                    }
+                }
+                None => {
+                    exits.insert(address, BlockExit::IndirectJump);
+                }
+            }
+        }
+
+        // Split the decoded instructions into blocks.
+        let mut current: Option<BasicBlock> = None;
+        let mut finished = Vec::new();
+        for (&address, insn) in &instructions {
+            let contiguous = current.as_ref().is_some_and(|b| b.end == address);
+            if !contiguous || leaders.contains(&address) {
+                if let Some(mut block) = current.take() {
+                    if contiguous {
+                        graph.edges.push(Edge {
+                            from: block.start,
+                            to: address,
+                            kind: EdgeKind::FallThrough,
+                        });
+                    }
+                    block.exit = exits.get(&last_address(&block)).copied();
+                    finished.push(block);
+                }
+                current = Some(BasicBlock {
+                    start: address,
+                    end: address,
+                    instructions: Vec::new(),
+                    successors: Vec::new(),
+                    predecessors: Vec::new(),
+                    exit: None,
+                });
+            }
+            let block = current.as_mut().expect("block started above");
+            block.end = insn.next_address();
+            block.instructions.push(insn.clone());
+            for &(target, kind) in targets.get(&address).into_iter().flatten() {
+                graph.edges.push(Edge {
+                    from: block.start,
+                    to: target,
+                    kind,
+                });
+            }
+            let splits = insn.is_terminator() || insn.flow() == Flow::ConditionalJump;
+            if splits {
+                let mut block = current.take().expect("block started above");
+                block.exit = exits.get(&address).copied();
+                if insn.flow() == Flow::ConditionalJump && instructions.contains_key(&block.end) {
+                    graph.edges.push(Edge {
+                        from: block.start,
+                        to: block.end,
+                        kind: EdgeKind::FallThrough,
+                    });
+                }
+                finished.push(block);
+            }
+        }
+        if let Some(mut block) = current {
+            block.exit = exits.get(&last_address(&block)).copied();
+            finished.push(block);
+        }
+
+        graph.blocks = finished.into_iter().map(|b| (b.start, b)).collect();
+        graph
+            .edges
+            .retain(|edge| graph.blocks.contains_key(&edge.to));
+        // A block reaching another in two ways, e.g. through a conditional
+        // jump to the next instruction, keeps the first edge found.
+        graph.edges.sort_by_key(|e| (e.from, e.to));
+        graph.edges.dedup_by_key(|e| (e.from, e.to));
+        for edge in &graph.edges {
+            if let Some(block) = graph.blocks.get_mut(&edge.from) {
+                block.successors.push(edge.to);
+            }
+            if let Some(block) = graph.blocks.get_mut(&edge.to) {
+                block.predecessors.push(edge.from);
+            }
+        }
+        graph
+    }
+
+    /// Returns the number of bytes from the entry to the end of the last block.
+    ///
+    /// Returns `None` if the graph is truncated, since the extent is then unknown.
+    pub fn size(&self) -> Option<usize> {
+        if self.truncated {
+            return None;
+        }
+        let end = self.blocks.values().map(|b| b.end).max()?;
+        Some(end.saturating_sub(self.entry))
+    }
+
+    /// Returns the block containing `address`.
+    pub fn block_containing(&self, address: usize) -> Option<&BasicBlock> {
+        self.blocks
+            .range(..=address)
+            .next_back()
+            .map(|(_, block)| block)
+            .filter(|block| address < block.end)
+    }
+
+    /// Returns the blocks through which control leaves the function.
+    pub fn exit_blocks(&self) -> Vec<&BasicBlock> {
+        self.blocks.values().filter(|b| b.exit.is_some()).collect()
+    }
+
+    /// Computes the immediate dominator of every block reachable from the entry.
+    ///
+    /// Uses the iterative algorithm of Cooper, Harvey and Kennedy over the
+    /// reverse postorder. The entry maps to itself.
+    pub fn dominators(&self) -> HashMap<usize, usize> {
+        let order = self.reverse_postorder();
+        let position: HashMap<usize, usize> =
+            order.iter().enumerate().map(|(i, &b)| (b, i)).collect();
+        let mut idom: HashMap<usize, usize> = HashMap::from([(self.entry, self.entry)]);
+
+        let intersect = |idom: &HashMap<usize, usize>, mut a: usize, mut b: usize| {
+            while a != b {
+                while position[&a] > position[&b] {
+                    a = idom[&a];
+                }
+                while position[&b] > position[&a] {
+                    b = idom[&b];
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in order.iter().skip(1) {
+                let mut processed = self.blocks[&block]
+                    .predecessors
+                    .iter()
+                    .filter(|p| idom.contains_key(p) && position.contains_key(p));
+                let Some(&first) = processed.next() else {
+                    continue;
+                };
+                let new = processed.fold(first, |acc, &p| intersect(&idom, acc, p));
+                if idom.get(&block) != Some(&new) {
+                    idom.insert(block, new);
+                    changed = true;
+                }
+            }
+        }
+        idom
+    }
+
+    /// Returns true if every path from the entry to `block` passes through `dominator`.
+    pub fn dominates(&self, dominator: usize, block: usize) -> bool {
+        let idom = self.dominators();
+        let mut current = block;
+        loop {
+            if current == dominator {
+                return true;
+            }
+            match idom.get(&current) {
+                Some(&parent) if parent != current => current = parent,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Finds the natural loops of the function, one per header.
+    pub fn loops(&self) -> Vec<Loop> {
+        let idom = self.dominators();
+        let dominated_by = |header: usize, mut block: usize| loop {
+            if block == header {
+                return true;
+            }
+            match idom.get(&block) {
+                Some(&parent) if parent != block => block = parent,
+                _ => return false,
+            }
+        };
+
+        let mut loops: BTreeMap<usize, Loop> = BTreeMap::new();
+        for edge in &self.edges {
+            if !idom.contains_key(&edge.from) || !dominated_by(edge.to, edge.from) {
+                continue;
+            }
+            let entry = loops.entry(edge.to).or_insert_with(|| Loop {
+                header: edge.to,
+                latches: Vec::new(),
+                blocks: BTreeSet::from([edge.to]),
+            });
+            entry.latches.push(edge.from);
+            let mut stack = vec![edge.from];
+            while let Some(block) = stack.pop() {
+                if entry.blocks.insert(block) {
+                    stack.extend(self.blocks[&block].predecessors.iter().copied());
+                }
+            }
+        }
+        loops.into_values().collect()
+    }
+
+    /// Renders the graph in Graphviz DOT format.
+    pub fn to_dot(&self) -> String {
+        let mut dot = format!("digraph \"sub_{:X}\" {{\n", self.entry);
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for insn in &block.instructions {
+                let _ = write!(label, "{insn}\\l");
+            }
+            let _ = writeln!(
+                dot,
+                "    \"{:X}\" [label=\"{}\"];",
+                block.start,
+                label.replace('"', "\\\"")
+            );
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::FallThrough => "color=gray",
+                EdgeKind::Conditional => "color=green",
+                EdgeKind::Jump => "color=blue",
+                EdgeKind::JumpTable => "color=purple, style=dashed",
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{:X}\" -> \"{:X}\" [{style}];",
+                edge.from, edge.to
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Returns the blocks reachable from the entry in reverse postorder.
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![(self.entry, 0usize)];
+        if !self.blocks.contains_key(&self.entry) {
+            return order;
+        }
+        visited.insert(self.entry);
+        while let Some((block, child)) = stack.pop() {
+            let successors = &self.blocks[&block].successors;
+            if let Some(&next) = successors.get(child) {
+                stack.push((block, child + 1));
+                if visited.insert(next) {
+                    stack.push((next, 0));
+                }
+            } else {
+                order.push(block);
+            }
+        }
+        order.reverse();
+        order
+    }
+}
+
+fn last_address(block: &BasicBlock) -> usize {
+    block.instructions.last().map_or(block.start, |i| i.address)
+}
+
+/// Returns the instructions executed before the one at `address`, most recent
+/// first, following the path back while each instruction is reached from a
+/// single place.
+fn preceding<'a>(
+    instructions: &'a BTreeMap<usize, Instruction>,
+    sources: &HashMap<usize, Vec<usize>>,
+    address: usize,
+) -> Vec<&'a Instruction> {
+    let mut path = Vec::new();
+    let mut current = address;
+    while path.len() < MAX_PRECEDING {
+        let Some(&[first, ref rest @ ..]) = sources.get(&current).map(Vec::as_slice) else {
+            break;
+        };
+        if rest.iter().any(|&source| source != first) || first == address {
+            break;
+        }
+        let Some(insn) = instructions.get(&first) else {
+            break;
+        };
+        path.push(insn);
+        current = first;
+    }
+    path
+}
+
+/// Recovers the targets of a jump table dispatched by `jump`.
+///
+/// `previous` holds the instructions executed before the jump, most recent
+/// first. The table bound comes from the nearest `cmp reg, imm`; without one,
+/// entries are read until one points outside `code`.
+fn resolve_jump_table(
+    jump: &Instruction,
+    previous: &[&Instruction],
+    code: &[u8],
+    base: usize,
+) -> Option<Vec<usize>> {
+    let lea_target = |register: u8| {
+        previous
+            .iter()
+            .find(|i| i.mnemonic == Mnemonic::Lea && i.reg_field() == Some(register))
+            .and_then(|i| i.rip_target())
+    };
+    let bound = previous
+        .iter()
+        .find(|i| i.mnemonic == Mnemonic::Cmp && i.memory.is_none())
+        .and_then(|i| i.immediate)
+        .and_then(|imm| usize::try_from(imm).ok())
+        .map(|imm| imm + 1)
+        .filter(|&count| count <= MAX_TABLE_ENTRIES);
+    let code_range = base..base + code.len();
+    let read = |address: usize, width: usize| {
+        let offset = address.checked_sub(base)?;
+        let bytes = code.get(offset..offset + width)?;
+        let mut buffer = [0u8; 8];
+        buffer[..width].copy_from_slice(bytes);
+        Some(u64::from_le_bytes(buffer) as usize)
+    };
+
+    let (table, width, relative_to) = match (jump.memory, jump.rm_register()) {
+        // jmp qword [table + index*8] or jmp qword [base + index*8 + disp]
+        (Some(memory), _) if memory.index.is_some() && memory.scale == 8 => {
+            let origin = match memory.base {
+                None => 0,
+                Some(register) => lea_target(register)?,
+            };
+            (
+                origin.wrapping_add_signed(memory.displacement as isize),
+                8,
+                None,
+            )
+        }
+        // mov r32, [base + index*4 + disp]; add reg, base; jmp reg
+        (None, Some(register)) => {
+            let load = previous.iter().find(|i| {
+                matches!(i.mnemonic, Mnemonic::Mov | Mnemonic::Movsxd)
+                    && i.reg_field() == Some(register)
+                    && i.memory.is_some_and(|m| m.index.is_some() && m.scale == 4)
+            })?;
+            let memory = load.memory?;
+            let origin = lea_target(memory.base?)?;
+            (
+                origin.wrapping_add_signed(memory.displacement as isize),
+                4,
+                Some(origin),
+            )
+        }
+        _ => return None,
+    };
+
+    let mut entries = Vec::new();
+    for i in 0..bound.unwrap_or(MAX_TABLE_ENTRIES) {
+        let Some(value) = read(table + i * width, width) else {
+            break;
+        };
+        let target = match relative_to {
+            Some(origin) => origin.wrapping_add(value),
+            None => value,
+        };
+        if !code_range.contains(&target) {
+            if bound.is_some() {
+                return None;
+            }
+            break;
+        }
+        entries.push(target);
+    }
+    (!entries.is_empty()).then_some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: usize = 0x1000;
+
+    #[test]
+    fn test_blocks_loops_and_dominators() {
+        let code = [
+            0x31, 0xC0, // 0x1000: xor eax, eax
+            0x85, 0xC9, // 0x1002: test ecx, ecx
+            0x74, 0x07, // 0x1004: je 0x100D
+            0x01, 0xC8, // 0x1006: add eax, ecx  (loop header)
+            0xFF, 0xC9, // 0x1008: dec ecx
+            0x75, 0xFA, // 0x100A: jne 0x1006
+            0xC3, // 0x100C: ret
+            0x83, 0xC8, 0xFF, // 0x100D: or eax, -1
+            0xC3, // 0x1010: ret
+        ];
+        let graph = ControlFlowGraph::build(&code, BASE, BASE, None);
+        let starts: Vec<usize> = graph.blocks.keys().copied().collect();
+        assert_eq!(starts, vec![0x1000, 0x1006, 0x100C, 0x100D]);
+        assert_eq!(graph.size(), Some(0x11));
+        assert_eq!(graph.blocks[&0x1000].successors, vec![0x1006, 0x100D]);
+        assert_eq!(graph.blocks[&0x1006].predecessors, vec![0x1000, 0x1006]);
+
+        let idom = graph.dominators();
+        assert_eq!(idom[&0x100C], 0x1006);
+        assert_eq!(idom[&0x100D], 0x1000);
+        assert!(graph.dominates(0x1000, 0x100C));
+        assert!(!graph.dominates(0x100D, 0x100C));
+
+        let loops = graph.loops();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].header, 0x1006);
+        assert_eq!(loops[0].latches, vec![0x1006]);
+
+        let exits: Vec<usize> = graph.exit_blocks().iter().map(|b| b.start).collect();
+        assert_eq!(exits, vec![0x100C, 0x100D]);
+        assert_eq!(graph.block_containing(0x1008).unwrap().start, 0x1006);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph \"sub_1000\""));
+        assert!(dot.contains("\"1000\" -> \"100D\" [color=green];"));
+    }
+
+    #[test]
+    fn test_jump_tables() {
+        let mut code = vec![0xCC; 0x80];
+        // 0x1000: cmp ecx, 2; ja 0x1030; lea rdx, [0x1000] (image base)
+        code[..3].copy_from_slice(&[0x83, 0xF9, 0x02]);
+        code[3..5].copy_from_slice(&[0x77, 0x2B]);
+        code[5..12].copy_from_slice(&[0x48, 0x8D, 0x15, 0xF4, 0xFF, 0xFF, 0xFF]);
+        // 0x100C: mov eax, [rdx+rcx*4+0x40]; add rax, rdx; jmp rax
+        code[12..19].copy_from_slice(&[0x8B, 0x84, 0x8A, 0x40, 0x00, 0x00, 0x00]);
+        code[19..22].copy_from_slice(&[0x48, 0x01, 0xD0]);
+        code[22..24].copy_from_slice(&[0xFF, 0xE0]);
+        // Cases at 0x1020, 0x1024, 0x1028 and the default at 0x1030.
+        for case in [0x20, 0x24, 0x28, 0x30] {
+            code[case..case + 3].copy_from_slice(&[0x31, 0xC0, 0xC3]);
+        }
+        for (i, rva) in [0x20u32, 0x24, 0x28].iter().enumerate() {
+            code[0x40 + i * 4..0x44 + i * 4].copy_from_slice(&rva.to_le_bytes());
+        }
+        let graph = ControlFlowGraph::build(&code, BASE, BASE, None);
+        let table: Vec<usize> = graph
+            .edges
+            .iter()
+            .filter(|e| e.kind == EdgeKind::JumpTable)
+            .map(|e| e.to)
+            .collect();
+        assert_eq!(table, vec![0x1020, 0x1024, 0x1028]);
+        assert_eq!(graph.exit_blocks().len(), 4);
+
+        // jmp qword [rcx*8 + 0x1060] with absolute entries
+        let mut code = vec![0xCC; 0x80];
+        code[..3].copy_from_slice(&[0x83, 0xF9, 0x01]);
+        code[3..5].copy_from_slice(&[0x77, 0x1B]);
+        code[5..12].copy_from_slice(&[0xFF, 0x24, 0xCD, 0x60, 0x10, 0x00, 0x00]);
+        code[0x20..0x23].copy_from_slice(&[0x31, 0xC0, 0xC3]);
+        code[0x60..0x68].copy_from_slice(&0x1020u64.to_le_bytes());
+        code[0x68..0x70].copy_from_slice(&0x1022u64.to_le_bytes());
+        let graph = ControlFlowGraph::build(&code, BASE, BASE, Some(0x1000..0x1030));
+        let targets: Vec<usize> = graph.blocks[&0x1005].successors.clone();
+        assert_eq!(targets, vec![0x1020, 0x1022]);
+    }
+
+    #[test]
+    fn test_jump_table_bound_from_predecessors() {
+        let mut code = vec![0xCC; 0x80];
+        // 0x1000: cmp ecx, 1; ja 0x1010; jmp 0x1020
+        code[..3].copy_from_slice(&[0x83, 0xF9, 0x01]);
+        code[3..5].copy_from_slice(&[0x77, 0x0B]);
+        code[5..7].copy_from_slice(&[0xEB, 0x19]);
+        // 0x1010: cmp ecx, 7; ret lies between the check and the dispatch.
+        code[0x10..0x14].copy_from_slice(&[0x83, 0xF9, 0x07, 0xC3]);
+        // 0x1020: jmp qword [rcx*8 + 0x1060]
+        code[0x20..0x27].copy_from_slice(&[0xFF, 0x24, 0xCD, 0x60, 0x10, 0x00, 0x00]);
+        for case in [0x30, 0x34] {
+            code[case..case + 3].copy_from_slice(&[0x31, 0xC0, 0xC3]);
+        }
+        code[0x60..0x68].copy_from_slice(&0x1030u64.to_le_bytes());
+        code[0x68..0x70].copy_from_slice(&0x1034u64.to_le_bytes());
+        let graph = ControlFlowGraph::build(&code, BASE, BASE, None);
+        assert_eq!(graph.blocks[&0x1020].successors, vec![0x1030, 0x1034]);
+        assert_eq!(graph.blocks[&0x1020].exit, None);
+    }
+
+    #[test]
+    fn test_jump_to_next_instruction() {
+        // test ecx, ecx; je 0x1004; ret
+        let code = [0x85, 0xC9, 0x74, 0x00, 0xC3];
+        let graph = ControlFlowGraph::build(&code, BASE, BASE, None);
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.blocks[&0x1000].successors, vec![0x1004]);
+        assert_eq!(graph.blocks[&0x1004].predecessors, vec![0x1000]);
+    }
+}
@@ -0,0 +1,894 @@
+//! Binary diffing to port addresses between builds of a target.
+//!
+//! Functions of two analyses are paired in phases, from the strongest
+//! evidence to the weakest:
+//! - strings referenced by a single function on both sides;
+//! - the same slot of the same RTTI class;
+//! - a control-flow shape that only one function has on each side;
+//! - the call sites of functions that are already paired;
+//! - finally, the mutual best structural similarity.
+//!
+//! Each pair records its method and a confidence. Byte signatures can then be
+//! regenerated for paired functions against the new image, with relocated
+//! operands wildcarded.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{AnalysisResult, DiscoveredFunction, StringReference};
+use crate::cache::{FNV_OFFSET, fnv};
+use crate::cfg::{BlockExit, ControlFlowGraph};
+use crate::disasm::{self, Flow, Instruction, OpcodeMap};
+use crate::pattern::Pattern;
+use crate::pe::PeImage;
+use crate::vtable::ClassHierarchy;
+
+/// Immediates below this magnitude are too common to identify a function.
+const MIN_CONSTANT: u64 = 0x100;
+/// Functions shorter than this are only paired on strong evidence.
+const MIN_FUZZY_INSTRUCTIONS: usize = 8;
+/// Lowest similarity accepted in the fuzzy phase.
+const MIN_SIMILARITY: f32 = 0.8;
+/// Lowest similarity that does not contradict the other kinds of evidence.
+const MIN_CONSISTENT_SIMILARITY: f32 = 0.5;
+/// Longest signature generated, in bytes.
+const MAX_SIGNATURE_LENGTH: usize = 64;
+/// Fewest concrete bytes in a signature.
+const MIN_SIGNATURE_BYTES: usize = 6;
+
+/// Structural features of a function that survive recompilation.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionFeatures {
+    pub instructions: usize,
+    pub blocks: usize,
+    pub edges: usize,
+    pub loops: usize,
+    /// Hash of the block degrees and exits in reverse postorder.
+    pub shape_hash: u64,
+    /// Targets of direct calls, in call-site order.
+    pub calls: Vec<usize>,
+    /// Sorted immediates that are neither small nor addresses in the module.
+    pub constants: Vec<i64>,
+}
+
+impl FunctionFeatures {
+    /// Extracts the features of `graph`; `module` is the address range of the containing image.
+    pub fn extract(graph: &ControlFlowGraph, module: Range<usize>) -> Self {
+        let mut features = Self {
+            blocks: graph.blocks.len(),
+            edges: graph.edges.len(),
+            loops: graph.loops().len(),
+            ..Self::default()
+        };
+        let mut shape = FNV_OFFSET;
+        for address in graph.reverse_postorder() {
+            let block = &graph.blocks[&address];
+            let exit = match block.exit {
+                None => 0,
+                Some(BlockExit::Return) => 1,
+                Some(BlockExit::TailCall(_)) => 2,
+                Some(BlockExit::IndirectJump) => 3,
+                Some(BlockExit::Trap) => 4,
+            };
+            let degrees = [
+                block.predecessors.len().min(0xFF) as u8,
+                block.successors.len().min(0xFF) as u8,
+                exit,
+            ];
+            shape = fnv(shape, &degrees);
+            features.instructions += block.instructions.len();
+            for insn in &block.instructions {
+                if insn.flow() == Flow::Call
+                    && let Some(target) = insn.branch_target()
+                {
+                    features.calls.push(target);
+                }
+                if let Some(value) = insn.immediate
+                    && value.unsigned_abs() >= MIN_CONSTANT
+                    && !module.contains(&(value as usize))
+                {
+                    features.constants.push(value);
+                }
+            }
+        }
+        features.shape_hash = shape;
+        features.constants.sort_unstable();
+        features.constants.dedup();
+        features
+    }
+}
+
+/// Evidence a pair of functions was matched on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MatchMethod {
+    /// Both reference a string no other function references.
+    UniqueString,
+    /// Same slot in the vtable of a class with the same name.
+    VtableSlot,
+    /// Control-flow shape and constants unique on both sides.
+    Structure,
+    /// Called from the same call site of a matched function.
+    CallSite,
+    /// Mutual best structural similarity.
+    Similarity,
+}
+
+/// A function of the old build paired with one of the new build.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FunctionMatch {
+    pub old_address: usize,
+    pub new_address: usize,
+    pub confidence: f32,
+    pub method: MatchMethod,
+}
+
+/// Outcome of diffing two analyses.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffResult {
+    /// Pairs sorted by old address.
+    pub matches: Vec<FunctionMatch>,
+    pub unmatched_old: Vec<usize>,
+    pub unmatched_new: Vec<usize>,
+}
+
+impl DiffResult {
+    /// Returns the match of the old function starting at `old_address`.
+    pub fn lookup(&self, old_address: usize) -> Option<&FunctionMatch> {
+        self.matches
+            .binary_search_by_key(&old_address, |m| m.old_address)
+            .ok()
+            .map(|i| &self.matches[i])
+    }
+
+    /// Returns the matches whose confidence is at least `min_confidence`.
+    pub fn confident(&self, min_confidence: f32) -> impl Iterator<Item = &FunctionMatch> {
+        self.matches
+            .iter()
+            .filter(move |m| m.confidence >= min_confidence)
+    }
+}
+
+/// The findings of one build that take part in a diff.
+#[derive(Debug, Clone, Copy)]
+pub struct DiffSource<'a> {
+    pub functions: &'a [DiscoveredFunction],
+    pub strings: &'a [StringReference],
+    pub classes: Option<&'a ClassHierarchy>,
+}
+
+impl<'a> From<&'a AnalysisResult> for DiffSource<'a> {
+    fn from(result: &'a AnalysisResult) -> Self {
+        Self {
+            functions: &result.functions,
+            strings: &result.string_references,
+            classes: Some(&result.class_hierarchy),
+        }
+    }
+}
+
+/// Pairs the functions of `old` with those of `new`.
+pub fn diff(old: &DiffSource, new: &DiffSource) -> DiffResult {
+    let mut matcher = Matcher::new(Side::new(old), Side::new(new));
+    matcher.match_strings();
+    matcher.match_vtable_slots();
+    matcher.match_structure();
+    matcher.propagate_calls();
+    matcher.match_similar();
+    matcher.propagate_calls();
+    matcher.finish()
+}
+
+/// A signature regenerated for a function of the new build.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortedSignature {
+    pub old_address: usize,
+    pub new_address: usize,
+    /// Confidence of the match the signature was generated from.
+    pub confidence: f32,
+    /// Pattern in the `"48 8B ?? 74 ??"` format of [`Pattern::new`].
+    pub pattern: String,
+}
+
+/// Regenerates signatures in the new image `pe` for the old functions in `old_addresses`.
+///
+/// Functions without a match, or without a unique signature, are skipped.
+pub fn regenerate_signatures(
+    pe: &PeImage,
+    diff: &DiffResult,
+    old_addresses: &[usize],
+) -> Vec<PortedSignature> {
+    old_addresses
+        .iter()
+        .filter_map(|&old| diff.lookup(old))
+        .filter_map(|m| {
+            Some(PortedSignature {
+                old_address: m.old_address,
+                new_address: m.new_address,
+                confidence: m.confidence,
+                pattern: generate_signature(pe, m.new_address)?,
+            })
+        })
+        .collect()
+}
+
+/// Generates the shortest signature of whole instructions starting at
+/// `address` that matches once in the executable sections of `pe`.
+///
+/// Branch displacements, RIP-relative displacements and immediates pointing
+/// into the image are wildcarded, since they change whenever code moves.
+pub fn generate_signature(pe: &PeImage, address: usize) -> Option<String> {
+    let image = pe.data();
+    let module = pe.base()..pe.base() + image.len();
+    let start = address.checked_sub(pe.base())?;
+    let code = image.get(start..(start + MAX_SIGNATURE_LENGTH).min(image.len()))?;
+    let sections: Vec<Range<usize>> = pe
+        .sections()
+        .iter()
+        .filter(|section| section.is_executable())
+        .map(|section| {
+            let range = section.rva_range();
+            range.start as usize..(range.end as usize).min(image.len())
+        })
+        .collect();
+
+    let mut bytes = Vec::new();
+    let mut candidates: Option<Vec<usize>> = None;
+    for insn in disasm::instructions(code, address) {
+        let offset = insn.address - address;
+        bytes.extend(masked_bytes(
+            &insn,
+            &code[offset..offset + insn.length],
+            &module,
+        ));
+        if bytes.iter().flatten().count() >= MIN_SIGNATURE_BYTES {
+            let text = pattern_text(&bytes);
+            let pattern = Pattern::new(&text).ok()?;
+            let remaining: Vec<usize> = match candidates.take() {
+                Some(previous) => previous
+                    .into_iter()
+                    .filter(|&o| pattern.matches_at(image, o))
+                    .collect(),
+                None => sections
+                    .iter()
+                    .flat_map(Range::clone)
+                    .filter(|&o| pattern.matches_at(image, o))
+                    .collect(),
+            };
+            match remaining.len() {
+                0 => return None,
+                1 => return Some(text),
+                _ => candidates = Some(remaining),
+            }
+        }
+        if matches!(insn.flow(), Flow::Return | Flow::Trap | Flow::IndirectJump) {
+            break;
+        }
+    }
+    None
+}
+
+/// Returns the bytes of `insn`, with the operands that depend on its location wildcarded.
+fn masked_bytes(insn: &Instruction, raw: &[u8], module: &Range<usize>) -> Vec<Option<u8>> {
+    let mut bytes: Vec<Option<u8>> = raw.iter().copied().map(Some).collect();
+    let len = raw.len();
+    if insn.relative.is_some() {
+        let size = if insn.map == OpcodeMap::Primary && !matches!(insn.opcode, 0xE8 | 0xE9) {
+            1
+        } else {
+            4
+        };
+        bytes[len.saturating_sub(size)..].fill(None);
+    }
+    if let Some(memory) = &insn.memory
+        && memory.rip_relative
+    {
+        // The displacement precedes an immediate of 0, 1, 2 or 4 bytes.
+        let displacement = (memory.displacement as i32).to_le_bytes();
+        let end = [0, 1, 2, 4]
+            .into_iter()
+            .filter_map(|immediate| len.checked_sub(immediate))
+            .find(|&end| end >= 4 && raw[end - 4..end] == displacement);
+        if let Some(end) = end {
+            bytes[end - 4..end].fill(None);
+        }
+    }
+    if let Some(value) = insn.immediate
+        && module.contains(&(value as usize))
+    {
+        let value = value.to_le_bytes();
+        if let Some(size) = [8, 4]
+            .into_iter()
+            .find(|&size| len >= size && raw[len - size..] == value[..size])
+        {
+            bytes[len - size..].fill(None);
+        }
+    }
+    bytes
+}
+
+fn pattern_text(bytes: &[Option<u8>]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 3);
+    for (i, byte) in bytes.iter().enumerate() {
+        if i > 0 {
+            text.push(' ');
+        }
+        match byte {
+            Some(byte) => {
+                let _ = write!(text, "{byte:02X}");
+            }
+            None => text.push_str("??"),
+        }
+    }
+    text
+}
+
+/// The functions of one build with the evidence gathered about them.
+struct Side<'a> {
+    /// Sorted by address.
+    functions: Vec<&'a DiscoveredFunction>,
+    index: HashMap<usize, usize>,
+    /// Strings referenced by each function.
+    strings: Vec<BTreeSet<&'a str>>,
+    /// Function in each slot of each class, for functions in a single slot.
+    slots: BTreeMap<(&'a str, usize), usize>,
+}
+
+impl<'a> Side<'a> {
+    fn new(source: &DiffSource<'a>) -> Self {
+        let mut functions: Vec<&DiscoveredFunction> = source.functions.iter().collect();
+        functions.sort_by_key(|f| f.address);
+        functions.dedup_by_key(|f| f.address);
+        let index = functions
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (f.address, i))
+            .collect();
+        let mut side = Self {
+            strings: vec![BTreeSet::new(); functions.len()],
+            functions,
+            index,
+            slots: BTreeMap::new(),
+        };
+
+        for string in source.strings {
+            for &reference in &string.references {
+                if let Some(i) = side.containing(reference) {
+                    side.strings[i].insert(string.value.as_str());
+                }
+            }
+        }
+
+        if let Some(classes) = source.classes {
+            let mut names: HashMap<&str, usize> = HashMap::new();
+            let mut uses: HashMap<usize, usize> = HashMap::new();
+            let mut slots = Vec::new();
+            for class in classes.get_all_classes() {
+                *names.entry(&class.name).or_default() += 1;
+                for function in &class.functions {
+                    if let Some(&i) = side.index.get(&function.address) {
+                        *uses.entry(i).or_default() += 1;
+                        slots.push(((class.name.as_str(), function.index), i));
+                    }
+                }
+            }
+            // Shared implementations and ambiguous names do not identify a slot.
+            side.slots = slots
+                .into_iter()
+                .filter(|((name, _), i)| !name.is_empty() && names[name] == 1 && uses[i] == 1)
+                .collect();
+        }
+        side
+    }
+
+    fn containing(&self, address: usize) -> Option<usize> {
+        let i = self
+            .functions
+            .partition_point(|f| f.address <= address)
+            .checked_sub(1)?;
+        let function = self.functions[i];
+        (function.address == address
+            || function
+                .size
+                .is_some_and(|size| address < function.address + size))
+        .then_some(i)
+    }
+
+    fn features(&self, i: usize) -> Option<&'a FunctionFeatures> {
+        self.functions[i].features.as_ref()
+    }
+
+    /// Key of the structural phase, for functions large enough to tell apart.
+    fn structure_key(&self, i: usize) -> Option<(u64, usize, u64)> {
+        let features = self.features(i)?;
+        if features.instructions < MIN_FUZZY_INSTRUCTIONS {
+            return None;
+        }
+        let constants = features
+            .constants
+            .iter()
+            .fold(FNV_OFFSET, |hash, c| fnv(hash, &c.to_le_bytes()));
+        Some((features.shape_hash, features.instructions, constants))
+    }
+}
+
+struct Matcher<'a> {
+    old: Side<'a>,
+    new: Side<'a>,
+    /// Old function index to new function index, confidence and method.
+    pairs: BTreeMap<usize, (usize, f32, MatchMethod)>,
+    paired_new: Vec<bool>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(old: Side<'a>, new: Side<'a>) -> Self {
+        let paired_new = vec![false; new.functions.len()];
+        Self {
+            old,
+            new,
+            pairs: BTreeMap::new(),
+            paired_new,
+        }
+    }
+
+    fn is_free(&self, old: usize, new: usize) -> bool {
+        !self.pairs.contains_key(&old) && !self.paired_new[new]
+    }
+
+    fn accept(&mut self, old: usize, new: usize, confidence: f32, method: MatchMethod) -> bool {
+        if !self.is_free(old, new) {
+            return false;
+        }
+        self.pairs
+            .insert(old, (new, confidence.clamp(0.0, 1.0), method));
+        self.paired_new[new] = true;
+        true
+    }
+
+    /// Structural similarity between 0 and 1, or `None` without features.
+    fn similarity(&self, old: usize, new: usize) -> Option<f32> {
+        let a = self.old.features(old)?;
+        let b = self.new.features(new)?;
+        let mut score = ratio(a.instructions, b.instructions)
+            + ratio(a.blocks, b.blocks)
+            + ratio(a.edges, b.edges)
+            + ratio(a.loops, b.loops)
+            + ratio(a.calls.len(), b.calls.len())
+            + if a.shape_hash == b.shape_hash {
+                1.0
+            } else {
+                0.0
+            };
+        let mut weight = 6.0;
+        if !a.constants.is_empty() || !b.constants.is_empty() {
+            let a_constants: BTreeSet<_> = a.constants.iter().collect();
+            let b_constants: BTreeSet<_> = b.constants.iter().collect();
+            score += 2.0 * overlap(&a_constants, &b_constants);
+            weight += 2.0;
+        }
+        let (a_strings, b_strings) = (&self.old.strings[old], &self.new.strings[new]);
+        if !a_strings.is_empty() || !b_strings.is_empty() {
+            score += 2.0 * overlap(a_strings, b_strings);
+            weight += 2.0;
+        }
+        Some(score / weight)
+    }
+
+    /// Caps `confidence` by the similarity, or rejects the pair if they look unrelated.
+    fn consistent(&self, old: usize, new: usize, confidence: f32) -> Option<f32> {
+        match self.similarity(old, new) {
+            Some(similarity) if similarity < MIN_CONSISTENT_SIMILARITY => None,
+            Some(similarity) => Some(confidence.min(0.5 + similarity / 2.0)),
+            None => Some(confidence),
+        }
+    }
+
+    fn match_strings(&mut self) {
+        let owners = |side: &Side<'a>| {
+            let mut owners: HashMap<&'a str, Vec<usize>> = HashMap::new();
+            for (i, strings) in side.strings.iter().enumerate() {
+                for &string in strings {
+                    owners.entry(string).or_default().push(i);
+                }
+            }
+            owners
+        };
+        let (old_owners, new_owners) = (owners(&self.old), owners(&self.new));
+
+        let mut anchors: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+        for (string, old) in &old_owners {
+            if let ([old], Some([new])) =
+                (old.as_slice(), new_owners.get(string).map(Vec::as_slice))
+            {
+                *anchors.entry((*old, *new)).or_default() += 1;
+            }
+        }
+        // Unique strings of one function pointing at different functions are contradictory.
+        let mut old_partners: HashMap<usize, usize> = HashMap::new();
+        let mut new_partners: HashMap<usize, usize> = HashMap::new();
+        for &(old, new) in anchors.keys() {
+            *old_partners.entry(old).or_default() += 1;
+            *new_partners.entry(new).or_default() += 1;
+        }
+        for ((old, new), count) in anchors {
+            if old_partners[&old] > 1 || new_partners[&new] > 1 {
+                continue;
+            }
+            let confidence = (0.7 + 0.1 * count as f32).min(0.95);
+            if let Some(confidence) = self.consistent(old, new, confidence) {
+                self.accept(old, new, confidence, MatchMethod::UniqueString);
+            }
+        }
+    }
+
+    fn match_vtable_slots(&mut self) {
+        let pairs: Vec<(usize, usize)> = self
+            .old
+            .slots
+            .iter()
+            .filter_map(|(slot, &old)| Some((old, *self.new.slots.get(slot)?)))
+            .collect();
+        for (old, new) in pairs {
+            if let Some(confidence) = self.consistent(old, new, 0.9) {
+                self.accept(old, new, confidence, MatchMethod::VtableSlot);
+            }
+        }
+    }
+
+    fn match_structure(&mut self) {
+        let keys = |side: &Side, free: &dyn Fn(usize) -> bool| {
+            let mut keys: HashMap<(u64, usize, u64), Vec<usize>> = HashMap::new();
+            for i in (0..side.functions.len()).filter(|&i| free(i)) {
+                if let Some(key) = side.structure_key(i) {
+                    keys.entry(key).or_default().push(i);
+                }
+            }
+            keys
+        };
+        let old_keys = keys(&self.old, &|i| !self.pairs.contains_key(&i));
+        let new_keys = keys(&self.new, &|i| !self.paired_new[i]);
+
+        let mut pairs: Vec<(usize, usize)> = old_keys
+            .iter()
+            .filter_map(
+                |(key, old)| match (old.as_slice(), new_keys.get(key)?.as_slice()) {
+                    ([old], [new]) => Some((*old, *new)),
+                    _ => None,
+                },
+            )
+            .collect();
+        pairs.sort_unstable();
+        for (old, new) in pairs {
+            if let Some(confidence) = self.consistent(old, new, 0.85) {
+                self.accept(old, new, confidence, MatchMethod::Structure);
+            }
+        }
+    }
+
+    /// Pairs the callees of matched functions whose call sites line up.
+    fn propagate_calls(&mut self) {
+        let mut pending: Vec<(usize, usize, f32)> = self
+            .pairs
+            .iter()
+            .map(|(&old, &(new, confidence, _))| (old, new, confidence))
+            .collect();
+        while let Some((old, new, confidence)) = pending.pop() {
+            let (Some(a), Some(b)) = (self.old.features(old), self.new.features(new)) else {
+                continue;
+            };
+            if a.calls.len() != b.calls.len() {
+                continue;
+            }
+            for (old_target, new_target) in a.calls.iter().zip(&b.calls) {
+                let (Some(&old_callee), Some(&new_callee)) = (
+                    self.old.index.get(old_target),
+                    self.new.index.get(new_target),
+                ) else {
+                    continue;
+                };
+                if !self.is_free(old_callee, new_callee) {
+                    continue;
+                }
+                if let Some(callee_confidence) =
+                    self.consistent(old_callee, new_callee, confidence * 0.95)
+                    && self.accept(
+                        old_callee,
+                        new_callee,
+                        callee_confidence,
+                        MatchMethod::CallSite,
+                    )
+                {
+                    pending.push((old_callee, new_callee, callee_confidence));
+                }
+            }
+        }
+    }
+
+    /// Pairs the remaining functions that are each other's most similar candidate.
+    fn match_similar(&mut self) {
+        let mut by_size: Vec<(usize, usize)> = (0..self.new.functions.len())
+            .filter(|&i| !self.paired_new[i])
+            .filter_map(|i| Some((self.new.features(i)?.instructions, i)))
+            .filter(|&(instructions, _)| instructions >= MIN_FUZZY_INSTRUCTIONS)
+            .collect();
+        by_size.sort_unstable();
+
+        let mut best_old: HashMap<usize, (usize, f32)> = HashMap::new();
+        let mut best_new: HashMap<usize, (usize, f32)> = HashMap::new();
+        for old in (0..self.old.functions.len()).filter(|i| !self.pairs.contains_key(i)) {
+            let Some(features) = self.old.features(old) else {
+                continue;
+            };
+            let size = features.instructions;
+            if size < MIN_FUZZY_INSTRUCTIONS {
+                continue;
+            }
+            let low = by_size.partition_point(|&(n, _)| n * 5 < size * 4);
+            let high = by_size.partition_point(|&(n, _)| n * 4 <= size * 5);
+            for &(_, new) in &by_size[low..high] {
+                let Some(similarity) = self.similarity(old, new) else {
+                    continue;
+                };
+                if similarity < MIN_SIMILARITY {
+                    continue;
+                }
+                if best_old
+                    .get(&old)
+                    .is_none_or(|&(_, best)| similarity > best)
+                {
+                    best_old.insert(old, (new, similarity));
+                }
+                if best_new
+                    .get(&new)
+                    .is_none_or(|&(_, best)| similarity > best)
+                {
+                    best_new.insert(new, (old, similarity));
+                }
+            }
+        }
+
+        let mut pairs: Vec<(usize, usize, f32)> = best_old
+            .into_iter()
+            .filter(|&(old, (new, _))| best_new.get(&new).is_some_and(|&(o, _)| o == old))
+            .map(|(old, (new, similarity))| (old, new, similarity))
+            .collect();
+        pairs.sort_unstable_by_key(|&(old, _, _)| old);
+        for (old, new, similarity) in pairs {
+            self.accept(old, new, similarity * 0.9, MatchMethod::Similarity);
+        }
+    }
+
+    fn finish(self) -> DiffResult {
+        let matches = self
+            .pairs
+            .iter()
+            .map(|(&old, &(new, confidence, method))| FunctionMatch {
+                old_address: self.old.functions[old].address,
+                new_address: self.new.functions[new].address,
+                confidence,
+                method,
+            })
+            .collect();
+        let unmatched_old = (0..self.old.functions.len())
+            .filter(|i| !self.pairs.contains_key(i))
+            .map(|i| self.old.functions[i].address)
+            .collect();
+        let unmatched_new = (0..self.new.functions.len())
+            .filter(|&i| !self.paired_new[i])
+            .map(|i| self.new.functions[i].address)
+            .collect();
+        DiffResult {
+            matches,
+            unmatched_old,
+            unmatched_new,
+        }
+    }
+}
+
+fn ratio(a: usize, b: usize) -> f32 {
+    if a == b {
+        1.0
+    } else {
+        a.min(b) as f32 / a.max(b) as f32
+    }
+}
+
+/// Jaccard index of two sets.
+fn overlap<T: Ord>(a: &BTreeSet<T>, b: &BTreeSet<T>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f32 / union as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{CallingConvention, StringEncoding};
+    use crate::pe::fixtures::{BASE, TEXT, build_image};
+    use crate::vtable::{ClassInfo, VirtualFunction};
+
+    fn function(
+        address: usize,
+        instructions: usize,
+        shape_hash: u64,
+        calls: Vec<usize>,
+        constants: Vec<i64>,
+    ) -> DiscoveredFunction {
+        DiscoveredFunction {
+            address,
+            size: Some(0x80),
+            name: None,
+            calling_convention: CallingConvention::Unknown,
+            parameters: Vec::new(),
+            return_type: None,
+            confidence: 1.0,
+            references: Vec::new(),
+            xrefs_to: Vec::new(),
+            xrefs_from: Vec::new(),
+            features: Some(FunctionFeatures {
+                instructions,
+                blocks: 3,
+                edges: 3,
+                loops: 0,
+                shape_hash,
+                calls,
+                constants,
+            }),
+        }
+    }
+
+    fn build(
+        base: usize,
+        inserted: bool,
+        c_instructions: usize,
+        c_shape: u64,
+    ) -> (
+        Vec<DiscoveredFunction>,
+        Vec<StringReference>,
+        ClassHierarchy,
+    ) {
+        let at = |offset: usize| base + offset + if inserted { 0x80 } else { 0 };
+        let mut functions = vec![
+            function(at(0x000), 20, 1, vec![at(0x100)], Vec::new()),
+            function(at(0x100), 6, 2, Vec::new(), Vec::new()),
+            function(
+                at(0x200),
+                c_instructions,
+                c_shape,
+                Vec::new(),
+                vec![0x1234_5678],
+            ),
+            function(at(0x300), 4, 4, Vec::new(), Vec::new()),
+        ];
+        if inserted {
+            functions.push(function(base, 9, 9, Vec::new(), Vec::new()));
+        }
+        let strings = vec![StringReference {
+            address: base + 0x8000,
+            value: "Player::Tick".to_string(),
+            encoding: StringEncoding::Ascii,
+            references: vec![at(0x10)],
+        }];
+        let mut classes = ClassHierarchy::new();
+        classes.add_class(ClassInfo {
+            vtable_address: base + 0x9000,
+            name: "CPlayer".to_string(),
+            functions: vec![VirtualFunction {
+                address: at(0x300),
+                index: 0,
+            }],
+            base_classes: Vec::new(),
+            derived_classes: Vec::new(),
+        });
+        (functions, strings, classes)
+    }
+
+    #[test]
+    fn test_diff_phases() {
+        let old_base = 0x1_4000_1000;
+        let new_base = 0x1_4001_1000;
+        let (old_functions, old_strings, old_classes) = build(old_base, false, 30, 3);
+        let (new_functions, new_strings, new_classes) = build(new_base, true, 31, 5);
+        let old = DiffSource {
+            functions: &old_functions,
+            strings: &old_strings,
+            classes: Some(&old_classes),
+        };
+        let new = DiffSource {
+            functions: &new_functions,
+            strings: &new_strings,
+            classes: Some(&new_classes),
+        };
+        let result = diff(&old, &new);
+
+        let expect = [
+            (0x000, MatchMethod::UniqueString),
+            (0x100, MatchMethod::CallSite),
+            (0x200, MatchMethod::Similarity),
+            (0x300, MatchMethod::VtableSlot),
+        ];
+        for (offset, method) in expect {
+            let found = result.lookup(old_base + offset).unwrap();
+            assert_eq!(found.new_address, new_base + 0x80 + offset);
+            assert_eq!(found.method, method);
+        }
+        assert!(
+            result.lookup(old_base + 0x100).unwrap().confidence
+                < result.lookup(old_base).unwrap().confidence
+        );
+        assert!(result.unmatched_old.is_empty());
+        assert_eq!(result.unmatched_new, vec![new_base]);
+    }
+
+    #[test]
+    fn test_generate_signature() {
+        let body: &[u8] = &[
+            0x48, 0x83, 0xEC, 0x28, // sub rsp, 0x28
+            0xE8, 0x10, 0x00, 0x00, 0x00, // call
+            0x48, 0x8B, 0x05, 0x00, 0x20, 0x00, 0x00, // mov rax, [rip+0x2000]
+        ];
+        let mut code = Vec::new();
+        code.extend_from_slice(body);
+        code.extend_from_slice(&[0x48, 0x83, 0xC4, 0x28, 0xC3]);
+        code.resize(0x20, 0xCC);
+        code.extend_from_slice(body);
+        code.extend_from_slice(&[0x48, 0x83, 0xC4, 0x30, 0xC3]);
+        let image = build_image(&code, &[], &[]);
+        let pe = PeImage::parse(&image, BASE).unwrap();
+
+        let first = generate_signature(&pe, BASE + TEXT).unwrap();
+        assert_eq!(
+            first,
+            "48 83 EC 28 E8 ?? ?? ?? ?? 48 8B 05 ?? ?? ?? ?? 48 83 C4 28"
+        );
+        assert_eq!(
+            generate_signature(&pe, BASE + TEXT + 0x20).unwrap(),
+            first.replace("C4 28", "C4 30")
+        );
+
+        let result = DiffResult {
+            matches: vec![FunctionMatch {
+                old_address: 0x1000,
+                new_address: BASE + TEXT + 0x20,
+                confidence: 0.9,
+                method: MatchMethod::Structure,
+            }],
+            ..DiffResult::default()
+        };
+        let ported = regenerate_signatures(&pe, &result, &[0x1000, 0x2000]);
+        assert_eq!(ported.len(), 1);
+        assert!(
+            Pattern::new(&ported[0].pattern)
+                .unwrap()
+                .matches_at(&image, TEXT + 0x20)
+        );
+    }
+
+    #[test]
+    fn test_masked_operands() {
+        let module = BASE..BASE + 0x4000;
+        let mut mov = vec![0x48, 0xB8];
+        mov.extend_from_slice(&(BASE as u64 + 0x3000).to_le_bytes());
+        let insn = disasm::decode(&mov, BASE).unwrap();
+        assert_eq!(
+            pattern_text(&masked_bytes(&insn, &mov, &module)),
+            "48 B8 ?? ?? ?? ?? ?? ?? ?? ??"
+        );
+
+        let jcc = [0x74, 0x05];
+        let insn = disasm::decode(&jcc, BASE).unwrap();
+        assert_eq!(pattern_text(&masked_bytes(&insn, &jcc, &module)), "74 ??");
+
+        // RIP-relative store followed by a 4-byte immediate.
+        let store = [0xC7, 0x05, 0x10, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00];
+        let insn = disasm::decode(&store, BASE).unwrap();
+        assert_eq!(
+            pattern_text(&masked_bytes(&insn, &store, &module)),
+            "C7 05 ?? ?? ?? ?? 01 00 00 00"
+        );
+    }
+}
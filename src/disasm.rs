@@ -0,0 +1,1012 @@
+//! x86-64 instruction decoder for analysis passes.
+//!
+//! A table-driven decoder for 64-bit mode. It handles legacy prefixes, REX,
+//! VEX and EVEX prefixes, `ModRM`/`SIB` addressing, displacements, immediates,
+//! RIP-relative operands and relative branch targets. Every instruction is
+//! decoded far enough to know its exact length, its memory operand, register
+//! fields and control flow; it is not an assembly formatter.
+
+use std::fmt;
+
+use crate::errors::Error;
+
+/// Architectural maximum instruction length.
+pub const MAX_INSTRUCTION_LENGTH: usize = 15;
+
+/// A general purpose or SSE register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Register {
+    Gpr(u8),
+    Xmm(u8),
+}
+
+impl Register {
+    pub const RAX: Register = Register::Gpr(0);
+    pub const RCX: Register = Register::Gpr(1);
+    pub const RDX: Register = Register::Gpr(2);
+    pub const RBX: Register = Register::Gpr(3);
+    pub const RSP: Register = Register::Gpr(4);
+    pub const RBP: Register = Register::Gpr(5);
+    pub const RSI: Register = Register::Gpr(6);
+    pub const RDI: Register = Register::Gpr(7);
+    pub const R8: Register = Register::Gpr(8);
+    pub const R9: Register = Register::Gpr(9);
+    pub const R10: Register = Register::Gpr(10);
+    pub const R11: Register = Register::Gpr(11);
+    pub const R12: Register = Register::Gpr(12);
+    pub const R13: Register = Register::Gpr(13);
+    pub const R14: Register = Register::Gpr(14);
+    pub const R15: Register = Register::Gpr(15);
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const GPR_NAMES: [&str; 16] = [
+            "RAX", "RCX", "RDX", "RBX", "RSP", "RBP", "RSI", "RDI", "R8", "R9", "R10", "R11",
+            "R12", "R13", "R14", "R15",
+        ];
+        match self {
+            Register::Gpr(n) => match GPR_NAMES.get(*n as usize) {
+                Some(name) => write!(f, "{}", name),
+                None => write!(f, "GPR{}", n),
+            },
+            Register::Xmm(n) => write!(f, "XMM{}", n),
+        }
+    }
+}
+
+/// Opcode map an instruction was decoded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpcodeMap {
+    Primary,
+    /// `0F xx`
+    Secondary,
+    /// `0F 38 xx`
+    Map0F38,
+    /// `0F 3A xx`
+    Map0F3A,
+    /// EVEX-only maps 5 and 6.
+    Evex(u8),
+}
+
+/// Legacy prefixes present on an instruction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Prefixes {
+    pub lock: bool,
+    pub rep: bool,
+    pub repne: bool,
+    pub operand_size: bool,
+    pub address_size: bool,
+    /// Raw segment override byte (`2E`, `36`, `3E`, `26`, `64`, `65`).
+    pub segment: Option<u8>,
+    /// The last of `66`/`F2`/`F3`, which selects the SSE variant.
+    pub mandatory: Option<u8>,
+}
+
+/// Decoded VEX or EVEX prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vex {
+    pub evex: bool,
+    pub w: bool,
+    /// Vector length: 0 = 128, 1 = 256, 2 = 512 bits.
+    pub length: u8,
+    /// Extra source register (`vvvv`), already un-inverted.
+    pub vvvv: u8,
+    /// Implied prefix: 0 = none, 1 = `66`, 2 = `F3`, 3 = `F2`.
+    pub pp: u8,
+}
+
+/// Decoded `ModRM` byte with REX/VEX extensions applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModRm {
+    pub mode: u8,
+    pub reg: u8,
+    pub rm: u8,
+}
+
+/// Memory operand addressed by `ModRM`/`SIB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryOperand {
+    /// Base register number, `None` for RIP-relative or absolute forms.
+    pub base: Option<u8>,
+    pub index: Option<u8>,
+    pub scale: u8,
+    pub displacement: i64,
+    pub rip_relative: bool,
+}
+
+impl MemoryOperand {
+    /// Returns true if the operand is `[base+disp]` for the given register.
+    pub fn is_based_on(&self, register: u8) -> bool {
+        self.base == Some(register) && self.index.is_none()
+    }
+}
+
+/// Instruction mnemonic, grouped where operand semantics are identical.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mnemonic {
+    Add,
+    Or,
+    Adc,
+    Sbb,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+    Test,
+    Inc,
+    Dec,
+    Neg,
+    Not,
+    Mul,
+    Imul,
+    Div,
+    Idiv,
+    Rol,
+    Ror,
+    Rcl,
+    Rcr,
+    Shl,
+    Shr,
+    Sar,
+    Shld,
+    Shrd,
+    Bt,
+    Bsf,
+    Bsr,
+    Mov,
+    Movzx,
+    Movsx,
+    Movsxd,
+    Lea,
+    Xchg,
+    Cmov,
+    Set,
+    Bswap,
+    Cmpxchg,
+    Xadd,
+    Push,
+    Pop,
+    Enter,
+    Leave,
+    Cdqe,
+    Cqo,
+    Call,
+    Jmp,
+    Jcc,
+    Loop,
+    Ret,
+    Int3,
+    Int,
+    Syscall,
+    Hlt,
+    Ud2,
+    Nop,
+    Cpuid,
+    Rdtsc,
+    Movs,
+    Cmps,
+    Stos,
+    Lods,
+    Scas,
+    Movups,
+    Movupd,
+    Movss,
+    Movsd,
+    Movaps,
+    Movapd,
+    Movd,
+    Movq,
+    Movdqa,
+    Movdqu,
+    SseAdd,
+    SseSub,
+    SseMul,
+    SseDiv,
+    SseMin,
+    SseMax,
+    SseSqrt,
+    SseAnd,
+    SseAndn,
+    SseOr,
+    SseXor,
+    SseConvert,
+    SseCompare,
+    Pxor,
+    X87,
+    Other,
+}
+
+/// Control flow effect of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Sequential,
+    Call,
+    IndirectCall,
+    Jump,
+    IndirectJump,
+    ConditionalJump,
+    Return,
+    /// `int3`, `ud2`, `hlt` and other instructions that do not fall through.
+    Trap,
+}
+
+/// A decoded instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: usize,
+    pub length: usize,
+    pub prefixes: Prefixes,
+    /// Raw REX byte, or 0 when absent.
+    pub rex: u8,
+    pub vex: Option<Vex>,
+    pub map: OpcodeMap,
+    pub opcode: u8,
+    pub modrm: Option<ModRm>,
+    pub memory: Option<MemoryOperand>,
+    pub immediate: Option<i64>,
+    /// Relative displacement of a direct branch.
+    pub relative: Option<i64>,
+    pub mnemonic: Mnemonic,
+}
+
+impl Instruction {
+    /// Address of the following instruction.
+    pub fn next_address(&self) -> usize {
+        self.address + self.length
+    }
+
+    /// Returns true if REX.W (or VEX/EVEX.W) is set.
+    pub fn is_wide(&self) -> bool {
+        self.rex & 8 != 0 || self.vex.is_some_and(|v| v.w)
+    }
+
+    /// Effective operand size in bytes for general purpose instructions.
+    ///
+    /// Byte-sized opcode forms are not distinguished; callers that care check
+    /// the opcode themselves.
+    pub fn operand_size(&self) -> u8 {
+        if self.is_wide() {
+            8
+        } else if self.prefixes.operand_size {
+            2
+        } else {
+            4
+        }
+    }
+
+    /// Target of a direct call or jump.
+    pub fn branch_target(&self) -> Option<usize> {
+        self.relative
+            .map(|rel| self.next_address().wrapping_add_signed(rel as isize))
+    }
+
+    /// Absolute address referenced by a RIP-relative memory operand.
+    pub fn rip_target(&self) -> Option<usize> {
+        self.memory.filter(|m| m.rip_relative).map(|m| {
+            self.next_address()
+                .wrapping_add_signed(m.displacement as isize)
+        })
+    }
+
+    /// The register named by the `ModRM.rm` field when it is not a memory operand.
+    pub fn rm_register(&self) -> Option<u8> {
+        match self.modrm {
+            Some(m) if m.mode == 3 => Some(m.rm),
+            _ => None,
+        }
+    }
+
+    /// The register named by the `ModRM.reg` field.
+    pub fn reg_field(&self) -> Option<u8> {
+        self.modrm.map(|m| m.reg)
+    }
+
+    /// Returns the control flow effect of the instruction.
+    pub fn flow(&self) -> Flow {
+        match self.mnemonic {
+            Mnemonic::Call if self.relative.is_some() => Flow::Call,
+            Mnemonic::Call => Flow::IndirectCall,
+            Mnemonic::Jmp if self.relative.is_some() => Flow::Jump,
+            Mnemonic::Jmp => Flow::IndirectJump,
+            Mnemonic::Jcc | Mnemonic::Loop => Flow::ConditionalJump,
+            Mnemonic::Ret => Flow::Return,
+            Mnemonic::Int3 | Mnemonic::Ud2 | Mnemonic::Hlt => Flow::Trap,
+            _ => Flow::Sequential,
+        }
+    }
+
+    /// Returns true if the instruction ends a straight-line sequence.
+    pub fn is_terminator(&self) -> bool {
+        matches!(
+            self.flow(),
+            Flow::Jump | Flow::IndirectJump | Flow::Return | Flow::Trap
+        )
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}: {:?}", self.address, self.mnemonic)?;
+        if let Some(target) = self.branch_target() {
+            write!(f, " 0x{:X}", target)?;
+        } else if let Some(target) = self.rip_target() {
+            write!(f, " [0x{:X}]", target)?;
+        } else if let Some(imm) = self.immediate {
+            write!(f, " 0x{:X}", imm)?;
+        }
+        Ok(())
+    }
+}
+
+/// Immediate operand encodings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Imm {
+    None,
+    Byte,
+    Word,
+    /// 16 or 32 bits depending on the operand size.
+    Z,
+    /// 16, 32 or 64 bits depending on the operand size (`mov r, imm`).
+    V,
+    /// `enter imm16, imm8`
+    Enter,
+    /// Absolute memory offset (`mov al, [moffs]`).
+    Moffs,
+    Rel8,
+    Rel32,
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    address: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Result<u8, Error> {
+        self.bytes
+            .get(self.pos)
+            .copied()
+            .ok_or(Error::TruncatedInstruction {
+                address: self.address,
+            })
+    }
+
+    fn next(&mut self) -> Result<u8, Error> {
+        let b = self.peek()?;
+        self.pos += 1;
+        if self.pos > MAX_INSTRUCTION_LENGTH {
+            return Err(Error::InvalidInstruction {
+                address: self.address,
+            });
+        }
+        Ok(b)
+    }
+
+    fn read(&mut self, size: usize) -> Result<i64, Error> {
+        let bytes =
+            self.bytes
+                .get(self.pos..self.pos + size)
+                .ok_or(Error::TruncatedInstruction {
+                    address: self.address,
+                })?;
+        self.pos += size;
+        if self.pos > MAX_INSTRUCTION_LENGTH {
+            return Err(Error::InvalidInstruction {
+                address: self.address,
+            });
+        }
+        Ok(match size {
+            1 => bytes[0] as i8 as i64,
+            2 => i16::from_le_bytes([bytes[0], bytes[1]]) as i64,
+            4 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64,
+            _ => i64::from_le_bytes(bytes.try_into().unwrap_or([0; 8])),
+        })
+    }
+}
+
+/// Decodes a single instruction at the start of `bytes`.
+///
+/// `address` is the runtime address of `bytes[0]` and is used to resolve
+/// relative branch and RIP-relative targets.
+pub fn decode(bytes: &[u8], address: usize) -> Result<Instruction, Error> {
+    let mut cur = Cursor {
+        bytes,
+        pos: 0,
+        address,
+    };
+    let invalid = || Error::InvalidInstruction { address };
+
+    let mut prefixes = Prefixes::default();
+    loop {
+        match cur.peek()? {
+            0xF0 => prefixes.lock = true,
+            0xF2 => {
+                prefixes.repne = true;
+                prefixes.mandatory = Some(0xF2);
+            }
+            0xF3 => {
+                prefixes.rep = true;
+                prefixes.mandatory = Some(0xF3);
+            }
+            0x66 => {
+                prefixes.operand_size = true;
+                prefixes.mandatory = Some(0x66);
+            }
+            0x67 => prefixes.address_size = true,
+            b @ (0x2E | 0x36 | 0x3E | 0x26 | 0x64 | 0x65) => prefixes.segment = Some(b),
+            _ => break,
+        }
+        cur.next()?;
+    }
+
+    let mut rex = 0u8;
+    if (0x40..=0x4F).contains(&cur.peek()?) {
+        rex = cur.next()?;
+    }
+    // Extension bits in REX layout (W R X B), also filled from VEX/EVEX.
+    let mut ext = rex & 0x0F;
+    let mut reg_high = 0u8;
+
+    let first = cur.next()?;
+    let mut vex = None;
+    let (map, opcode) = match first {
+        0x0F => match cur.next()? {
+            0x38 => (OpcodeMap::Map0F38, cur.next()?),
+            0x3A => (OpcodeMap::Map0F3A, cur.next()?),
+            op => (OpcodeMap::Secondary, op),
+        },
+        0xC5 => {
+            let b1 = cur.next()?;
+            ext = (!b1 >> 5) & 4;
+            vex = Some(Vex {
+                evex: false,
+                w: false,
+                length: (b1 >> 2) & 1,
+                vvvv: (!b1 >> 3) & 0xF,
+                pp: b1 & 3,
+            });
+            (OpcodeMap::Secondary, cur.next()?)
+        }
+        0xC4 => {
+            let b1 = cur.next()?;
+            let b2 = cur.next()?;
+            ext = ((!b1 >> 5) & 7) | ((b2 >> 4) & 8);
+            vex = Some(Vex {
+                evex: false,
+                w: b2 & 0x80 != 0,
+                length: (b2 >> 2) & 1,
+                vvvv: (!b2 >> 3) & 0xF,
+                pp: b2 & 3,
+            });
+            let map = match b1 & 0x1F {
+                1 => OpcodeMap::Secondary,
+                2 => OpcodeMap::Map0F38,
+                3 => OpcodeMap::Map0F3A,
+                _ => return Err(invalid()),
+            };
+            (map, cur.next()?)
+        }
+        0x62 => {
+            let p0 = cur.next()?;
+            let p1 = cur.next()?;
+            let p2 = cur.next()?;
+            if p1 & 0x04 == 0 {
+                return Err(invalid());
+            }
+            ext = ((!p0 >> 5) & 7) | ((p1 >> 4) & 8);
+            reg_high = ((!p0 >> 4) & 1) << 4;
+            vex = Some(Vex {
+                evex: true,
+                w: p1 & 0x80 != 0,
+                length: (p2 >> 5) & 3,
+                vvvv: ((!p1 >> 3) & 0xF) | (((!p2 >> 3) & 1) << 4),
+                pp: p1 & 3,
+            });
+            let map = match p0 & 7 {
+                1 => OpcodeMap::Secondary,
+                2 => OpcodeMap::Map0F38,
+                3 => OpcodeMap::Map0F3A,
+                m @ (5 | 6) => OpcodeMap::Evex(m),
+                _ => return Err(invalid()),
+            };
+            (map, cur.next()?)
+        }
+        op => (OpcodeMap::Primary, op),
+    };
+
+    let (has_modrm, mut imm) = match map {
+        OpcodeMap::Primary => primary_encoding(opcode).ok_or_else(invalid)?,
+        OpcodeMap::Secondary => secondary_encoding(opcode).ok_or_else(invalid)?,
+        OpcodeMap::Map0F38 | OpcodeMap::Evex(_) => (true, Imm::None),
+        OpcodeMap::Map0F3A => (true, Imm::Byte),
+    };
+
+    let mut modrm = None;
+    let mut memory = None;
+    if has_modrm {
+        let byte = cur.next()?;
+        let mode = byte >> 6;
+        let reg = ((byte >> 3) & 7) | ((ext & 4) << 1) | reg_high;
+        let rm_low = byte & 7;
+        let rm = rm_low | ((ext & 1) << 3);
+        modrm = Some(ModRm { mode, reg, rm });
+
+        if mode != 3 {
+            let mut operand = MemoryOperand {
+                base: Some(rm),
+                index: None,
+                scale: 1,
+                displacement: 0,
+                rip_relative: false,
+            };
+            let mut disp_size = match mode {
+                1 => 1,
+                2 => 4,
+                _ => 0,
+            };
+            if rm_low == 4 {
+                let sib = cur.next()?;
+                let index = ((sib >> 3) & 7) | ((ext & 2) << 2);
+                operand.index = (index != 4).then_some(index);
+                operand.scale = 1 << (sib >> 6);
+                let base_low = sib & 7;
+                if base_low == 5 && mode == 0 {
+                    operand.base = None;
+                    disp_size = 4;
+                } else {
+                    operand.base = Some(base_low | ((ext & 1) << 3));
+                }
+            } else if rm_low == 5 && mode == 0 {
+                operand.base = None;
+                operand.rip_relative = true;
+                disp_size = 4;
+            }
+            if disp_size > 0 {
+                operand.displacement = cur.read(disp_size)?;
+            }
+            memory = Some(operand);
+        }
+    }
+
+    // Group 3 `test` is the only form of F6/F7 with an immediate.
+    if map == OpcodeMap::Primary && matches!(opcode, 0xF6 | 0xF7) {
+        let reg = modrm.map_or(0, |m| m.reg & 7);
+        imm = match (opcode, reg) {
+            (0xF6, 0 | 1) => Imm::Byte,
+            (0xF7, 0 | 1) => Imm::Z,
+            _ => Imm::None,
+        };
+    }
+
+    let wide = ext & 8 != 0;
+    let z_size = if prefixes.operand_size && !wide { 2 } else { 4 };
+    let mut immediate = None;
+    let mut relative = None;
+    match imm {
+        Imm::None => {}
+        Imm::Byte => immediate = Some(cur.read(1)?),
+        Imm::Word => immediate = Some(cur.read(2)? & 0xFFFF),
+        Imm::Z => immediate = Some(cur.read(z_size)?),
+        Imm::V => immediate = Some(cur.read(if wide { 8 } else { z_size })?),
+        Imm::Enter => {
+            immediate = Some(cur.read(2)? & 0xFFFF);
+            cur.read(1)?;
+        }
+        Imm::Moffs => {
+            immediate = Some(cur.read(if prefixes.address_size { 4 } else { 8 })?);
+        }
+        Imm::Rel8 => relative = Some(cur.read(1)?),
+        Imm::Rel32 => relative = Some(cur.read(4)?),
+    }
+
+    let mut insn = Instruction {
+        address,
+        length: cur.pos,
+        prefixes,
+        rex,
+        vex,
+        map,
+        opcode,
+        modrm,
+        memory,
+        immediate,
+        relative,
+        mnemonic: Mnemonic::Other,
+    };
+    insn.mnemonic = mnemonic_of(&insn);
+    Ok(insn)
+}
+
+/// `ModRM` presence and immediate kind for the one-byte opcode map.
+fn primary_encoding(op: u8) -> Option<(bool, Imm)> {
+    Some(match op {
+        0x06 | 0x07 | 0x0E | 0x16 | 0x17 | 0x1E | 0x1F | 0x27 | 0x2F | 0x37 | 0x3F | 0x60
+        | 0x61 | 0x82 | 0x9A | 0xCE | 0xD4 | 0xD5 | 0xD6 | 0xEA => return None,
+        0x00..=0x3F => match op & 7 {
+            0..=3 => (true, Imm::None),
+            4 => (false, Imm::Byte),
+            5 => (false, Imm::Z),
+            _ => return None,
+        },
+        0x50..=0x5F => (false, Imm::None),
+        0x63 => (true, Imm::None),
+        0x68 => (false, Imm::Z),
+        0x69 => (true, Imm::Z),
+        0x6A => (false, Imm::Byte),
+        0x6B => (true, Imm::Byte),
+        0x6C..=0x6F => (false, Imm::None),
+        0x70..=0x7F => (false, Imm::Rel8),
+        0x80 | 0x83 => (true, Imm::Byte),
+        0x81 => (true, Imm::Z),
+        0x84..=0x8F => (true, Imm::None),
+        0x90..=0x9F => (false, Imm::None),
+        0xA0..=0xA3 => (false, Imm::Moffs),
+        0xA8 => (false, Imm::Byte),
+        0xA9 => (false, Imm::Z),
+        0xA4..=0xAF => (false, Imm::None),
+        0xB0..=0xB7 => (false, Imm::Byte),
+        0xB8..=0xBF => (false, Imm::V),
+        0xC0 | 0xC1 | 0xC6 => (true, Imm::Byte),
+        0xC7 => (true, Imm::Z),
+        0xC2 | 0xCA => (false, Imm::Word),
+        0xC8 => (false, Imm::Enter),
+        0xCD => (false, Imm::Byte),
+        0xC3 | 0xC9 | 0xCB | 0xCC | 0xCF => (false, Imm::None),
+        0xD0..=0xD3 | 0xD8..=0xDF => (true, Imm::None),
+        0xD7 => (false, Imm::None),
+        0xE0..=0xE3 | 0xEB => (false, Imm::Rel8),
+        0xE4..=0xE7 => (false, Imm::Byte),
+        0xE8 | 0xE9 => (false, Imm::Rel32),
+        0xEC..=0xEF => (false, Imm::None),
+        0xF1 | 0xF4 | 0xF5 | 0xF8..=0xFD => (false, Imm::None),
+        0xF6 | 0xF7 | 0xFE | 0xFF => (true, Imm::None),
+        _ => return None,
+    })
+}
+
+/// `ModRM` presence and immediate kind for the `0F` opcode map.
+fn secondary_encoding(op: u8) -> Option<(bool, Imm)> {
+    Some(match op {
+        0x04 | 0x0A | 0x0C | 0x24..=0x27 | 0x36 | 0x39 | 0x3B..=0x3F | 0x7A | 0x7B => {
+            return None;
+        }
+        0x05..=0x09 | 0x0B | 0x0E | 0x30..=0x35 | 0x37 | 0xA0..=0xA2 | 0xA8..=0xAA => {
+            (false, Imm::None)
+        }
+        0x77 => (false, Imm::None),
+        0x80..=0x8F => (false, Imm::Rel32),
+        0xC8..=0xCF => (false, Imm::None),
+        0x0F | 0x70..=0x73 | 0xA4 | 0xAC | 0xBA | 0xC2 | 0xC4..=0xC6 => (true, Imm::Byte),
+        _ => (true, Imm::None),
+    })
+}
+
+const GROUP1: [Mnemonic; 8] = [
+    Mnemonic::Add,
+    Mnemonic::Or,
+    Mnemonic::Adc,
+    Mnemonic::Sbb,
+    Mnemonic::And,
+    Mnemonic::Sub,
+    Mnemonic::Xor,
+    Mnemonic::Cmp,
+];
+
+const GROUP2: [Mnemonic; 8] = [
+    Mnemonic::Rol,
+    Mnemonic::Ror,
+    Mnemonic::Rcl,
+    Mnemonic::Rcr,
+    Mnemonic::Shl,
+    Mnemonic::Shr,
+    Mnemonic::Shl,
+    Mnemonic::Sar,
+];
+
+const GROUP3: [Mnemonic; 8] = [
+    Mnemonic::Test,
+    Mnemonic::Test,
+    Mnemonic::Not,
+    Mnemonic::Neg,
+    Mnemonic::Mul,
+    Mnemonic::Imul,
+    Mnemonic::Div,
+    Mnemonic::Idiv,
+];
+
+fn mnemonic_of(insn: &Instruction) -> Mnemonic {
+    let group = insn.modrm.map_or(0, |m| (m.reg & 7) as usize);
+    let op = insn.opcode;
+    match insn.map {
+        OpcodeMap::Primary => match op {
+            0x00..=0x3F => GROUP1[(op >> 3) as usize],
+            0x50..=0x57 | 0x68 | 0x6A | 0x9C => Mnemonic::Push,
+            0x58..=0x5F | 0x8F | 0x9D => Mnemonic::Pop,
+            0x63 => Mnemonic::Movsxd,
+            0x69 | 0x6B => Mnemonic::Imul,
+            0x70..=0x7F | 0xE3 => Mnemonic::Jcc,
+            0x80..=0x83 => GROUP1[group],
+            0x84 | 0x85 | 0xA8 | 0xA9 => Mnemonic::Test,
+            0x86 | 0x87 => Mnemonic::Xchg,
+            0x88..=0x8C | 0x8E | 0xA0..=0xA3 | 0xB0..=0xBF | 0xC6 | 0xC7 => Mnemonic::Mov,
+            0x8D => Mnemonic::Lea,
+            0x90 if insn.rex & 1 == 0 => Mnemonic::Nop,
+            0x90..=0x97 => Mnemonic::Xchg,
+            0x98 => Mnemonic::Cdqe,
+            0x99 => Mnemonic::Cqo,
+            0xA4 | 0xA5 => Mnemonic::Movs,
+            0xA6 | 0xA7 => Mnemonic::Cmps,
+            0xAA | 0xAB => Mnemonic::Stos,
+            0xAC | 0xAD => Mnemonic::Lods,
+            0xAE | 0xAF => Mnemonic::Scas,
+            0xC0 | 0xC1 | 0xD0..=0xD3 => GROUP2[group],
+            0xC2 | 0xC3 | 0xCA | 0xCB => Mnemonic::Ret,
+            0xC8 => Mnemonic::Enter,
+            0xC9 => Mnemonic::Leave,
+            0xCC => Mnemonic::Int3,
+            0xCD => Mnemonic::Int,
+            0xD8..=0xDF => Mnemonic::X87,
+            0xE0..=0xE2 => Mnemonic::Loop,
+            0xE8 => Mnemonic::Call,
+            0xE9 | 0xEB => Mnemonic::Jmp,
+            0xF4 => Mnemonic::Hlt,
+            0xF6 | 0xF7 => GROUP3[group],
+            0xFE => match group {
+                0 => Mnemonic::Inc,
+                1 => Mnemonic::Dec,
+                _ => Mnemonic::Other,
+            },
+            0xFF => match group {
+                0 => Mnemonic::Inc,
+                1 => Mnemonic::Dec,
+                2 | 3 => Mnemonic::Call,
+                4 | 5 => Mnemonic::Jmp,
+                6 => Mnemonic::Push,
+                _ => Mnemonic::Other,
+            },
+            _ => Mnemonic::Other,
+        },
+        OpcodeMap::Secondary => {
+            let prefix = match insn.vex {
+                Some(v) => [None, Some(0x66), Some(0xF3), Some(0xF2)][v.pp as usize],
+                None => insn.prefixes.mandatory,
+            };
+            match op {
+                0x05 => Mnemonic::Syscall,
+                0x0B => Mnemonic::Ud2,
+                0x18..=0x1F => Mnemonic::Nop,
+                0x10 | 0x11 => match prefix {
+                    Some(0xF3) => Mnemonic::Movss,
+                    Some(0xF2) => Mnemonic::Movsd,
+                    Some(0x66) => Mnemonic::Movupd,
+                    _ => Mnemonic::Movups,
+                },
+                0x28 | 0x29 => match prefix {
+                    Some(0x66) => Mnemonic::Movapd,
+                    _ => Mnemonic::Movaps,
+                },
+                0x2A | 0x2C | 0x2D | 0x5A | 0x5B | 0xE6 => Mnemonic::SseConvert,
+                0x2E | 0x2F | 0xC2 => Mnemonic::SseCompare,
+                0x40..=0x4F => Mnemonic::Cmov,
+                0x51 => Mnemonic::SseSqrt,
+                0x54 => Mnemonic::SseAnd,
+                0x55 => Mnemonic::SseAndn,
+                0x56 => Mnemonic::SseOr,
+                0x57 => Mnemonic::SseXor,
+                0x58 => Mnemonic::SseAdd,
+                0x59 => Mnemonic::SseMul,
+                0x5C => Mnemonic::SseSub,
+                0x5D => Mnemonic::SseMin,
+                0x5E => Mnemonic::SseDiv,
+                0x5F => Mnemonic::SseMax,
+                0x6E => {
+                    if insn.is_wide() {
+                        Mnemonic::Movq
+                    } else {
+                        Mnemonic::Movd
+                    }
+                }
+                0x7E => {
+                    if prefix == Some(0xF3) || insn.is_wide() {
+                        Mnemonic::Movq
+                    } else {
+                        Mnemonic::Movd
+                    }
+                }
+                0xD6 => Mnemonic::Movq,
+                0x6F | 0x7F => match prefix {
+                    Some(0x66) => Mnemonic::Movdqa,
+                    Some(0xF3) => Mnemonic::Movdqu,
+                    _ => Mnemonic::Movq,
+                },
+                0x80..=0x8F => Mnemonic::Jcc,
+                0x90..=0x9F => Mnemonic::Set,
+                0xA2 => Mnemonic::Cpuid,
+                0x31 => Mnemonic::Rdtsc,
+                0xA3 | 0xAB | 0xB3 | 0xBB => Mnemonic::Bt,
+                0xBA if group >= 4 => Mnemonic::Bt,
+                0xA4 | 0xA5 => Mnemonic::Shld,
+                0xAC | 0xAD => Mnemonic::Shrd,
+                0xAF => Mnemonic::Imul,
+                0xB0 | 0xB1 => Mnemonic::Cmpxchg,
+                0xB6 | 0xB7 => Mnemonic::Movzx,
+                0xBC => Mnemonic::Bsf,
+                0xBD => Mnemonic::Bsr,
+                0xBE | 0xBF => Mnemonic::Movsx,
+                0xC0 | 0xC1 => Mnemonic::Xadd,
+                0xC8..=0xCF => Mnemonic::Bswap,
+                0xEF => Mnemonic::Pxor,
+                _ => Mnemonic::Other,
+            }
+        }
+        _ => Mnemonic::Other,
+    }
+}
+
+/// Iterator decoding consecutive instructions from a byte buffer.
+///
+/// Iteration stops at the end of the buffer or at the first byte sequence
+/// that does not decode; `offset` tells where it stopped.
+pub struct Instructions<'a> {
+    bytes: &'a [u8],
+    address: usize,
+    offset: usize,
+}
+
+impl Instructions<'_> {
+    /// Offset of the next byte to decode.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl Iterator for Instructions<'_> {
+    type Item = Instruction;
+
+    fn next(&mut self) -> Option<Instruction> {
+        if self.offset >= self.bytes.len() {
+            return None;
+        }
+        match decode(&self.bytes[self.offset..], self.address + self.offset) {
+            Ok(insn) => {
+                self.offset += insn.length;
+                Some(insn)
+            }
+            Err(_) => {
+                self.bytes = &self.bytes[..self.offset];
+                None
+            }
+        }
+    }
+}
+
+/// Decodes instructions linearly from `bytes`, which start at `address`.
+pub fn instructions(bytes: &[u8], address: usize) -> Instructions<'_> {
+    Instructions {
+        bytes,
+        address,
+        offset: 0,
+    }
+}
+
+/// Estimates the size of the function whose first byte is `bytes[0]`.
+///
+/// Instructions are decoded linearly while tracking the furthest forward
+/// branch target inside the buffer. The function ends at the first return,
+/// trap or long unconditional jump that no earlier branch jumps past. Short
+/// unconditional jumps are assumed to stay inside the function.
+pub fn function_size(bytes: &[u8], address: usize) -> Option<usize> {
+    let end = address + bytes.len();
+    let mut furthest = address;
+    for insn in instructions(bytes, address) {
+        let next = insn.next_address();
+        let flow = insn.flow();
+        if let Some(target) = insn.branch_target() {
+            let internal =
+                flow == Flow::ConditionalJump || (flow == Flow::Jump && insn.length == 2);
+            if internal && target > furthest && target < end {
+                furthest = target;
+            }
+        }
+        let ends = match flow {
+            Flow::Return | Flow::Trap | Flow::IndirectJump => true,
+            Flow::Jump => insn.length != 2,
+            _ => false,
+        };
+        if ends && next > furthest {
+            return Some(next - address);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one(bytes: &[u8]) -> Instruction {
+        decode(bytes, 0x1000).unwrap()
+    }
+
+    #[test]
+    fn test_lengths() {
+        let cases: &[(&[u8], usize)] = &[
+            (&[0x55], 1),                                           // push rbp
+            (&[0x48, 0x89, 0xE5], 3),                               // mov rbp, rsp
+            (&[0x48, 0x83, 0xEC, 0x28], 4),                         // sub rsp, 0x28
+            (&[0x48, 0x89, 0x5C, 0x24, 0x08], 5),                   // mov [rsp+8], rbx
+            (&[0x48, 0x8B, 0x05, 0x10, 0x00, 0x00, 0x00], 7),       // mov rax, [rip+0x10]
+            (&[0x48, 0xB8, 1, 2, 3, 4, 5, 6, 7, 8], 10),            // mov rax, imm64
+            (&[0x66, 0xC7, 0x41, 0x08, 0x34, 0x12], 6),             // mov word [rcx+8], 0x1234
+            (&[0xF3, 0x0F, 0x10, 0x44, 0x24, 0x30], 6),             // movss xmm0, [rsp+0x30]
+            (&[0xC5, 0xFA, 0x10, 0x41, 0x10], 5),                   // vmovss xmm0, [rcx+0x10]
+            (&[0xC4, 0xE3, 0x79, 0x04, 0xC0, 0x1B], 6),             // vpermilps xmm0, xmm0, 0x1b
+            (&[0x62, 0xF1, 0x7C, 0x48, 0x10, 0x01], 6),             // vmovups zmm0, [rcx]
+            (&[0xF7, 0x05, 0, 0, 0, 0, 1, 0, 0, 0], 10),            // test dword [rip], 1
+            (&[0xF7, 0xD8], 2),                                     // neg eax
+            (&[0x0F, 0x1F, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00], 8), // nop dword [rax+rax]
+            (&[0xC8, 0x10, 0x00, 0x00], 4),                         // enter 0x10, 0
+            (&[0x48, 0xA1, 1, 2, 3, 4, 5, 6, 7, 8], 10),            // mov rax, [moffs64]
+            (&[0x0F, 0x3A, 0x0F, 0xC1, 0x08], 5),                   // palignr mm0, mm1, 8
+            (&[0x42, 0x8B, 0x04, 0x85, 0x00, 0x10, 0x00, 0x00], 8), // mov eax, [r8*4+0x1000]
+        ];
+        for (bytes, len) in cases {
+            assert_eq!(one(bytes).length, *len, "{:02X?}", bytes);
+        }
+        assert!(decode(&[0x06], 0).is_err());
+        assert!(decode(&[0x48, 0x8B], 0).is_err());
+    }
+
+    #[test]
+    fn test_operands_and_targets() {
+        let lea = one(&[0x48, 0x8D, 0x0D, 0xF9, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(lea.mnemonic, Mnemonic::Lea);
+        assert_eq!(lea.reg_field(), Some(1));
+        assert_eq!(lea.rip_target(), Some(0x1000));
+
+        let call = one(&[0xE8, 0x10, 0x00, 0x00, 0x00]);
+        assert_eq!(call.flow(), Flow::Call);
+        assert_eq!(call.branch_target(), Some(0x1015));
+
+        let jcc = one(&[0x0F, 0x84, 0xF0, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(jcc.flow(), Flow::ConditionalJump);
+        assert_eq!(jcc.branch_target(), Some(0xFF6));
+
+        let store = one(&[0x4C, 0x89, 0x44, 0x24, 0x18]);
+        assert_eq!(store.mnemonic, Mnemonic::Mov);
+        assert_eq!(store.reg_field(), Some(8));
+        let mem = store.memory.unwrap();
+        assert!(mem.is_based_on(4));
+        assert_eq!(mem.displacement, 0x18);
+
+        let sib = one(&[0x42, 0x8B, 0x04, 0x85, 0x00, 0x10, 0x00, 0x00]);
+        let mem = sib.memory.unwrap();
+        assert_eq!((mem.base, mem.index, mem.scale), (None, Some(8), 4));
+
+        let vcall = one(&[0xFF, 0x50, 0x18]);
+        assert_eq!(vcall.flow(), Flow::IndirectCall);
+        assert_eq!(one(&[0xC3]).flow(), Flow::Return);
+    }
+
+    #[test]
+    fn test_function_size() {
+        let code = [
+            0x48, 0x83, 0xEC, 0x28, // sub rsp, 0x28
+            0x85, 0xC9, // test ecx, ecx
+            0x74, 0x05, // je +5
+            0x48, 0x83, 0xC4, 0x28, // add rsp, 0x28
+            0xC3, // ret
+            0x31, 0xC0, // xor eax, eax
+            0x48, 0x83, 0xC4, 0x28, // add rsp, 0x28
+            0xC3, // ret
+            0xCC, 0xCC, 0xCC, 0xC3,
+        ];
+        assert_eq!(function_size(&code, 0x1000), Some(20));
+        // A ret byte inside an immediate does not end the function.
+        let imm = [0xB8, 0xC3, 0x00, 0x00, 0x00, 0xC3];
+        assert_eq!(function_size(&imm, 0), Some(6));
+        assert_eq!(instructions(&imm, 0).count(), 2);
+    }
+}
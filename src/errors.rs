@@ -64,6 +64,34 @@ pub enum Error {
     // Hooks
     #[error("hook install failed")]
     HookInstall(#[source] ilhook::HookError),
+    #[error("Hook not found: {0}")]
+    HookNotFound(String),
+    #[error("Hook module not found: {0}")]
+    HookModuleNotFound(String),
+    #[error("Hook cannot be toggled: {0}")]
+    HookNotToggleable(String),
+    #[error("Hook module {module} failed to start: {source}")]
+    HookModuleStart {
+        module: String,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("Module not loaded: {0}")]
+    ModuleNotLoaded(String),
+    #[error("Import {dll}!{function} not found in module at 0x{base:X}")]
+    ImportNotFound {
+        base: usize,
+        dll: String,
+        function: String,
+    },
+    #[error("Export {function} not found in module at 0x{base:X}")]
+    ExportNotFound { base: usize, function: String },
+    #[error("Invalid trace spec: {0}")]
+    InvalidTraceSpec(String),
+    #[error("No free debug register for breakpoint at 0x{0:X}")]
+    NoFreeDebugRegister(usize),
+    #[error("Invalid breakpoint: {0}")]
+    InvalidBreakpoint(String),
 
     // Pattern matching
     #[error("Invalid hex value: {0}")]
@@ -88,6 +116,18 @@ pub enum Error {
     QueryFailed { reason: String },
     #[error("Invalid address: 0x{address:X}")]
     InvalidAddress { address: usize },
+    #[error("Invalid address expression: {0}")]
+    InvalidExpression(String),
+
+    // Disassembly
+    #[error("Invalid instruction at 0x{address:X}")]
+    InvalidInstruction { address: usize },
+    #[error("Truncated instruction at 0x{address:X}")]
+    TruncatedInstruction { address: usize },
+
+    // PE parsing
+    #[error("Invalid PE image: {0}")]
+    InvalidImage(String),
 
     // Analysis
     #[error("Memory error: {0}")]
@@ -96,6 +136,16 @@ pub enum Error {
     ScanError(String),
     #[error("Analysis failed: {0}")]
     AnalysisFailed(String),
+    #[error("Invalid analysis cache: {0}")]
+    InvalidCache(String),
+
+    // Type library
+    #[error("Unknown type: {0}")]
+    UnknownType(String),
+    #[error("Invalid type {name}: {reason}")]
+    InvalidType { name: String, reason: String },
+    #[error("Header parse error at line {line}: {message}")]
+    HeaderParse { line: usize, message: String },
 
     // Generic fallbacks
     #[error("windows api error")]
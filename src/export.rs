@@ -0,0 +1,1192 @@
+//! Export of analysis results to disassemblers and debuggers.
+//!
+//! Findings from the injected runtime are written as files the usual tools
+//! can load: IDAPython and Binary Ninja scripts, a Ghidra symbol list with a
+//! C header for the recovered structures, an x64dbg database, and plain JSON
+//! and CSV dumps. Runtime addresses are converted to RVAs of one module, so
+//! the output applies regardless of where ASLR placed the module.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::analysis::{
+    AnalysisResult, CallingConvention, DataType, DiscoveredFunction, DiscoveredStruct,
+    ParameterLocation, StringEncoding, StringReference,
+};
+use crate::errors::Error;
+use crate::vtable::{ClassHierarchy, ClassInfo};
+
+/// Longest string quoted in a comment.
+const MAX_COMMENT_STRING: usize = 80;
+
+/// Output format of an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    /// IDAPython script naming functions and applying types.
+    IdaPython,
+    /// Symbol list for `ImportSymbolsScript.py` and a C header for "Parse C Source".
+    Ghidra,
+    /// Binary Ninja Python script.
+    BinaryNinja,
+    /// x64dbg database (`.dd64`) with labels, comments, bookmarks and functions.
+    X64dbg,
+    /// Complete JSON dump.
+    Json,
+    /// CSV tables of functions, structure fields, classes and strings.
+    Csv,
+}
+
+impl ExportFormat {
+    /// All supported formats.
+    pub const ALL: [ExportFormat; 6] = [
+        ExportFormat::IdaPython,
+        ExportFormat::Ghidra,
+        ExportFormat::BinaryNinja,
+        ExportFormat::X64dbg,
+        ExportFormat::Json,
+        ExportFormat::Csv,
+    ];
+
+    /// Human-readable name of the format.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::IdaPython => "IDAPython",
+            ExportFormat::Ghidra => "Ghidra",
+            ExportFormat::BinaryNinja => "Binary Ninja",
+            ExportFormat::X64dbg => "x64dbg",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Csv => "CSV",
+        }
+    }
+}
+
+/// Module the export is made for.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// File name of the module as the tools know it, e.g. `game.exe`.
+    pub module_name: String,
+    /// Runtime base address of the module.
+    pub module_base: usize,
+    /// Size of the module image; findings outside it are skipped.
+    pub module_size: usize,
+    /// Image base of the module in the disassembler database. Only the Ghidra
+    /// symbol list uses absolute addresses; the scripts rebase at run time.
+    pub database_base: usize,
+    /// Functions below this confidence are skipped.
+    pub min_confidence: f32,
+    /// Whether to comment the instructions referencing strings.
+    pub string_comments: bool,
+}
+
+impl ExportOptions {
+    /// Creates options for the module mapped at `module_base`.
+    pub fn new(module_name: impl Into<String>, module_base: usize, module_size: usize) -> Self {
+        Self {
+            module_name: module_name.into(),
+            module_base,
+            module_size,
+            database_base: 0x1_4000_0000,
+            min_confidence: 0.0,
+            string_comments: true,
+        }
+    }
+
+    fn rva(&self, address: usize) -> Option<usize> {
+        address
+            .checked_sub(self.module_base)
+            .filter(|&rva| rva < self.module_size)
+    }
+
+    /// File name stem derived from the module name.
+    fn stem(&self) -> String {
+        let name = self
+            .module_name
+            .rsplit_once('.')
+            .map_or(self.module_name.as_str(), |(stem, _)| stem);
+        c_identifier(name)
+    }
+}
+
+/// The findings to export.
+#[derive(Debug, Clone, Copy)]
+pub struct ExportSource<'a> {
+    pub functions: &'a [DiscoveredFunction],
+    pub structures: &'a [DiscoveredStruct],
+    pub classes: Option<&'a ClassHierarchy>,
+    pub strings: &'a [StringReference],
+}
+
+impl<'a> From<&'a AnalysisResult> for ExportSource<'a> {
+    fn from(result: &'a AnalysisResult) -> Self {
+        Self {
+            functions: &result.functions,
+            structures: &result.structures,
+            classes: Some(&result.class_hierarchy),
+            strings: &result.string_references,
+        }
+    }
+}
+
+/// A generated file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportFile {
+    pub name: String,
+    pub contents: String,
+}
+
+/// Renders `source` in `format`.
+pub fn export(
+    source: &ExportSource,
+    format: ExportFormat,
+    options: &ExportOptions,
+) -> Vec<ExportFile> {
+    let annotations = Annotations::collect(source, options);
+    let stem = options.stem();
+    let file = |suffix: &str, contents: String| ExportFile {
+        name: format!("{stem}{suffix}"),
+        contents,
+    };
+    match format {
+        ExportFormat::IdaPython => vec![file("_ida.py", ida_script(&annotations, source))],
+        ExportFormat::Ghidra => vec![
+            file("_ghidra.txt", ghidra_symbols(&annotations, options)),
+            file(
+                "_ghidra.h",
+                c_header(source.structures, &options.module_name),
+            ),
+        ],
+        ExportFormat::BinaryNinja => vec![file("_binja.py", binja_script(&annotations, source))],
+        ExportFormat::X64dbg => vec![file(".dd64", x64dbg_database(&annotations, options))],
+        ExportFormat::Json => vec![file(".json", json_dump(&annotations, source, options))],
+        ExportFormat::Csv => csv_tables(&annotations, source, options)
+            .into_iter()
+            .map(|(suffix, contents)| file(suffix, contents))
+            .collect(),
+    }
+}
+
+/// Renders `source` in `format` and writes the files to `directory`.
+pub fn export_to_directory(
+    source: &ExportSource,
+    format: ExportFormat,
+    options: &ExportOptions,
+    directory: &Path,
+) -> Result<Vec<PathBuf>, Error> {
+    std::fs::create_dir_all(directory)?;
+    export(source, format, options)
+        .into_iter()
+        .map(|file| {
+            let path = directory.join(&file.name);
+            std::fs::write(&path, file.contents)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// A function in the exported module.
+#[derive(Debug, Clone)]
+struct ExportedFunction {
+    rva: usize,
+    size: Option<usize>,
+    name: String,
+    /// Whether the name came from the analysis rather than being generated.
+    named: bool,
+    prototype: Option<String>,
+}
+
+/// Names, comments and prototypes keyed by RVA, shared by all formats.
+#[derive(Debug, Default)]
+struct Annotations {
+    functions: BTreeMap<usize, ExportedFunction>,
+    /// Data labels such as vtables.
+    labels: BTreeMap<usize, String>,
+    comments: BTreeMap<usize, Vec<String>>,
+    classes: Vec<ClassInfo>,
+}
+
+impl Annotations {
+    fn collect(source: &ExportSource, options: &ExportOptions) -> Self {
+        let mut annotations = Self::default();
+        let mut classes: Vec<ClassInfo> = source
+            .classes
+            .map(|hierarchy| hierarchy.get_all_classes().cloned().collect())
+            .unwrap_or_default();
+        classes.sort_by_key(|class| class.vtable_address);
+
+        // Owning class of each virtual function, first vtable wins.
+        let mut owners: HashMap<usize, (&str, usize)> = HashMap::new();
+        for class in &classes {
+            for function in &class.functions {
+                owners
+                    .entry(function.address)
+                    .or_insert((class.name.as_str(), function.index));
+            }
+        }
+        let struct_names: Vec<&str> = source.structures.iter().map(|s| s.name.as_str()).collect();
+
+        for function in source.functions {
+            if function.confidence < options.min_confidence {
+                continue;
+            }
+            let Some(rva) = options.rva(function.address) else {
+                continue;
+            };
+            let owner = owners.get(&function.address).map(|&(class, _)| class);
+            let (name, named) = match (&function.name, owners.get(&function.address)) {
+                (Some(name), _) => (symbol_name(name), true),
+                (None, Some((class, index))) => {
+                    (format!("{}::vfunc_{index}", symbol_name(class)), true)
+                }
+                (None, None) => (format!("sub_{rva:X}"), false),
+            };
+            let this_type = owner.filter(|class| struct_names.contains(class));
+            annotations.functions.insert(
+                rva,
+                ExportedFunction {
+                    rva,
+                    size: function.size,
+                    prototype: prototype(function, &name, this_type),
+                    name,
+                    named,
+                },
+            );
+        }
+
+        for class in &classes {
+            for function in &class.functions {
+                let Some(rva) = options.rva(function.address) else {
+                    continue;
+                };
+                annotations
+                    .functions
+                    .entry(rva)
+                    .or_insert_with(|| ExportedFunction {
+                        rva,
+                        size: None,
+                        name: format!("{}::vfunc_{}", symbol_name(&class.name), function.index),
+                        named: true,
+                        prototype: None,
+                    });
+            }
+            let Some(rva) = options.rva(class.vtable_address) else {
+                continue;
+            };
+            annotations
+                .labels
+                .insert(rva, format!("{}::vftable", symbol_name(&class.name)));
+            let bases: Vec<&str> = class
+                .base_classes
+                .iter()
+                .filter_map(|&base| classes.iter().find(|c| c.vtable_address == base))
+                .map(|c| c.name.as_str())
+                .collect();
+            let mut comment = format!(
+                "vftable of {} ({} functions)",
+                class.name,
+                class.functions.len()
+            );
+            if !bases.is_empty() {
+                let _ = write!(comment, ", bases: {}", bases.join(", "));
+            }
+            annotations.comment(rva, comment);
+        }
+
+        if options.string_comments {
+            for string in source.strings {
+                let text = quote_string(string);
+                for &reference in &string.references {
+                    if let Some(rva) = options.rva(reference) {
+                        annotations.comment(rva, text.clone());
+                    }
+                }
+            }
+        }
+
+        annotations.classes = classes;
+        annotations
+    }
+
+    fn comment(&mut self, rva: usize, text: String) {
+        let comments = self.comments.entry(rva).or_default();
+        if !comments.contains(&text) {
+            comments.push(text);
+        }
+    }
+
+    fn comment_text(&self, rva: usize) -> Option<String> {
+        self.comments.get(&rva).map(|comments| comments.join("; "))
+    }
+
+    /// All labels, functions first.
+    fn all_labels(&self) -> impl Iterator<Item = (usize, &str, bool)> {
+        self.functions
+            .values()
+            .map(|f| (f.rva, f.name.as_str(), true))
+            .chain(
+                self.labels
+                    .iter()
+                    .map(|(&rva, name)| (rva, name.as_str(), false)),
+            )
+    }
+}
+
+/// Quotes a string for a comment, truncated to [`MAX_COMMENT_STRING`] characters.
+fn quote_string(string: &StringReference) -> String {
+    let prefix = if string.encoding == StringEncoding::Unicode {
+        "L"
+    } else {
+        ""
+    };
+    let mut text: String = string
+        .value
+        .chars()
+        .take(MAX_COMMENT_STRING)
+        .flat_map(char::escape_default)
+        .collect();
+    if string.value.chars().count() > MAX_COMMENT_STRING {
+        text.push_str("...");
+    }
+    format!("{prefix}\"{text}\"")
+}
+
+/// Makes a label acceptable to every tool: no whitespace or control characters.
+fn symbol_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_whitespace() || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Makes a valid C identifier from `name`.
+fn c_identifier(name: &str) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    identifier
+}
+
+/// C spelling of a data type.
+fn c_type(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Unknown | DataType::UInt64 => "uint64_t".to_string(),
+        DataType::Void => "void".to_string(),
+        DataType::Bool => "bool".to_string(),
+        DataType::Int8 => "int8_t".to_string(),
+        DataType::UInt8 => "uint8_t".to_string(),
+        DataType::Int16 => "int16_t".to_string(),
+        DataType::UInt16 => "uint16_t".to_string(),
+        DataType::Int32 => "int32_t".to_string(),
+        DataType::UInt32 => "uint32_t".to_string(),
+        DataType::Int64 => "int64_t".to_string(),
+        DataType::Float32 => "float".to_string(),
+        DataType::Float64 => "double".to_string(),
+        DataType::Pointer(inner) | DataType::Array(inner, _) => match **inner {
+            DataType::Unknown => "void *".to_string(),
+            ref inner => format!("{} *", c_type(inner)),
+        },
+        DataType::Struct(name)
+        | DataType::Class(name)
+        | DataType::Union(name)
+        | DataType::Enum(name) => c_identifier(name),
+    }
+}
+
+/// Declares a variable or field named `name` of `data_type`.
+fn c_declaration(data_type: &DataType, name: &str) -> String {
+    match data_type {
+        DataType::Array(inner, count) => format!("{} {name}[{count}]", c_type(inner)),
+        _ => {
+            let ty = c_type(data_type);
+            if ty.ends_with('*') {
+                format!("{ty}{name}")
+            } else {
+                format!("{ty} {name}")
+            }
+        }
+    }
+}
+
+/// Builds a C prototype from the inferred signature, if there is one.
+///
+/// `this_type` names the structure of the owning class for virtual functions.
+fn prototype(function: &DiscoveredFunction, name: &str, this_type: Option<&str>) -> Option<String> {
+    if function.parameters.is_empty() && function.return_type.is_none() {
+        return None;
+    }
+    let convention = match function.calling_convention {
+        CallingConvention::Cdecl => "__cdecl",
+        CallingConvention::Stdcall => "__stdcall",
+        CallingConvention::Thiscall => "__thiscall",
+        CallingConvention::Vectorcall => "__vectorcall",
+        CallingConvention::Fastcall | CallingConvention::Unknown => "__fastcall",
+    };
+    let thiscall = function.calling_convention == CallingConvention::Thiscall;
+    let parameters: Vec<String> = function
+        .parameters
+        .iter()
+        .map(|parameter| {
+            if thiscall && parameter.index == 0 {
+                let ty = this_type.map_or("void".to_string(), c_identifier);
+                return format!("{ty} *this");
+            }
+            let data_type = match &parameter.data_type {
+                DataType::Array(inner, _) => DataType::Pointer(inner.clone()),
+                other => other.clone(),
+            };
+            c_declaration(&data_type, &format!("a{}", parameter.index + 1))
+        })
+        .collect();
+    let return_type = c_type(function.return_type.as_ref().unwrap_or(&DataType::Unknown));
+    let parameters = if parameters.is_empty() {
+        "void".to_string()
+    } else {
+        parameters.join(", ")
+    };
+    Some(format!(
+        "{return_type} {convention} {}({parameters});",
+        c_identifier(name)
+    ))
+}
+
+/// C header declaring the recovered structures with exact offsets.
+///
+/// Structures are packed and gaps are filled with explicit padding, so the
+/// offsets survive whatever alignment rules the importing tool applies.
+pub fn c_header(structures: &[DiscoveredStruct], module_name: &str) -> String {
+    let mut out = format!("// Structures recovered from {module_name}.\n");
+    if !structures.is_empty() {
+        out.push('\n');
+        out.push_str(&c_declarations(structures));
+    }
+    out
+}
+
+fn c_declarations(structures: &[DiscoveredStruct]) -> String {
+    let mut out = String::new();
+    for structure in structures {
+        let name = c_identifier(&structure.name);
+        let _ = writeln!(out, "typedef struct {name} {name};");
+    }
+    out.push_str("\n#pragma pack(push, 1)\n");
+    for structure in structures {
+        let name = c_identifier(&structure.name);
+        out.push('\n');
+        if !structure.base_classes.is_empty() {
+            let _ = writeln!(out, "// Bases: {}", structure.base_classes.join(", "));
+        }
+        let _ = writeln!(out, "struct {name} {{");
+        let mut fields = structure.fields.clone();
+        fields.sort_by_key(|field| field.offset);
+        let mut next = 0;
+        let mut bit_unit = None;
+        for field in fields.iter().filter(|field| field.size > 0) {
+            if let Some(bits) = field.bit_field
+                && bit_unit == Some(field.offset)
+            {
+                let name = c_identifier(&field.name);
+                let _ = writeln!(
+                    out,
+                    "    {} {name} : {};",
+                    c_type(&field.data_type),
+                    bits.width
+                );
+                continue;
+            }
+            if field.offset < next {
+                continue;
+            }
+            if field.offset > next {
+                let _ = writeln!(
+                    out,
+                    "    uint8_t _pad_{next:X}[0x{:X}];",
+                    field.offset - next
+                );
+            }
+            let declaration = match (&field.data_type, field.bit_field) {
+                (DataType::Unknown, _) => {
+                    format!("uint8_t {}[0x{:X}]", c_identifier(&field.name), field.size)
+                }
+                (data_type, Some(bits)) => {
+                    format!(
+                        "{} {} : {}",
+                        c_type(data_type),
+                        c_identifier(&field.name),
+                        bits.width
+                    )
+                }
+                (data_type, None) => c_declaration(data_type, &c_identifier(&field.name)),
+            };
+            bit_unit = field.bit_field.map(|_| field.offset);
+            let _ = writeln!(out, "    {declaration}; // 0x{:X}", field.offset);
+            next = field.offset + field.size;
+        }
+        if structure.size > next {
+            let _ = writeln!(
+                out,
+                "    uint8_t _pad_{next:X}[0x{:X}];",
+                structure.size - next
+            );
+        }
+        out.push_str("};\n");
+    }
+    out.push_str("\n#pragma pack(pop)\n");
+    out
+}
+
+/// Quotes `text` as a JSON string, which is also a valid Python literal.
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Python tables shared by the IDA and Binary Ninja scripts.
+fn python_tables(annotations: &Annotations, source: &ExportSource) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "HEADER = {}\n",
+        json_string(&c_declarations(source.structures))
+    );
+
+    out.push_str("FUNCTIONS = [\n");
+    for function in annotations.functions.values() {
+        let _ = writeln!(
+            out,
+            "    (0x{:X}, 0x{:X}),",
+            function.rva,
+            function.size.unwrap_or(0)
+        );
+    }
+    out.push_str("]\n\nLABELS = [\n");
+    for (rva, name, is_function) in annotations.all_labels() {
+        let _ = writeln!(
+            out,
+            "    (0x{rva:X}, {}, {}),",
+            json_string(name),
+            if is_function { "True" } else { "False" }
+        );
+    }
+    out.push_str("]\n\nCOMMENTS = [\n");
+    for &rva in annotations.comments.keys() {
+        let text = annotations.comment_text(rva).unwrap_or_default();
+        let _ = writeln!(out, "    (0x{rva:X}, {}),", json_string(&text));
+    }
+    out.push_str("]\n\nPROTOTYPES = [\n");
+    for function in annotations.functions.values() {
+        if let Some(prototype) = &function.prototype {
+            let _ = writeln!(
+                out,
+                "    (0x{:X}, {}),",
+                function.rva,
+                json_string(prototype)
+            );
+        }
+    }
+    out.push_str("]\n");
+    out
+}
+
+fn ida_script(annotations: &Annotations, source: &ExportSource) -> String {
+    let mut out = String::from(
+        "# Analysis export for IDA. Run with File > Script file.\n\
+         import ida_funcs\nimport idaapi\nimport idc\n\n",
+    );
+    out.push_str(&python_tables(annotations, source));
+    out.push_str(
+        "\nbase = idaapi.get_imagebase()\n\
+         if HEADER:\n\
+         \x20   idc.parse_decls(HEADER, idc.PT_SILENT)\n\
+         for rva, size in FUNCTIONS:\n\
+         \x20   ida_funcs.add_func(base + rva, base + rva + size if size else idc.BADADDR)\n\
+         for rva, name, _ in LABELS:\n\
+         \x20   idc.set_name(base + rva, name, idc.SN_NOWARN | idc.SN_NOCHECK | idc.SN_FORCE)\n\
+         for rva, text in COMMENTS:\n\
+         \x20   idc.set_cmt(base + rva, text, 0)\n\
+         for rva, prototype in PROTOTYPES:\n\
+         \x20   idc.SetType(base + rva, prototype)\n",
+    );
+    out
+}
+
+fn binja_script(annotations: &Annotations, source: &ExportSource) -> String {
+    let mut out = String::from(
+        "# Analysis export for Binary Ninja. Run from the Python console or as a snippet.\n\
+         from binaryninja import Symbol, SymbolType\n\n",
+    );
+    out.push_str(&python_tables(annotations, source));
+    out.push_str(
+        "\nbase = bv.start\n\
+         if HEADER:\n\
+         \x20   for name, ty in bv.parse_types_from_string(HEADER).types.items():\n\
+         \x20       bv.define_user_type(name, ty)\n\
+         for rva, size in FUNCTIONS:\n\
+         \x20   bv.add_user_function(bv.platform, base + rva)\n\
+         for rva, name, is_function in LABELS:\n\
+         \x20   kind = SymbolType.FunctionSymbol if is_function else SymbolType.DataSymbol\n\
+         \x20   bv.define_user_symbol(Symbol(kind, base + rva, name))\n\
+         for rva, text in COMMENTS:\n\
+         \x20   bv.set_comment_at(base + rva, text)\n\
+         for rva, prototype in PROTOTYPES:\n\
+         \x20   function = bv.get_function_at(base + rva)\n\
+         \x20   if function is not None:\n\
+         \x20       function.type = bv.parse_type_string(prototype.rstrip(\";\"))[0]\n",
+    );
+    out
+}
+
+/// Symbol list in the `name address [f|l]` format read by `ImportSymbolsScript.py`.
+fn ghidra_symbols(annotations: &Annotations, options: &ExportOptions) -> String {
+    let mut out = String::new();
+    for (rva, name, is_function) in annotations.all_labels() {
+        let kind = if is_function { "f" } else { "l" };
+        let _ = writeln!(out, "{name} 0x{:X} {kind}", options.database_base + rva);
+    }
+    out
+}
+
+fn x64dbg_database(annotations: &Annotations, options: &ExportOptions) -> String {
+    let module = json_string(&options.module_name);
+    let entry = |rva: usize, text: Option<&str>| match text {
+        Some(text) => format!(
+            "    {{\"module\": {module}, \"address\": \"0x{rva:X}\", \"manual\": true, \"text\": {}}}",
+            json_string(text)
+        ),
+        None => {
+            format!("    {{\"module\": {module}, \"address\": \"0x{rva:X}\", \"manual\": true}}")
+        }
+    };
+
+    let labels: Vec<String> = annotations
+        .all_labels()
+        .map(|(rva, name, _)| entry(rva, Some(name)))
+        .collect();
+    let comments: Vec<String> = annotations
+        .comments
+        .keys()
+        .filter_map(|&rva| {
+            let mut text = annotations.comment_text(rva)?;
+            if let Some(prototype) = annotations
+                .functions
+                .get(&rva)
+                .and_then(|f| f.prototype.as_ref())
+            {
+                text = format!("{prototype} {text}");
+            }
+            Some(entry(rva, Some(&text)))
+        })
+        .chain(
+            annotations
+                .functions
+                .values()
+                .filter(|f| !annotations.comments.contains_key(&f.rva))
+                .filter_map(|f| Some(entry(f.rva, Some(f.prototype.as_ref()?)))),
+        )
+        .collect();
+    let bookmarks: Vec<String> = annotations
+        .functions
+        .values()
+        .filter(|f| f.named)
+        .map(|f| entry(f.rva, None))
+        .collect();
+    let functions: Vec<String> = annotations
+        .functions
+        .values()
+        .filter_map(|f| {
+            let size = f.size.filter(|&size| size > 0)?;
+            Some(format!(
+                "    {{\"module\": {module}, \"start\": \"0x{:X}\", \"end\": \"0x{:X}\", \"manual\": true, \"icount\": 0}}",
+                f.rva,
+                f.rva + size - 1
+            ))
+        })
+        .collect();
+
+    let section = |name: &str, entries: &[String]| {
+        if entries.is_empty() {
+            format!("  \"{name}\": []")
+        } else {
+            format!("  \"{name}\": [\n{}\n  ]", entries.join(",\n"))
+        }
+    };
+    format!(
+        "{{\n{},\n{},\n{},\n{}\n}}\n",
+        section("labels", &labels),
+        section("comments", &comments),
+        section("bookmarks", &bookmarks),
+        section("functions", &functions)
+    )
+}
+
+fn json_data_type(data_type: Option<&DataType>) -> String {
+    match data_type {
+        Some(data_type) => json_string(&c_type(data_type)),
+        None => "null".to_string(),
+    }
+}
+
+fn json_addresses(addresses: &[usize]) -> String {
+    let items: Vec<String> = addresses.iter().map(|a| format!("\"0x{a:X}\"")).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn json_dump(annotations: &Annotations, source: &ExportSource, options: &ExportOptions) -> String {
+    let functions: Vec<String> = source
+        .functions
+        .iter()
+        .filter_map(|function| {
+            let rva = options.rva(function.address)?;
+            let exported = annotations.functions.get(&rva)?;
+            let parameters: Vec<String> = function
+                .parameters
+                .iter()
+                .map(|p| {
+                    let location = match &p.location {
+                        ParameterLocation::Register(register) => json_string(register),
+                        ParameterLocation::Stack(offset) => json_string(&format!("[rsp+0x{offset:X}]")),
+                        ParameterLocation::Unknown => "null".to_string(),
+                    };
+                    format!(
+                        "{{\"index\": {}, \"type\": {}, \"location\": {location}, \"confidence\": {}}}",
+                        p.index,
+                        json_data_type(Some(&p.data_type)),
+                        p.confidence
+                    )
+                })
+                .collect();
+            Some(format!(
+                "    {{\"address\": \"0x{:X}\", \"rva\": \"0x{rva:X}\", \"name\": {}, \"size\": {}, \
+                 \"calling_convention\": \"{:?}\", \"parameters\": [{}], \"return_type\": {}, \
+                 \"confidence\": {}, \"prototype\": {}, \"xrefs_to\": {}, \"xrefs_from\": {}}}",
+                function.address,
+                json_string(&exported.name),
+                function.size.map_or("null".to_string(), |size| size.to_string()),
+                function.calling_convention,
+                parameters.join(", "),
+                json_data_type(function.return_type.as_ref()),
+                function.confidence,
+                exported.prototype.as_deref().map_or("null".to_string(), json_string),
+                json_addresses(&function.xrefs_to),
+                json_addresses(&function.xrefs_from)
+            ))
+        })
+        .collect();
+
+    let structures: Vec<String> = source
+        .structures
+        .iter()
+        .map(|structure| {
+            let fields: Vec<String> = structure
+                .fields
+                .iter()
+                .map(|field| {
+                    format!(
+                        "{{\"name\": {}, \"offset\": {}, \"size\": {}, \"type\": {}}}",
+                        json_string(&field.name),
+                        field.offset,
+                        field.size,
+                        json_data_type(Some(&field.data_type))
+                    )
+                })
+                .collect();
+            let bases: Vec<String> = structure
+                .base_classes
+                .iter()
+                .map(|b| json_string(b))
+                .collect();
+            format!(
+                "    {{\"name\": {}, \"size\": {}, \"vtable_offset\": {}, \"base_classes\": [{}], \
+                 \"confidence\": {}, \"fields\": [{}]}}",
+                json_string(&structure.name),
+                structure.size,
+                structure
+                    .vtable_offset
+                    .map_or("null".to_string(), |o| o.to_string()),
+                bases.join(", "),
+                structure.confidence,
+                fields.join(", ")
+            )
+        })
+        .collect();
+
+    let classes: Vec<String> = annotations
+        .classes
+        .iter()
+        .map(|class| {
+            let functions: Vec<usize> = class.functions.iter().map(|f| f.address).collect();
+            format!(
+                "    {{\"name\": {}, \"vtable\": \"0x{:X}\", \"functions\": {}, \"base_classes\": {}, \"derived_classes\": {}}}",
+                json_string(&class.name),
+                class.vtable_address,
+                json_addresses(&functions),
+                json_addresses(&class.base_classes),
+                json_addresses(&class.derived_classes)
+            )
+        })
+        .collect();
+
+    let strings: Vec<String> = source
+        .strings
+        .iter()
+        .map(|string| {
+            format!(
+                "    {{\"address\": \"0x{:X}\", \"encoding\": \"{:?}\", \"value\": {}, \"references\": {}}}",
+                string.address,
+                string.encoding,
+                json_string(&string.value),
+                json_addresses(&string.references)
+            )
+        })
+        .collect();
+
+    let section = |name: &str, entries: &[String]| {
+        if entries.is_empty() {
+            format!("  \"{name}\": []")
+        } else {
+            format!("  \"{name}\": [\n{}\n  ]", entries.join(",\n"))
+        }
+    };
+    format!(
+        "{{\n  \"module\": {},\n  \"base\": \"0x{:X}\",\n{},\n{},\n{},\n{}\n}}\n",
+        json_string(&options.module_name),
+        options.module_base,
+        section("functions", &functions),
+        section("structures", &structures),
+        section("classes", &classes),
+        section("strings", &strings)
+    )
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn csv_tables(
+    annotations: &Annotations,
+    source: &ExportSource,
+    options: &ExportOptions,
+) -> Vec<(&'static str, String)> {
+    let mut functions = String::from(
+        "address,rva,name,size,calling_convention,parameters,return_type,confidence,prototype\n",
+    );
+    for function in source.functions {
+        let Some(exported) = options
+            .rva(function.address)
+            .and_then(|rva| annotations.functions.get(&rva))
+        else {
+            continue;
+        };
+        let _ = writeln!(
+            functions,
+            "0x{:X},0x{:X},{},{},{:?},{},{},{},{}",
+            function.address,
+            exported.rva,
+            csv_field(&exported.name),
+            function.size.map_or(String::new(), |size| size.to_string()),
+            function.calling_convention,
+            function.parameters.len(),
+            function.return_type.as_ref().map_or(String::new(), c_type),
+            function.confidence,
+            csv_field(exported.prototype.as_deref().unwrap_or(""))
+        );
+    }
+
+    let mut fields = String::from("structure,offset,name,size,type\n");
+    for structure in source.structures {
+        for field in &structure.fields {
+            let _ = writeln!(
+                fields,
+                "{},0x{:X},{},{},{}",
+                csv_field(&structure.name),
+                field.offset,
+                csv_field(&field.name),
+                field.size,
+                csv_field(&c_type(&field.data_type))
+            );
+        }
+    }
+
+    let mut classes = String::from("name,vtable,functions,base_classes,derived_classes\n");
+    for class in &annotations.classes {
+        let _ = writeln!(
+            classes,
+            "{},0x{:X},{},{},{}",
+            csv_field(&class.name),
+            class.vtable_address,
+            class.functions.len(),
+            class.base_classes.len(),
+            class.derived_classes.len()
+        );
+    }
+
+    let mut strings = String::from("address,encoding,value,references\n");
+    for string in source.strings {
+        let references: Vec<String> = string
+            .references
+            .iter()
+            .map(|r| format!("0x{r:X}"))
+            .collect();
+        let _ = writeln!(
+            strings,
+            "0x{:X},{:?},{},{}",
+            string.address,
+            string.encoding,
+            csv_field(&string.value),
+            references.join(" ")
+        );
+    }
+
+    vec![
+        ("_functions.csv", functions),
+        ("_fields.csv", fields),
+        ("_classes.csv", classes),
+        ("_strings.csv", strings),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{Parameter, StructField};
+    use crate::vtable::VirtualFunction;
+
+    const BASE: usize = 0x7FF6_0000_0000;
+
+    fn function(rva: usize, name: Option<&str>) -> DiscoveredFunction {
+        DiscoveredFunction {
+            address: BASE + rva,
+            size: Some(0x40),
+            name: name.map(str::to_string),
+            calling_convention: CallingConvention::Unknown,
+            parameters: Vec::new(),
+            return_type: None,
+            confidence: 1.0,
+            references: Vec::new(),
+            xrefs_to: Vec::new(),
+            xrefs_from: Vec::new(),
+            features: None,
+        }
+    }
+
+    fn sample() -> (
+        Vec<DiscoveredFunction>,
+        Vec<DiscoveredStruct>,
+        ClassHierarchy,
+        Vec<StringReference>,
+    ) {
+        let mut tick = function(0x1000, None);
+        tick.calling_convention = CallingConvention::Thiscall;
+        tick.return_type = Some(DataType::Bool);
+        tick.parameters = vec![
+            Parameter {
+                index: 0,
+                data_type: DataType::Pointer(Box::new(DataType::Unknown)),
+                location: ParameterLocation::Register("RCX".to_string()),
+                confidence: 0.9,
+            },
+            Parameter {
+                index: 1,
+                data_type: DataType::Float32,
+                location: ParameterLocation::Register("XMM1".to_string()),
+                confidence: 0.9,
+            },
+        ];
+        let functions = vec![
+            tick,
+            function(0x2000, Some("Log Message")),
+            function(0x1_0000_0000, None),
+        ];
+
+        let structures = vec![DiscoveredStruct {
+            name: "Actor".to_string(),
+            size: 0x20,
+            fields: vec![
+                StructField {
+                    name: "vtable".to_string(),
+                    offset: 0,
+                    data_type: DataType::Pointer(Box::new(DataType::Void)),
+                    size: 8,
+                    bit_field: None,
+                },
+                StructField {
+                    name: "field_10".to_string(),
+                    offset: 0x10,
+                    data_type: DataType::Float32,
+                    size: 4,
+                    bit_field: None,
+                },
+            ],
+            vtable_offset: Some(0),
+            base_classes: Vec::new(),
+            confidence: 0.8,
+        }];
+
+        let mut classes = ClassHierarchy::new();
+        classes.add_class(ClassInfo {
+            vtable_address: BASE + 0x5000,
+            name: "Actor".to_string(),
+            functions: vec![
+                VirtualFunction {
+                    address: BASE + 0x3000,
+                    index: 0,
+                },
+                VirtualFunction {
+                    address: BASE + 0x1000,
+                    index: 1,
+                },
+            ],
+            base_classes: Vec::new(),
+            derived_classes: Vec::new(),
+        });
+
+        let strings = vec![StringReference {
+            address: BASE + 0x6000,
+            value: "Tick \"%s\"".to_string(),
+            encoding: StringEncoding::Ascii,
+            references: vec![BASE + 0x1010],
+        }];
+        (functions, structures, classes, strings)
+    }
+
+    #[test]
+    fn test_annotations_and_scripts() {
+        let (functions, structures, classes, strings) = sample();
+        let source = ExportSource {
+            functions: &functions,
+            structures: &structures,
+            classes: Some(&classes),
+            strings: &strings,
+        };
+        let options = ExportOptions::new("Game.exe", BASE, 0x10000);
+
+        let files = export(&source, ExportFormat::IdaPython, &options);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "Game_ida.py");
+        let script = &files[0].contents;
+        assert!(script.contains("    (0x1000, \"Actor::vfunc_1\", True),"));
+        assert!(script.contains("    (0x2000, \"Log_Message\", True),"));
+        assert!(script.contains("    (0x3000, \"Actor::vfunc_0\", True),"));
+        assert!(script.contains("    (0x5000, \"Actor::vftable\", False),"));
+        assert!(script.contains("    (0x1010, \"\\\"Tick \\\\\\\"%s\\\\\\\"\\\"\"),"));
+        assert!(
+            script.contains("(0x1000, \"bool __thiscall Actor__vfunc_1(Actor *this, float a2);\")")
+        );
+        assert!(!script.contains("0x100000000"));
+
+        let ghidra = export(&source, ExportFormat::Ghidra, &options);
+        assert_eq!(
+            ghidra[0].contents.lines().next(),
+            Some("Actor::vfunc_1 0x140001000 f")
+        );
+        let header = &ghidra[1].contents;
+        assert!(header.contains("typedef struct Actor Actor;"));
+        assert!(header.contains(
+            "struct Actor {\n    void *vtable; // 0x0\n    uint8_t _pad_8[0x8];\n    float field_10; // 0x10\n    uint8_t _pad_14[0xC];\n};"
+        ));
+    }
+
+    #[test]
+    fn test_x64dbg_database() {
+        let (functions, structures, classes, strings) = sample();
+        let source = ExportSource {
+            functions: &functions,
+            structures: &structures,
+            classes: Some(&classes),
+            strings: &strings,
+        };
+        let options = ExportOptions::new("Game.exe", BASE, 0x10000);
+        let database = &export(&source, ExportFormat::X64dbg, &options)[0];
+        assert_eq!(database.name, "Game.dd64");
+        let text = &database.contents;
+        assert!(text.contains(
+            "{\"module\": \"Game.exe\", \"address\": \"0x2000\", \"manual\": true, \"text\": \"Log_Message\"}"
+        ));
+        assert!(text.contains("\"address\": \"0x5000\", \"manual\": true, \"text\": \"vftable of Actor (2 functions)\"}"));
+        assert!(text.contains("\"start\": \"0x1000\", \"end\": \"0x103F\""));
+        assert!(
+            text.contains("\"text\": \"bool __thiscall Actor__vfunc_1(Actor *this, float a2);\"}")
+        );
+        let bookmarks = text
+            .split("\"bookmarks\"")
+            .nth(1)
+            .unwrap()
+            .split("\"functions\"")
+            .next()
+            .unwrap();
+        assert_eq!(bookmarks.matches("\"address\"").count(), 3);
+    }
+
+    #[test]
+    fn test_json_and_csv_escaping() {
+        let (functions, structures, classes, strings) = sample();
+        let source = ExportSource {
+            functions: &functions,
+            structures: &structures,
+            classes: Some(&classes),
+            strings: &strings,
+        };
+        let options = ExportOptions::new("Game.exe", BASE, 0x10000);
+        let json = &export(&source, ExportFormat::Json, &options)[0].contents;
+        assert!(json.contains("\"value\": \"Tick \\\"%s\\\"\""));
+        assert!(json.contains("\"rva\": \"0x2000\", \"name\": \"Log_Message\""));
+        assert_eq!(json.matches("\"rva\"").count(), 2);
+        assert_eq!(json_string("a\u{1}\n"), "\"a\\u0001\\n\"");
+
+        let csv = export(&source, ExportFormat::Csv, &options);
+        let names: Vec<&str> = csv.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "Game_functions.csv",
+                "Game_fields.csv",
+                "Game_classes.csv",
+                "Game_strings.csv"
+            ]
+        );
+        assert!(
+            csv[0]
+                .contents
+                .contains(",\"bool __thiscall Actor__vfunc_1(Actor *this, float a2);\"\n")
+        );
+        assert_eq!(
+            csv[3].contents.lines().nth(1),
+            Some(
+                format!(
+                    "0x{:X},Ascii,\"Tick \"\"%s\"\"\",0x{:X}",
+                    BASE + 0x6000,
+                    BASE + 0x1010
+                )
+                .as_str()
+            )
+        );
+    }
+}
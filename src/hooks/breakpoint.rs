@@ -0,0 +1,750 @@
+//! Hardware breakpoint hooks.
+//!
+//! [`HookContext::install_breakpoint`](super::HookContext::install_breakpoint)
+//! hooks an address without writing to it: the address goes into one of the
+//! debug registers `DR0`-`DR3` of every thread, and a vectored exception
+//! handler turns the resulting `EXCEPTION_SINGLE_STEP` into a call to the
+//! handler with a [`BreakpointHit`]. This keeps `.text` untouched for code
+//! that checksums it, and can also watch data: [`Watch::Write`] and
+//! [`Watch::ReadWrite`] fire on accesses, which
+//! [`HookContext::find_accesses`](super::HookContext::find_accesses) uses to
+//! find the instructions touching an address.
+//!
+//! There are only four debug registers, so at most [`SLOTS`] breakpoints are
+//! armed at once. Threads started after a breakpoint was armed do not have it;
+//! re-enabling its guard arms it on them too.
+
+use core::cell::Cell;
+use core::fmt;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use ilhook::x64::Registers;
+
+use super::threads::ThreadControl;
+use crate::errors::{Error, Result};
+
+/// Number of debug address registers.
+pub const SLOTS: usize = 4;
+
+/// The DR7 bits owned by breakpoint hooks: the local and global enables and
+/// the condition and length fields of `DR0`-`DR3`.
+pub(crate) const DR7_MASK: u64 = 0xFFFF_00FF;
+
+/// The resume flag, which suppresses instruction breakpoints for one instruction.
+const RFLAGS_RF: u64 = 1 << 16;
+
+/// What a breakpoint fires on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    /// Before the instruction at the address executes.
+    Execute,
+    /// After an instruction writes to the `size` bytes at the address.
+    Write { size: usize },
+    /// After an instruction reads or writes the `size` bytes at the address.
+    ReadWrite { size: usize },
+}
+
+impl Watch {
+    /// Checks that the debug registers can watch `address` this way: data
+    /// watches cover 1, 2, 4 or 8 bytes aligned to their size.
+    pub fn validate(self, address: usize) -> Result<()> {
+        let size = match self {
+            Watch::Execute => return Ok(()),
+            Watch::Write { size } | Watch::ReadWrite { size } => size,
+        };
+        if !matches!(size, 1 | 2 | 4 | 8) {
+            return Err(Error::InvalidBreakpoint(format!(
+                "cannot watch {size} bytes, only 1, 2, 4 or 8"
+            )));
+        }
+        if !address.is_multiple_of(size) {
+            return Err(Error::InvalidBreakpoint(format!(
+                "0x{address:X} is not aligned to {size} bytes"
+            )));
+        }
+        Ok(())
+    }
+
+    /// The DR7 bits arming `slot` for this watch.
+    fn dr7_bits(self, slot: usize) -> u64 {
+        let (condition, size) = match self {
+            Watch::Execute => (0b00, 1),
+            Watch::Write { size } => (0b01, size),
+            Watch::ReadWrite { size } => (0b11, size),
+        };
+        let length = match size {
+            2 => 0b01,
+            4 => 0b11,
+            8 => 0b10,
+            _ => 0b00,
+        };
+        let enable = 1 << (slot * 2);
+        enable | (condition | length << 2) << (16 + slot * 4)
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watch::Execute => f.write_str("execute"),
+            Watch::Write { size } => write!(f, "write {size}"),
+            Watch::ReadWrite { size } => write!(f, "read/write {size}"),
+        }
+    }
+}
+
+/// Returns the DR7 value arming `watches`, indexed by debug register.
+pub(crate) fn dr7(watches: &[Option<Watch>; SLOTS]) -> u64 {
+    watches
+        .iter()
+        .enumerate()
+        .filter_map(|(slot, watch)| watch.map(|watch| watch.dr7_bits(slot)))
+        .fold(0, |dr7, bits| dr7 | bits)
+}
+
+/// Register state at a hardware breakpoint.
+pub struct BreakpointHit<'a> {
+    registers: &'a mut Registers,
+    address: usize,
+    watch: Watch,
+    instruction: usize,
+    resume: usize,
+}
+
+impl BreakpointHit<'_> {
+    /// The watched address.
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    pub fn watch(&self) -> Watch {
+        self.watch
+    }
+
+    /// Where the thread stopped. This is the watched address for
+    /// [`Watch::Execute`]; data breakpoints trap after the access, so for them
+    /// it is the instruction following the one that accessed the address.
+    pub fn instruction(&self) -> usize {
+        self.instruction
+    }
+
+    /// The registers of the interrupted thread. Changes are written back
+    /// when the handler returns, except to `rsp`.
+    pub fn registers(&self) -> &Registers {
+        self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        self.registers
+    }
+
+    /// Continues at `address` instead of [`instruction`](Self::instruction).
+    pub fn redirect(&mut self, address: usize) {
+        self.resume = address;
+    }
+
+    /// Where the thread continues.
+    pub fn resume_address(&self) -> usize {
+        self.resume
+    }
+}
+
+type Handler = dyn Fn(&mut BreakpointHit) + Send + Sync;
+
+pub(crate) struct Breakpoint {
+    address: usize,
+    watch: Watch,
+    handler: Box<Handler>,
+    calls: Arc<AtomicU64>,
+}
+
+impl Breakpoint {
+    pub(crate) fn new<H>(address: usize, watch: Watch, handler: H, calls: Arc<AtomicU64>) -> Self
+    where
+        H: Fn(&mut BreakpointHit) + Send + Sync + 'static,
+    {
+        Self {
+            address,
+            watch,
+            handler: Box::new(handler),
+            calls,
+        }
+    }
+}
+
+/// Breakpoints by debug register.
+static ARMED: RwLock<[Option<Arc<Breakpoint>>; SLOTS]> = RwLock::new([None, None, None, None]);
+
+thread_local! {
+    /// Set while a handler runs, so breakpoints it hits itself do not recurse.
+    static HANDLING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// An armed breakpoint, disarmed on drop.
+pub(crate) struct Armed {
+    slot: usize,
+}
+
+/// Arms `breakpoint` in a free debug register of every thread.
+pub(crate) fn arm(breakpoint: Arc<Breakpoint>) -> Result<Armed> {
+    breakpoint.watch.validate(breakpoint.address)?;
+    let mut armed = ARMED.write().unwrap();
+    let slot = armed
+        .iter()
+        .position(Option::is_none)
+        .ok_or(Error::NoFreeDebugRegister(breakpoint.address))?;
+    armed[slot] = Some(breakpoint);
+    if let Err(e) = system::add_handler().and_then(|()| apply(&armed)) {
+        armed[slot] = None;
+        let _ = apply(&armed);
+        return Err(e);
+    }
+    Ok(Armed { slot })
+}
+
+impl Drop for Armed {
+    fn drop(&mut self) {
+        let mut armed = ARMED.write().unwrap();
+        armed[self.slot] = None;
+        if let Err(e) = apply(&armed) {
+            log::error!("failed to clear debug register {}: {e}", self.slot);
+        }
+        if armed.iter().all(Option::is_none) {
+            system::remove_handler();
+        }
+    }
+}
+
+/// Writes the armed breakpoints to the debug registers of every thread.
+fn apply(armed: &[Option<Arc<Breakpoint>>; SLOTS]) -> Result<()> {
+    let addresses = armed
+        .each_ref()
+        .map(|breakpoint| breakpoint.as_ref().map_or(0, |b| b.address as u64));
+    let watches = armed
+        .each_ref()
+        .map(|breakpoint| breakpoint.as_ref().map(|b| b.watch));
+    let updated = system::set_debug_registers(addresses, dr7(&watches))?;
+    log::debug!("debug registers updated on {updated} threads");
+    Ok(())
+}
+
+/// Debug register access on top of [`ThreadControl`].
+pub(crate) trait DebugRegisters: ThreadControl {
+    /// Points `DR0`-`DR3` of a suspended `thread` at `addresses` and replaces
+    /// the breakpoint bits of its DR7 with `dr7`.
+    fn set_debug_registers(
+        &mut self,
+        thread: &Self::Thread,
+        addresses: [u64; SLOTS],
+        dr7: u64,
+    ) -> bool;
+
+    /// Does the same for the calling thread, which cannot suspend itself.
+    fn set_own_debug_registers(&mut self, addresses: [u64; SLOTS], dr7: u64) -> bool;
+}
+
+/// Sets the debug registers of every thread from the calling one, suspending
+/// the others while they are updated. A helper thread would deadlock when
+/// this runs under the loader lock. Returns the number of threads updated.
+fn update_threads<T: DebugRegisters>(
+    control: &mut T,
+    addresses: [u64; SLOTS],
+    dr7: u64,
+) -> Result<usize> {
+    let mut updated = 0;
+    for thread in control.threads()? {
+        if !control.suspend(&thread) {
+            continue;
+        }
+        if control.set_debug_registers(&thread, addresses, dr7) {
+            updated += 1;
+        }
+        control.resume(&thread);
+    }
+    if control.set_own_debug_registers(addresses, dr7) {
+        updated += 1;
+    }
+    Ok(updated)
+}
+
+/// Handles a debug exception raised with status `dr6` on a thread whose DR7
+/// is `dr7`. Returns false if none of the breakpoints caused it.
+pub(crate) fn dispatch(dr6: u64, dr7: u64, registers: &mut Registers, rip: &mut u64) -> bool {
+    let mut handled = false;
+    for slot in 0..SLOTS {
+        let hit = dr6 & (1 << slot) != 0;
+        let enabled = dr7 & (1 << (slot * 2)) != 0;
+        if !hit || !enabled {
+            continue;
+        }
+        handled = true;
+        // Execute breakpoints fire before the instruction; without RF the
+        // thread would stop at it again.
+        if (dr7 >> (16 + slot * 4)) & 0b11 == 0 {
+            registers.rflags |= RFLAGS_RF;
+        }
+        // The slot may have been disarmed while this thread still had it.
+        let Some(breakpoint) = ARMED.read().unwrap()[slot].clone() else {
+            continue;
+        };
+        breakpoint.calls.fetch_add(1, Ordering::Relaxed);
+        if HANDLING.with(|handling| handling.replace(true)) {
+            continue;
+        }
+        let mut hit = BreakpointHit {
+            registers: &mut *registers,
+            address: breakpoint.address,
+            watch: breakpoint.watch,
+            instruction: *rip as usize,
+            resume: *rip as usize,
+        };
+        (breakpoint.handler)(&mut hit);
+        *rip = hit.resume as u64;
+        HANDLING.with(|handling| handling.set(false));
+    }
+    handled
+}
+
+/// One instruction that accessed a watched address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Access {
+    /// See [`BreakpointHit::instruction`].
+    pub instruction: usize,
+    pub count: u64,
+}
+
+/// Counts the instructions that hit a data breakpoint, as collected by
+/// [`HookContext::find_accesses`](super::HookContext::find_accesses).
+#[derive(Debug, Default)]
+pub struct AccessLog {
+    hits: Mutex<BTreeMap<usize, u64>>,
+}
+
+impl AccessLog {
+    pub fn record(&self, hit: &BreakpointHit) {
+        *self
+            .hits
+            .lock()
+            .unwrap()
+            .entry(hit.instruction())
+            .or_default() += 1;
+    }
+
+    /// The recorded instructions in address order.
+    pub fn accesses(&self) -> Vec<Access> {
+        self.hits
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&instruction, &count)| Access { instruction, count })
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.hits.lock().unwrap().clear();
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod system {
+    use core::cell::Cell;
+    use core::ffi::c_void;
+    use std::sync::Mutex;
+
+    use ilhook::x64::Registers;
+    use windows::Win32::Foundation::EXCEPTION_SINGLE_STEP;
+    use windows::Win32::System::Diagnostics::Debug::{
+        AddVectoredExceptionHandler, CONTEXT, CONTEXT_DEBUG_REGISTERS_AMD64,
+        EXCEPTION_CONTINUE_EXECUTION, EXCEPTION_CONTINUE_SEARCH, EXCEPTION_POINTERS, M128A,
+        RaiseException, RemoveVectoredExceptionHandler,
+    };
+
+    use super::{DR7_MASK, DebugRegisters, SLOTS};
+    use crate::errors::{Error, Result};
+    use crate::hooks::threads::{ProcessThreads, ThreadHandle};
+
+    /// Exception raised by a thread to have [`on_exception`] set its own debug
+    /// registers.
+    const SET_DEBUG_REGISTERS: u32 = 0xE0DB_0001;
+
+    /// The registered exception handler, stored as an address.
+    static HANDLER: Mutex<Option<usize>> = Mutex::new(None);
+
+    thread_local! {
+        /// Debug registers for [`on_exception`] to give the raising thread.
+        static OWN_REGISTERS: Cell<Option<([u64; SLOTS], u64)>> = const { Cell::new(None) };
+    }
+
+    pub(super) fn add_handler() -> Result<()> {
+        let mut registered = HANDLER.lock().unwrap();
+        if registered.is_none() {
+            let handle = unsafe { AddVectoredExceptionHandler(1, Some(on_exception)) };
+            if handle.is_null() {
+                return Err(Error::InvalidBreakpoint(
+                    "AddVectoredExceptionHandler failed".into(),
+                ));
+            }
+            *registered = Some(handle as usize);
+        }
+        Ok(())
+    }
+
+    pub(super) fn remove_handler() {
+        if let Some(handle) = HANDLER.lock().unwrap().take() {
+            unsafe { RemoveVectoredExceptionHandler(handle as *const c_void) };
+        }
+    }
+
+    /// Sets the debug registers of every thread, including the calling one.
+    /// Returns the number of threads updated.
+    pub(super) fn set_debug_registers(addresses: [u64; SLOTS], dr7: u64) -> Result<usize> {
+        super::update_threads(&mut ProcessThreads, addresses, dr7)
+    }
+
+    impl DebugRegisters for ProcessThreads {
+        fn set_debug_registers(
+            &mut self,
+            thread: &ThreadHandle,
+            addresses: [u64; SLOTS],
+            dr7: u64,
+        ) -> bool {
+            ProcessThreads::set_debug_registers(self, thread, addresses, dr7)
+        }
+
+        /// `SetThreadContext` cannot change the debug registers of the calling
+        /// thread, so it raises an exception and the handler changes them in
+        /// the context it continues with.
+        fn set_own_debug_registers(&mut self, addresses: [u64; SLOTS], dr7: u64) -> bool {
+            if HANDLER.lock().unwrap().is_none() {
+                return false;
+            }
+            OWN_REGISTERS.set(Some((addresses, dr7)));
+            unsafe { RaiseException(SET_DEBUG_REGISTERS, 0, None) };
+            OWN_REGISTERS.take().is_none()
+        }
+    }
+
+    fn xmm(value: &M128A) -> u128 {
+        value.Low as u128 | (value.High as u64 as u128) << 64
+    }
+
+    fn set_xmm(slot: &mut M128A, value: u128) {
+        slot.Low = value as u64;
+        slot.High = (value >> 64) as i64;
+    }
+
+    unsafe extern "system" fn on_exception(info: *mut EXCEPTION_POINTERS) -> i32 {
+        let info = unsafe { &*info };
+        let code = unsafe { (*info.ExceptionRecord).ExceptionCode };
+        let context: &mut CONTEXT = unsafe { &mut *info.ContextRecord };
+        if code.0 as u32 == SET_DEBUG_REGISTERS {
+            let Some((addresses, dr7)) = OWN_REGISTERS.take() else {
+                return EXCEPTION_CONTINUE_SEARCH;
+            };
+            let [dr0, dr1, dr2, dr3] = addresses;
+            context.Dr0 = dr0;
+            context.Dr1 = dr1;
+            context.Dr2 = dr2;
+            context.Dr3 = dr3;
+            context.Dr7 = (context.Dr7 & !DR7_MASK) | dr7;
+            context.ContextFlags |= CONTEXT_DEBUG_REGISTERS_AMD64;
+            return EXCEPTION_CONTINUE_EXECUTION;
+        }
+        if code != EXCEPTION_SINGLE_STEP {
+            return EXCEPTION_CONTINUE_SEARCH;
+        }
+        let vectors = unsafe { &mut context.Anonymous.Anonymous };
+        let mut registers = Registers {
+            xmm0: xmm(&vectors.Xmm0),
+            xmm1: xmm(&vectors.Xmm1),
+            xmm2: xmm(&vectors.Xmm2),
+            xmm3: xmm(&vectors.Xmm3),
+            r15: context.R15,
+            r14: context.R14,
+            r13: context.R13,
+            r12: context.R12,
+            r11: context.R11,
+            r10: context.R10,
+            r9: context.R9,
+            r8: context.R8,
+            rbp: context.Rbp,
+            rdi: context.Rdi,
+            rsi: context.Rsi,
+            rdx: context.Rdx,
+            rcx: context.Rcx,
+            rbx: context.Rbx,
+            rsp: context.Rsp,
+            rflags: context.EFlags as u64,
+            _no_use: 0,
+            rax: context.Rax,
+        };
+        let mut rip = context.Rip;
+        if !super::dispatch(context.Dr6, context.Dr7, &mut registers, &mut rip) {
+            return EXCEPTION_CONTINUE_SEARCH;
+        }
+        set_xmm(&mut vectors.Xmm0, registers.xmm0);
+        set_xmm(&mut vectors.Xmm1, registers.xmm1);
+        set_xmm(&mut vectors.Xmm2, registers.xmm2);
+        set_xmm(&mut vectors.Xmm3, registers.xmm3);
+        context.R15 = registers.r15;
+        context.R14 = registers.r14;
+        context.R13 = registers.r13;
+        context.R12 = registers.r12;
+        context.R11 = registers.r11;
+        context.R10 = registers.r10;
+        context.R9 = registers.r9;
+        context.R8 = registers.r8;
+        context.Rbp = registers.rbp;
+        context.Rdi = registers.rdi;
+        context.Rsi = registers.rsi;
+        context.Rdx = registers.rdx;
+        context.Rcx = registers.rcx;
+        context.Rbx = registers.rbx;
+        context.Rax = registers.rax;
+        context.EFlags = registers.rflags as u32;
+        context.Rip = rip;
+        context.Dr6 = 0;
+        EXCEPTION_CONTINUE_EXECUTION
+    }
+}
+
+/// Without debug register access the breakpoint table is only bookkeeping;
+/// [`dispatch`] can still be driven directly.
+#[cfg(not(target_os = "windows"))]
+mod system {
+    use super::SLOTS;
+    use crate::errors::Result;
+
+    pub(super) fn add_handler() -> Result<()> {
+        Ok(())
+    }
+
+    pub(super) fn remove_handler() {}
+
+    pub(super) fn set_debug_registers(_addresses: [u64; SLOTS], _dr7: u64) -> Result<usize> {
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch_encoding() {
+        assert_eq!(Watch::Execute.dr7_bits(0), 0b1);
+        assert_eq!(Watch::Write { size: 4 }.dr7_bits(1), 0b0100 | 0b1101 << 20);
+        assert_eq!(
+            Watch::ReadWrite { size: 8 }.dr7_bits(3),
+            0b0100_0000 | 0b1011 << 28
+        );
+        let watches = [
+            Some(Watch::Execute),
+            None,
+            Some(Watch::Write { size: 2 }),
+            None,
+        ];
+        assert_eq!(dr7(&watches), 0b01_0001 | 0b0101 << 24);
+        assert_eq!(dr7(&watches) & !DR7_MASK, 0);
+
+        assert!(Watch::Execute.validate(0x1001).is_ok());
+        assert!(Watch::Write { size: 4 }.validate(0x1004).is_ok());
+        assert!(Watch::Write { size: 4 }.validate(0x1002).is_err());
+        assert!(Watch::ReadWrite { size: 3 }.validate(0x1000).is_err());
+    }
+
+    fn registers() -> Registers {
+        Registers {
+            xmm0: 0,
+            xmm1: 0,
+            xmm2: 0,
+            xmm3: 0,
+            r15: 0,
+            r14: 0,
+            r13: 0,
+            r12: 0,
+            r11: 0,
+            r10: 0,
+            r9: 0,
+            r8: 0,
+            rbp: 0,
+            rdi: 0,
+            rsi: 0,
+            rdx: 0,
+            rcx: 7,
+            rbx: 0,
+            rsp: 0,
+            rflags: 0x202,
+            _no_use: 0,
+            rax: 0,
+        }
+    }
+
+    #[test]
+    fn test_dispatch_runs_armed_handlers() {
+        let calls = Arc::new(AtomicU64::new(0));
+        let execute = Arc::new(Breakpoint::new(
+            0x1000,
+            Watch::Execute,
+            |hit| {
+                hit.registers_mut().rcx *= 2;
+                hit.redirect(0x2000);
+            },
+            calls.clone(),
+        ));
+        let log = Arc::new(AccessLog::default());
+        let recorder = log.clone();
+        let write = Arc::new(Breakpoint::new(
+            0x5000,
+            Watch::Write { size: 8 },
+            move |hit| recorder.record(hit),
+            Arc::new(AtomicU64::new(0)),
+        ));
+        let first = arm(execute).unwrap();
+        let second = arm(write).unwrap();
+        let slots = [first.slot, second.slot];
+        let watches = ARMED
+            .read()
+            .unwrap()
+            .each_ref()
+            .map(|b| b.as_ref().map(|b| b.watch));
+        let dr7 = dr7(&watches);
+
+        let mut regs = registers();
+        let mut rip = 0x1000;
+        assert!(dispatch(1 << slots[0], dr7, &mut regs, &mut rip));
+        assert_eq!(
+            (regs.rcx, rip, calls.load(Ordering::Relaxed)),
+            (14, 0x2000, 1)
+        );
+        assert_ne!(regs.rflags & RFLAGS_RF, 0);
+
+        let mut regs = registers();
+        for rip in [0x3004, 0x3010, 0x3004] {
+            assert!(dispatch(1 << slots[1], dr7, &mut regs, &mut { rip }));
+        }
+        assert_eq!(regs.rflags & RFLAGS_RF, 0);
+        let counts: Vec<(usize, u64)> = log
+            .accesses()
+            .iter()
+            .map(|a| (a.instruction, a.count))
+            .collect();
+        assert_eq!(counts, [(0x3004, 2), (0x3010, 1)]);
+
+        // A single-step exception unrelated to the breakpoints is not handled.
+        assert!(!dispatch(1 << 14, dr7, &mut regs, &mut 0));
+
+        let extra: Vec<_> = (0..SLOTS - 2)
+            .map(|i| {
+                let noop = Breakpoint::new(0x8000 + i * 8, Watch::Execute, |_| {}, Arc::default());
+                arm(Arc::new(noop)).unwrap()
+            })
+            .collect();
+        let full = Breakpoint::new(0x9000, Watch::Execute, |_| {}, Arc::default());
+        assert!(matches!(
+            arm(Arc::new(full)),
+            Err(Error::NoFreeDebugRegister(0x9000))
+        ));
+        drop((first, second, extra));
+        assert!(ARMED.read().unwrap().iter().all(Option::is_none));
+    }
+
+    /// Thread 0 is the calling one; every call records the thread making it.
+    #[derive(Default)]
+    struct FakeThreads {
+        registers: Vec<([u64; SLOTS], u64)>,
+        suspended: Vec<bool>,
+        callers: Vec<std::thread::ThreadId>,
+    }
+
+    impl ThreadControl for FakeThreads {
+        type Thread = usize;
+
+        fn threads(&mut self) -> Result<Vec<usize>> {
+            Ok((1..self.registers.len()).collect())
+        }
+
+        fn suspend(&mut self, thread: &usize) -> bool {
+            self.suspended[*thread] = true;
+            true
+        }
+
+        fn resume(&mut self, thread: &usize) {
+            self.suspended[*thread] = false;
+        }
+
+        fn instruction_pointer(&mut self, _thread: &usize) -> Option<usize> {
+            None
+        }
+
+        fn set_instruction_pointer(&mut self, _thread: &usize, _address: usize) -> bool {
+            false
+        }
+
+        fn alloc_code(&mut self, _code: &[u8]) -> Option<usize> {
+            None
+        }
+
+        fn free_code(&mut self, _address: usize) {}
+    }
+
+    impl DebugRegisters for FakeThreads {
+        fn set_debug_registers(
+            &mut self,
+            thread: &usize,
+            addresses: [u64; SLOTS],
+            dr7: u64,
+        ) -> bool {
+            assert!(self.suspended[*thread]);
+            self.callers.push(std::thread::current().id());
+            let old = self.registers[*thread].1;
+            self.registers[*thread] = (addresses, (old & !DR7_MASK) | dr7);
+            true
+        }
+
+        fn set_own_debug_registers(&mut self, addresses: [u64; SLOTS], dr7: u64) -> bool {
+            self.callers.push(std::thread::current().id());
+            let old = self.registers[0].1;
+            self.registers[0] = (addresses, (old & !DR7_MASK) | dr7);
+            true
+        }
+    }
+
+    #[test]
+    fn test_update_threads_from_calling_thread() {
+        // DR7 bit 10 is reserved and must survive.
+        let mut control = FakeThreads {
+            registers: vec![([0; SLOTS], 0x400); 3],
+            suspended: vec![false; 3],
+            ..Default::default()
+        };
+        let addresses = [0x1000, 0, 0x5000, 0];
+        let watches = [
+            Some(Watch::Execute),
+            None,
+            Some(Watch::Write { size: 8 }),
+            None,
+        ];
+        let armed = dr7(&watches);
+        assert_eq!(update_threads(&mut control, addresses, armed).unwrap(), 3);
+        assert!(
+            control
+                .registers
+                .iter()
+                .all(|&r| r == (addresses, 0x400 | armed))
+        );
+
+        assert_eq!(update_threads(&mut control, [0; SLOTS], 0).unwrap(), 3);
+        assert!(control.registers.iter().all(|&r| r == ([0; SLOTS], 0x400)));
+        assert!(control.suspended.iter().all(|&suspended| !suspended));
+        assert_eq!(control.callers.len(), 6);
+        assert!(
+            control
+                .callers
+                .iter()
+                .all(|&caller| caller == std::thread::current().id())
+        );
+    }
+}
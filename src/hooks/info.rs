@@ -0,0 +1,306 @@
+//! Metadata kept for every hook installed through a [`HookContext`](super::HookContext).
+//!
+//! The [`HookManager`](super::HookManager) turns it into [`HookInfo`] records
+//! that can be logged or written as a JSON report, so a broken build shows
+//! exactly which hooks are live, where they point and whether they are called.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::breakpoint::Watch;
+use crate::disasm;
+use crate::errors::Result;
+
+/// Number of bytes ilhook overwrites at the target and restores on unhook.
+pub const PATCH_SIZE: usize = 14;
+
+/// Bytes read from a target before patching, enough for the instructions
+/// covering [`PATCH_SIZE`] bytes.
+pub const ORIGINAL_WINDOW: usize = PATCH_SIZE + 15;
+
+/// How a hook was installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HookKind {
+    /// A jmp-back hook from [`HookContext::install_jmp_back`](super::HookContext::install_jmp_back).
+    JmpBack,
+    /// A function hook from [`HookContext::install_retn`](super::HookContext::install_retn).
+    Retn,
+    /// A typed function hook from [`HookContext::hook`](super::HookContext::hook).
+    Typed,
+    /// A mid-function hook from [`HookContext::install_mid`](super::HookContext::install_mid).
+    Mid,
+    /// An import table hook from [`HookContext::install_iat`](super::HookContext::install_iat).
+    Iat,
+    /// An export table hook from [`HookContext::install_eat`](super::HookContext::install_eat).
+    Eat,
+    /// A call tracer from [`HookContext::install_trace`](super::HookContext::install_trace).
+    Trace,
+    /// A hardware breakpoint from [`HookContext::install_breakpoint`](super::HookContext::install_breakpoint).
+    Breakpoint,
+    /// A guard created directly with [`HookGuard::own`](super::HookGuard::own)
+    /// or [`HookGuard::toggleable`](super::HookGuard::toggleable).
+    Custom,
+}
+
+/// Everything known about one hook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HookInfo {
+    pub name: String,
+    pub module: String,
+    pub kind: HookKind,
+    pub target: Option<usize>,
+    /// `module+0xoffset` of the target, or a name given with
+    /// [`HookGuard::with_symbol`](super::HookGuard::with_symbol).
+    pub symbol: Option<String>,
+    /// The function type of typed hooks.
+    pub signature: Option<String>,
+    /// The bytes at the target before it was first patched.
+    pub original_bytes: Vec<u8>,
+    /// Where the patched jump at the target leads while the hook is enabled.
+    /// For table hooks the target is the patched slot and this is the replacement.
+    pub trampoline: Option<usize>,
+    /// Milliseconds since the Unix epoch when the hook was last enabled.
+    pub installed_at: Option<u64>,
+    pub calls: u64,
+    pub enabled: bool,
+    pub toggleable: bool,
+}
+
+impl HookInfo {
+    /// Formats the record as a single `key=value` log line.
+    pub fn log_line(&self) -> String {
+        let mut line = format!(
+            "hook name={} module={} kind={:?} enabled={} calls={}",
+            self.name, self.module, self.kind, self.enabled, self.calls
+        );
+        if let Some(target) = self.target {
+            line.push_str(&format!(" target=0x{target:x}"));
+        }
+        if let Some(symbol) = &self.symbol {
+            line.push_str(&format!(" symbol={symbol}"));
+        }
+        if let Some(signature) = &self.signature {
+            line.push_str(&format!(" signature=\"{signature}\""));
+        }
+        if let Some(trampoline) = self.trampoline {
+            line.push_str(&format!(" trampoline=0x{trampoline:x}"));
+        }
+        if !self.original_bytes.is_empty() {
+            let bytes: Vec<String> = self
+                .original_bytes
+                .iter()
+                .map(|byte| format!("{byte:02X}"))
+                .collect();
+            line.push_str(&format!(" original=\"{}\"", bytes.join(" ")));
+        }
+        line
+    }
+}
+
+/// Metadata a guard keeps about the hook it owns.
+#[derive(Debug)]
+pub(crate) struct HookMetadata {
+    pub(crate) kind: HookKind,
+    pub(crate) target: Option<usize>,
+    pub(crate) symbol: Option<String>,
+    pub(crate) signature: Option<String>,
+    pub(crate) original_bytes: Vec<u8>,
+    pub(crate) trampoline: Option<usize>,
+    pub(crate) installed_at: Option<u64>,
+    pub(crate) calls: Arc<AtomicU64>,
+}
+
+impl HookMetadata {
+    pub(crate) fn custom() -> Self {
+        Self {
+            kind: HookKind::Custom,
+            target: None,
+            symbol: None,
+            signature: None,
+            original_bytes: Vec::new(),
+            trampoline: None,
+            installed_at: None,
+            calls: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Records a hook about to be placed at `target`, reading the bytes it will
+    /// overwrite.
+    ///
+    /// # Safety
+    ///
+    /// `target` must point to at least [`PATCH_SIZE`] readable bytes.
+    pub(crate) unsafe fn at(kind: HookKind, target: usize, calls: Arc<AtomicU64>) -> Self {
+        let original_bytes =
+            unsafe { core::slice::from_raw_parts(target as *const u8, PATCH_SIZE) }.to_vec();
+        Self {
+            kind,
+            target: Some(target),
+            symbol: resolve_symbol(target),
+            signature: None,
+            original_bytes,
+            trampoline: None,
+            installed_at: None,
+            calls,
+        }
+    }
+
+    /// Records a hardware breakpoint on `target`, which is not modified.
+    pub(crate) fn breakpoint(target: usize, watch: Watch, calls: Arc<AtomicU64>) -> Self {
+        Self {
+            kind: HookKind::Breakpoint,
+            target: Some(target),
+            symbol: resolve_symbol(target),
+            signature: Some(watch.to_string()),
+            original_bytes: Vec::new(),
+            trampoline: None,
+            installed_at: None,
+            calls,
+        }
+    }
+
+    /// Records a table hook about to replace the `size` byte slot at `slot`
+    /// with a pointer to `replacement`.
+    ///
+    /// # Safety
+    ///
+    /// `slot` must point to `size` readable bytes.
+    pub(crate) unsafe fn slot(
+        kind: HookKind,
+        slot: usize,
+        size: usize,
+        replacement: usize,
+        symbol: String,
+    ) -> Self {
+        let original_bytes =
+            unsafe { core::slice::from_raw_parts(slot as *const u8, size) }.to_vec();
+        Self {
+            kind,
+            target: Some(slot),
+            symbol: Some(symbol),
+            signature: None,
+            original_bytes,
+            trampoline: Some(replacement),
+            installed_at: None,
+            calls: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Updates the trampoline and install time after the hook was (re)installed.
+    /// Table hooks keep the replacement they were created with, and breakpoints
+    /// have no trampoline.
+    pub(crate) fn installed(&mut self) {
+        if !matches!(
+            self.kind,
+            HookKind::Iat | HookKind::Eat | HookKind::Breakpoint
+        ) {
+            self.trampoline = self
+                .target
+                .and_then(|target| unsafe { jump_destination(target) });
+        }
+        self.installed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|elapsed| elapsed.as_millis() as u64);
+    }
+
+    pub(crate) fn calls(&self) -> u64 {
+        self.calls.load(Ordering::Relaxed)
+    }
+}
+
+/// Returns the length of the whole instructions at the start of `code` that
+/// cover [`PATCH_SIZE`] bytes. ilhook moves exactly these to the trampoline.
+pub(crate) fn patched_length(code: &[u8], address: usize) -> Result<usize> {
+    covered_instructions(code, address, PATCH_SIZE).map(|(length, _)| length)
+}
+
+/// Returns the length and number of the whole instructions at the start of
+/// `code` that cover `size` bytes.
+pub(crate) fn covered_instructions(
+    code: &[u8],
+    address: usize,
+    size: usize,
+) -> Result<(usize, usize)> {
+    let mut length = 0;
+    let mut count = 0;
+    while length < size {
+        length += disasm::decode(&code[length..], address + length)?.length;
+        count += 1;
+    }
+    Ok((length, count))
+}
+
+/// Returns the size of the jump ilhook wrote at the start of `code`.
+pub(crate) fn jump_length(code: &[u8]) -> Option<usize> {
+    match code {
+        [0xE9, ..] => Some(5),
+        [0xFF, 0x25, 0, 0, 0, 0, ..] => Some(PATCH_SIZE),
+        _ => None,
+    }
+}
+
+/// Decodes the jump ilhook writes at a hooked address.
+///
+/// # Safety
+///
+/// `address` must point to at least [`PATCH_SIZE`] readable bytes.
+pub(crate) unsafe fn jump_destination(address: usize) -> Option<usize> {
+    let bytes = unsafe { core::slice::from_raw_parts(address as *const u8, PATCH_SIZE) };
+    match bytes {
+        [0xE9, rel @ ..] => {
+            let rel = i32::from_le_bytes(rel[..4].try_into().ok()?);
+            Some(address.wrapping_add(5).wrapping_add_signed(rel as isize))
+        }
+        [0xFF, 0x25, 0, 0, 0, 0, absolute @ ..] => {
+            Some(u64::from_le_bytes(absolute[..8].try_into().ok()?) as usize)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn resolve_symbol(address: usize) -> Option<String> {
+    let (module, base) = crate::winapi::module_at(address)?;
+    Some(format!("{module}+0x{:x}", address - base))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn resolve_symbol(_address: usize) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jump_destination() {
+        let mut near = [0xCCu8; PATCH_SIZE];
+        near[0] = 0xE9;
+        near[1..5].copy_from_slice(&(-0x20i32).to_le_bytes());
+        let address = near.as_ptr() as usize;
+        assert_eq!(
+            unsafe { jump_destination(address) },
+            Some(address + 5 - 0x20)
+        );
+
+        let mut far = [0u8; PATCH_SIZE];
+        far[..2].copy_from_slice(&[0xFF, 0x25]);
+        far[6..].copy_from_slice(&0x7FF6_1234_5678u64.to_le_bytes());
+        assert_eq!(
+            unsafe { jump_destination(far.as_ptr() as usize) },
+            Some(0x7FF6_1234_5678)
+        );
+
+        let code = [0x48u8, 0x89, 0x5C, 0x24, 0x08, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(unsafe { jump_destination(code.as_ptr() as usize) }, None);
+
+        assert_eq!(jump_length(&near), Some(5));
+        assert_eq!(jump_length(&far), Some(PATCH_SIZE));
+        assert_eq!(jump_length(&code), None);
+    }
+}
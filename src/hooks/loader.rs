@@ -0,0 +1,349 @@
+//! Deferred hooks for DLLs loaded after startup.
+//!
+//! A [`HookModule`](super::HookModule) that names a
+//! [`target_module`](super::HookModule::target_module) is initialized when that
+//! DLL is mapped, and its hooks are removed again when it unloads. The
+//! [`HookManager`](super::HookManager) reacts to [`ModuleEvent`]s; a
+//! [`ModuleWatcher`] produces them for the global manager from loader
+//! notifications (`LdrRegisterDllNotification`) and from polling, which also
+//! covers systems where the notification API is unavailable.
+//!
+//! While hooks are attached to a DLL, the manager holds a [`ModulePin`] on it,
+//! so the DLL stays mapped until its hooks are removed.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use super::tables;
+
+/// A DLL mapped into the process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedModule {
+    pub name: String,
+    pub base: usize,
+    pub size: usize,
+}
+
+impl LoadedModule {
+    /// Returns true if `name` refers to this module. DLL names compare
+    /// case-insensitively, as the loader does.
+    pub fn is(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        (self.base..self.base + self.size).contains(&address)
+    }
+}
+
+/// A reference that keeps a DLL mapped while hooks are attached to it, so it
+/// cannot unload under them. Released on drop.
+pub(crate) struct ModulePin {
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    base: usize,
+}
+
+impl ModulePin {
+    /// Pins `module`, returning `None` if it is no longer mapped.
+    pub(crate) fn new(module: &LoadedModule) -> Option<Self> {
+        #[cfg(target_os = "windows")]
+        if !crate::winapi::pin_module(module.base) {
+            log::warn!(
+                "could not pin {}, its hooks are abandoned if it unloads",
+                module.name
+            );
+            return None;
+        }
+        Some(Self { base: module.base })
+    }
+}
+
+impl Drop for ModulePin {
+    fn drop(&mut self) {
+        #[cfg(target_os = "windows")]
+        crate::winapi::unpin_module(self.base);
+    }
+}
+
+/// A change in the set of loaded modules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleEvent {
+    /// The module was mapped and its hooks can be installed.
+    Loaded(LoadedModule),
+    /// The loader reported the module unloading. Pinned modules are still
+    /// mapped and their hooks are removed normally; the hooks of any other
+    /// are abandoned like for [`Gone`](Self::Gone).
+    Unloading(String),
+    /// The module is already unmapped. Its hooks are abandoned without
+    /// restoring the original bytes, which no longer exist.
+    Gone(String),
+}
+
+/// Looks up a loaded module by name.
+pub fn loaded_module(name: &str) -> Option<LoadedModule> {
+    let base = tables::module_base(name).ok()?;
+    let size = unsafe { tables::module_image(base) }.ok()?.size_of_image() as usize;
+    Some(LoadedModule {
+        name: name.to_string(),
+        base,
+        size,
+    })
+}
+
+/// Compares the DLLs deferred modules wait for with what is loaded now and
+/// returns the events that bring the manager up to date. `watched` pairs each
+/// DLL name with the module it is attached to, if any.
+pub(crate) fn poll_events(
+    watched: &[(&'static str, Option<LoadedModule>)],
+    lookup: impl Fn(&str) -> Option<LoadedModule>,
+) -> Vec<ModuleEvent> {
+    let mut events = Vec::new();
+    for (name, attached) in watched {
+        let current = lookup(name);
+        match (attached, current) {
+            (Some(attached), Some(current)) if attached.base == current.base => {}
+            (Some(attached), current) => {
+                events.push(ModuleEvent::Gone(attached.name.clone()));
+                events.extend(current.map(ModuleEvent::Loaded));
+            }
+            (None, Some(current)) => events.push(ModuleEvent::Loaded(current)),
+            (None, None) => {}
+        }
+    }
+    events
+}
+
+/// Feeds [`ModuleEvent`]s to the global manager for config type `C` until dropped.
+///
+/// Events are handled on a background thread, never in the loader callback,
+/// which runs under the loader lock where suspending threads or running module
+/// code can deadlock.
+///
+/// Dropping the watcher does not wait for its thread, so it is safe from
+/// `DllMain`; the thread exits within one polling interval.
+pub struct ModuleWatcher {
+    stop: Arc<AtomicBool>,
+    #[cfg(target_os = "windows")]
+    notification: Option<system::Registration>,
+}
+
+impl ModuleWatcher {
+    /// Starts watching, polling every `interval` besides loader notifications.
+    pub fn start<C>(interval: Duration) -> Self
+    where
+        C: Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        #[cfg(target_os = "windows")]
+        let notification = system::Registration::new(sender.clone())
+            .inspect_err(|e| log::warn!("dll notifications unavailable, polling only: {e}"))
+            .ok();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            // Keeps the channel open when notifications are unavailable.
+            let _sender = sender;
+            log::debug!("module watcher started");
+            // Catch DLLs that loaded before the registration.
+            poll::<C>();
+            while !stopped.load(Ordering::SeqCst) {
+                match receiver.recv_timeout(interval) {
+                    Ok(event) => dispatch::<C>(event),
+                    Err(RecvTimeoutError::Timeout) => poll::<C>(),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            log::debug!("module watcher stopped");
+        });
+        Self {
+            stop,
+            #[cfg(target_os = "windows")]
+            notification,
+        }
+    }
+}
+
+impl Drop for ModuleWatcher {
+    fn drop(&mut self) {
+        #[cfg(target_os = "windows")]
+        drop(self.notification.take());
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+fn dispatch<C: Send + Sync + 'static>(event: ModuleEvent) {
+    let _ = super::with_manager::<C, _>(|mgr| mgr.handle_module_event(event));
+}
+
+fn poll<C: Send + Sync + 'static>() {
+    let Some(watched) = super::with_manager::<C, _>(|mgr| mgr.watched_modules()) else {
+        return;
+    };
+    for event in poll_events(&watched, loaded_module) {
+        dispatch::<C>(event);
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod system {
+    use core::ffi::c_void;
+    use std::sync::mpsc::Sender;
+
+    use windows::Win32::System::LibraryLoader::{GetModuleHandleW, GetProcAddress};
+    use windows::core::{s, w};
+
+    use super::{LoadedModule, ModuleEvent};
+    use crate::errors::{Error, Result};
+
+    const REASON_LOADED: u32 = 1;
+    const REASON_UNLOADED: u32 = 2;
+
+    #[repr(C)]
+    struct UnicodeString {
+        length: u16,
+        maximum_length: u16,
+        buffer: *const u16,
+    }
+
+    /// `LDR_DLL_NOTIFICATION_DATA`; loaded and unloaded data share the layout.
+    #[repr(C)]
+    struct NotificationData {
+        flags: u32,
+        full_dll_name: *const UnicodeString,
+        base_dll_name: *const UnicodeString,
+        dll_base: *mut c_void,
+        size_of_image: u32,
+    }
+
+    type Callback =
+        unsafe extern "system" fn(reason: u32, data: *const NotificationData, context: *mut c_void);
+    type Register = unsafe extern "system" fn(
+        flags: u32,
+        callback: Callback,
+        context: *mut c_void,
+        cookie: *mut *mut c_void,
+    ) -> i32;
+    type Unregister = unsafe extern "system" fn(cookie: *mut c_void) -> i32;
+
+    struct Context {
+        sender: Sender<ModuleEvent>,
+    }
+
+    /// A registered DLL notification, unregistered on drop.
+    pub(super) struct Registration {
+        cookie: *mut c_void,
+        unregister: Unregister,
+        context: *mut Context,
+    }
+
+    unsafe impl Send for Registration {}
+
+    impl Registration {
+        pub(super) fn new(sender: Sender<ModuleEvent>) -> Result<Self> {
+            let ntdll = unsafe { GetModuleHandleW(w!("ntdll.dll")) }?;
+            let missing = || Error::ModuleNotLoaded("ntdll.dll!LdrRegisterDllNotification".into());
+            let register = unsafe { GetProcAddress(ntdll, s!("LdrRegisterDllNotification")) }
+                .ok_or_else(missing)?;
+            let unregister = unsafe { GetProcAddress(ntdll, s!("LdrUnregisterDllNotification")) }
+                .ok_or_else(missing)?;
+            let register = unsafe {
+                core::mem::transmute::<unsafe extern "system" fn() -> isize, Register>(register)
+            };
+            let unregister = unsafe {
+                core::mem::transmute::<unsafe extern "system" fn() -> isize, Unregister>(unregister)
+            };
+
+            let context = Box::into_raw(Box::new(Context { sender }));
+            let mut cookie = core::ptr::null_mut();
+            let status = unsafe { register(0, notify, context.cast(), &mut cookie) };
+            if status < 0 {
+                drop(unsafe { Box::from_raw(context) });
+                return Err(Error::ModuleNotLoaded(format!(
+                    "LdrRegisterDllNotification failed: 0x{status:08X}"
+                )));
+            }
+            Ok(Self {
+                cookie,
+                unregister,
+                context,
+            })
+        }
+    }
+
+    impl Drop for Registration {
+        fn drop(&mut self) {
+            // Takes the loader lock, so no callback is running afterwards.
+            unsafe { (self.unregister)(self.cookie) };
+            drop(unsafe { Box::from_raw(self.context) });
+        }
+    }
+
+    /// Runs under the loader lock: events are only queued.
+    unsafe extern "system" fn notify(
+        reason: u32,
+        data: *const NotificationData,
+        context: *mut c_void,
+    ) {
+        let context = unsafe { &*context.cast::<Context>() };
+        let data = unsafe { &*data };
+        let name = unsafe {
+            let name = &*data.base_dll_name;
+            let chars = core::slice::from_raw_parts(name.buffer, name.length as usize / 2);
+            String::from_utf16_lossy(chars)
+        };
+        let event = match reason {
+            REASON_LOADED => ModuleEvent::Loaded(LoadedModule {
+                name,
+                base: data.dll_base as usize,
+                size: data.size_of_image as usize,
+            }),
+            REASON_UNLOADED => ModuleEvent::Unloading(name),
+            _ => return,
+        };
+        let _ = context.sender.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(name: &str, base: usize) -> LoadedModule {
+        LoadedModule {
+            name: name.to_string(),
+            base,
+            size: 0x1000,
+        }
+    }
+
+    #[test]
+    fn test_poll_events() {
+        let loaded = [module("plugin.dll", 0x1000), module("render.dll", 0x9000)];
+        let lookup = |name: &str| loaded.iter().find(|m| m.is(name)).cloned();
+        let watched = [
+            ("PLUGIN.DLL", None),
+            ("render.dll", Some(module("render.dll", 0x5000))),
+            ("audio.dll", Some(module("audio.dll", 0x7000))),
+            ("net.dll", None),
+        ];
+        assert_eq!(
+            poll_events(&watched, lookup),
+            vec![
+                ModuleEvent::Loaded(module("plugin.dll", 0x1000)),
+                ModuleEvent::Gone("render.dll".into()),
+                ModuleEvent::Loaded(module("render.dll", 0x9000)),
+                ModuleEvent::Gone("audio.dll".into()),
+            ]
+        );
+        assert!(
+            poll_events(
+                &[("plugin.dll", Some(module("plugin.dll", 0x1000)))],
+                lookup
+            )
+            .is_empty()
+        );
+    }
+}
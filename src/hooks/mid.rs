@@ -0,0 +1,499 @@
+//! Mid-function hooks.
+//!
+//! [`HookContext::install_mid`](super::HookContext::install_mid) runs a closure
+//! at an arbitrary instruction with a [`MidContext`] that can read and modify
+//! the general purpose registers, all sixteen XMM registers and RFLAGS, and
+//! decide where execution continues. ilhook itself only saves `xmm0`-`xmm3`,
+//! so the callback goes through a naked shim that saves and restores
+//! `xmm4`-`xmm15` around the handler.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ilhook::x64::{HookType, Registers};
+
+use super::info::{ORIGINAL_WINDOW, patched_length};
+use super::threads::CodeHook;
+use crate::errors::Result;
+
+/// A general purpose register. `rsp` is read-only and exposed through
+/// [`MidContext::rsp`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gpr {
+    Rax,
+    Rbx,
+    Rcx,
+    Rdx,
+    Rsi,
+    Rdi,
+    Rbp,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+/// A status flag in RFLAGS, valued by its bit position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Carry = 0,
+    Parity = 2,
+    Adjust = 4,
+    Zero = 6,
+    Sign = 7,
+    Direction = 10,
+    Overflow = 11,
+}
+
+/// Register state and control flow at a mid-function hook.
+pub struct MidContext<'a> {
+    registers: &'a mut Registers,
+    /// `xmm4`-`xmm15`, saved by the shim.
+    xmm_high: &'a mut [u128; 12],
+    address: usize,
+    original: usize,
+    skip: usize,
+    resume: usize,
+}
+
+impl<'a> MidContext<'a> {
+    fn new(
+        registers: &'a mut Registers,
+        xmm_high: &'a mut [u128; 12],
+        address: usize,
+        original: usize,
+        skip: usize,
+    ) -> Self {
+        Self {
+            registers,
+            xmm_high,
+            address,
+            original,
+            skip,
+            resume: original,
+        }
+    }
+
+    /// The hooked address.
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// All registers saved by ilhook, including `xmm0`-`xmm3`.
+    pub fn registers(&self) -> &Registers {
+        self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        self.registers
+    }
+
+    pub fn get(&self, gpr: Gpr) -> u64 {
+        let r = &*self.registers;
+        match gpr {
+            Gpr::Rax => r.rax,
+            Gpr::Rbx => r.rbx,
+            Gpr::Rcx => r.rcx,
+            Gpr::Rdx => r.rdx,
+            Gpr::Rsi => r.rsi,
+            Gpr::Rdi => r.rdi,
+            Gpr::Rbp => r.rbp,
+            Gpr::R8 => r.r8,
+            Gpr::R9 => r.r9,
+            Gpr::R10 => r.r10,
+            Gpr::R11 => r.r11,
+            Gpr::R12 => r.r12,
+            Gpr::R13 => r.r13,
+            Gpr::R14 => r.r14,
+            Gpr::R15 => r.r15,
+        }
+    }
+
+    pub fn set(&mut self, gpr: Gpr, value: u64) {
+        let r = &mut *self.registers;
+        let slot = match gpr {
+            Gpr::Rax => &mut r.rax,
+            Gpr::Rbx => &mut r.rbx,
+            Gpr::Rcx => &mut r.rcx,
+            Gpr::Rdx => &mut r.rdx,
+            Gpr::Rsi => &mut r.rsi,
+            Gpr::Rdi => &mut r.rdi,
+            Gpr::Rbp => &mut r.rbp,
+            Gpr::R8 => &mut r.r8,
+            Gpr::R9 => &mut r.r9,
+            Gpr::R10 => &mut r.r10,
+            Gpr::R11 => &mut r.r11,
+            Gpr::R12 => &mut r.r12,
+            Gpr::R13 => &mut r.r13,
+            Gpr::R14 => &mut r.r14,
+            Gpr::R15 => &mut r.r15,
+        };
+        *slot = value;
+    }
+
+    /// Returns XMM register `index` (0-15).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is 16 or more.
+    pub fn xmm(&self, index: usize) -> u128 {
+        let r = &*self.registers;
+        match index {
+            0 => r.xmm0,
+            1 => r.xmm1,
+            2 => r.xmm2,
+            3 => r.xmm3,
+            _ => self.xmm_high[index - 4],
+        }
+    }
+
+    /// Sets XMM register `index` (0-15).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is 16 or more.
+    pub fn set_xmm(&mut self, index: usize, value: u128) {
+        let r = &mut *self.registers;
+        match index {
+            0 => r.xmm0 = value,
+            1 => r.xmm1 = value,
+            2 => r.xmm2 = value,
+            3 => r.xmm3 = value,
+            _ => self.xmm_high[index - 4] = value,
+        }
+    }
+
+    /// Returns the low scalar float of XMM register `index`.
+    pub fn xmm_f32(&self, index: usize) -> f32 {
+        f32::from_bits(self.xmm(index) as u32)
+    }
+
+    /// Sets the low scalar float of XMM register `index`, keeping the other lanes.
+    pub fn set_xmm_f32(&mut self, index: usize, value: f32) {
+        let xmm = self.xmm(index) & !u128::from(u32::MAX);
+        self.set_xmm(index, xmm | u128::from(value.to_bits()));
+    }
+
+    /// Returns the low scalar double of XMM register `index`.
+    pub fn xmm_f64(&self, index: usize) -> f64 {
+        f64::from_bits(self.xmm(index) as u64)
+    }
+
+    /// Sets the low scalar double of XMM register `index`, keeping the high lane.
+    pub fn set_xmm_f64(&mut self, index: usize, value: f64) {
+        let xmm = self.xmm(index) & !u128::from(u64::MAX);
+        self.set_xmm(index, xmm | u128::from(value.to_bits()));
+    }
+
+    /// Returns the four packed floats of XMM register `index`, lowest lane first.
+    pub fn xmm_f32x4(&self, index: usize) -> [f32; 4] {
+        let xmm = self.xmm(index);
+        core::array::from_fn(|lane| f32::from_bits((xmm >> (lane * 32)) as u32))
+    }
+
+    pub fn set_xmm_f32x4(&mut self, index: usize, lanes: [f32; 4]) {
+        let xmm = lanes.iter().enumerate().fold(0u128, |xmm, (lane, value)| {
+            xmm | u128::from(value.to_bits()) << (lane * 32)
+        });
+        self.set_xmm(index, xmm);
+    }
+
+    pub fn flags(&self) -> u64 {
+        self.registers.rflags
+    }
+
+    pub fn set_flags(&mut self, flags: u64) {
+        self.registers.rflags = flags;
+    }
+
+    pub fn flag(&self, flag: Flag) -> bool {
+        self.registers.rflags & (1 << flag as u32) != 0
+    }
+
+    pub fn set_flag(&mut self, flag: Flag, value: bool) {
+        let bit = 1 << flag as u32;
+        if value {
+            self.registers.rflags |= bit;
+        } else {
+            self.registers.rflags &= !bit;
+        }
+    }
+
+    /// The stack pointer at the hooked instruction. It cannot be changed.
+    pub fn rsp(&self) -> usize {
+        self.registers.rsp as usize
+    }
+
+    /// Reads a `T` at `rsp + offset`.
+    ///
+    /// # Safety
+    ///
+    /// The stack must hold a valid `T` at that offset.
+    pub unsafe fn stack<T: Copy>(&self, offset: usize) -> T {
+        unsafe { ((self.rsp() + offset) as *const T).read_unaligned() }
+    }
+
+    /// Writes a `T` at `rsp + offset`.
+    ///
+    /// # Safety
+    ///
+    /// The stack slot must be writable and owned by the hooked function.
+    pub unsafe fn set_stack<T: Copy>(&mut self, offset: usize, value: T) {
+        unsafe { ((self.rsp() + offset) as *mut T).write_unaligned(value) }
+    }
+
+    /// Continues with the instructions overwritten by the hook. This is the
+    /// default.
+    pub fn proceed(&mut self) {
+        self.resume = self.original;
+    }
+
+    /// Skips the instructions overwritten by the hook and continues after them.
+    pub fn skip(&mut self) {
+        self.resume = self.skip;
+    }
+
+    /// The address execution continues at when [`skip`](Self::skip) is used.
+    pub fn skip_address(&self) -> usize {
+        self.skip
+    }
+
+    /// Continues at `address` instead.
+    pub fn redirect(&mut self, address: usize) {
+        self.resume = address;
+    }
+
+    /// Where execution continues after the handler returns.
+    pub fn resume_address(&self) -> usize {
+        self.resume
+    }
+}
+
+type Handler = dyn Fn(&mut MidContext) + Send + Sync;
+
+struct State {
+    handler: Box<Handler>,
+    address: usize,
+    skip: usize,
+    calls: Arc<AtomicU64>,
+}
+
+/// A mid-function handler bound to its address, installable several times.
+/// Hook points created by [`install`](Self::install) must be dropped before it.
+pub(crate) struct MidDetour(Box<State>);
+
+impl MidDetour {
+    /// Prepares `handler` for `address`, decoding the instructions the hook
+    /// will overwrite.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the start of an instruction followed by at least
+    /// [`ORIGINAL_WINDOW`] readable bytes.
+    pub(crate) unsafe fn new<H>(address: usize, handler: H, calls: Arc<AtomicU64>) -> Result<Self>
+    where
+        H: Fn(&mut MidContext) + Send + Sync + 'static,
+    {
+        let code = unsafe { core::slice::from_raw_parts(address as *const u8, ORIGINAL_WINDOW) };
+        let skip = address + patched_length(code, address)?;
+        Ok(Self(Box::new(State {
+            handler: Box::new(handler),
+            address,
+            skip,
+            calls,
+        })))
+    }
+
+    /// Hooks the address.
+    ///
+    /// # Safety
+    ///
+    /// See [`HookContext::install_mid`](super::HookContext::install_mid).
+    pub(crate) unsafe fn install(&self, freeze: bool) -> Result<CodeHook> {
+        let user_data = &*self.0 as *const State as usize;
+        let hook_type = || HookType::JmpToRet(mid_entry);
+        unsafe { super::hook_point(self.0.address, hook_type, user_data, freeze) }
+    }
+}
+
+/// ilhook callback. Saves `xmm4`-`xmm15`, which ilhook does not, into a
+/// buffer passed to [`mid_handler`] and loads them back afterwards, so the
+/// handler may read and change them.
+#[unsafe(naked)]
+unsafe extern "win64" fn mid_entry(
+    _registers: *mut Registers,
+    _ori_func_ptr: usize,
+    _user_data: usize,
+) -> usize {
+    core::arch::naked_asm!(
+        // 0x20 shadow space, 12 XMM registers, and 8 bytes to align the call.
+        "sub rsp, 0xE8",
+        "movups [rsp + 0x20], xmm4",
+        "movups [rsp + 0x30], xmm5",
+        "movups [rsp + 0x40], xmm6",
+        "movups [rsp + 0x50], xmm7",
+        "movups [rsp + 0x60], xmm8",
+        "movups [rsp + 0x70], xmm9",
+        "movups [rsp + 0x80], xmm10",
+        "movups [rsp + 0x90], xmm11",
+        "movups [rsp + 0xA0], xmm12",
+        "movups [rsp + 0xB0], xmm13",
+        "movups [rsp + 0xC0], xmm14",
+        "movups [rsp + 0xD0], xmm15",
+        "lea r9, [rsp + 0x20]",
+        "call {handler}",
+        "movups xmm4, [rsp + 0x20]",
+        "movups xmm5, [rsp + 0x30]",
+        "movups xmm6, [rsp + 0x40]",
+        "movups xmm7, [rsp + 0x50]",
+        "movups xmm8, [rsp + 0x60]",
+        "movups xmm9, [rsp + 0x70]",
+        "movups xmm10, [rsp + 0x80]",
+        "movups xmm11, [rsp + 0x90]",
+        "movups xmm12, [rsp + 0xA0]",
+        "movups xmm13, [rsp + 0xB0]",
+        "movups xmm14, [rsp + 0xC0]",
+        "movups xmm15, [rsp + 0xD0]",
+        "add rsp, 0xE8",
+        "ret",
+        handler = sym mid_handler,
+    )
+}
+
+/// Runs the handler and returns the address to continue at.
+unsafe extern "win64" fn mid_handler(
+    registers: *mut Registers,
+    ori_func_ptr: usize,
+    user_data: usize,
+    xmm_high: *mut [u128; 12],
+) -> usize {
+    let state = unsafe { &*(user_data as *const State) };
+    state.calls.fetch_add(1, Ordering::Relaxed);
+    let mut ctx = MidContext::new(
+        unsafe { &mut *registers },
+        unsafe { &mut *xmm_high },
+        state.address,
+        ori_func_ptr,
+        state.skip,
+    );
+    (state.handler)(&mut ctx);
+    ctx.resume
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::HookContext;
+    use std::sync::RwLock;
+
+    fn registers() -> Registers {
+        Registers {
+            xmm0: 0,
+            xmm1: 0,
+            xmm2: 0,
+            xmm3: 0,
+            r15: 0,
+            r14: 0,
+            r13: 0,
+            r12: 0,
+            r11: 0,
+            r10: 0,
+            r9: 0,
+            r8: 0,
+            rbp: 0,
+            rdi: 0,
+            rsi: 0,
+            rdx: 0,
+            rcx: 0,
+            rbx: 0,
+            rsp: 0,
+            rflags: 0x202,
+            _no_use: 0,
+            rax: 0,
+        }
+    }
+
+    #[test]
+    fn test_mid_context_accessors() {
+        let mut registers = registers();
+        let mut xmm_high = [0u128; 12];
+        let stack = [0u64, 0x1234];
+        registers.rsp = stack.as_ptr() as u64;
+        let mut ctx = MidContext::new(&mut registers, &mut xmm_high, 0x1000, 0x2000, 0x100F);
+
+        ctx.set(Gpr::R12, 7);
+        ctx.set_xmm_f32(1, 1.5);
+        ctx.set_xmm_f64(9, -2.0);
+        ctx.set_xmm_f32x4(15, [1.0, 2.0, 3.0, 4.0]);
+        ctx.set_flag(Flag::Zero, true);
+        ctx.set_flag(Flag::Carry, false);
+        assert_eq!(ctx.get(Gpr::R12), 7);
+        assert_eq!((ctx.xmm_f32(1), ctx.xmm_f64(9)), (1.5, -2.0));
+        assert_eq!(ctx.xmm_f32x4(15), [1.0, 2.0, 3.0, 4.0]);
+        assert!(ctx.flag(Flag::Zero) && !ctx.flag(Flag::Carry));
+        assert_eq!(ctx.flags(), 0x242);
+        assert_eq!(unsafe { ctx.stack::<u64>(8) }, 0x1234);
+
+        assert_eq!(ctx.resume_address(), 0x2000);
+        ctx.skip();
+        assert_eq!(ctx.resume_address(), 0x100F);
+        ctx.redirect(0x3000);
+        assert_eq!(ctx.resume_address(), 0x3000);
+
+        assert_eq!(registers.r12, 7);
+        assert_eq!(registers.xmm1 as u32, 1.5f32.to_bits());
+        assert_eq!(xmm_high[5] as u64, (-2.0f64).to_bits());
+    }
+
+    // mov rax, 1; add rax, 2; add rax, 4 (15 bytes, all moved by the hook);
+    // add rax, 8; movaps xmm0, xmm5; ret
+    #[unsafe(naked)]
+    unsafe extern "win64" fn probe() -> u64 {
+        core::arch::naked_asm!(
+            "mov rax, 1",
+            "add rax, 2",
+            "add rax, 4",
+            "add rax, 8",
+            "movaps xmm0, xmm5",
+            "ret",
+        )
+    }
+
+    #[test]
+    fn test_mid_hook_skips_and_edits_registers() {
+        let ctx = HookContext {
+            config: Arc::new(RwLock::new(())),
+            freeze_threads: false,
+            module: None,
+        };
+        let address = probe as unsafe extern "win64" fn() -> u64 as usize;
+        let guard = unsafe {
+            ctx.install_mid(address, |mid| {
+                mid.set(Gpr::Rax, 100);
+                mid.set_xmm_f64(5, 2.5);
+                mid.skip();
+            })
+        }
+        .unwrap();
+
+        let probe = std::hint::black_box(probe as unsafe extern "win64" fn() -> u64);
+        let result = unsafe { probe() };
+        assert_eq!(result, 108);
+        assert_eq!(guard.calls(), 1);
+
+        let as_float = std::hint::black_box(unsafe {
+            core::mem::transmute::<
+                unsafe extern "win64" fn() -> u64,
+                unsafe extern "win64" fn() -> f64,
+            >(probe)
+        });
+        assert_eq!(unsafe { as_float() }, 2.5);
+
+        drop(guard);
+        assert_eq!(unsafe { probe() }, 15);
+    }
+}
@@ -2,17 +2,53 @@
 
 use core::any::Any;
 use core::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, OnceLock, RwLock};
 
 use crate::errors::{Error, Result};
-use ilhook::x64::{CallbackOption, HookFlags, HookType, Hooker, Registers};
+use crate::pattern::{Pattern, PatternScanner};
+use ilhook::x64::{
+    CallbackOption, HookFlags, HookType, Hooker, JmpBackRoutine, Registers, RetnRoutine,
+};
+
+pub mod breakpoint;
+pub mod info;
+pub mod loader;
+pub mod mid;
+pub mod tables;
+pub mod threads;
+pub mod trace;
+pub mod typed;
+
+pub use breakpoint::{Access, AccessLog, BreakpointHit, Watch};
+pub use info::{HookInfo, HookKind};
+pub use loader::{LoadedModule, ModuleEvent, ModuleWatcher};
+pub use mid::{Flag, Gpr, MidContext};
+pub use threads::{FreezeReport, ThreadControl, ThreadFreezer};
+pub use trace::{ArgKind, Trace, TraceBuffer, TraceRecord, TraceSpec, TraceTarget, Value};
+pub use typed::{Argument, HookFn, ReturnValue};
+
+use info::HookMetadata;
+use loader::ModulePin;
+use threads::CodeHook;
 
 /// Context passed to modules during initialization.
 pub struct HookContext<C: Send + Sync + 'static> {
     config: Arc<RwLock<C>>,
+    freeze_threads: bool,
+    module: Option<LoadedModule>,
 }
 
 impl<C: Send + Sync + 'static> HookContext<C> {
+    fn new(config: Arc<RwLock<C>>, freeze_threads: bool, module: Option<LoadedModule>) -> Self {
+        Self {
+            config,
+            freeze_threads,
+            module,
+        }
+    }
+
     pub fn config(&self) -> std::sync::RwLockReadGuard<'_, C> {
         self.config.read().unwrap()
     }
@@ -20,6 +56,36 @@ impl<C: Send + Sync + 'static> HookContext<C> {
         self.config.write().unwrap()
     }
 
+    /// The DLL named by [`HookModule::target_module`], once it is loaded.
+    pub fn target_module(&self) -> Option<&LoadedModule> {
+        self.module.as_ref()
+    }
+
+    /// Finds `pattern` in the executable sections of the target module and
+    /// returns the address of the first match.
+    pub fn find_pattern(&self, pattern: &str) -> Result<usize> {
+        let module = self
+            .module
+            .as_ref()
+            .ok_or_else(|| Error::ScanError(format!("no target module to search for {pattern}")))?;
+        let image = unsafe { tables::module_image(module.base) }?;
+        let compiled = Pattern::new(pattern)?;
+        let scanner = PatternScanner::new();
+        for section in image.sections().iter().filter(|s| s.is_executable()) {
+            let range = section.rva_range();
+            let Some(code) = image.data().get(range.start as usize..range.end as usize) else {
+                continue;
+            };
+            if let Some(found) = scanner.scan_pattern_first(&compiled, code) {
+                return Ok(module.base + range.start as usize + found.offset);
+            }
+        }
+        Err(Error::ScanError(format!(
+            "pattern not found in {}: {pattern}",
+            module.name
+        )))
+    }
+
     pub unsafe fn install_jmp_back(
         &self,
         target_address: usize,
@@ -27,19 +93,23 @@ impl<C: Send + Sync + 'static> HookContext<C> {
         user_data: usize,
     ) -> Result<HookGuard> {
         log::debug!("install_jmp_back: target=0x{target_address:x} user_data=0x{user_data:x}");
-        let hook = unsafe {
-            Hooker::new(
-                target_address,
-                HookType::JmpBack(callback),
-                CallbackOption::None,
-                user_data,
-                HookFlags::empty(),
-            )
-            .hook()
-        }
-        .map_err(|e| Error::HookInstall(e))?;
+        let calls = Arc::new(AtomicU64::new(0));
+        let metadata =
+            unsafe { HookMetadata::at(HookKind::JmpBack, target_address, calls.clone()) };
+        let counted = Box::new(Counted {
+            callback,
+            user_data,
+            calls,
+        });
+        let freeze = self.freeze_threads;
+        let guard = HookGuard::toggleable(format!("0x{target_address:x}"), move || unsafe {
+            let data = &*counted as *const Counted<JmpBackRoutine> as usize;
+            let hook_type = || HookType::JmpBack(counted_jmp_back);
+            hook_point(target_address, hook_type, data, freeze)
+        })?
+        .with_metadata(metadata);
         log::info!("jmp_back hook installed at 0x{target_address:x}");
-        Ok(HookGuard::own(hook))
+        Ok(guard)
     }
 
     pub unsafe fn install_retn(
@@ -53,52 +123,526 @@ impl<C: Send + Sync + 'static> HookContext<C> {
         user_data: usize,
     ) -> Result<HookGuard> {
         log::debug!("install_retn: target=0x{target_address:x} user_data=0x{user_data:x}");
-        let hook = unsafe {
+        let calls = Arc::new(AtomicU64::new(0));
+        let metadata = unsafe { HookMetadata::at(HookKind::Retn, target_address, calls.clone()) };
+        let counted = Box::new(Counted {
+            callback,
+            user_data,
+            calls,
+        });
+        let freeze = self.freeze_threads;
+        let guard = HookGuard::toggleable(format!("0x{target_address:x}"), move || unsafe {
+            let data = &*counted as *const Counted<RetnRoutine> as usize;
+            hook_point(
+                target_address,
+                || HookType::Retn(counted_retn),
+                data,
+                freeze,
+            )
+        })?
+        .with_metadata(metadata);
+        log::info!("retn hook installed at 0x{target_address:x}");
+        Ok(guard)
+    }
+
+    /// Replaces the function at `target_address` with a typed `detour`.
+    ///
+    /// `F` is the function pointer type of the target, for example
+    /// `unsafe extern "win64" fn(usize, f32) -> i32`. The detour receives the
+    /// original function as an `F` and the arguments as a tuple, and its result
+    /// is returned to the caller. It may capture state; the state is dropped
+    /// with the guard after the hook is removed.
+    ///
+    /// # Safety
+    ///
+    /// `target_address` must be the entry of a function of type `F`.
+    pub unsafe fn hook<F, D>(&self, target_address: usize, detour: D) -> Result<HookGuard>
+    where
+        F: HookFn,
+        D: Fn(F, F::Args) -> F::Output + Send + Sync + 'static,
+    {
+        log::debug!("hook: target=0x{target_address:x}");
+        let calls = Arc::new(AtomicU64::new(0));
+        let mut metadata =
+            unsafe { HookMetadata::at(HookKind::Typed, target_address, calls.clone()) };
+        metadata.signature = Some(core::any::type_name::<F>().to_string());
+        let detour = typed::TypedDetour::<F>::new(detour, calls);
+        let freeze = self.freeze_threads;
+        let guard = HookGuard::toggleable(format!("0x{target_address:x}"), move || unsafe {
+            detour.install(target_address, freeze)
+        })?
+        .with_metadata(metadata);
+        log::info!("typed hook installed at 0x{target_address:x}");
+        Ok(guard)
+    }
+
+    /// Runs `handler` every time execution reaches `address`, which may be
+    /// any instruction inside a function.
+    ///
+    /// The handler gets a [`MidContext`] to read and change registers, XMM
+    /// registers and flags, and to skip the overwritten instructions or
+    /// continue elsewhere. By default the overwritten instructions run
+    /// after the handler.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the start of an instruction, and the instructions
+    /// covering the first [`info::PATCH_SIZE`] bytes must be safe to move:
+    /// no jump into them from elsewhere in the function.
+    pub unsafe fn install_mid<H>(&self, address: usize, handler: H) -> Result<HookGuard>
+    where
+        H: Fn(&mut MidContext) + Send + Sync + 'static,
+    {
+        log::debug!("install_mid: address=0x{address:x}");
+        let calls = Arc::new(AtomicU64::new(0));
+        let metadata = unsafe { HookMetadata::at(HookKind::Mid, address, calls.clone()) };
+        let detour = unsafe { mid::MidDetour::new(address, handler, calls) }?;
+        let freeze = self.freeze_threads;
+        let guard = HookGuard::toggleable(format!("0x{address:x}"), move || unsafe {
+            detour.install(freeze)
+        })?
+        .with_metadata(metadata);
+        log::info!("mid hook installed at 0x{address:x}");
+        Ok(guard)
+    }
+
+    /// Installs `trace`, recording its calls to the trace's buffer until the
+    /// guard is dropped. Signatures are resolved with [`find_pattern`](Self::find_pattern).
+    ///
+    /// # Safety
+    ///
+    /// The target must be a function whose arguments match the trace's spec.
+    pub unsafe fn install_trace(&self, trace: Trace) -> Result<HookGuard> {
+        let address = match trace.target() {
+            TraceTarget::Address(address) => *address,
+            TraceTarget::Pattern(pattern) => self.find_pattern(pattern)?,
+        };
+        log::debug!("install_trace: {} at 0x{address:x}", trace.name());
+        let name = trace.name().to_string();
+        let calls = Arc::new(AtomicU64::new(0));
+        let signature = trace.spec().to_string();
+        let detour = trace::TraceDetour::new(trace, address, calls.clone())?;
+        let mut metadata = unsafe { HookMetadata::at(HookKind::Trace, address, calls) };
+        metadata.signature = Some(signature);
+        let freeze = self.freeze_threads;
+        let guard = HookGuard::toggleable(name.clone(), move || unsafe { detour.install(freeze) })?
+            .with_metadata(metadata);
+        log::info!("trace {name} installed at 0x{address:x}");
+        Ok(guard)
+    }
+
+    /// Runs `handler` when `address` is executed or accessed, as selected by
+    /// `watch`, using a hardware breakpoint instead of patching code. At most
+    /// [`breakpoint::SLOTS`] breakpoints can be enabled at once.
+    ///
+    /// # Safety
+    ///
+    /// Register changes made by `handler` apply to whichever thread hit the
+    /// breakpoint and must leave it in a valid state.
+    pub unsafe fn install_breakpoint<H>(
+        &self,
+        address: usize,
+        watch: Watch,
+        handler: H,
+    ) -> Result<HookGuard>
+    where
+        H: Fn(&mut BreakpointHit) + Send + Sync + 'static,
+    {
+        log::debug!("install_breakpoint: address=0x{address:x} watch={watch}");
+        watch.validate(address)?;
+        let calls = Arc::new(AtomicU64::new(0));
+        let metadata = HookMetadata::breakpoint(address, watch, calls.clone());
+        let breakpoint = Arc::new(breakpoint::Breakpoint::new(address, watch, handler, calls));
+        let guard = HookGuard::toggleable(format!("0x{address:x}"), move || {
+            breakpoint::arm(breakpoint.clone())
+        })?
+        .with_metadata(metadata);
+        log::info!("breakpoint installed at 0x{address:x} ({watch})");
+        Ok(guard)
+    }
+
+    /// Records the instructions that access `address` while the guard is
+    /// enabled, for example to find what writes to a value.
+    pub fn find_accesses(
+        &self,
+        address: usize,
+        watch: Watch,
+    ) -> Result<(HookGuard, Arc<AccessLog>)> {
+        if watch == Watch::Execute {
+            return Err(Error::InvalidBreakpoint(
+                "access logging needs a data watch".into(),
+            ));
+        }
+        let log = Arc::new(AccessLog::default());
+        let recorder = log.clone();
+        // Recording leaves the registers untouched.
+        let guard =
+            unsafe { self.install_breakpoint(address, watch, move |hit| recorder.record(hit)) }?;
+        Ok((guard, log))
+    }
+
+    /// Redirects the calls `module` makes to `dll!function` to `replacement`
+    /// by patching its Import Address Table. Other modules are unaffected.
+    ///
+    /// Read the original with [`tables::import_address`] before installing.
+    ///
+    /// # Safety
+    ///
+    /// `replacement` must have the signature of the imported function.
+    pub unsafe fn install_iat(
+        &self,
+        module: &str,
+        dll: &str,
+        function: &str,
+        replacement: usize,
+    ) -> Result<HookGuard> {
+        let base = tables::module_base(module)?;
+        unsafe { self.install_iat_at(base, dll, function, replacement) }
+    }
+
+    /// [`install_iat`](Self::install_iat) for the module mapped at `base`.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the base of a loaded module that outlives the guard, and
+    /// `replacement` must have the signature of the imported function.
+    pub unsafe fn install_iat_at(
+        &self,
+        base: usize,
+        dll: &str,
+        function: &str,
+        replacement: usize,
+    ) -> Result<HookGuard> {
+        log::debug!("install_iat: base=0x{base:x} import={dll}!{function}");
+        let slot = unsafe { tables::import_slot(base, dll, function) }?;
+        let symbol = format!("{dll}!{function}");
+        let metadata = unsafe { HookMetadata::slot(HookKind::Iat, slot, 8, replacement, symbol) };
+        let guard = HookGuard::toggleable(format!("0x{slot:x}"), move || unsafe {
+            tables::SlotPatch::pointer(slot, replacement)
+        })?
+        .with_metadata(metadata);
+        log::info!("iat hook installed for {dll}!{function} at 0x{slot:x}");
+        Ok(guard)
+    }
+
+    /// Makes `module` export `replacement` as `function` by patching its
+    /// Export Address Table. Only lookups made after the patch see it.
+    ///
+    /// Read the original with [`tables::export_address`] before installing.
+    ///
+    /// # Safety
+    ///
+    /// `replacement` must have the signature of the exported function.
+    pub unsafe fn install_eat(
+        &self,
+        module: &str,
+        function: &str,
+        replacement: usize,
+    ) -> Result<HookGuard> {
+        let base = tables::module_base(module)?;
+        unsafe { self.install_eat_at(base, function, replacement) }
+    }
+
+    /// [`install_eat`](Self::install_eat) for the module mapped at `base`.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the base of a loaded module that outlives the guard, and
+    /// `replacement` must have the signature of the exported function.
+    pub unsafe fn install_eat_at(
+        &self,
+        base: usize,
+        function: &str,
+        replacement: usize,
+    ) -> Result<HookGuard> {
+        log::debug!("install_eat: base=0x{base:x} export={function}");
+        let slot = unsafe { tables::export_slot(base, function) }?;
+        let size_of_image = unsafe { tables::module_image(base) }?.size_of_image() as usize;
+        let rva = tables::export_rva(base, size_of_image, replacement)?;
+        let metadata = unsafe {
+            HookMetadata::slot(HookKind::Eat, slot, 4, replacement, function.to_string())
+        };
+        let guard = HookGuard::toggleable(format!("0x{slot:x}"), move || unsafe {
+            tables::SlotPatch::rva(slot, rva)
+        })?
+        .with_metadata(metadata);
+        log::info!("eat hook installed for {function} at 0x{slot:x}");
+        Ok(guard)
+    }
+}
+
+/// Patches `target_address` with an ilhook hook of `hook_type`, freezing
+/// other threads while the bytes change if `freeze_threads` is set.
+unsafe fn hook_point(
+    target_address: usize,
+    hook_type: impl Fn() -> HookType,
+    user_data: usize,
+    freeze_threads: bool,
+) -> Result<CodeHook> {
+    unsafe {
+        CodeHook::install(target_address, freeze_threads, || {
+            #[cfg(target_os = "windows")]
+            if freeze_threads {
+                return threads::hook_frozen(
+                    threads::ProcessThreads::default,
+                    target_address,
+                    hook_type,
+                    user_data,
+                );
+            }
             Hooker::new(
                 target_address,
-                HookType::Retn(callback),
+                hook_type(),
                 CallbackOption::None,
                 user_data,
                 HookFlags::empty(),
             )
             .hook()
-        }
-        .map_err(|e| Error::HookInstall(e))?;
-        log::info!("retn hook installed at 0x{target_address:x}");
-        Ok(HookGuard::own(hook))
+            .map(|point| (point, Vec::new()))
+            .map_err(Error::HookInstall)
+        })
     }
 }
 
+/// A raw callback with its user data, passed to a shim that counts calls.
+struct Counted<T> {
+    callback: T,
+    user_data: usize,
+    calls: Arc<AtomicU64>,
+}
+
+unsafe extern "win64" fn counted_jmp_back(registers: *mut Registers, data: usize) {
+    let counted = unsafe { &*(data as *const Counted<JmpBackRoutine>) };
+    counted.calls.fetch_add(1, Ordering::Relaxed);
+    unsafe { (counted.callback)(registers, counted.user_data) }
+}
+
+unsafe extern "win64" fn counted_retn(
+    registers: *mut Registers,
+    ori_func_ptr: usize,
+    data: usize,
+) -> usize {
+    let counted = unsafe { &*(data as *const Counted<RetnRoutine>) };
+    counted.calls.fetch_add(1, Ordering::Relaxed);
+    unsafe { (counted.callback)(registers, ori_func_ptr, counted.user_data) }
+}
+
+type Install = Box<dyn FnMut() -> Result<Box<dyn Any>> + Send>;
+
 /// RAII token representing an installed hook.
-/// Dropping this value should unhook.
+/// Dropping this value unhooks.
+///
+/// Guards returned by [`HookContext`] can also be disabled, which restores the
+/// original bytes, and enabled again without being dropped.
 pub struct HookGuard {
+    name: String,
     inner: Option<Box<dyn Any>>,
+    /// A disabled code hook, kept until the guard is dropped because other
+    /// threads may still run through its trampoline, and reused on enable.
+    retired: Option<Box<dyn Any>>,
+    install: Option<Install>,
+    metadata: HookMetadata,
 }
 
 impl HookGuard {
+    /// Wraps an already installed hook. The guard cannot be toggled.
     pub fn own<T: 'static>(value: T) -> Self {
         Self {
+            name: String::from("hook"),
             inner: Some(Box::new(value)),
+            retired: None,
+            install: None,
+            metadata: HookMetadata::custom(),
         }
     }
 
     pub fn empty() -> Self {
-        Self { inner: None }
+        Self {
+            name: String::from("empty"),
+            inner: None,
+            retired: None,
+            install: None,
+            metadata: HookMetadata::custom(),
+        }
+    }
+
+    /// Installs a hook by calling `install`, which is called again each time
+    /// the guard is re-enabled. Dropping the returned value must unhook.
+    ///
+    /// State captured by `install` lives as long as the guard, so detours can
+    /// safely point into it.
+    pub fn toggleable<T, F>(name: impl Into<String>, mut install: F) -> Result<Self>
+    where
+        T: 'static,
+        F: FnMut() -> Result<T> + Send + 'static,
+    {
+        let mut install: Install = Box::new(move || Ok(Box::new(install()?) as Box<dyn Any>));
+        let inner = install()?;
+        let mut metadata = HookMetadata::custom();
+        metadata.installed();
+        Ok(Self {
+            name: name.into(),
+            inner: Some(inner),
+            retired: None,
+            install: Some(install),
+            metadata,
+        })
+    }
+
+    fn with_metadata(mut self, mut metadata: HookMetadata) -> Self {
+        if self.is_enabled() {
+            metadata.installed();
+        }
+        self.metadata = metadata;
+        self
+    }
+
+    /// Sets the symbol reported for the target, for example `Player::TakeDamage`.
+    pub fn with_symbol(mut self, symbol: impl Into<String>) -> Self {
+        self.metadata.symbol = Some(symbol.into());
+        self
+    }
+
+    /// Sets the signature reported for the target.
+    pub fn with_signature(mut self, signature: impl Into<String>) -> Self {
+        self.metadata.signature = Some(signature.into());
+        self
+    }
+
+    /// Renames the guard. Names are used to look hooks up in the [`HookManager`].
+    pub fn named(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Returns true if the hook can be disabled and enabled again.
+    pub fn is_toggleable(&self) -> bool {
+        self.install.is_some()
+    }
+
+    /// Reinstalls a disabled hook. Does nothing if it is already enabled.
+    pub fn enable(&mut self) -> Result<()> {
+        if self.inner.is_some() {
+            return Ok(());
+        }
+        let install = self
+            .install
+            .as_mut()
+            .ok_or_else(|| Error::HookNotToggleable(self.name.clone()))?;
+        let inner = match self.retired.take() {
+            Some(mut retired) => {
+                if let Some(hook) = retired.downcast_mut::<CodeHook>()
+                    && let Err(error) = hook.reapply()
+                {
+                    self.retired = Some(retired);
+                    return Err(error);
+                }
+                retired
+            }
+            None => install()?,
+        };
+        self.inner = Some(inner);
+        self.metadata.installed();
+        log::info!("hook enabled: {}", self.name);
+        Ok(())
+    }
+
+    /// Removes the hook and restores the original bytes, keeping the guard so
+    /// the hook can be enabled again.
+    ///
+    /// Code hooks keep their trampoline and detour state until the guard is
+    /// dropped, so threads still inside the hook, including the caller when
+    /// disabling from a detour, return through valid memory.
+    pub fn disable(&mut self) -> Result<()> {
+        if self.install.is_none() {
+            return Err(Error::HookNotToggleable(self.name.clone()));
+        }
+        let Some(mut inner) = self.inner.take() else {
+            return Ok(());
+        };
+        if let Some(hook) = inner.downcast_mut::<CodeHook>() {
+            if let Err(error) = hook.lift() {
+                self.inner = Some(inner);
+                return Err(error);
+            }
+            self.retired = Some(inner);
+        }
+        log::info!("hook disabled: {}", self.name);
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> Result<()> {
+        if enabled {
+            self.enable()
+        } else {
+            self.disable()
+        }
+    }
+
+    /// Returns how often the hook was called, for hooks installed through a
+    /// [`HookContext`].
+    pub fn calls(&self) -> u64 {
+        self.metadata.calls()
+    }
+
+    /// Describes the hook, as installed by `module`.
+    pub fn info(&self, module: &str) -> HookInfo {
+        let metadata = &self.metadata;
+        HookInfo {
+            name: self.name.clone(),
+            module: module.to_string(),
+            kind: metadata.kind,
+            target: metadata.target,
+            symbol: metadata.symbol.clone(),
+            signature: metadata.signature.clone(),
+            original_bytes: metadata.original_bytes.clone(),
+            trampoline: metadata.trampoline.filter(|_| self.is_enabled()),
+            installed_at: metadata.installed_at,
+            calls: metadata.calls(),
+            enabled: self.is_enabled(),
+            toggleable: self.is_toggleable(),
+        }
     }
 }
 
 impl fmt::Debug for HookGuard {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("HookGuard").finish_non_exhaustive()
+        f.debug_struct("HookGuard")
+            .field("name", &self.name)
+            .field("enabled", &self.is_enabled())
+            .finish_non_exhaustive()
     }
 }
 
 impl Drop for HookGuard {
     fn drop(&mut self) {
-        if self.inner.is_some() {
-            log::debug!("unhooking guard");
+        if let Some(mut inner) = self.inner.take() {
+            log::debug!("unhooking guard: {}", self.name);
+            // ilhook frees the trampoline even when threads keep it from
+            // restoring the original bytes, so they are restored first.
+            if let Some(hook) = inner.downcast_mut::<CodeHook>()
+                && let Err(error) = hook.lift()
+            {
+                log::error!(
+                    "leaking hook {} that could not be removed: {error}",
+                    self.name
+                );
+                core::mem::forget(inner);
+                core::mem::forget(self.install.take());
+                return;
+            }
+            // Unhook before the installer and the state it owns are dropped.
+            drop(inner);
         }
-        let _ = self.inner.take();
+        let _ = self.retired.take();
     }
 }
 
@@ -114,6 +658,37 @@ where
     fn init(&mut self, ctx: &HookContext<C>) -> Result<Vec<HookGuard>>;
 
     fn shutdown(&mut self) {}
+
+    /// The DLL this module hooks, if it may load after startup. The module is
+    /// initialized once the DLL is loaded, with the DLL available through
+    /// [`HookContext::target_module`], and shut down with its hooks removed
+    /// when the DLL unloads. A [`ModuleWatcher`] must be running for DLLs
+    /// loaded after [`HookManager::start`].
+    fn target_module(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Whether [`HookManager::start`] fails when this module fails to
+    /// initialize. Optional modules are skipped and their failure recorded.
+    fn required(&self) -> bool {
+        true
+    }
+}
+
+/// An optional module that failed to initialize during [`HookManager::start`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleFailure {
+    pub module: &'static str,
+    pub error: String,
+}
+
+/// The state of one installed hook, as reported by [`HookManager::hooks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookStatus {
+    pub module: &'static str,
+    pub name: String,
+    pub enabled: bool,
+    pub toggleable: bool,
 }
 
 /// Manages module registration and hook lifetimes.
@@ -123,7 +698,12 @@ where
 {
     config: Arc<RwLock<C>>,
     modules: Vec<Box<dyn HookModule<C>>>,
-    guards: Vec<HookGuard>,
+    guards: Vec<(&'static str, HookGuard)>,
+    failures: Vec<ModuleFailure>,
+    /// Modules with a target DLL that were initialized for the loaded DLL,
+    /// including those whose `init` failed, with the pin keeping it mapped.
+    attached: Vec<(usize, LoadedModule, Option<ModulePin>)>,
+    freeze_threads: bool,
     started: bool,
 }
 
@@ -137,6 +717,9 @@ where
             config: Arc::new(RwLock::new(config)),
             modules: Vec::new(),
             guards: Vec::new(),
+            failures: Vec::new(),
+            attached: Vec::new(),
+            freeze_threads: true,
             started: false,
         }
     }
@@ -152,6 +735,13 @@ where
         *self.config.write().unwrap() = config;
     }
 
+    /// Sets whether other threads are suspended while hooks are patched and
+    /// restored, see [`ThreadFreezer`]. Enabled by default; applies to hooks
+    /// installed by later calls to [`start`](Self::start).
+    pub fn set_freeze_threads(&mut self, freeze_threads: bool) {
+        self.freeze_threads = freeze_threads;
+    }
+
     pub fn register<M>(&mut self, module: M) -> &mut Self
     where
         M: HookModule<C>,
@@ -162,39 +752,307 @@ where
         self
     }
 
+    /// Initializes every registered module.
+    ///
+    /// Installation is all-or-nothing: if a required module fails, the hooks
+    /// installed by earlier modules are removed, those modules are shut down
+    /// and the error is returned, so `start` can be retried. Optional modules
+    /// that fail are skipped and listed in [`failures`](Self::failures).
+    /// Modules whose [`target_module`](HookModule::target_module) is not loaded
+    /// yet wait for [`handle_module_event`](Self::handle_module_event).
     pub fn start(&mut self) -> Result<()> {
         if self.started {
             return Ok(());
         }
-        let ctx = HookContext {
-            config: self.config.clone(),
-        };
-        for module in &mut self.modules {
-            log::info!("starting module: {}", module.name());
-            let mut installed = module.init(&ctx)?;
-            self.guards.append(&mut installed);
+        self.failures.clear();
+        let mut installed: Vec<(&'static str, HookGuard)> = Vec::new();
+        let mut initialized = Vec::new();
+        let mut attached = Vec::new();
+        for (index, module) in self.modules.iter_mut().enumerate() {
+            let name = module.name();
+            let target = match module.target_module() {
+                Some(dll) => match loader::loaded_module(dll) {
+                    Some(loaded) => Some(loaded),
+                    None => {
+                        log::info!("deferring module {name} until {dll} loads");
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            log::info!("starting module: {}", name);
+            let ctx = HookContext::new(self.config.clone(), self.freeze_threads, target.clone());
+            if let Some(loaded) = target {
+                let pin = ModulePin::new(&loaded);
+                attached.push((index, loaded, pin));
+            }
+            match module.init(&ctx) {
+                Ok(guards) => {
+                    installed.extend(guards.into_iter().map(|guard| (name, guard)));
+                    initialized.push(index);
+                }
+                Err(e) if module.required() => {
+                    log::error!("required module {name} failed, rolling back: {e}");
+                    // Unhook in reverse installation order.
+                    installed.into_iter().rev().for_each(drop);
+                    for &index in initialized.iter().rev() {
+                        self.modules[index].shutdown();
+                    }
+                    return Err(Error::HookModuleStart {
+                        module: name.to_string(),
+                        source: Box::new(e),
+                    });
+                }
+                Err(e) => {
+                    log::warn!("optional module {name} failed, skipping: {e}");
+                    self.failures.push(ModuleFailure {
+                        module: name,
+                        error: e.to_string(),
+                    });
+                }
+            }
         }
+        self.guards = installed;
+        self.attached = attached;
         self.started = true;
         log::info!(
-            "HookManager started: {} modules, {} hooks",
+            "HookManager started: {} modules, {} hooks, {} failed",
             self.modules.len(),
-            self.guards.len()
+            self.guards.len(),
+            self.failures.len()
         );
         Ok(())
     }
 
+    /// Optional modules that failed during the last [`start`](Self::start).
+    pub fn failures(&self) -> &[ModuleFailure] {
+        &self.failures
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
     pub fn stop(&mut self) {
         if !self.started {
             return;
         }
-        for module in &mut self.modules {
+        for (index, module) in self.modules.iter_mut().enumerate() {
+            let failed = self.failures.iter().any(|f| f.module == module.name());
+            let waiting = module.target_module().is_some()
+                && !self
+                    .attached
+                    .iter()
+                    .any(|(attached, ..)| *attached == index);
+            if failed || waiting {
+                continue;
+            }
             log::info!("stopping module: {}", module.name());
             module.shutdown();
         }
         self.guards.clear();
+        self.failures.clear();
+        self.attached.clear();
         self.started = false;
         log::info!("HookManager stopped");
     }
+
+    /// Starts or stops the modules that target the DLL of `event`. Does nothing
+    /// before [`start`](Self::start).
+    ///
+    /// A module that fails to initialize is recorded in
+    /// [`failures`](Self::failures) and retried the next time its DLL loads.
+    pub fn handle_module_event(&mut self, event: ModuleEvent) {
+        if !self.started {
+            return;
+        }
+        match event {
+            ModuleEvent::Loaded(loaded) => self.attach(loaded),
+            ModuleEvent::Unloading(name) => self.detach(&name, true),
+            ModuleEvent::Gone(name) => self.detach(&name, false),
+        }
+    }
+
+    /// The DLL each deferred module waits for, with the loaded DLL it is
+    /// attached to.
+    pub fn watched_modules(&self) -> Vec<(&'static str, Option<LoadedModule>)> {
+        self.modules
+            .iter()
+            .enumerate()
+            .filter_map(|(index, module)| {
+                let dll = module.target_module()?;
+                let attached = self
+                    .attached
+                    .iter()
+                    .find(|(attached, ..)| *attached == index)
+                    .map(|(_, loaded, _)| loaded.clone());
+                Some((dll, attached))
+            })
+            .collect()
+    }
+
+    fn attach(&mut self, loaded: LoadedModule) {
+        for (index, module) in self.modules.iter_mut().enumerate() {
+            let targets = module.target_module().is_some_and(|dll| loaded.is(dll));
+            if !targets
+                || self
+                    .attached
+                    .iter()
+                    .any(|(attached, ..)| *attached == index)
+            {
+                continue;
+            }
+            let name = module.name();
+            log::info!(
+                "{} loaded at 0x{:x}, starting module: {name}",
+                loaded.name,
+                loaded.base
+            );
+            let ctx = HookContext::new(
+                self.config.clone(),
+                self.freeze_threads,
+                Some(loaded.clone()),
+            );
+            self.failures.retain(|failure| failure.module != name);
+            self.attached
+                .push((index, loaded.clone(), ModulePin::new(&loaded)));
+            match module.init(&ctx) {
+                Ok(guards) => self
+                    .guards
+                    .extend(guards.into_iter().map(|guard| (name, guard))),
+                Err(e) => {
+                    log::warn!("module {name} failed for {}: {e}", loaded.name);
+                    self.failures.push(ModuleFailure {
+                        module: name,
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Removes the hooks of modules attached to `dll` and shuts them down.
+    /// Unless `mapped` and pinned, the DLL's memory may be gone and the hooks
+    /// are leaked instead of restoring bytes into it.
+    fn detach(&mut self, dll: &str, mapped: bool) {
+        let (detached, attached) = core::mem::take(&mut self.attached)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(_, loaded, _)| loaded.is(dll));
+        self.attached = attached;
+        for (index, loaded, pin) in detached {
+            let mapped = mapped && pin.is_some();
+            let module = &mut self.modules[index];
+            let name = module.name();
+            log::info!("{} unloaded, stopping module: {name}", loaded.name);
+            let (owned, rest) = core::mem::take(&mut self.guards)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(owner, _)| *owner == name);
+            self.guards = rest;
+            for (_, guard) in owned.into_iter().rev() {
+                if mapped {
+                    drop(guard);
+                } else {
+                    core::mem::forget(guard);
+                }
+            }
+            if !self.failures.iter().any(|failure| failure.module == name) {
+                module.shutdown();
+            }
+            // Only now may the DLL unload.
+            drop(pin);
+        }
+    }
+
+    /// Lists the installed hooks in installation order.
+    pub fn hooks(&self) -> Vec<HookStatus> {
+        self.guards
+            .iter()
+            .map(|(module, guard)| HookStatus {
+                module,
+                name: guard.name().to_string(),
+                enabled: guard.is_enabled(),
+                toggleable: guard.is_toggleable(),
+            })
+            .collect()
+    }
+
+    /// Describes every installed hook in installation order.
+    pub fn hook_info(&self) -> Vec<HookInfo> {
+        self.guards
+            .iter()
+            .map(|(module, guard)| guard.info(module))
+            .collect()
+    }
+
+    /// Logs one structured line per installed hook.
+    pub fn log_report(&self) {
+        for info in self.hook_info() {
+            log::info!("{}", info.log_line());
+        }
+    }
+
+    /// Serializes [`hook_info`](Self::hook_info) as pretty-printed JSON.
+    pub fn report_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(&self.hook_info())
+            .map_err(|e| Error::Io(std::io::Error::from(e)))
+    }
+
+    /// Writes the JSON report to `path`.
+    pub fn write_report(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.report_json()?)?;
+        Ok(())
+    }
+
+    /// Returns whether the hook called `name` installed by `module` is
+    /// enabled, or `None` if there is no such hook.
+    pub fn is_hook_enabled(&self, module: &str, name: &str) -> Option<bool> {
+        self.guards
+            .iter()
+            .find(|(owner, guard)| *owner == module && guard.name() == name)
+            .map(|(_, guard)| guard.is_enabled())
+    }
+
+    /// Enables or disables the hook called `name` installed by `module`
+    /// without shutting the module down. Names only need to be unique within
+    /// a module.
+    pub fn set_hook_enabled(&mut self, module: &str, name: &str, enabled: bool) -> Result<()> {
+        let (_, guard) = self
+            .guards
+            .iter_mut()
+            .find(|(owner, guard)| *owner == module && guard.name() == name)
+            .ok_or_else(|| Error::HookNotFound(format!("{module}/{name}")))?;
+        guard.set_enabled(enabled)
+    }
+
+    /// Enables or disables every toggleable hook installed by `module`.
+    ///
+    /// If one hook fails, the hooks already toggled are switched back before
+    /// the error is returned.
+    pub fn set_module_enabled(&mut self, module: &str, enabled: bool) -> Result<()> {
+        if !self.modules.iter().any(|m| m.name() == module) {
+            return Err(Error::HookModuleNotFound(module.to_string()));
+        }
+        let mut toggled: Vec<&mut HookGuard> = Vec::new();
+        for (_, guard) in self.guards.iter_mut().filter(|(owner, guard)| {
+            *owner == module && guard.is_toggleable() && guard.is_enabled() != enabled
+        }) {
+            if let Err(error) = guard.set_enabled(enabled) {
+                for guard in toggled {
+                    if let Err(restore) = guard.set_enabled(!enabled) {
+                        log::error!("failed to restore hook {}: {restore}", guard.name());
+                    }
+                }
+                return Err(error);
+            }
+            toggled.push(guard);
+        }
+        log::info!(
+            "module {}: {}",
+            module,
+            if enabled { "enabled" } else { "disabled" }
+        );
+        Ok(())
+    }
 }
 
 /// A type-erased global manager so you can plug in your cheat-wide config type.
@@ -273,3 +1131,385 @@ where
 {
     let _ = with_manager::<C, _>(|mgr| mgr.stop());
 }
+
+/// Enables or disables a hook of the global manager by module and name.
+///
+/// Must not be called from `HookModule::init` or `shutdown`, which run while
+/// the global manager is locked.
+pub fn set_hook_enabled<C>(module: &str, name: &str, enabled: bool) -> Result<()>
+where
+    C: Send + Sync + 'static,
+{
+    with_manager::<C, _>(|mgr| mgr.set_hook_enabled(module, name, enabled))
+        .unwrap_or_else(|| Err(Error::HookNotFound(format!("{module}/{name}"))))
+}
+
+/// Enables or disables all hooks of a module of the global manager, so a
+/// module can turn itself off and on at runtime.
+///
+/// Must not be called from `HookModule::init` or `shutdown`, which run while
+/// the global manager is locked.
+pub fn set_module_enabled<C>(module: &str, enabled: bool) -> Result<()>
+where
+    C: Send + Sync + 'static,
+{
+    with_manager::<C, _>(|mgr| mgr.set_module_enabled(module, enabled))
+        .unwrap_or_else(|| Err(Error::HookModuleNotFound(module.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Patch(Arc<AtomicUsize>);
+
+    impl Drop for Patch {
+        fn drop(&mut self) {
+            self.0.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_toggleable_guard() {
+        let live = Arc::new(AtomicUsize::new(0));
+        let counter = live.clone();
+        let mut guard = HookGuard::toggleable("patch", move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(Patch(counter.clone()))
+        })
+        .unwrap();
+        assert!(guard.is_enabled() && guard.is_toggleable());
+        assert_eq!(live.load(Ordering::SeqCst), 1);
+
+        guard.disable().unwrap();
+        guard.disable().unwrap();
+        assert!(!guard.is_enabled());
+        assert_eq!(live.load(Ordering::SeqCst), 0);
+
+        guard.enable().unwrap();
+        guard.enable().unwrap();
+        assert_eq!(live.load(Ordering::SeqCst), 1);
+        drop(guard);
+        assert_eq!(live.load(Ordering::SeqCst), 0);
+
+        let mut owned = HookGuard::own(());
+        assert!(matches!(owned.disable(), Err(Error::HookNotToggleable(_))));
+        assert!(owned.is_enabled());
+    }
+
+    type Triple = unsafe extern "win64" fn(u32) -> u32;
+
+    #[inline(never)]
+    unsafe extern "win64" fn triple(value: u32) -> u32 {
+        let value = std::hint::black_box(value);
+        std::hint::black_box(value.wrapping_mul(3) ^ std::hint::black_box(0))
+    }
+
+    struct TripleModule;
+
+    impl HookModule<()> for TripleModule {
+        fn name(&self) -> &'static str {
+            "TripleModule"
+        }
+
+        fn init(&mut self, ctx: &HookContext<()>) -> Result<Vec<HookGuard>> {
+            let guard = unsafe {
+                ctx.hook::<Triple, _>(triple as Triple as usize, |original, (value,)| {
+                    original(value) + 1
+                })?
+            };
+            Ok(vec![guard.named("triple")])
+        }
+    }
+
+    #[test]
+    fn test_manager_toggles_hooks() {
+        let target = std::hint::black_box(triple as Triple);
+        let mut manager = HookManager::new(());
+        manager.register(TripleModule);
+        manager.start().unwrap();
+        assert_eq!(unsafe { target(2) }, 7);
+
+        let info = &manager.hook_info()[0];
+        assert_eq!(
+            (info.kind, info.target, info.calls),
+            (HookKind::Typed, Some(triple as Triple as usize), 1)
+        );
+        assert_eq!(info.original_bytes.len(), info::PATCH_SIZE);
+        assert!(info.trampoline.is_some() && info.installed_at.is_some());
+        let trampoline = info.trampoline;
+        assert!(
+            manager
+                .report_json()
+                .unwrap()
+                .contains("\"name\": \"triple\"")
+        );
+
+        manager
+            .set_hook_enabled("TripleModule", "triple", false)
+            .unwrap();
+        assert_eq!(unsafe { target(2) }, 6);
+        assert_eq!(
+            manager.is_hook_enabled("TripleModule", "triple"),
+            Some(false)
+        );
+
+        manager.set_module_enabled("TripleModule", true).unwrap();
+        assert_eq!(unsafe { target(2) }, 7);
+        // The disabled hook was kept and reapplied, not installed again.
+        assert_eq!(manager.hook_info()[0].trampoline, trampoline);
+        assert_eq!(
+            manager.hooks(),
+            vec![HookStatus {
+                module: "TripleModule",
+                name: String::from("triple"),
+                enabled: true,
+                toggleable: true,
+            }]
+        );
+
+        assert!(matches!(
+            manager.set_hook_enabled("TripleModule", "missing", true),
+            Err(Error::HookNotFound(_))
+        ));
+        manager.stop();
+        assert_eq!(unsafe { target(2) }, 6);
+    }
+
+    /// Installs a hook with the same name as every other instance.
+    struct SharedName(&'static str);
+
+    impl HookModule<()> for SharedName {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn init(&mut self, _ctx: &HookContext<()>) -> Result<Vec<HookGuard>> {
+            Ok(vec![HookGuard::toggleable("shared", || Ok(()))?])
+        }
+    }
+
+    /// Installs three hooks whose middle one can only be installed once.
+    struct FlakyModule;
+
+    impl HookModule<()> for FlakyModule {
+        fn name(&self) -> &'static str {
+            "FlakyModule"
+        }
+
+        fn init(&mut self, _ctx: &HookContext<()>) -> Result<Vec<HookGuard>> {
+            let installs = AtomicUsize::new(0);
+            Ok(vec![
+                HookGuard::toggleable("first", || Ok(()))?,
+                HookGuard::toggleable("flaky", move || {
+                    match installs.fetch_add(1, Ordering::SeqCst) {
+                        0 => Ok(()),
+                        _ => Err(Error::HookNotFound(String::from("flaky"))),
+                    }
+                })?,
+                HookGuard::toggleable("last", || Ok(()))?,
+            ])
+        }
+    }
+
+    #[test]
+    fn test_module_toggle_rolls_back_on_failure() {
+        let mut manager = HookManager::new(());
+        manager.register(FlakyModule);
+        manager.start().unwrap();
+        manager.set_module_enabled("FlakyModule", false).unwrap();
+
+        assert!(matches!(
+            manager.set_module_enabled("FlakyModule", true),
+            Err(Error::HookNotFound(_))
+        ));
+        for name in ["first", "flaky", "last"] {
+            assert_eq!(manager.is_hook_enabled("FlakyModule", name), Some(false));
+        }
+        manager.stop();
+    }
+
+    #[test]
+    fn test_hooks_are_found_by_module_and_name() {
+        let mut manager = HookManager::new(());
+        manager.register(SharedName("first"));
+        manager.register(SharedName("second"));
+        manager.start().unwrap();
+
+        manager.set_hook_enabled("second", "shared", false).unwrap();
+        assert_eq!(manager.is_hook_enabled("first", "shared"), Some(true));
+        assert_eq!(manager.is_hook_enabled("second", "shared"), Some(false));
+        assert!(matches!(
+            manager.set_hook_enabled("third", "shared", true),
+            Err(Error::HookNotFound(_))
+        ));
+        manager.stop();
+    }
+
+    struct PatchModule {
+        name: &'static str,
+        live: Arc<AtomicUsize>,
+        fail: bool,
+        required: bool,
+        shutdowns: Arc<AtomicUsize>,
+        target: Option<&'static str>,
+        base: Arc<AtomicUsize>,
+    }
+
+    impl PatchModule {
+        fn new(name: &'static str, live: &Arc<AtomicUsize>) -> Self {
+            Self {
+                name,
+                live: live.clone(),
+                fail: false,
+                required: true,
+                shutdowns: Arc::new(AtomicUsize::new(0)),
+                target: None,
+                base: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+    }
+
+    impl HookModule<()> for PatchModule {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn init(&mut self, ctx: &HookContext<()>) -> Result<Vec<HookGuard>> {
+            if let Some(module) = ctx.target_module() {
+                self.base.store(module.base, Ordering::SeqCst);
+            }
+            let live = self.live.clone();
+            let guard = HookGuard::toggleable(self.name, move || {
+                live.fetch_add(1, Ordering::SeqCst);
+                Ok(Patch(live.clone()))
+            })?;
+            if self.fail {
+                return Err(Error::HookNotFound(self.name.to_string()));
+            }
+            Ok(vec![guard])
+        }
+
+        fn shutdown(&mut self) {
+            self.shutdowns.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn required(&self) -> bool {
+            self.required
+        }
+
+        fn target_module(&self) -> Option<&'static str> {
+            self.target
+        }
+    }
+
+    #[test]
+    fn test_start_rolls_back_on_required_failure() {
+        let live = Arc::new(AtomicUsize::new(0));
+        let shutdowns = Arc::new(AtomicUsize::new(0));
+        let mut manager = HookManager::new(());
+        manager.register(PatchModule {
+            shutdowns: shutdowns.clone(),
+            ..PatchModule::new("first", &live)
+        });
+        manager.register(PatchModule {
+            fail: true,
+            ..PatchModule::new("second", &live)
+        });
+        manager.register(PatchModule::new("third", &live));
+
+        let error = manager.start().unwrap_err();
+        assert!(matches!(error, Error::HookModuleStart { ref module, .. } if module == "second"));
+        assert_eq!(live.load(Ordering::SeqCst), 0);
+        assert!(!manager.is_started() && manager.hooks().is_empty());
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+
+        let error = manager.start().unwrap_err();
+        assert!(matches!(error, Error::HookModuleStart { .. }));
+        assert_eq!(live.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_start_skips_optional_failure() {
+        let live = Arc::new(AtomicUsize::new(0));
+        let mut manager = HookManager::new(());
+        manager.register(PatchModule::new("first", &live));
+        manager.register(PatchModule {
+            fail: true,
+            required: false,
+            ..PatchModule::new("second", &live)
+        });
+        manager.register(PatchModule::new("third", &live));
+
+        manager.start().unwrap();
+        assert!(manager.is_started());
+        assert_eq!(live.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            manager.failures(),
+            [ModuleFailure {
+                module: "second",
+                error: String::from("Hook not found: second"),
+            }]
+        );
+
+        manager.stop();
+        assert_eq!(live.load(Ordering::SeqCst), 0);
+        assert!(manager.failures().is_empty());
+    }
+
+    #[test]
+    fn test_deferred_module_follows_dll() {
+        let live = Arc::new(AtomicUsize::new(0));
+        let deferred = PatchModule {
+            target: Some("plugin.dll"),
+            ..PatchModule::new("deferred", &live)
+        };
+        let (shutdowns, base) = (deferred.shutdowns.clone(), deferred.base.clone());
+        let loaded = |base| {
+            ModuleEvent::Loaded(LoadedModule {
+                name: String::from("PLUGIN.DLL"),
+                base,
+                size: 0x1000,
+            })
+        };
+        let mut manager = HookManager::new(());
+        manager.register(PatchModule::new("eager", &live));
+        manager.register(deferred);
+
+        manager.handle_module_event(loaded(0x1000));
+        manager.start().unwrap();
+        assert_eq!(live.load(Ordering::SeqCst), 1);
+        assert_eq!(manager.watched_modules(), [("plugin.dll", None)]);
+
+        manager.handle_module_event(loaded(0x1000));
+        manager.handle_module_event(loaded(0x1000));
+        assert_eq!(live.load(Ordering::SeqCst), 2);
+        assert_eq!(base.load(Ordering::SeqCst), 0x1000);
+        assert_eq!(manager.is_hook_enabled("deferred", "deferred"), Some(true));
+
+        manager.handle_module_event(ModuleEvent::Unloading(String::from("plugin.dll")));
+        assert_eq!(live.load(Ordering::SeqCst), 1);
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+        assert_eq!(manager.is_hook_enabled("deferred", "deferred"), None);
+
+        // A vanished DLL leaks its hooks instead of restoring them.
+        manager.handle_module_event(loaded(0x2000));
+        manager.handle_module_event(ModuleEvent::Gone(String::from("plugin.dll")));
+        assert_eq!(live.load(Ordering::SeqCst), 2);
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 2);
+
+        // So does an unload of a DLL that could not be pinned, as it may be
+        // unmapped by the time the event is handled.
+        manager.handle_module_event(loaded(0x3000));
+        manager.attached[0].2 = None;
+        manager.handle_module_event(ModuleEvent::Unloading(String::from("plugin.dll")));
+        assert_eq!(live.load(Ordering::SeqCst), 3);
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 3);
+
+        manager.handle_module_event(loaded(0x4000));
+        manager.stop();
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 4);
+        assert_eq!(live.load(Ordering::SeqCst), 2);
+    }
+}
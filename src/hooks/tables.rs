@@ -0,0 +1,373 @@
+//! Import and export table hooks.
+//!
+//! Instead of patching code, these hooks swap a function pointer in a loaded
+//! module's tables:
+//!
+//! - an Import Address Table (IAT) slot redirects the calls one module makes
+//!   to a function it imports, leaving every other caller alone;
+//! - an Export Address Table (EAT) slot redirects later `GetProcAddress`
+//!   lookups and imports bound after the patch. Pointers resolved earlier
+//!   keep reaching the original function.
+//!
+//! The tables are found by walking the module's mapped image with
+//! [`PeImage`], so the same code runs against test images built in memory.
+
+use crate::errors::{Error, Result};
+use crate::pe::PeImage;
+
+/// Size of the page read to find `SizeOfImage` before mapping the whole image.
+const HEADER_SIZE: usize = 0x1000;
+
+/// Returns the base address of a module loaded in the current process.
+pub fn module_base(module: &str) -> Result<usize> {
+    #[cfg(target_os = "windows")]
+    let base = crate::winapi::module_base(module);
+    #[cfg(not(target_os = "windows"))]
+    let base = None;
+    base.ok_or_else(|| Error::ModuleNotLoaded(module.to_string()))
+}
+
+/// Views the module mapped at `base` as a PE image.
+///
+/// # Safety
+///
+/// `base` must be the base of a module that stays mapped while the image is used.
+pub unsafe fn module_image(base: usize) -> Result<PeImage<'static>> {
+    let header = unsafe { core::slice::from_raw_parts(base as *const u8, HEADER_SIZE) };
+    let size = PeImage::parse(header, base)?.size_of_image() as usize;
+    let image = unsafe { core::slice::from_raw_parts(base as *const u8, size.max(HEADER_SIZE)) };
+    PeImage::parse(image, base)
+}
+
+/// Returns the address of the IAT slot through which the module at `base`
+/// calls `dll!function`.
+///
+/// # Safety
+///
+/// See [`module_image`].
+pub unsafe fn import_slot(base: usize, dll: &str, function: &str) -> Result<usize> {
+    let image = unsafe { module_image(base) }?;
+    let import = image
+        .find_import(dll, function)
+        .ok_or_else(|| Error::ImportNotFound {
+            base,
+            dll: dll.to_string(),
+            function: function.to_string(),
+        })?;
+    Ok(base + import.slot as usize)
+}
+
+/// Returns the address the module at `base` currently calls for
+/// `dll!function`. Read it before installing an IAT hook to keep the original.
+///
+/// # Safety
+///
+/// See [`module_image`].
+pub unsafe fn import_address(base: usize, dll: &str, function: &str) -> Result<usize> {
+    let slot = unsafe { import_slot(base, dll, function) }?;
+    Ok(unsafe { (slot as *const usize).read_volatile() })
+}
+
+/// Returns the address of the EAT slot holding the RVA of `function` in the
+/// module at `base`.
+///
+/// # Safety
+///
+/// See [`module_image`].
+pub unsafe fn export_slot(base: usize, function: &str) -> Result<usize> {
+    let image = unsafe { module_image(base) }?;
+    let export = image
+        .find_export(function)
+        .filter(|export| export.forwarder.is_none())
+        .ok_or_else(|| Error::ExportNotFound {
+            base,
+            function: function.to_string(),
+        })?;
+    Ok(base + export.slot as usize)
+}
+
+/// Returns the address `function` currently resolves to in the module at `base`.
+///
+/// # Safety
+///
+/// See [`module_image`].
+pub unsafe fn export_address(base: usize, function: &str) -> Result<usize> {
+    let slot = unsafe { export_slot(base, function) }?;
+    Ok(base + unsafe { (slot as *const u32).read_volatile() } as usize)
+}
+
+/// Returns the EAT value that makes the module at `base` export `replacement`.
+///
+/// EAT entries are 32-bit RVAs, so a replacement outside the 4 GiB above the
+/// module goes through a jump stub allocated there. The stub is never freed:
+/// code that resolved the export while it was hooked may still call it.
+pub(crate) fn export_rva(base: usize, size_of_image: usize, replacement: usize) -> Result<u32> {
+    if let Some(rva) = replacement
+        .checked_sub(base)
+        .and_then(|rva| u32::try_from(rva).ok())
+    {
+        return Ok(rva);
+    }
+    let mut stub = [0u8; 14];
+    stub[..6].copy_from_slice(&[0xFF, 0x25, 0, 0, 0, 0]);
+    stub[6..].copy_from_slice(&(replacement as u64).to_le_bytes());
+    let no_memory = || Error::WriteFailed {
+        address: replacement,
+        reason: format!("no memory for an export stub within 4 GiB of 0x{base:X}"),
+    };
+    let stub = system::alloc_between(base + size_of_image, base + u32::MAX as usize, &stub)
+        .ok_or_else(no_memory)?;
+    u32::try_from(stub - base).map_err(|_| no_memory())
+}
+
+/// A table slot overwritten with a new value, restored on drop.
+pub(crate) struct SlotPatch {
+    address: usize,
+    original: u64,
+    size: usize,
+}
+
+impl SlotPatch {
+    /// Overwrites the pointer-sized IAT slot at `address` with `value`.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be an IAT slot of a loaded module.
+    pub(crate) unsafe fn pointer(address: usize, value: usize) -> Result<Self> {
+        unsafe { Self::write(address, value as u64, 8) }
+    }
+
+    /// Overwrites the 32-bit EAT slot at `address` with `rva`.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be an EAT slot of a loaded module.
+    pub(crate) unsafe fn rva(address: usize, rva: u32) -> Result<Self> {
+        unsafe { Self::write(address, rva as u64, 4) }
+    }
+
+    unsafe fn write(address: usize, value: u64, size: usize) -> Result<Self> {
+        let original = unsafe { read_slot(address, size) };
+        unsafe { system::write_slot(address, value, size) }?;
+        Ok(Self {
+            address,
+            original,
+            size,
+        })
+    }
+}
+
+impl Drop for SlotPatch {
+    fn drop(&mut self) {
+        if let Err(error) = unsafe { system::write_slot(self.address, self.original, self.size) } {
+            log::error!("failed to restore table slot 0x{:x}: {error}", self.address);
+        }
+    }
+}
+
+/// Reads a 4 or 8 byte slot.
+///
+/// # Safety
+///
+/// `address` must point to `size` readable, aligned bytes.
+pub(crate) unsafe fn read_slot(address: usize, size: usize) -> u64 {
+    match size {
+        4 => unsafe { (address as *const u32).read_volatile() as u64 },
+        _ => unsafe { (address as *const u64).read_volatile() },
+    }
+}
+
+/// Writes a slot with a single aligned store, so other threads see either the
+/// old or the new pointer.
+unsafe fn store_slot(address: usize, value: u64, size: usize) {
+    match size {
+        4 => unsafe { (address as *mut u32).write_volatile(value as u32) },
+        _ => unsafe { (address as *mut u64).write_volatile(value) },
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod system {
+    use core::ffi::c_void;
+
+    use windows::Win32::System::Memory::{
+        MEM_COMMIT, MEM_RESERVE, PAGE_EXECUTE_READWRITE, PAGE_PROTECTION_FLAGS, VirtualAlloc,
+        VirtualProtect,
+    };
+
+    use crate::errors::{Error, Result};
+
+    /// Allocation granularity of `VirtualAlloc` reservations.
+    const GRANULARITY: usize = 0x1_0000;
+
+    /// Writes a table slot, making its page writable for the duration.
+    ///
+    /// The page keeps execute access while unprotected, since tables can share
+    /// a page with code that other threads are running.
+    pub(super) unsafe fn write_slot(address: usize, value: u64, size: usize) -> Result<()> {
+        let failed = |error: windows::core::Error| Error::WriteFailed {
+            address,
+            reason: format!("VirtualProtect failed: {error}"),
+        };
+        let mut old = PAGE_PROTECTION_FLAGS::default();
+        unsafe {
+            VirtualProtect(
+                address as *const c_void,
+                size,
+                PAGE_EXECUTE_READWRITE,
+                &mut old,
+            )
+        }
+        .map_err(failed)?;
+        unsafe { super::store_slot(address, value, size) };
+        let mut ignored = PAGE_PROTECTION_FLAGS::default();
+        unsafe { VirtualProtect(address as *const c_void, size, old, &mut ignored) }.map_err(failed)
+    }
+
+    /// Copies `code` to executable memory at the first free address at or
+    /// above `start` where it ends at or below `end`.
+    pub(super) fn alloc_between(start: usize, end: usize, code: &[u8]) -> Option<usize> {
+        let first = start.next_multiple_of(GRANULARITY);
+        let last = end.checked_sub(code.len())?;
+        (first..=last)
+            .step_by(GRANULARITY)
+            .find_map(|candidate| {
+                let memory = unsafe {
+                    VirtualAlloc(
+                        Some(candidate as *const c_void),
+                        code.len(),
+                        MEM_COMMIT | MEM_RESERVE,
+                        PAGE_EXECUTE_READWRITE,
+                    )
+                };
+                (!memory.is_null()).then_some(memory as usize)
+            })
+            .inspect(|&memory| unsafe {
+                core::ptr::copy_nonoverlapping(code.as_ptr(), memory as *mut u8, code.len());
+            })
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod system {
+    use crate::errors::Result;
+
+    pub(super) unsafe fn write_slot(address: usize, value: u64, size: usize) -> Result<()> {
+        unsafe { super::store_slot(address, value, size) };
+        Ok(())
+    }
+
+    pub(super) fn alloc_between(_start: usize, _end: usize, _code: &[u8]) -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::HookContext;
+    use crate::pe::fixtures::{BOUND, build_image, put_exports, put_imports};
+    use std::sync::{Arc, RwLock};
+
+    /// An image padded so its base is 8-byte aligned, like a mapped module.
+    struct Mapped(Vec<u64>);
+
+    impl Mapped {
+        fn new(image: &[u8]) -> Self {
+            let mut words = vec![0u64; image.len().div_ceil(8)];
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    image.as_ptr(),
+                    words.as_mut_ptr().cast(),
+                    image.len(),
+                )
+            };
+            Self(words)
+        }
+
+        fn base(&self) -> usize {
+            self.0.as_ptr() as usize
+        }
+    }
+
+    fn context() -> HookContext<()> {
+        HookContext {
+            config: Arc::new(RwLock::new(())),
+            freeze_threads: false,
+            module: None,
+        }
+    }
+
+    #[test]
+    fn test_iat_hook_patches_and_restores_slot() {
+        let mut image = build_image(&[0xC3], &[], &[]);
+        put_imports(
+            &mut image,
+            &[("KERNEL32.dll", &["CloseHandle", "CreateFileW"])],
+        );
+        let module = Mapped::new(&image);
+        let base = module.base();
+
+        let original = unsafe { import_address(base, "kernel32.dll", "CreateFileW") }.unwrap();
+        assert_eq!(original as u64, BOUND + 0x10);
+        let mut guard =
+            unsafe { context().install_iat_at(base, "kernel32.dll", "CreateFileW", 0x1234) }
+                .unwrap();
+        assert_eq!(
+            unsafe { import_address(base, "kernel32.dll", "CreateFileW") }.unwrap(),
+            0x1234
+        );
+        assert_eq!(
+            unsafe { import_address(base, "kernel32.dll", "CloseHandle") }.unwrap() as u64,
+            BOUND
+        );
+
+        let info = guard.info("test");
+        assert_eq!(info.kind, crate::hooks::HookKind::Iat);
+        assert_eq!(info.trampoline, Some(0x1234));
+        assert_eq!(info.original_bytes, (original as u64).to_le_bytes());
+
+        guard.disable().unwrap();
+        assert_eq!(
+            unsafe { import_address(base, "kernel32.dll", "CreateFileW") }.unwrap(),
+            original
+        );
+        guard.enable().unwrap();
+        drop(guard);
+        assert_eq!(
+            unsafe { import_address(base, "kernel32.dll", "CreateFileW") }.unwrap(),
+            original
+        );
+        assert!(unsafe { context().install_iat_at(base, "user32.dll", "CreateFileW", 0) }.is_err());
+    }
+
+    #[test]
+    fn test_eat_hook_patches_and_restores_slot() {
+        let mut image = build_image(&[0xC3], &[], &[]);
+        put_exports(
+            &mut image,
+            "game.exe",
+            &[("Tick", 0x1000), ("Render", 0x1040)],
+        );
+        let module = Mapped::new(&image);
+        let base = module.base();
+
+        let guard = unsafe { context().install_eat_at(base, "Render", base + 0x1080) }.unwrap();
+        assert_eq!(
+            unsafe { export_address(base, "Render") }.unwrap(),
+            base + 0x1080
+        );
+        assert_eq!(
+            unsafe { export_address(base, "Tick") }.unwrap(),
+            base + 0x1000
+        );
+        drop(guard);
+        assert_eq!(
+            unsafe { export_address(base, "Render") }.unwrap(),
+            base + 0x1040
+        );
+
+        assert_eq!(export_rva(0x1_0000, 0x4000, 0x1_2345).unwrap(), 0x2345);
+        assert!(unsafe { context().install_eat_at(base, "Update", base) }.is_err());
+    }
+}
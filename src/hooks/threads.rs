@@ -0,0 +1,1287 @@
+//! Thread suspension around code patching.
+//!
+//! While ilhook overwrites the first bytes of a target, other threads may be
+//! executing them. [`ThreadFreezer`] plugs into ilhook's thread callbacks: it
+//! suspends every other thread before the patch, moves threads whose
+//! instruction pointer lies inside the overwritten bytes, and resumes them
+//! after the patch. The threads themselves are reached through
+//! [`ThreadControl`], so the fixup logic can be tested without Windows.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ilhook::HookError;
+use ilhook::x64::{CallbackOption, HookFlags, HookPoint, HookType, Hooker, ThreadCallback};
+
+use super::info::{
+    ORIGINAL_WINDOW, PATCH_SIZE, covered_instructions, jump_destination, jump_length,
+};
+use crate::disasm;
+use crate::errors::{Error, Result};
+
+/// How often a patch blocked by a thread is attempted.
+const FREEZE_ATTEMPTS: usize = 20;
+
+/// Largest relocation stub: the tail of the patched instructions followed by
+/// an absolute jump back.
+const STUB_CAPACITY: usize = ORIGINAL_WINDOW + 14;
+
+/// Longest code ilhook 2.1.2 writes ahead of the moved instructions in a
+/// trampoline: saving the registers, calling the detour and restoring them.
+const TRAMPOLINE_HEADER: usize = 139 + 135;
+
+/// Most bytes ilhook 2.1.2 emits for one moved instruction, counting its entry
+/// in the relocation table.
+const RELOCATED_MAX: usize = 40;
+
+/// The jump over ilhook's relocation table and the padding that aligns it.
+const RELOCATION_TABLE: usize = 12;
+
+/// Size of ilhook's absolute jump back to the target.
+const JUMP_BACK: usize = 14;
+
+/// Returns how many bytes of a trampoline can hold code when ilhook moved
+/// `instructions` instructions into it. This stays within the buffer ilhook
+/// allocates for up to [`PATCH_SIZE`] instructions.
+pub const fn trampoline_len(instructions: usize) -> usize {
+    TRAMPOLINE_HEADER + RELOCATION_TABLE + instructions * RELOCATED_MAX + JUMP_BACK
+}
+
+/// Access to the other threads of the process.
+///
+/// Everything except [`threads`](Self::threads) runs while threads are
+/// suspended, so implementations must not allocate from the heap or log.
+pub trait ThreadControl: Send + 'static {
+    type Thread: Send;
+
+    /// Lists every thread of the process except the calling one.
+    fn threads(&mut self) -> Result<Vec<Self::Thread>>;
+
+    /// Suspends `thread`, returning false if it could not be suspended.
+    fn suspend(&mut self, thread: &Self::Thread) -> bool;
+
+    fn resume(&mut self, thread: &Self::Thread);
+
+    fn instruction_pointer(&mut self, thread: &Self::Thread) -> Option<usize>;
+
+    fn set_instruction_pointer(&mut self, thread: &Self::Thread, address: usize) -> bool;
+
+    /// Copies `code` to executable memory, returning its address.
+    fn alloc_code(&mut self, code: &[u8]) -> Option<usize>;
+
+    /// Frees memory returned by [`alloc_code`](Self::alloc_code).
+    fn free_code(&mut self, address: usize);
+}
+
+/// The code a hooked target jumps to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trampoline {
+    pub address: usize,
+    /// Bytes at `address` that can hold code.
+    pub len: usize,
+    /// Length of the instructions ilhook moved out of the target.
+    pub moved: usize,
+}
+
+impl Trampoline {
+    /// Locates the trampoline of the hook at `target`, whose bytes before
+    /// hooking are `original`, from the jump written over it.
+    ///
+    /// # Safety
+    ///
+    /// `target` must point to at least [`PATCH_SIZE`] readable bytes.
+    pub unsafe fn of(target: usize, original: &[u8]) -> Option<Self> {
+        let jump = unsafe { core::slice::from_raw_parts(target as *const u8, PATCH_SIZE) };
+        let (moved, instructions) =
+            covered_instructions(original, target, jump_length(jump)?).ok()?;
+        Some(Self {
+            address: unsafe { jump_destination(target) }?,
+            len: trampoline_len(instructions),
+            moved,
+        })
+    }
+}
+
+/// A relocation stub some thread was moved into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StubCode {
+    pub address: usize,
+    pub len: usize,
+}
+
+impl StubCode {
+    fn contains(&self, rip: usize) -> bool {
+        (self.address..self.address + self.len).contains(&rip)
+    }
+}
+
+/// Instructions copied out of the patched range for a thread stopped in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stub {
+    code: [u8; STUB_CAPACITY],
+    len: usize,
+}
+
+impl Stub {
+    pub fn code(&self) -> &[u8] {
+        &self.code[..self.len]
+    }
+}
+
+/// What to do with a suspended thread when its target is patched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fixup {
+    /// The thread is outside the patched bytes.
+    None,
+    /// The thread is at the target itself and is moved to the trampoline.
+    Entry,
+    /// The thread is inside the patched instructions and continues in a copy
+    /// of the remaining ones.
+    Relocate(Stub),
+    /// The thread is in the trampoline's copy of the patched instructions and
+    /// is moved back to the original instruction at this address.
+    Original(usize),
+    /// The thread cannot be moved safely; patching must be retried.
+    Blocked,
+}
+
+/// Decides how to move a thread at `rip` when the code at `target`, whose
+/// bytes before patching are `original`, is patched.
+pub fn plan_fixup(original: &[u8], target: usize, rip: usize) -> Fixup {
+    if rip == target {
+        return Fixup::Entry;
+    }
+    let Some(offset) = rip
+        .checked_sub(target)
+        .filter(|&offset| offset < PATCH_SIZE)
+    else {
+        return Fixup::None;
+    };
+
+    let mut stub = Stub {
+        code: [0; STUB_CAPACITY],
+        len: 0,
+    };
+    let mut cursor = 0;
+    while cursor < PATCH_SIZE {
+        let Ok(instruction) = disasm::decode(&original[cursor..], target + cursor) else {
+            return Fixup::Blocked;
+        };
+        let end = cursor + instruction.length;
+        if cursor >= offset {
+            // Copied instructions must behave the same at the stub's address.
+            if instruction.relative.is_some() || instruction.memory.is_some_and(|m| m.rip_relative)
+            {
+                return Fixup::Blocked;
+            }
+            stub.code[stub.len..stub.len + instruction.length]
+                .copy_from_slice(&original[cursor..end]);
+            stub.len += instruction.length;
+        }
+        cursor = end;
+        if cursor > offset && stub.len == 0 {
+            // The thread is stopped inside an instruction we decoded differently.
+            return Fixup::Blocked;
+        }
+    }
+
+    // jmp qword ptr [rip+0] back to the first instruction after the patch.
+    let back = (target + cursor) as u64;
+    stub.code[stub.len..stub.len + 6].copy_from_slice(&[0xFF, 0x25, 0, 0, 0, 0]);
+    stub.code[stub.len + 6..stub.len + 14].copy_from_slice(&back.to_le_bytes());
+    stub.len += 14;
+    Fixup::Relocate(stub)
+}
+
+/// Decides how to move a thread at `rip` when the hook at `target`, whose
+/// bytes before hooking are `original`, is removed. `trampoline` holds the
+/// code the hook jumps to, at `trampoline_address`, and ilhook moved the first
+/// `moved` bytes of the target into it.
+///
+/// ilhook ends the trampoline with a copy of the moved instructions and a
+/// jump back to the first instruction after them. Instructions copied without
+/// relocation line up with the original; a thread at the entry goes back to
+/// `target`, and one anywhere else in the trampoline cannot be moved.
+pub fn plan_unhook_fixup(
+    original: &[u8],
+    target: usize,
+    moved: usize,
+    trampoline: &[u8],
+    trampoline_address: usize,
+    rip: usize,
+) -> Fixup {
+    let Some(position) = rip
+        .checked_sub(trampoline_address)
+        .filter(|&position| position < trampoline.len())
+    else {
+        return Fixup::None;
+    };
+    // A thread moved to the trampoline's entry has not run any of it yet.
+    if position == 0 {
+        return Fixup::Original(target);
+    }
+
+    // Starts of the instructions after the last one ilhook had to relocate.
+    let mut unchanged = [0; PATCH_SIZE];
+    let mut count = 0;
+    let mut end = 0;
+    while end < moved {
+        let Ok(instruction) = disasm::decode(&original[end..], target + end) else {
+            return Fixup::Blocked;
+        };
+        if instruction.relative.is_some() || instruction.memory.is_some_and(|m| m.rip_relative) {
+            count = 0;
+        } else {
+            unchanged[count] = end;
+            count += 1;
+        }
+        end += instruction.length;
+    }
+
+    // jmp qword ptr [rip+0] back to the first instruction after the patch.
+    let mut back = [0; JUMP_BACK];
+    back[..6].copy_from_slice(&[0xFF, 0x25, 0, 0, 0, 0]);
+    back[6..].copy_from_slice(&((target + end) as u64).to_le_bytes());
+    let Some(jump) = trampoline
+        .windows(back.len())
+        .position(|window| window == back)
+    else {
+        return Fixup::Blocked;
+    };
+    if position == jump {
+        return Fixup::Original(target + end);
+    }
+    for &offset in &unchanged[..count] {
+        if jump.checked_sub(end - offset) == Some(position)
+            && trampoline[position..jump] == original[offset..end]
+        {
+            return Fixup::Original(target + offset);
+        }
+    }
+    Fixup::Blocked
+}
+
+/// Outcome of the last freeze, read after threads were resumed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FreezeReport {
+    pub suspended: usize,
+    pub moved_to_trampoline: usize,
+    pub relocated: usize,
+    /// Threads moved out of the trampoline when unhooking.
+    pub moved_to_original: usize,
+    /// Threads whose instruction pointer could not be updated.
+    pub failed: usize,
+    /// The patch was refused because a thread could not be moved.
+    pub blocked: bool,
+}
+
+struct FreezeState<T: ThreadControl> {
+    control: T,
+    threads: Vec<T::Thread>,
+    suspended: Vec<bool>,
+    fixups: Vec<Fixup>,
+    hooked: bool,
+    /// The trampoline the target jumps to while hooked.
+    trampoline: Option<Trampoline>,
+    /// Relocation stubs threads may still be running.
+    stubs: Vec<StubCode>,
+    report: FreezeReport,
+}
+
+impl<T: ThreadControl> FreezeState<T> {
+    fn resume_all(&mut self) {
+        for (thread, &suspended) in self.threads.iter().zip(&self.suspended) {
+            if suspended {
+                self.control.resume(thread);
+            }
+        }
+    }
+
+    /// Frees the relocation stubs no thread is running. A thread cannot enter
+    /// a stub on its own, so one that is idle while every thread is suspended
+    /// stays idle.
+    fn reclaim_stubs(&mut self) {
+        if self.suspended.contains(&false) {
+            return;
+        }
+        let Self {
+            control,
+            threads,
+            stubs,
+            ..
+        } = self;
+        stubs.retain(|stub| {
+            let busy = threads.iter().any(|thread| {
+                control
+                    .instruction_pointer(thread)
+                    .is_none_or(|rip| stub.contains(rip))
+            });
+            if !busy {
+                control.free_code(stub.address);
+            }
+            busy
+        });
+    }
+}
+
+/// Suspends other threads while ilhook patches or restores `target`.
+///
+/// Clones share their state, so one clone can be handed to ilhook and another
+/// kept to read the [`FreezeReport`].
+pub struct ThreadFreezer<T: ThreadControl> {
+    target: usize,
+    original: [u8; ORIGINAL_WINDOW],
+    state: Arc<Mutex<FreezeState<T>>>,
+}
+
+impl<T: ThreadControl> Clone for ThreadFreezer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            target: self.target,
+            original: self.original,
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T: ThreadControl> ThreadFreezer<T> {
+    /// Prepares to patch `target`, whose current bytes are `original`.
+    pub fn new(control: T, target: usize, original: [u8; ORIGINAL_WINDOW]) -> Self {
+        Self {
+            target,
+            original,
+            state: Arc::new(Mutex::new(FreezeState {
+                control,
+                threads: Vec::new(),
+                suspended: Vec::new(),
+                fixups: Vec::new(),
+                hooked: false,
+                trampoline: None,
+                stubs: Vec::new(),
+                report: FreezeReport::default(),
+            })),
+        }
+    }
+
+    /// Reads the bytes at `target` and prepares to patch it.
+    ///
+    /// # Safety
+    ///
+    /// `target` must point to at least [`ORIGINAL_WINDOW`] readable bytes.
+    pub unsafe fn at(control: T, target: usize) -> Self {
+        Self::new(control, target, unsafe { read_window(target) })
+    }
+
+    /// Prepares to restore the original bytes of a target hooked with
+    /// `trampoline` instead.
+    fn unhooking(self, trampoline: Option<Trampoline>) -> Self {
+        let mut state = self.state.lock().unwrap();
+        state.hooked = true;
+        state.trampoline = trampoline;
+        drop(state);
+        self
+    }
+
+    /// Hands over stubs from earlier patches of the target, to be freed once
+    /// no thread runs them.
+    fn with_stubs(self, stubs: Vec<StubCode>) -> Self {
+        self.state.lock().unwrap().stubs = stubs;
+        self
+    }
+
+    /// Takes the stubs threads may still be running.
+    pub fn take_stubs(&self) -> Vec<StubCode> {
+        std::mem::take(&mut self.state.lock().unwrap().stubs)
+    }
+
+    pub fn report(&self) -> FreezeReport {
+        self.state.lock().unwrap().report
+    }
+
+    /// Suspends all other threads and plans their fixups. Returns false, with
+    /// every thread resumed, if one of them cannot be moved.
+    fn freeze(&self) -> bool {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        // Allocate before suspending: a suspended thread may hold the heap lock.
+        state.threads = match state.control.threads() {
+            Ok(threads) => threads,
+            Err(_) => return false,
+        };
+        let count = state.threads.len();
+        state.suspended = Vec::with_capacity(count);
+        state.fixups = Vec::with_capacity(count);
+        state.stubs.reserve(count);
+        state.report = FreezeReport::default();
+
+        for thread in &state.threads {
+            let suspended = state.control.suspend(thread);
+            state.suspended.push(suspended);
+            state.report.suspended += suspended as usize;
+        }
+        for (thread, &suspended) in state.threads.iter().zip(&state.suspended) {
+            let fixup = match state.control.instruction_pointer(thread) {
+                Some(rip) if suspended && !state.hooked => {
+                    plan_fixup(&self.original, self.target, rip)
+                }
+                Some(rip) if suspended => match state.trampoline {
+                    Some(trampoline) => plan_unhook_fixup(
+                        &self.original,
+                        self.target,
+                        trampoline.moved,
+                        unsafe {
+                            core::slice::from_raw_parts(
+                                trampoline.address as *const u8,
+                                trampoline.len,
+                            )
+                        },
+                        trampoline.address,
+                        rip,
+                    ),
+                    None => Fixup::None,
+                },
+                _ => Fixup::None,
+            };
+            state.fixups.push(fixup);
+        }
+
+        if state.fixups.contains(&Fixup::Blocked) {
+            state.resume_all();
+            state.report.blocked = true;
+            return false;
+        }
+        true
+    }
+
+    /// Applies the planned fixups and resumes all threads.
+    fn thaw(&self) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        if !state.hooked {
+            state.trampoline = unsafe { Trampoline::of(self.target, &self.original) };
+        }
+        for (thread, fixup) in state.threads.iter().zip(&state.fixups) {
+            let destination = match fixup {
+                Fixup::Entry => {
+                    state.report.moved_to_trampoline += 1;
+                    state.trampoline.map(|trampoline| trampoline.address)
+                }
+                Fixup::Relocate(stub) => {
+                    state.report.relocated += 1;
+                    let address = state.control.alloc_code(stub.code());
+                    if let Some(address) = address {
+                        state.stubs.push(StubCode {
+                            address,
+                            len: stub.code().len(),
+                        });
+                    }
+                    address
+                }
+                Fixup::Original(address) => {
+                    state.report.moved_to_original += 1;
+                    Some(*address)
+                }
+                Fixup::None | Fixup::Blocked => continue,
+            };
+            let moved = destination
+                .is_some_and(|address| state.control.set_instruction_pointer(thread, address));
+            state.report.failed += !moved as usize;
+        }
+        state.hooked = !state.hooked;
+        state.reclaim_stubs();
+        state.resume_all();
+        state.threads.clear();
+        state.suspended.clear();
+        state.fixups.clear();
+    }
+}
+
+impl<T: ThreadControl> ThreadCallback for ThreadFreezer<T> {
+    fn pre(&self) -> bool {
+        self.freeze()
+    }
+
+    fn post(&self) {
+        self.thaw()
+    }
+}
+
+/// Installs a hook with ilhook while other threads are frozen by a
+/// [`ThreadFreezer`] over `control()`. A patch blocked by a thread that cannot
+/// be moved is retried a few times. Returns the hook with the relocation stubs
+/// threads were moved into.
+///
+/// # Safety
+///
+/// Same as [`Hooker::hook`](ilhook::x64::Hooker::hook).
+pub(crate) unsafe fn hook_frozen<T: ThreadControl>(
+    mut control: impl FnMut() -> T,
+    target_address: usize,
+    hook_type: impl Fn() -> HookType,
+    user_data: usize,
+) -> Result<(HookPoint, Vec<StubCode>)> {
+    let mut attempt = 1;
+    loop {
+        let freezer = unsafe { ThreadFreezer::at(control(), target_address) };
+        let result = unsafe {
+            Hooker::new(
+                target_address,
+                hook_type(),
+                CallbackOption::Some(Box::new(freezer.clone())),
+                user_data,
+                HookFlags::empty(),
+            )
+            .hook()
+        };
+        let report = freezer.report();
+        match result {
+            Ok(hook) => {
+                log::debug!(
+                    "patched 0x{target_address:x} with {} threads suspended, {} moved to the trampoline, {} relocated, {} failed",
+                    report.suspended,
+                    report.moved_to_trampoline,
+                    report.relocated,
+                    report.failed
+                );
+                return Ok((hook, freezer.take_stubs()));
+            }
+            Err(HookError::PreHook) if attempt < FREEZE_ATTEMPTS => {
+                log::debug!("thread blocks patching 0x{target_address:x}, retrying");
+                attempt += 1;
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Err(e) => return Err(Error::HookInstall(e)),
+        }
+    }
+}
+
+/// Writes `code` over `target` while other threads are frozen by a
+/// [`ThreadFreezer`] over `control()`, retrying like [`hook_frozen`].
+/// `hooked` tells whether `target` currently jumps to `trampoline`. Idle
+/// `stubs` are freed and new ones added.
+///
+/// # Safety
+///
+/// `original` must be the bytes of `target` before it was hooked, and `code`
+/// either their first [`PATCH_SIZE`] bytes or the hook's jump. `trampoline`
+/// must point to `trampoline.len` readable bytes.
+unsafe fn patch_frozen<T: ThreadControl>(
+    mut control: impl FnMut() -> T,
+    target: usize,
+    original: [u8; ORIGINAL_WINDOW],
+    hooked: bool,
+    trampoline: Option<Trampoline>,
+    code: &[u8],
+    stubs: &mut Vec<StubCode>,
+) -> Result<()> {
+    for _ in 0..FREEZE_ATTEMPTS {
+        let mut freezer =
+            ThreadFreezer::new(control(), target, original).with_stubs(std::mem::take(stubs));
+        if hooked {
+            freezer = freezer.unhooking(trampoline);
+        }
+        let written = unsafe { write_code(target, code, Some(&freezer)) };
+        *stubs = freezer.take_stubs();
+        if written? {
+            return Ok(());
+        }
+        log::debug!("thread blocks patching 0x{target:x}, retrying");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    Err(Error::HookInstall(HookError::PreHook))
+}
+
+/// Overwrites the start of `target` with `code` between the `pre` and `post`
+/// callbacks of `freezer`. Returns false, leaving the code unchanged, if
+/// `pre` fails.
+///
+/// # Safety
+///
+/// `target` must point to at least `code.len()` bytes of code.
+unsafe fn write_code(
+    target: usize,
+    code: &[u8],
+    freezer: Option<&dyn ThreadCallback>,
+) -> Result<bool> {
+    let protection = unsafe { system::unprotect(target, code.len()) }?;
+    let frozen = freezer.is_none_or(|freezer| freezer.pre());
+    if frozen {
+        unsafe { core::ptr::copy_nonoverlapping(code.as_ptr(), target as *mut u8, code.len()) };
+        if let Some(freezer) = freezer {
+            freezer.post();
+        }
+    }
+    unsafe { system::restore_protection(target, code.len(), protection) };
+    Ok(frozen)
+}
+
+/// Copies the [`ORIGINAL_WINDOW`] bytes at `target`.
+///
+/// # Safety
+///
+/// `target` must point to at least [`ORIGINAL_WINDOW`] readable bytes.
+unsafe fn read_window(target: usize) -> [u8; ORIGINAL_WINDOW] {
+    let mut window = [0; ORIGINAL_WINDOW];
+    window.copy_from_slice(unsafe {
+        core::slice::from_raw_parts(target as *const u8, ORIGINAL_WINDOW)
+    });
+    window
+}
+
+/// An installed ilhook hook whose jump can be removed and written back.
+///
+/// Removing the jump keeps the trampoline: threads that entered the hook
+/// earlier may still be running its relocated instructions or returning
+/// through it from the detour. It is freed with the hook point when this is
+/// dropped. Relocation stubs are freed by the next patch no thread runs them
+/// at; those still running when the hook is dropped are leaked.
+pub(crate) struct CodeHook {
+    /// Unhooks and frees the trampoline when dropped.
+    _point: HookPoint,
+    target: usize,
+    /// The target's bytes before it was hooked.
+    original: [u8; ORIGINAL_WINDOW],
+    /// The jump ilhook wrote over the target.
+    patched: [u8; PATCH_SIZE],
+    trampoline: Option<Trampoline>,
+    /// Stubs threads were moved into while patching the target.
+    stubs: Vec<StubCode>,
+    freeze_threads: bool,
+    lifted: bool,
+}
+
+impl CodeHook {
+    /// Hooks `target` by calling `hook`, recording the bytes it replaces.
+    /// `hook` returns the hook with the relocation stubs it moved threads into.
+    ///
+    /// # Safety
+    ///
+    /// `target` must point to at least [`ORIGINAL_WINDOW`] readable bytes.
+    pub(crate) unsafe fn install(
+        target: usize,
+        freeze_threads: bool,
+        hook: impl FnOnce() -> Result<(HookPoint, Vec<StubCode>)>,
+    ) -> Result<Self> {
+        let original = unsafe { read_window(target) };
+        let (point, stubs) = hook()?;
+        let mut patched = [0; PATCH_SIZE];
+        patched.copy_from_slice(&unsafe { read_window(target) }[..PATCH_SIZE]);
+        Ok(Self {
+            _point: point,
+            target,
+            original,
+            patched,
+            trampoline: unsafe { Trampoline::of(target, &original) },
+            stubs,
+            freeze_threads,
+            lifted: false,
+        })
+    }
+
+    /// Writes the original bytes back over the jump. The trampoline stays.
+    pub(crate) fn lift(&mut self) -> Result<()> {
+        if !self.lifted {
+            let original = self.original;
+            self.write(&original[..PATCH_SIZE], true)?;
+            self.lifted = true;
+        }
+        Ok(())
+    }
+
+    /// Writes the jump to the trampoline again after [`lift`](Self::lift).
+    pub(crate) fn reapply(&mut self) -> Result<()> {
+        if self.lifted {
+            let patched = self.patched;
+            self.write(&patched, false)?;
+            self.lifted = false;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, code: &[u8], hooked: bool) -> Result<()> {
+        #[cfg(target_os = "windows")]
+        if self.freeze_threads {
+            return unsafe {
+                patch_frozen(
+                    ProcessThreads::default,
+                    self.target,
+                    self.original,
+                    hooked,
+                    self.trampoline,
+                    code,
+                    &mut self.stubs,
+                )
+            };
+        }
+        #[cfg(not(target_os = "windows"))]
+        let _ = (self.freeze_threads, hooked);
+        unsafe { write_code(self.target, code, None) }.map(|_| ())
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub use self::system::{ProcessThreads, ThreadHandle};
+
+#[cfg(target_os = "windows")]
+mod system {
+    use core::ffi::c_void;
+    use windows::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows::Win32::System::Diagnostics::Debug::{
+        CONTEXT, CONTEXT_CONTROL_AMD64, CONTEXT_DEBUG_REGISTERS_AMD64, FlushInstructionCache,
+        GetThreadContext, SetThreadContext,
+    };
+    use windows::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, TH32CS_SNAPTHREAD, THREADENTRY32, Thread32First, Thread32Next,
+    };
+
+    use windows::Win32::System::Memory::{
+        MEM_COMMIT, MEM_RELEASE, MEM_RESERVE, PAGE_EXECUTE_READWRITE, PAGE_PROTECTION_FLAGS,
+        VirtualAlloc, VirtualFree, VirtualProtect,
+    };
+    use windows::Win32::System::Threading::{
+        GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId, OpenThread, ResumeThread,
+        SuspendThread, THREAD_GET_CONTEXT, THREAD_SET_CONTEXT, THREAD_SUSPEND_RESUME,
+    };
+
+    use super::ThreadControl;
+    use crate::errors::{Error, Result};
+    use crate::hooks::breakpoint::DR7_MASK;
+
+    /// Makes `size` bytes of code at `address` writable, returning their
+    /// previous protection.
+    pub(super) unsafe fn unprotect(address: usize, size: usize) -> Result<u32> {
+        let mut old = PAGE_PROTECTION_FLAGS::default();
+        unsafe {
+            VirtualProtect(
+                address as *const c_void,
+                size,
+                PAGE_EXECUTE_READWRITE,
+                &mut old,
+            )
+        }
+        .map_err(|error| Error::WriteFailed {
+            address,
+            reason: format!("VirtualProtect failed: {error}"),
+        })?;
+        Ok(old.0)
+    }
+
+    /// Restores the protection [`unprotect`] returned and flushes the
+    /// instruction cache for the rewritten code.
+    pub(super) unsafe fn restore_protection(address: usize, size: usize, protection: u32) {
+        let mut ignored = PAGE_PROTECTION_FLAGS::default();
+        unsafe {
+            let _ = VirtualProtect(
+                address as *const c_void,
+                size,
+                PAGE_PROTECTION_FLAGS(protection),
+                &mut ignored,
+            );
+            let _ =
+                FlushInstructionCache(GetCurrentProcess(), Some(address as *const c_void), size);
+        }
+    }
+
+    /// An open handle to another thread of the process.
+    pub struct ThreadHandle(HANDLE);
+
+    unsafe impl Send for ThreadHandle {}
+
+    impl Drop for ThreadHandle {
+        fn drop(&mut self) {
+            let _ = unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    /// `CONTEXT` must be 16-byte aligned for `GetThreadContext`.
+    #[repr(C, align(16))]
+    struct AlignedContext(CONTEXT);
+
+    /// The threads of the current process.
+    #[derive(Debug, Default)]
+    pub struct ProcessThreads;
+
+    impl ProcessThreads {
+        fn context(thread: &ThreadHandle) -> Option<AlignedContext> {
+            let mut context = AlignedContext(unsafe { core::mem::zeroed() });
+            context.0.ContextFlags = CONTEXT_CONTROL_AMD64;
+            unsafe { GetThreadContext(thread.0, &mut context.0) }.ok()?;
+            Some(context)
+        }
+
+        /// Points `DR0`-`DR3` of a suspended `thread` at `addresses` and
+        /// replaces the breakpoint bits of its DR7 with `dr7`.
+        pub(crate) fn set_debug_registers(
+            &mut self,
+            thread: &ThreadHandle,
+            addresses: [u64; 4],
+            dr7: u64,
+        ) -> bool {
+            let mut context = AlignedContext(unsafe { core::mem::zeroed() });
+            context.0.ContextFlags = CONTEXT_DEBUG_REGISTERS_AMD64;
+            if unsafe { GetThreadContext(thread.0, &mut context.0) }.is_err() {
+                return false;
+            }
+            let [dr0, dr1, dr2, dr3] = addresses;
+            context.0.Dr0 = dr0;
+            context.0.Dr1 = dr1;
+            context.0.Dr2 = dr2;
+            context.0.Dr3 = dr3;
+            context.0.Dr7 = (context.0.Dr7 & !DR7_MASK) | dr7;
+            unsafe { SetThreadContext(thread.0, &context.0) }.is_ok()
+        }
+    }
+
+    impl ThreadControl for ProcessThreads {
+        type Thread = ThreadHandle;
+
+        fn threads(&mut self) -> Result<Vec<ThreadHandle>> {
+            let process = unsafe { GetCurrentProcessId() };
+            let current = unsafe { GetCurrentThreadId() };
+            let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) }?;
+            let snapshot = ThreadHandle(snapshot);
+            let mut entry = THREADENTRY32 {
+                dwSize: size_of::<THREADENTRY32>() as u32,
+                ..Default::default()
+            };
+            let mut threads = Vec::new();
+            let mut next = unsafe { Thread32First(snapshot.0, &mut entry) };
+            while next.is_ok() {
+                if entry.th32OwnerProcessID == process && entry.th32ThreadID != current {
+                    let access = THREAD_SUSPEND_RESUME | THREAD_GET_CONTEXT | THREAD_SET_CONTEXT;
+                    // Threads may exit between the snapshot and here.
+                    if let Ok(handle) = unsafe { OpenThread(access, false, entry.th32ThreadID) } {
+                        threads.push(ThreadHandle(handle));
+                    }
+                }
+                next = unsafe { Thread32Next(snapshot.0, &mut entry) };
+            }
+            Ok(threads)
+        }
+
+        fn suspend(&mut self, thread: &ThreadHandle) -> bool {
+            unsafe { SuspendThread(thread.0) != u32::MAX }
+        }
+
+        fn resume(&mut self, thread: &ThreadHandle) {
+            unsafe { ResumeThread(thread.0) };
+        }
+
+        fn instruction_pointer(&mut self, thread: &ThreadHandle) -> Option<usize> {
+            Self::context(thread).map(|context| context.0.Rip as usize)
+        }
+
+        fn set_instruction_pointer(&mut self, thread: &ThreadHandle, address: usize) -> bool {
+            let Some(mut context) = Self::context(thread) else {
+                return false;
+            };
+            context.0.Rip = address as u64;
+            unsafe { SetThreadContext(thread.0, &context.0) }.is_ok()
+        }
+
+        fn alloc_code(&mut self, code: &[u8]) -> Option<usize> {
+            let memory = unsafe {
+                VirtualAlloc(
+                    None,
+                    code.len(),
+                    MEM_COMMIT | MEM_RESERVE,
+                    PAGE_EXECUTE_READWRITE,
+                )
+            };
+            if memory.is_null() {
+                return None;
+            }
+            unsafe {
+                core::ptr::copy_nonoverlapping(code.as_ptr(), memory.cast::<u8>(), code.len());
+                let _ = FlushInstructionCache(GetCurrentProcess(), Some(memory), code.len());
+            }
+            Some(memory as usize)
+        }
+
+        fn free_code(&mut self, address: usize) {
+            let _ = unsafe { VirtualFree(address as *mut c_void, 0, MEM_RELEASE) };
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod system {
+    use crate::errors::Result;
+
+    /// ilhook leaves the pages it patched writable outside Windows.
+    pub(super) unsafe fn unprotect(_address: usize, _size: usize) -> Result<u32> {
+        Ok(0)
+    }
+
+    pub(super) unsafe fn restore_protection(_address: usize, _size: usize, _protection: u32) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sub rsp, 0x28; mov rax, rcx; mov [rsp+0x30], rdx; xor eax, eax; ret
+    const PROLOGUE: [u8; 17] = [
+        0x48, 0x83, 0xEC, 0x28, 0x48, 0x89, 0xC8, 0x48, 0x89, 0x54, 0x24, 0x30, 0x31, 0xC0, 0xC3,
+        0xCC, 0xCC,
+    ];
+
+    fn original(code: &[u8]) -> [u8; ORIGINAL_WINDOW] {
+        let mut window = [0xCC; ORIGINAL_WINDOW];
+        window[..code.len()].copy_from_slice(code);
+        window
+    }
+
+    #[test]
+    fn test_plan_fixup() {
+        let window = original(&PROLOGUE);
+        let target = 0x1000;
+        assert_eq!(plan_fixup(&window, target, target), Fixup::Entry);
+        assert_eq!(plan_fixup(&window, target, target + 0x20), Fixup::None);
+        assert_eq!(plan_fixup(&window, target, target + 2), Fixup::Blocked);
+
+        let Fixup::Relocate(stub) = plan_fixup(&window, target, target + 7) else {
+            panic!("expected a relocation");
+        };
+        // mov [rsp+0x30], rdx; xor eax, eax; then jmp back to target + 14.
+        assert_eq!(&stub.code()[..7], &PROLOGUE[7..14]);
+        assert_eq!(&stub.code()[7..13], &[0xFF, 0x25, 0, 0, 0, 0]);
+        assert_eq!(stub.code()[13..21], (target as u64 + 14).to_le_bytes());
+
+        // lea rax, [rip+0x10] cannot be copied.
+        let mut code = PROLOGUE;
+        code[7..14].copy_from_slice(&[0x48, 0x8D, 0x05, 0x10, 0, 0, 0]);
+        assert_eq!(
+            plan_fixup(&original(&code), target, target + 7),
+            Fixup::Blocked
+        );
+    }
+
+    #[derive(Default)]
+    struct FakeThreads {
+        rips: Vec<usize>,
+        suspended: Vec<bool>,
+        code: Vec<Vec<u8>>,
+        /// Every instruction pointer change, shared with the test.
+        moves: Arc<Mutex<Vec<(usize, usize)>>>,
+        /// Every freed stub, shared with the test.
+        freed: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl ThreadControl for FakeThreads {
+        type Thread = usize;
+
+        fn threads(&mut self) -> Result<Vec<usize>> {
+            self.suspended = vec![false; self.rips.len()];
+            Ok((0..self.rips.len()).collect())
+        }
+
+        fn suspend(&mut self, &thread: &usize) -> bool {
+            self.suspended[thread] = true;
+            true
+        }
+
+        fn resume(&mut self, &thread: &usize) {
+            self.suspended[thread] = false;
+        }
+
+        fn instruction_pointer(&mut self, &thread: &usize) -> Option<usize> {
+            Some(self.rips[thread])
+        }
+
+        fn set_instruction_pointer(&mut self, &thread: &usize, address: usize) -> bool {
+            self.rips[thread] = address;
+            self.moves.lock().unwrap().push((thread, address));
+            true
+        }
+
+        fn alloc_code(&mut self, code: &[u8]) -> Option<usize> {
+            self.code.push(code.to_vec());
+            Some(0xC0DE_0000 + self.code.len())
+        }
+
+        fn free_code(&mut self, address: usize) {
+            self.freed.lock().unwrap().push(address);
+        }
+    }
+
+    #[test]
+    fn test_freezer_moves_threads() {
+        // The "target" is a buffer we patch by hand the way ilhook would.
+        let mut code = original(&PROLOGUE);
+        let target = code.as_mut_ptr() as usize;
+        let outside = target + 0x100;
+        let threads = FakeThreads {
+            rips: vec![target, target + 4, outside],
+            ..Default::default()
+        };
+        let freezer = unsafe { ThreadFreezer::at(threads, target) };
+
+        assert!(freezer.pre());
+        assert!(
+            freezer
+                .state
+                .lock()
+                .unwrap()
+                .control
+                .suspended
+                .iter()
+                .all(|&s| s)
+        );
+        code[0] = 0xE9;
+        code[1..5].copy_from_slice(&0x40i32.to_le_bytes());
+        freezer.post();
+
+        let state = freezer.state.lock().unwrap();
+        assert_eq!(
+            state.control.rips,
+            vec![target + 5 + 0x40, 0xC0DE_0001, outside]
+        );
+        assert_eq!(state.control.code[0][..10], PROLOGUE[4..14]);
+        assert!(state.control.suspended.iter().all(|&s| !s));
+        assert_eq!(
+            state.report,
+            FreezeReport {
+                suspended: 3,
+                moved_to_trampoline: 1,
+                relocated: 1,
+                moved_to_original: 0,
+                failed: 0,
+                blocked: false,
+            }
+        );
+        drop(state);
+
+        // A thread in the middle of an instruction blocks the patch.
+        let threads = FakeThreads {
+            rips: vec![target + 1],
+            ..Default::default()
+        };
+        let freezer = ThreadFreezer::new(threads, target, original(&PROLOGUE));
+        assert!(!freezer.pre());
+        assert!(freezer.report().blocked);
+        assert!(!freezer.state.lock().unwrap().control.suspended[0]);
+    }
+
+    #[inline(never)]
+    unsafe extern "win64" fn negate(value: i64) -> i64 {
+        std::hint::black_box(-std::hint::black_box(value) ^ std::hint::black_box(0))
+    }
+
+    unsafe extern "win64" fn negate_detour(
+        _registers: *mut ilhook::x64::Registers,
+        _ori_func_ptr: usize,
+        _user_data: usize,
+    ) -> usize {
+        42
+    }
+
+    #[test]
+    fn test_hook_frozen_installs_and_restores() {
+        type Negate = unsafe extern "win64" fn(i64) -> i64;
+        let target = negate as Negate as usize;
+        let (hook, stubs) = unsafe {
+            hook_frozen(
+                || FakeThreads {
+                    rips: vec![target],
+                    ..Default::default()
+                },
+                target,
+                || HookType::Retn(negate_detour),
+                0,
+            )
+        }
+        .unwrap();
+        assert!(stubs.is_empty());
+        let function = std::hint::black_box(negate as Negate);
+        assert_eq!(unsafe { function(5) }, 42);
+        assert!(unsafe { jump_destination(target) }.is_some());
+        drop(hook);
+        assert_eq!(unsafe { function(5) }, -5);
+    }
+
+    #[inline(never)]
+    unsafe extern "win64" fn triple(value: i64) -> i64 {
+        std::hint::black_box(std::hint::black_box(value).wrapping_mul(3) ^ std::hint::black_box(0))
+    }
+
+    #[test]
+    fn test_code_hook_lifts_and_reapplies() {
+        type Triple = unsafe extern "win64" fn(i64) -> i64;
+        let target = triple as Triple as usize;
+        let mut hook = unsafe {
+            CodeHook::install(target, false, || {
+                hook_frozen(
+                    FakeThreads::default,
+                    target,
+                    || HookType::Retn(negate_detour),
+                    0,
+                )
+            })
+        }
+        .unwrap();
+        let function = std::hint::black_box(triple as Triple);
+        let trampoline = unsafe { jump_destination(target) };
+        assert_eq!(unsafe { function(5) }, 42);
+
+        hook.lift().unwrap();
+        assert_eq!(unsafe { function(5) }, 15);
+        hook.reapply().unwrap();
+        assert_eq!(unsafe { function(5) }, 42);
+        assert_eq!(unsafe { jump_destination(target) }, trampoline);
+
+        hook.lift().unwrap();
+        drop(hook);
+        assert_eq!(unsafe { function(5) }, 15);
+    }
+
+    /// A target with a `jmp` to a trampoline 0x40 bytes after it, laid out
+    /// like ilhook's: glue, then the patched instructions and a jump back.
+    fn hooked_memory() -> Vec<u8> {
+        let mut memory = vec![0xCC; 0x80];
+        let target = memory.as_ptr() as usize;
+        memory[..PROLOGUE.len()].copy_from_slice(&PROLOGUE);
+        let trampoline = &mut memory[0x40..];
+        trampoline[..0x20].fill(0x90);
+        trampoline[0x20..0x2E].copy_from_slice(&PROLOGUE[..14]);
+        trampoline[0x2E..0x34].copy_from_slice(&[0xFF, 0x25, 0, 0, 0, 0]);
+        trampoline[0x34..0x3C].copy_from_slice(&(target as u64 + 14).to_le_bytes());
+        memory
+    }
+
+    /// The trampoline of [`hooked_memory`].
+    fn hooked_trampoline(target: usize) -> Trampoline {
+        Trampoline {
+            address: target + 0x40,
+            len: 0x40,
+            moved: 14,
+        }
+    }
+
+    #[test]
+    fn test_plan_unhook_fixup() {
+        let memory = hooked_memory();
+        let target = memory.as_ptr() as usize;
+        let window = original(&PROLOGUE);
+        let trampoline = &memory[0x40..];
+        let address = target + 0x40;
+        let plan = |rip| plan_unhook_fixup(&window, target, 14, trampoline, address, rip);
+
+        assert_eq!(plan(target), Fixup::None);
+        assert_eq!(plan(address), Fixup::Original(target));
+        assert_eq!(plan(address + 0x20), Fixup::Original(target));
+        assert_eq!(plan(address + 0x27), Fixup::Original(target + 7));
+        assert_eq!(plan(address + 0x2E), Fixup::Original(target + 14));
+        assert_eq!(plan(address + 0x28), Fixup::Blocked);
+        assert_eq!(plan(address + 0x10), Fixup::Blocked);
+
+        // Instructions before a relocated one may have moved.
+        let mut code = PROLOGUE;
+        code[7..14].copy_from_slice(&[0x48, 0x8D, 0x05, 0x10, 0, 0, 0]);
+        let window = original(&code);
+        let plan = |rip| plan_unhook_fixup(&window, target, 14, trampoline, address, rip);
+        assert_eq!(plan(address + 0x20), Fixup::Blocked);
+        assert_eq!(plan(address + 0x2E), Fixup::Original(target + 14));
+    }
+
+    #[test]
+    fn test_trampoline_of_near_jump() {
+        // A 5-byte jump moves only sub rsp, 0x28; mov rax, rcx.
+        let mut memory = vec![0xCC; 0x80];
+        let target = memory.as_mut_ptr() as usize;
+        let window = original(&PROLOGUE);
+        memory[..PROLOGUE.len()].copy_from_slice(&PROLOGUE);
+        memory[0] = 0xE9;
+        memory[1..5].copy_from_slice(&0x3Bi32.to_le_bytes());
+        let trampoline = unsafe { Trampoline::of(target, &window) }.unwrap();
+        assert_eq!(trampoline.address, target + 0x40);
+        assert_eq!(trampoline.moved, 7);
+        assert_eq!(trampoline.len, trampoline_len(2));
+
+        let code = &mut memory[0x40..];
+        code[..0x20].fill(0x90);
+        code[0x20..0x27].copy_from_slice(&PROLOGUE[..7]);
+        code[0x27..0x2D].copy_from_slice(&[0xFF, 0x25, 0, 0, 0, 0]);
+        code[0x2D..0x35].copy_from_slice(&(target as u64 + 7).to_le_bytes());
+        let code = &memory[0x40..];
+        let address = trampoline.address;
+        let plan = |rip| plan_unhook_fixup(&window, target, 7, code, address, rip);
+        assert_eq!(plan(address + 0x24), Fixup::Original(target + 4));
+        assert_eq!(plan(address + 0x27), Fixup::Original(target + 7));
+    }
+
+    #[test]
+    fn test_patch_frozen_moves_threads() {
+        let mut memory = hooked_memory();
+        let target = memory.as_mut_ptr() as usize;
+        let trampoline = hooked_trampoline(target);
+        let window = original(&PROLOGUE);
+        let mut jump = [0; PATCH_SIZE];
+        jump[..6].copy_from_slice(&[0xFF, 0x25, 0, 0, 0, 0]);
+        jump[6..].copy_from_slice(&(trampoline.address as u64).to_le_bytes());
+        let moves = Arc::new(Mutex::new(Vec::new()));
+        let freed = Arc::new(Mutex::new(Vec::new()));
+        let threads = |rip: usize| {
+            let moves = moves.clone();
+            let freed = freed.clone();
+            move || FakeThreads {
+                rips: vec![rip],
+                moves: moves.clone(),
+                freed: freed.clone(),
+                ..Default::default()
+            }
+        };
+
+        let mut stubs = Vec::new();
+        let hook = |rip, stubs: &mut Vec<StubCode>| unsafe {
+            patch_frozen(threads(rip), target, window, false, None, &jump, stubs)
+        };
+        let unhook = |rip, stubs: &mut Vec<StubCode>| unsafe {
+            patch_frozen(
+                threads(rip),
+                target,
+                window,
+                true,
+                Some(trampoline),
+                &window[..PATCH_SIZE],
+                stubs,
+            )
+        };
+
+        hook(target, &mut stubs).unwrap();
+        assert_eq!(memory[..PATCH_SIZE], jump);
+        assert_eq!(*moves.lock().unwrap(), [(0, trampoline.address)]);
+
+        // A thread in the trampoline's glue blocks every attempt.
+        assert!(matches!(
+            unhook(trampoline.address + 0x10, &mut stubs),
+            Err(Error::HookInstall(HookError::PreHook))
+        ));
+        assert_eq!(memory[..PATCH_SIZE], jump);
+
+        unhook(trampoline.address + 0x27, &mut stubs).unwrap();
+        assert_eq!(memory[..PATCH_SIZE], PROLOGUE[..PATCH_SIZE]);
+        assert_eq!(moves.lock().unwrap()[1], (0, target + 7));
+
+        // A thread inside an instruction blocks every attempt.
+        assert!(matches!(
+            hook(target + 1, &mut stubs),
+            Err(Error::HookInstall(HookError::PreHook))
+        ));
+        assert_eq!(memory[..PATCH_SIZE], PROLOGUE[..PATCH_SIZE]);
+
+        // A relocation stub is kept while a thread runs it and freed after.
+        hook(target + 4, &mut stubs).unwrap();
+        let stub = StubCode {
+            address: 0xC0DE_0001,
+            len: 10 + JUMP_BACK,
+        };
+        assert_eq!(stubs, [stub]);
+        unhook(stub.address + 3, &mut stubs).unwrap();
+        assert_eq!(stubs, [stub]);
+        assert!(freed.lock().unwrap().is_empty());
+        hook(target + 0x60, &mut stubs).unwrap();
+        assert!(stubs.is_empty());
+        assert_eq!(*freed.lock().unwrap(), [stub.address]);
+    }
+}
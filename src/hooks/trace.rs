@@ -0,0 +1,883 @@
+//! Declarative call tracing.
+//!
+//! A [`Trace`] names a function by address or signature and describes its
+//! arguments with a [`TraceSpec`] such as `ptr, f32, cstr -> u32`.
+//! [`HookContext::install_trace`](super::HookContext::install_trace) hooks the
+//! function and appends a [`TraceRecord`] with the decoded arguments, return
+//! value, caller and thread to a [`TraceBuffer`] on every call that passes the
+//! trace's filter and rate limit.
+//!
+//! Arguments are read following the Windows x64 calling convention at the
+//! function entry: the first four from `rcx`, `rdx`, `r8`, `r9` or `xmm0`-`xmm3`
+//! by position, the rest from the stack. Specs without a return type use a
+//! jmp-back hook; with one, the hook calls the original itself, which is only
+//! possible for functions taking up to four arguments.
+
+use core::cell::Cell;
+use core::fmt;
+use core::str::FromStr;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ilhook::x64::{HookType, Registers};
+
+use super::threads::CodeHook;
+use crate::errors::{Error, Result};
+
+/// Records kept by [`TraceBuffer::global`].
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// Longest string read for `cstr` and `wstr` values, in characters.
+const MAX_STRING: usize = 256;
+
+/// Strings are read in chunks that do not cross a page boundary.
+const PAGE_SIZE: usize = 0x1000;
+
+/// Arguments passed in registers; a traced function with a return value may
+/// not take more.
+const REGISTER_ARGS: usize = 4;
+
+/// Offset of the fifth argument from `rsp` at the function entry: the return
+/// address and the 0x20 byte shadow space come first.
+const STACK_ARGS_OFFSET: usize = 0x28;
+
+/// How to decode one argument or return value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    I32,
+    I64,
+    U32,
+    U64,
+    Bool,
+    Ptr,
+    F32,
+    F64,
+    /// A pointer to a NUL-terminated narrow string.
+    CStr,
+    /// A pointer to a NUL-terminated UTF-16 string.
+    WStr,
+}
+
+impl ArgKind {
+    const NAMES: [(&'static str, ArgKind); 10] = [
+        ("i32", ArgKind::I32),
+        ("i64", ArgKind::I64),
+        ("u32", ArgKind::U32),
+        ("u64", ArgKind::U64),
+        ("bool", ArgKind::Bool),
+        ("ptr", ArgKind::Ptr),
+        ("f32", ArgKind::F32),
+        ("f64", ArgKind::F64),
+        ("cstr", ArgKind::CStr),
+        ("wstr", ArgKind::WStr),
+    ];
+
+    /// Returns true for kinds passed in XMM registers.
+    pub fn is_float(self) -> bool {
+        matches!(self, ArgKind::F32 | ArgKind::F64)
+    }
+
+    /// Decodes the 64-bit register or stack slot holding the value.
+    pub fn decode(self, bits: u64) -> Value {
+        match self {
+            ArgKind::I32 => Value::Int(bits as i32 as i64),
+            ArgKind::I64 => Value::Int(bits as i64),
+            ArgKind::U32 => Value::UInt(bits as u32 as u64),
+            ArgKind::U64 => Value::UInt(bits),
+            ArgKind::Bool => Value::Bool(bits as u8 != 0),
+            ArgKind::Ptr => Value::Ptr(bits as usize),
+            ArgKind::F32 => Value::F32(f32::from_bits(bits as u32)),
+            ArgKind::F64 => Value::F64(f64::from_bits(bits)),
+            ArgKind::CStr => Value::Str {
+                address: bits as usize,
+                text: read_c_string(bits as usize),
+            },
+            ArgKind::WStr => Value::Str {
+                address: bits as usize,
+                text: read_wide_string(bits as usize),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ArgKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = Self::NAMES
+            .iter()
+            .find(|(_, kind)| kind == self)
+            .expect("every kind is named");
+        f.write_str(name)
+    }
+}
+
+impl FromStr for ArgKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s.trim()))
+            .map(|(_, kind)| *kind)
+            .ok_or_else(|| Error::InvalidTraceSpec(format!("unknown argument type `{}`", s.trim())))
+    }
+}
+
+/// Argument and return types of a traced function, written like
+/// `ptr, f32, cstr -> u32`. The return type is optional.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TraceSpec {
+    pub args: Vec<ArgKind>,
+    pub ret: Option<ArgKind>,
+}
+
+impl FromStr for TraceSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (args, ret) = match s.split_once("->") {
+            Some((args, ret)) => (args, Some(ret.parse()?)),
+            None => (s, None),
+        };
+        let args = args
+            .split(',')
+            .filter(|arg| !arg.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<_>>()?;
+        Ok(Self { args, ret })
+    }
+}
+
+impl fmt::Display for TraceSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, arg) in self.args.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{arg}")?;
+        }
+        if let Some(ret) = self.ret {
+            write!(f, " -> {ret}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A decoded argument or return value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    UInt(u64),
+    Bool(bool),
+    Ptr(usize),
+    F32(f32),
+    F64(f64),
+    /// A string argument; `text` is `None` if the pointer was unreadable.
+    Str {
+        address: usize,
+        text: Option<String>,
+    },
+}
+
+impl Value {
+    /// The value as an integer, for integer, boolean and pointer values.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Int(value) => Some(value as u64),
+            Value::UInt(value) => Some(value),
+            Value::Bool(value) => Some(value as u64),
+            Value::Ptr(value) => Some(value as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::F32(value) => Some(value as f64),
+            Value::F64(value) => Some(value),
+            Value::Int(value) => Some(value as f64),
+            Value::UInt(value) => Some(value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str { text, .. } => text.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{value}"),
+            Value::UInt(value) => write!(f, "{value}"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Ptr(value) => write!(f, "0x{value:x}"),
+            Value::F32(value) => write!(f, "{value}"),
+            Value::F64(value) => write!(f, "{value}"),
+            Value::Str {
+                text: Some(text), ..
+            } => write!(f, "{text:?}"),
+            Value::Str {
+                address,
+                text: None,
+            } => write!(f, "0x{address:x}"),
+        }
+    }
+}
+
+/// One traced call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    /// Position in the buffer's history, counting records that were overwritten.
+    pub sequence: u64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub trace: String,
+    pub address: usize,
+    /// The return address of the call.
+    pub caller: usize,
+    pub thread_id: u32,
+    pub args: Vec<Value>,
+    pub ret: Option<Value>,
+}
+
+impl TraceRecord {
+    /// Formats the record as a single log line.
+    pub fn log_line(&self) -> String {
+        let args: Vec<String> = self.args.iter().map(Value::to_string).collect();
+        let mut line = format!(
+            "#{} {} tid={} {}({})",
+            self.sequence,
+            self.timestamp,
+            self.thread_id,
+            self.trace,
+            args.join(", ")
+        );
+        if let Some(ret) = &self.ret {
+            line.push_str(&format!(" -> {ret}"));
+        }
+        line.push_str(&format!(" caller=0x{:x}", self.caller));
+        line
+    }
+}
+
+/// A bounded ring buffer of trace records shared by traces and viewers.
+#[derive(Debug)]
+pub struct TraceBuffer {
+    records: Mutex<VecDeque<TraceRecord>>,
+    capacity: AtomicUsize,
+    sequence: AtomicU64,
+}
+
+impl TraceBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity: AtomicUsize::new(capacity.max(1)),
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    /// The buffer traces write to unless given another one.
+    pub fn global() -> Arc<TraceBuffer> {
+        static GLOBAL: OnceLock<Arc<TraceBuffer>> = OnceLock::new();
+        GLOBAL
+            .get_or_init(|| Arc::new(TraceBuffer::new(DEFAULT_CAPACITY)))
+            .clone()
+    }
+
+    /// Appends `record`, numbering it and dropping the oldest record when full.
+    pub fn push(&self, mut record: TraceRecord) {
+        let mut records = self.records.lock().unwrap();
+        record.sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        while records.len() >= self.capacity() {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Copies the records, oldest first.
+    pub fn snapshot(&self) -> Vec<TraceRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Copies the records numbered `sequence` or later, oldest first.
+    pub fn since(&self, sequence: u64) -> Vec<TraceRecord> {
+        let records = self.records.lock().unwrap();
+        let start = records.partition_point(|record| record.sequence < sequence);
+        records.range(start..).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Changes the capacity, dropping the oldest records if it shrinks.
+    pub fn set_capacity(&self, capacity: usize) {
+        let capacity = capacity.max(1);
+        self.capacity.store(capacity, Ordering::Relaxed);
+        let mut records = self.records.lock().unwrap();
+        let excess = records.len().saturating_sub(capacity);
+        records.drain(..excess);
+    }
+
+    /// Number of records pushed so far, including overwritten ones.
+    pub fn total(&self) -> u64 {
+        self.sequence.load(Ordering::Relaxed)
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().unwrap().clear();
+    }
+
+    /// Writes one [`log_line`](TraceRecord::log_line) per record to `path`.
+    pub fn dump(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut text = String::new();
+        for record in self.snapshot() {
+            text.push_str(&record.log_line());
+            text.push('\n');
+        }
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+/// Where a trace is installed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceTarget {
+    Address(usize),
+    /// A byte signature, resolved with [`HookContext::find_pattern`](super::HookContext::find_pattern).
+    Pattern(String),
+}
+
+type Filter = dyn Fn(&[Value]) -> bool + Send + Sync;
+
+/// A function to trace, built up and passed to
+/// [`HookContext::install_trace`](super::HookContext::install_trace).
+pub struct Trace {
+    name: String,
+    target: TraceTarget,
+    spec: TraceSpec,
+    per_second: Option<u32>,
+    filter: Option<Box<Filter>>,
+    buffer: Option<Arc<TraceBuffer>>,
+}
+
+impl Trace {
+    /// Traces the function at `address`.
+    pub fn address(name: impl Into<String>, address: usize, spec: TraceSpec) -> Self {
+        Self::new(name.into(), TraceTarget::Address(address), spec)
+    }
+
+    /// Traces the function found by the byte signature `pattern`.
+    pub fn pattern(name: impl Into<String>, pattern: impl Into<String>, spec: TraceSpec) -> Self {
+        Self::new(name.into(), TraceTarget::Pattern(pattern.into()), spec)
+    }
+
+    fn new(name: String, target: TraceTarget, spec: TraceSpec) -> Self {
+        Self {
+            name,
+            target,
+            spec,
+            per_second: None,
+            filter: None,
+            buffer: None,
+        }
+    }
+
+    /// Records at most `per_second` calls per second; the rest still run but
+    /// are not recorded.
+    pub fn rate_limit(mut self, per_second: u32) -> Self {
+        self.per_second = Some(per_second);
+        self
+    }
+
+    /// Records only calls whose decoded arguments pass `filter`.
+    pub fn filter(mut self, filter: impl Fn(&[Value]) -> bool + Send + Sync + 'static) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Writes to `buffer` instead of [`TraceBuffer::global`].
+    pub fn buffer(mut self, buffer: Arc<TraceBuffer>) -> Self {
+        self.buffer = Some(buffer);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn target(&self) -> &TraceTarget {
+        &self.target
+    }
+
+    pub fn spec(&self) -> &TraceSpec {
+        &self.spec
+    }
+}
+
+/// Allows a fixed number of records per wall-clock second.
+struct RateLimit {
+    per_second: u32,
+    /// The current second in the high half and the records allowed in it in
+    /// the low half, so both change together.
+    window: AtomicU64,
+}
+
+impl RateLimit {
+    fn new(per_second: u32) -> Self {
+        Self {
+            per_second,
+            window: AtomicU64::new(0),
+        }
+    }
+
+    fn allow(&self, now_ms: u64) -> bool {
+        let second = (now_ms / 1000) & u64::from(u32::MAX);
+        let mut window = self.window.load(Ordering::Relaxed);
+        loop {
+            let count = if window >> 32 == second {
+                window as u32
+            } else {
+                0
+            };
+            if count >= self.per_second {
+                return false;
+            }
+            let next = second << 32 | u64::from(count + 1);
+            match self.window.compare_exchange_weak(
+                window,
+                next,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => window = current,
+            }
+        }
+    }
+}
+
+struct State {
+    name: String,
+    address: usize,
+    spec: TraceSpec,
+    rate_limit: Option<RateLimit>,
+    filter: Option<Box<Filter>>,
+    buffer: Arc<TraceBuffer>,
+    calls: Arc<AtomicU64>,
+}
+
+thread_local! {
+    /// Set while a record is built, so functions traced and also called by the
+    /// tracer itself do not recurse.
+    static RECORDING: Cell<bool> = const { Cell::new(false) };
+}
+
+impl State {
+    fn arguments(&self, registers: &Registers) -> Vec<Value> {
+        self.spec
+            .args
+            .iter()
+            .enumerate()
+            .map(|(index, kind)| kind.decode(unsafe { argument_bits(registers, index, *kind) }))
+            .collect()
+    }
+
+    /// Decodes the arguments and returns them if the call should be recorded.
+    ///
+    /// Without a filter the rate limit is checked first, so dropped calls do
+    /// not read their string arguments.
+    fn admit(&self, registers: &Registers) -> Option<Vec<Value>> {
+        let allow = || {
+            self.rate_limit
+                .as_ref()
+                .is_none_or(|limit| limit.allow(now_ms()))
+        };
+        let Some(filter) = &self.filter else {
+            return allow().then(|| self.arguments(registers));
+        };
+        let args = self.arguments(registers);
+        (filter(&args) && allow()).then_some(args)
+    }
+
+    fn record(&self, registers: &Registers, args: Vec<Value>, ret: Option<Value>) {
+        self.buffer.push(TraceRecord {
+            sequence: 0,
+            timestamp: now_ms(),
+            trace: self.name.clone(),
+            address: self.address,
+            caller: unsafe { (registers.rsp as *const usize).read() },
+            thread_id: system::current_thread_id(),
+            args,
+            ret,
+        });
+    }
+}
+
+/// A trace bound to its address, installable several times. Hook points
+/// created by [`install`](Self::install) must be dropped before it.
+pub(crate) struct TraceDetour(Box<State>);
+
+impl TraceDetour {
+    pub(crate) fn new(trace: Trace, address: usize, calls: Arc<AtomicU64>) -> Result<Self> {
+        if trace.spec.ret.is_some() && trace.spec.args.len() > REGISTER_ARGS {
+            return Err(Error::InvalidTraceSpec(format!(
+                "{}: return values can only be traced for up to {REGISTER_ARGS} arguments",
+                trace.name
+            )));
+        }
+        Ok(Self(Box::new(State {
+            name: trace.name,
+            address,
+            spec: trace.spec,
+            rate_limit: trace.per_second.map(RateLimit::new),
+            filter: trace.filter,
+            buffer: trace.buffer.unwrap_or_else(TraceBuffer::global),
+            calls,
+        })))
+    }
+
+    /// Hooks the traced function.
+    ///
+    /// # Safety
+    ///
+    /// See [`HookContext::install_trace`](super::HookContext::install_trace).
+    pub(crate) unsafe fn install(&self, freeze: bool) -> Result<CodeHook> {
+        let user_data = &*self.0 as *const State as usize;
+        let returns = self.0.spec.ret.is_some();
+        let hook_type = || {
+            if returns {
+                HookType::Retn(trace_retn)
+            } else {
+                HookType::JmpBack(trace_jmp_back)
+            }
+        };
+        unsafe { super::hook_point(self.0.address, hook_type, user_data, freeze) }
+    }
+}
+
+/// Runs `f` unless the thread is already building a record.
+fn guarded<R>(f: impl FnOnce() -> R) -> Option<R> {
+    if RECORDING.with(|recording| recording.replace(true)) {
+        return None;
+    }
+    let result = f();
+    RECORDING.with(|recording| recording.set(false));
+    Some(result)
+}
+
+unsafe extern "win64" fn trace_jmp_back(registers: *mut Registers, user_data: usize) {
+    let state = unsafe { &*(user_data as *const State) };
+    let registers = unsafe { &*registers };
+    state.calls.fetch_add(1, Ordering::Relaxed);
+    guarded(|| {
+        if let Some(args) = state.admit(registers) {
+            state.record(registers, args, None);
+        }
+    });
+}
+
+unsafe extern "win64" fn trace_retn(
+    registers: *mut Registers,
+    ori_func_ptr: usize,
+    user_data: usize,
+) -> usize {
+    let state = unsafe { &*(user_data as *const State) };
+    let registers = unsafe { &mut *registers };
+    state.calls.fetch_add(1, Ordering::Relaxed);
+    // Arguments are decoded before the call, while string pointers are valid.
+    let args = guarded(|| state.admit(registers)).flatten();
+    let rax = unsafe { call_with_registers(registers, ori_func_ptr) };
+    if let Some(args) = args {
+        let kind = state.spec.ret.expect("retn traces have a return type");
+        let bits = if kind.is_float() {
+            registers.xmm0 as u64
+        } else {
+            rax
+        };
+        guarded(|| state.record(registers, args, Some(kind.decode(bits))));
+    }
+    rax as usize
+}
+
+/// Calls `function` with the argument registers in `registers`, stores the
+/// returned `xmm0` back into them and returns `rax`.
+#[unsafe(naked)]
+unsafe extern "win64" fn call_with_registers(_registers: *mut Registers, _function: usize) -> u64 {
+    core::arch::naked_asm!(
+        "push rbx",
+        "sub rsp, 0x20",
+        "mov rbx, rcx",
+        "mov rax, rdx",
+        "movups xmm0, [rbx + {xmm0}]",
+        "movups xmm1, [rbx + {xmm1}]",
+        "movups xmm2, [rbx + {xmm2}]",
+        "movups xmm3, [rbx + {xmm3}]",
+        "mov rcx, [rbx + {rcx}]",
+        "mov rdx, [rbx + {rdx}]",
+        "mov r8, [rbx + {r8}]",
+        "mov r9, [rbx + {r9}]",
+        "call rax",
+        "movups [rbx + {xmm0}], xmm0",
+        "add rsp, 0x20",
+        "pop rbx",
+        "ret",
+        xmm0 = const core::mem::offset_of!(Registers, xmm0),
+        xmm1 = const core::mem::offset_of!(Registers, xmm1),
+        xmm2 = const core::mem::offset_of!(Registers, xmm2),
+        xmm3 = const core::mem::offset_of!(Registers, xmm3),
+        rcx = const core::mem::offset_of!(Registers, rcx),
+        rdx = const core::mem::offset_of!(Registers, rdx),
+        r8 = const core::mem::offset_of!(Registers, r8),
+        r9 = const core::mem::offset_of!(Registers, r9),
+    )
+}
+
+/// Reads the slot holding argument `index` at the function entry.
+///
+/// # Safety
+///
+/// `registers` must be captured at the entry of a function taking the argument.
+unsafe fn argument_bits(registers: &Registers, index: usize, kind: ArgKind) -> u64 {
+    match (index, kind.is_float()) {
+        (0, true) => registers.xmm0 as u64,
+        (1, true) => registers.xmm1 as u64,
+        (2, true) => registers.xmm2 as u64,
+        (3, true) => registers.xmm3 as u64,
+        (0, false) => registers.rcx,
+        (1, false) => registers.rdx,
+        (2, false) => registers.r8,
+        (3, false) => registers.r9,
+        _ => {
+            let slot = registers.rsp as usize + STACK_ARGS_OFFSET + (index - REGISTER_ARGS) * 8;
+            unsafe { (slot as *const u64).read() }
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Reads up to [`MAX_STRING`] characters of type `T` at `address`, stopping at
+/// NUL or at the first unreadable page.
+///
+/// Each read stays within one page, so a string ending before an unreadable
+/// page is still read whole.
+fn read_units<T: Copy + Default + PartialEq>(address: usize) -> Option<Vec<T>> {
+    if address == 0 {
+        return None;
+    }
+    let mut units = Vec::new();
+    while units.len() < MAX_STRING {
+        let start = units.len();
+        let at = address + start * size_of::<T>();
+        let count = ((PAGE_SIZE - at % PAGE_SIZE) / size_of::<T>()).clamp(1, MAX_STRING - start);
+        units.resize(start + count, T::default());
+        if !system::read(
+            at,
+            units[start..].as_mut_ptr().cast(),
+            count * size_of::<T>(),
+        ) {
+            units.truncate(start);
+            return (!units.is_empty()).then_some(units);
+        }
+        if let Some(end) = units[start..].iter().position(|unit| *unit == T::default()) {
+            units.truncate(start + end);
+            break;
+        }
+    }
+    Some(units)
+}
+
+fn read_c_string(address: usize) -> Option<String> {
+    read_units::<u8>(address).map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn read_wide_string(address: usize) -> Option<String> {
+    read_units::<u16>(address).map(|units| String::from_utf16_lossy(&units))
+}
+
+#[cfg(target_os = "windows")]
+mod system {
+    use windows::Win32::System::Diagnostics::Debug::ReadProcessMemory;
+    use windows::Win32::System::Threading::{GetCurrentProcess, GetCurrentThreadId};
+
+    /// Copies `size` bytes at `address` to `out`, returning false instead of
+    /// faulting if they are not readable.
+    pub(super) fn read(address: usize, out: *mut u8, size: usize) -> bool {
+        unsafe {
+            ReadProcessMemory(
+                GetCurrentProcess(),
+                address as *const _,
+                out.cast(),
+                size,
+                None,
+            )
+        }
+        .is_ok()
+    }
+
+    pub(super) fn current_thread_id() -> u32 {
+        unsafe { GetCurrentThreadId() }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod system {
+    pub(super) fn read(address: usize, out: *mut u8, size: usize) -> bool {
+        unsafe { core::ptr::copy_nonoverlapping(address as *const u8, out, size) };
+        true
+    }
+
+    pub(super) fn current_thread_id() -> u32 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::HookContext;
+    use std::sync::RwLock;
+
+    fn context() -> HookContext<()> {
+        HookContext {
+            config: Arc::new(RwLock::new(())),
+            freeze_threads: false,
+            module: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        let spec: TraceSpec = "ptr, F32 , cstr -> f64".parse().unwrap();
+        assert_eq!(spec.args, [ArgKind::Ptr, ArgKind::F32, ArgKind::CStr]);
+        assert_eq!(spec.ret, Some(ArgKind::F64));
+        assert_eq!(spec.to_string(), "ptr, f32, cstr -> f64");
+        assert_eq!("".parse::<TraceSpec>().unwrap(), TraceSpec::default());
+        assert!("ptr, long".parse::<TraceSpec>().is_err());
+        assert!("u32 ->".parse::<TraceSpec>().is_err());
+
+        assert_eq!(ArgKind::I32.decode(u32::MAX as u64), Value::Int(-1));
+        assert_eq!(
+            ArgKind::F32.decode(1.5f32.to_bits() as u64),
+            Value::F32(1.5)
+        );
+        let text = b"trace\0";
+        let value = ArgKind::CStr.decode(text.as_ptr() as u64);
+        assert_eq!(value.as_str(), Some("trace"));
+        let long = [b'a'; MAX_STRING + 8];
+        let value = ArgKind::CStr.decode(long.as_ptr() as u64);
+        assert_eq!(value.as_str().map(str::len), Some(MAX_STRING));
+    }
+
+    #[test]
+    fn test_rate_limit_window() {
+        let limit = RateLimit::new(2);
+        let kept = (0..5).filter(|_| limit.allow(10_000)).count();
+        assert_eq!(kept, 2);
+        assert!(!limit.allow(10_999));
+        assert!(limit.allow(11_000));
+        assert!(limit.allow(11_999));
+        assert!(!limit.allow(11_999));
+    }
+
+    type Mix = unsafe extern "win64" fn(u32, f32, *const u8, f64, u64) -> u64;
+
+    #[inline(never)]
+    unsafe extern "win64" fn mix(a: u32, b: f32, c: *const u8, d: f64, e: u64) -> u64 {
+        std::hint::black_box(a as u64 + b as u64 + c as u64 + d as u64 + e)
+    }
+
+    #[test]
+    fn test_jmp_back_trace_records_arguments() {
+        let buffer = Arc::new(TraceBuffer::new(8));
+        let spec = "u32, f32, cstr, f64, u64".parse().unwrap();
+        let trace = Trace::address("mix", mix as Mix as usize, spec)
+            .buffer(buffer.clone())
+            .filter(|args| args[0].as_u64() != Some(0));
+        let guard = unsafe { context().install_trace(trace) }.unwrap();
+
+        let target = std::hint::black_box(mix as Mix);
+        let name = b"hello\0";
+        assert_eq!(
+            unsafe { target(1, 2.5, name.as_ptr(), 4.0, 5) },
+            12 + name.as_ptr() as u64
+        );
+        unsafe { target(0, 0.0, core::ptr::null(), 0.0, 0) };
+        drop(guard);
+        unsafe { target(1, 0.0, core::ptr::null(), 0.0, 0) };
+
+        let records = buffer.snapshot();
+        assert_eq!(records.len(), 1);
+        let args: Vec<String> = records[0].args.iter().map(Value::to_string).collect();
+        assert_eq!(args, ["1", "2.5", "\"hello\"", "4", "5"]);
+        assert_eq!(records[0].ret, None);
+        assert_ne!(records[0].caller, 0);
+    }
+
+    type Scale = unsafe extern "win64" fn(u32, f32) -> f32;
+
+    #[inline(never)]
+    unsafe extern "win64" fn scale(value: u32, factor: f32) -> f32 {
+        std::hint::black_box(value as f32 * factor)
+    }
+
+    #[test]
+    fn test_retn_trace_records_return_value() {
+        let buffer = Arc::new(TraceBuffer::new(8));
+        let trace = Trace::address(
+            "scale",
+            scale as Scale as usize,
+            "u32, f32 -> f32".parse().unwrap(),
+        )
+        .buffer(buffer.clone())
+        .rate_limit(2);
+        let guard = unsafe { context().install_trace(trace) }.unwrap();
+        // Make the calls early in a second so they share one rate limit window.
+        while now_ms() % 1000 > 500 {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let target = std::hint::black_box(scale as Scale);
+        for value in 1..=4 {
+            assert_eq!(unsafe { target(value, 1.5) }, value as f32 * 1.5);
+        }
+        assert_eq!(guard.calls(), 4);
+        drop(guard);
+
+        let records = buffer.snapshot();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].ret, Some(Value::F32(3.0)));
+        assert_eq!(records[0].ret, Some(Value::F32(1.5)));
+        assert!(records[0].log_line().contains("scale(1, 1.5) -> 1.5"));
+
+        buffer.set_capacity(1);
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.total(), records.len() as u64);
+        assert_eq!(buffer.since(0).len(), 1);
+        assert!(buffer.since(buffer.total()).is_empty());
+        let spec = "u32, u32, u32, u32, u32 -> u32".parse().unwrap();
+        assert!(unsafe { context().install_trace(Trace::address("five", 0, spec)) }.is_err());
+    }
+}
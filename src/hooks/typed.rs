@@ -0,0 +1,384 @@
+//! Typed function hooks.
+//!
+//! [`HookContext::hook`](super::HookContext::hook) replaces a function with a
+//! closure that receives the decoded arguments and a typed pointer to the
+//! original function instead of raw [`Registers`]. Arguments and return values
+//! follow the Windows x64 calling convention: the first four arguments are
+//! read from `rcx`, `rdx`, `r8` and `r9`, or from `xmm0`-`xmm3` for floating
+//! point values, and the rest from the stack.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ilhook::x64::{HookType, Registers};
+
+use super::threads::CodeHook;
+use crate::errors::Result;
+
+/// Returns the general purpose register or stack slot holding argument `index`.
+///
+/// # Safety
+///
+/// For `index >= 4`, `registers.rsp` must point to the stack at function entry.
+unsafe fn integer_slot(registers: &Registers, index: usize) -> u64 {
+    match index {
+        0 => registers.rcx,
+        1 => registers.rdx,
+        2 => registers.r8,
+        3 => registers.r9,
+        // The return address and the 0x20 byte home area precede stack arguments.
+        _ => unsafe { registers.get_stack(index + 1) },
+    }
+}
+
+/// Returns the XMM register or stack slot holding argument `index`.
+///
+/// # Safety
+///
+/// See [`integer_slot`].
+unsafe fn float_slot(registers: &Registers, index: usize) -> u128 {
+    match index {
+        0 => registers.xmm0,
+        1 => registers.xmm1,
+        2 => registers.xmm2,
+        3 => registers.xmm3,
+        _ => unsafe { registers.get_stack(index + 1) as u128 },
+    }
+}
+
+/// A type that can be passed as an argument to a hooked function.
+///
+/// # Safety
+///
+/// Implementations must read the value from the slot the Windows x64 calling
+/// convention assigns to it.
+pub unsafe trait Argument: Copy + 'static {
+    /// Reads argument `index` from the register state at function entry.
+    ///
+    /// # Safety
+    ///
+    /// `registers` must hold the state at entry of a function taking at least
+    /// `index + 1` arguments.
+    unsafe fn read(registers: &Registers, index: usize) -> Self;
+}
+
+macro_rules! integer_arguments {
+    ($($ty:ty),*) => {
+        $(
+            unsafe impl Argument for $ty {
+                unsafe fn read(registers: &Registers, index: usize) -> Self {
+                    unsafe { integer_slot(registers, index) as $ty }
+                }
+            }
+        )*
+    };
+}
+
+integer_arguments!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+unsafe impl Argument for bool {
+    unsafe fn read(registers: &Registers, index: usize) -> Self {
+        unsafe { integer_slot(registers, index) as u8 != 0 }
+    }
+}
+
+unsafe impl Argument for f32 {
+    unsafe fn read(registers: &Registers, index: usize) -> Self {
+        f32::from_bits(unsafe { float_slot(registers, index) } as u32)
+    }
+}
+
+unsafe impl Argument for f64 {
+    unsafe fn read(registers: &Registers, index: usize) -> Self {
+        f64::from_bits(unsafe { float_slot(registers, index) } as u64)
+    }
+}
+
+unsafe impl<T: 'static> Argument for *const T {
+    unsafe fn read(registers: &Registers, index: usize) -> Self {
+        unsafe { integer_slot(registers, index) as usize as *const T }
+    }
+}
+
+unsafe impl<T: 'static> Argument for *mut T {
+    unsafe fn read(registers: &Registers, index: usize) -> Self {
+        unsafe { integer_slot(registers, index) as usize as *mut T }
+    }
+}
+
+/// A type that can be returned from a hooked function.
+///
+/// # Safety
+///
+/// Implementations must store the value where the Windows x64 calling
+/// convention returns it.
+pub unsafe trait ReturnValue: 'static {
+    /// Stores the value in `registers` and returns the value for `rax`.
+    fn write(self, registers: &mut Registers) -> usize;
+}
+
+unsafe impl ReturnValue for () {
+    fn write(self, _registers: &mut Registers) -> usize {
+        0
+    }
+}
+
+macro_rules! integer_returns {
+    ($($ty:ty),*) => {
+        $(
+            unsafe impl ReturnValue for $ty {
+                fn write(self, _registers: &mut Registers) -> usize {
+                    self as usize
+                }
+            }
+        )*
+    };
+}
+
+integer_returns!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, bool);
+
+unsafe impl ReturnValue for f32 {
+    fn write(self, registers: &mut Registers) -> usize {
+        registers.xmm0 = self.to_bits() as u128;
+        0
+    }
+}
+
+unsafe impl ReturnValue for f64 {
+    fn write(self, registers: &mut Registers) -> usize {
+        registers.xmm0 = self.to_bits() as u128;
+        0
+    }
+}
+
+unsafe impl<T: 'static> ReturnValue for *const T {
+    fn write(self, _registers: &mut Registers) -> usize {
+        self as usize
+    }
+}
+
+unsafe impl<T: 'static> ReturnValue for *mut T {
+    fn write(self, _registers: &mut Registers) -> usize {
+        self as usize
+    }
+}
+
+/// A function pointer type that can be hooked with a typed detour.
+///
+/// Implemented for `unsafe extern "win64"` and `unsafe extern "system"`
+/// function pointers with up to eight [`Argument`]s and a [`ReturnValue`].
+///
+/// # Safety
+///
+/// `Args` must list the parameters of the function type in order.
+pub unsafe trait HookFn: Copy + Send + Sync + 'static {
+    /// The parameters as a tuple.
+    type Args;
+    type Output: ReturnValue;
+
+    /// Converts a code address into a function pointer.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the entry of a function with this signature.
+    unsafe fn from_address(address: usize) -> Self;
+
+    /// Reads the arguments from the register state at function entry.
+    ///
+    /// # Safety
+    ///
+    /// `registers` must hold the state at entry of a function of this type.
+    unsafe fn read_args(registers: &Registers) -> Self::Args;
+
+    /// Calls the function with arguments in tuple form.
+    ///
+    /// # Safety
+    ///
+    /// The function must be safe to call with `args`.
+    unsafe fn call(self, args: Self::Args) -> Self::Output;
+}
+
+macro_rules! hook_fns {
+    ($abi:literal; $($arg:ident),*) => {
+        unsafe impl<R: ReturnValue, $($arg: Argument),*> HookFn for unsafe extern $abi fn($($arg),*) -> R {
+            type Args = ($($arg,)*);
+            type Output = R;
+
+            unsafe fn from_address(address: usize) -> Self {
+                unsafe { core::mem::transmute::<usize, Self>(address) }
+            }
+
+            #[allow(unused_mut, unused_variables, unused_assignments, clippy::unused_unit)]
+            unsafe fn read_args(registers: &Registers) -> Self::Args {
+                let mut index = 0;
+                ($({
+                    let value = unsafe { <$arg as Argument>::read(registers, index) };
+                    index += 1;
+                    value
+                },)*)
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn call(self, args: Self::Args) -> R {
+                let ($($arg,)*) = args;
+                unsafe { self($($arg),*) }
+            }
+        }
+    };
+}
+
+macro_rules! hook_fn_arities {
+    ($abi:literal) => {
+        hook_fns!($abi;);
+        hook_fns!($abi; A);
+        hook_fns!($abi; A, B);
+        hook_fns!($abi; A, B, C);
+        hook_fns!($abi; A, B, C, D);
+        hook_fns!($abi; A, B, C, D, E);
+        hook_fns!($abi; A, B, C, D, E, F);
+        hook_fns!($abi; A, B, C, D, E, F, G);
+        hook_fns!($abi; A, B, C, D, E, F, G, H);
+    };
+}
+
+hook_fn_arities!("win64");
+hook_fn_arities!("system");
+
+type Detour<F> = dyn Fn(F, <F as HookFn>::Args) -> <F as HookFn>::Output + Send + Sync;
+
+struct State<F: HookFn> {
+    detour: Box<Detour<F>>,
+    calls: Arc<AtomicU64>,
+}
+
+/// A boxed detour that can be installed at a target, possibly several times.
+/// Hook points created by [`install`](Self::install) must be dropped before it.
+pub(crate) struct TypedDetour<F: HookFn>(Box<State<F>>);
+
+unsafe extern "win64" fn typed_retn<F: HookFn>(
+    registers: *mut Registers,
+    ori_func_ptr: usize,
+    user_data: usize,
+) -> usize {
+    let state = unsafe { &*(user_data as *const State<F>) };
+    state.calls.fetch_add(1, Ordering::Relaxed);
+    let registers = unsafe { &mut *registers };
+    let args = unsafe { F::read_args(registers) };
+    let original = unsafe { F::from_address(ori_func_ptr) };
+    (state.detour)(original, args).write(registers)
+}
+
+impl<F: HookFn> TypedDetour<F> {
+    /// Wraps `detour`, counting its calls in `calls`.
+    pub(crate) fn new<D>(detour: D, calls: Arc<AtomicU64>) -> Self
+    where
+        D: Fn(F, F::Args) -> F::Output + Send + Sync + 'static,
+    {
+        Self(Box::new(State {
+            detour: Box::new(detour),
+            calls,
+        }))
+    }
+
+    /// Replaces the function at `target_address` with the detour.
+    ///
+    /// # Safety
+    ///
+    /// `target_address` must be the entry of a function of type `F`.
+    pub(crate) unsafe fn install(&self, target_address: usize, freeze: bool) -> Result<CodeHook> {
+        let user_data = &*self.0 as *const State<F> as usize;
+        let hook_type = || HookType::Retn(typed_retn::<F>);
+        unsafe { super::hook_point(target_address, hook_type, user_data, freeze) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::HookContext;
+    use std::sync::RwLock;
+    use std::sync::atomic::AtomicUsize;
+
+    fn registers() -> Registers {
+        Registers {
+            xmm0: 0,
+            xmm1: 0,
+            xmm2: 0,
+            xmm3: 0,
+            r15: 0,
+            r14: 0,
+            r13: 0,
+            r12: 0,
+            r11: 0,
+            r10: 0,
+            r9: 0,
+            r8: 0,
+            rbp: 0,
+            rdi: 0,
+            rsi: 0,
+            rdx: 0,
+            rcx: 0,
+            rbx: 0,
+            rsp: 0,
+            rflags: 0,
+            _no_use: 0,
+            rax: 0,
+        }
+    }
+
+    #[test]
+    fn test_read_arguments() {
+        // Return address, home area, then the fifth and sixth arguments.
+        let stack: [u64; 7] = [0, 0, 0, 0, 0, 0xAB, 2.5f64.to_bits()];
+        let mut registers = registers();
+        registers.rcx = 0xFFFF_FFFF_FFFF_FFFE;
+        registers.xmm1 = 1.5f32.to_bits() as u128;
+        registers.r8 = 0x1000;
+        registers.r9 = 1;
+        registers.rsp = stack.as_ptr() as u64;
+
+        type Target = unsafe extern "win64" fn(i32, f32, *const u8, bool, u8, f64) -> f32;
+        let (a, b, c, d, e, f) = unsafe { Target::read_args(&registers) };
+        assert_eq!(
+            (a, b, c as usize, d, e, f),
+            (-2, 1.5, 0x1000, true, 0xAB, 2.5)
+        );
+
+        assert_eq!(1.25f32.write(&mut registers), 0);
+        assert_eq!(registers.xmm0 as u32, 1.25f32.to_bits());
+    }
+
+    type Scale = unsafe extern "win64" fn(usize, f32) -> i32;
+
+    #[inline(never)]
+    unsafe extern "win64" fn scale(value: usize, factor: f32) -> i32 {
+        let scaled = std::hint::black_box(value as f32) * std::hint::black_box(factor);
+        std::hint::black_box(scaled as i32 + std::hint::black_box(1) - 1)
+    }
+
+    #[test]
+    fn test_typed_hook_calls_detour_and_original() {
+        let ctx = HookContext {
+            config: Arc::new(RwLock::new(())),
+            freeze_threads: false,
+            module: None,
+        };
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let guard = unsafe {
+            ctx.hook::<Scale, _>(scale as Scale as usize, move |original, (value, factor)| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                original(value, factor * 2.0) + 1
+            })
+        }
+        .unwrap();
+
+        let target = std::hint::black_box(scale as Scale);
+        assert_eq!(unsafe { target(10, 1.5) }, 31);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        drop(guard);
+        assert_eq!(unsafe { target(10, 1.5) }, 15);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,324 @@
+//! Live instance discovery for classes with a known vtable.
+//!
+//! Every polymorphic object stores its vtable address in its first qword, so
+//! scanning writable memory for that value yields the live instances of a
+//! class. Candidates can be validated with a user predicate, read back as
+//! typed views, and tracked across repeated scans to see which objects were
+//! created or destroyed in between.
+
+use std::collections::BTreeSet;
+use std::marker::PhantomData;
+
+use crate::errors::Error;
+use crate::memory::{MemoryRegion, MemoryScanner, MemoryState};
+use crate::vtable::VTable;
+
+/// Bytes read per step while scanning, so the scan keeps a single small copy
+/// of target memory instead of one per region.
+const CHUNK_SIZE: usize = 0x10000;
+
+/// Plain data that can be read back from arbitrary object memory.
+///
+/// # Safety
+///
+/// Every bit pattern of `size_of::<Self>()` bytes must be a valid value of
+/// the type. Types with niches or invalid states (`bool`, `char`, enums,
+/// references, `NonNull`) must not implement this trait.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! pod_types {
+    ($($ty:ty),* $(,)?) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+pod_types!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+unsafe impl<T: 'static> Pod for *const T {}
+
+unsafe impl<T: 'static> Pod for *mut T {}
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// A live object whose first qword points at a known vtable.
+#[derive(Debug)]
+pub struct Instance<T> {
+    address: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Instance<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Instance<T> {}
+
+impl<T> Instance<T> {
+    /// Wraps an object address as a typed view.
+    pub fn new(address: usize) -> Self {
+        Self {
+            address,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the address of the object.
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// Returns a raw pointer to the object in the current process.
+    pub fn as_ptr(&self) -> *mut T {
+        self.address as *mut T
+    }
+
+    /// Reinterprets the instance as a view of another type.
+    pub fn cast<U>(&self) -> Instance<U> {
+        Instance::new(self.address)
+    }
+}
+
+impl<T: Pod> Instance<T> {
+    /// Reads a snapshot of the object through the scanner.
+    pub fn read(&self, scanner: &MemoryScanner) -> Result<T, Error> {
+        let data = scanner.read_memory(self.address, std::mem::size_of::<T>())?;
+        Ok(unsafe { std::ptr::read_unaligned(data.as_ptr() as *const T) })
+    }
+}
+
+/// Scans writable memory for objects referencing a vtable.
+pub struct InstanceScanner<'a> {
+    scanner: &'a MemoryScanner,
+}
+
+impl<'a> InstanceScanner<'a> {
+    /// Creates an instance scanner backed by a memory scanner.
+    pub fn new(scanner: &'a MemoryScanner) -> Self {
+        Self { scanner }
+    }
+
+    /// Finds the addresses of all objects whose vtable pointer equals `vtable_address`.
+    ///
+    /// Only pointer-aligned matches in writable, non-executable regions are
+    /// reported; the vtable address also appears in code and read-only data.
+    /// Regions are compared in place through one reusable buffer, and hits
+    /// inside that buffer are dropped so the scan never reports its own copy.
+    pub fn find_addresses(&self, vtable_address: usize) -> Result<Vec<usize>, Error> {
+        let mut chunk = vec![0u8; CHUNK_SIZE];
+        let own = chunk.as_ptr() as usize..chunk.as_ptr() as usize + CHUNK_SIZE;
+        let mut addresses = Vec::new();
+
+        for region in self.scanner.enumerate_regions()? {
+            if !is_instance_region(&region) {
+                continue;
+            }
+
+            let end = region.base_address + region.size;
+            let mut address = region.base_address;
+            while address < end {
+                let len = CHUNK_SIZE.min(end - address);
+                if self
+                    .scanner
+                    .read_memory_into(address, &mut chunk[..len])
+                    .is_ok()
+                {
+                    addresses.extend(
+                        find_pointer(&chunk[..len], address, vtable_address)
+                            .filter(|hit| !own.contains(hit)),
+                    );
+                }
+                address += len;
+            }
+        }
+
+        // Leave no stale copy of the last match behind for a later scan.
+        chunk.fill(0);
+        std::hint::black_box(&chunk);
+
+        addresses.sort_unstable();
+        addresses.dedup();
+        Ok(addresses)
+    }
+
+    /// Finds all instances of the class owning `vtable`.
+    pub fn find<T>(&self, vtable: &VTable) -> Result<Vec<Instance<T>>, Error> {
+        Ok(self
+            .find_addresses(vtable.base_address)?
+            .into_iter()
+            .map(Instance::new)
+            .collect())
+    }
+
+    /// Finds instances whose address passes `predicate`.
+    ///
+    /// Use this to reject stale objects that still carry a vtable pointer,
+    /// e.g. by checking a back-pointer or a reference count.
+    pub fn find_where<T>(
+        &self,
+        vtable: &VTable,
+        predicate: impl Fn(usize) -> bool,
+    ) -> Result<Vec<Instance<T>>, Error> {
+        Ok(self
+            .find_addresses(vtable.base_address)?
+            .into_iter()
+            .filter(|&address| predicate(address))
+            .map(Instance::new)
+            .collect())
+    }
+
+    /// Finds instances whose typed snapshot passes `predicate`.
+    ///
+    /// Candidates that cannot be read in full are skipped.
+    pub fn find_typed<T: Pod>(
+        &self,
+        vtable: &VTable,
+        predicate: impl Fn(&T) -> bool,
+    ) -> Result<Vec<Instance<T>>, Error> {
+        Ok(self
+            .find::<T>(vtable)?
+            .into_iter()
+            .filter(|instance| {
+                instance
+                    .read(self.scanner)
+                    .is_ok_and(|value| predicate(&value))
+            })
+            .collect())
+    }
+}
+
+/// Returns true for committed, writable, non-executable regions that can hold objects.
+pub fn is_instance_region(region: &MemoryRegion) -> bool {
+    region.state == MemoryState::Commit && region.is_writable() && !region.is_executable()
+}
+
+/// Yields the addresses of pointer-aligned slots in `data` that hold `value`.
+///
+/// `base` is the address `data` was read from and must be pointer-aligned.
+pub fn find_pointer(data: &[u8], base: usize, value: usize) -> impl Iterator<Item = usize> + '_ {
+    const WIDTH: usize = std::mem::size_of::<usize>();
+    data.chunks_exact(WIDTH)
+        .enumerate()
+        .filter(move |(_, slot)| usize::from_le_bytes((*slot).try_into().unwrap()) == value)
+        .map(move |(index, _)| base + index * WIDTH)
+}
+
+/// Instances that appeared or disappeared between two scans.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstanceDelta {
+    pub appeared: Vec<usize>,
+    pub disappeared: Vec<usize>,
+}
+
+impl InstanceDelta {
+    /// Returns true if nothing changed between the scans.
+    pub fn is_empty(&self) -> bool {
+        self.appeared.is_empty() && self.disappeared.is_empty()
+    }
+}
+
+/// Tracks the instances of a class across repeated scans.
+#[derive(Debug, Clone)]
+pub struct InstanceWatcher {
+    vtable_address: usize,
+    known: BTreeSet<usize>,
+}
+
+impl InstanceWatcher {
+    /// Creates a watcher for the class owning `vtable`.
+    pub fn new(vtable: &VTable) -> Self {
+        Self {
+            vtable_address: vtable.base_address,
+            known: BTreeSet::new(),
+        }
+    }
+
+    /// Returns the instances seen by the last scan.
+    pub fn known(&self) -> impl Iterator<Item = usize> + '_ {
+        self.known.iter().copied()
+    }
+
+    /// Rescans memory and reports what changed since the previous poll.
+    ///
+    /// The first poll reports every live instance as appeared.
+    pub fn poll(&mut self, scanner: &MemoryScanner) -> Result<InstanceDelta, Error> {
+        let current = InstanceScanner::new(scanner).find_addresses(self.vtable_address)?;
+        Ok(self.update(current))
+    }
+
+    /// Replaces the known set with `current` and returns the difference.
+    pub fn update(&mut self, current: impl IntoIterator<Item = usize>) -> InstanceDelta {
+        let current: BTreeSet<usize> = current.into_iter().collect();
+        let delta = InstanceDelta {
+            appeared: current.difference(&self.known).copied().collect(),
+            disappeared: self.known.difference(&current).copied().collect(),
+        };
+        self.known = current;
+        delta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{MemoryProtection, MemoryType};
+
+    fn region(protection: MemoryProtection, state: MemoryState) -> MemoryRegion {
+        MemoryRegion {
+            base_address: 0x2000,
+            size: 0x1000,
+            protection,
+            state,
+            region_type: MemoryType::Private,
+        }
+    }
+
+    #[test]
+    fn test_is_instance_region() {
+        assert!(is_instance_region(&region(
+            MemoryProtection::ReadWrite,
+            MemoryState::Commit
+        )));
+        assert!(!is_instance_region(&region(
+            MemoryProtection::ReadOnly,
+            MemoryState::Commit
+        )));
+        assert!(!is_instance_region(&region(
+            MemoryProtection::ExecuteReadWrite,
+            MemoryState::Commit
+        )));
+        assert!(!is_instance_region(&region(
+            MemoryProtection::ReadWrite,
+            MemoryState::Reserve
+        )));
+    }
+
+    #[test]
+    fn test_find_pointer_aligned_slots() {
+        let value: usize = 0x1122_3344_5566_7788;
+        let width = std::mem::size_of::<usize>();
+        let mut data = vec![0u8; width * 4 + 3];
+        data[width..width * 2].copy_from_slice(&value.to_le_bytes());
+        data[width * 2 + 1..width * 3 + 1].copy_from_slice(&value.to_le_bytes());
+        data[width * 3..width * 4].copy_from_slice(&value.to_le_bytes());
+
+        let hits: Vec<usize> = find_pointer(&data, 0x2000, value).collect();
+        assert_eq!(hits, vec![0x2000 + width, 0x2000 + width * 3]);
+    }
+
+    #[test]
+    fn test_instance_watcher_delta() {
+        let mut watcher = InstanceWatcher::new(&VTable::new(0x1000));
+        let first = watcher.update([0x10, 0x20]);
+        assert_eq!(first.appeared, vec![0x10, 0x20]);
+        assert!(first.disappeared.is_empty());
+
+        let second = watcher.update([0x20, 0x30]);
+        assert_eq!(second.appeared, vec![0x30]);
+        assert_eq!(second.disappeared, vec![0x10]);
+        assert!(watcher.update([0x20, 0x30]).is_empty());
+    }
+}
@@ -26,14 +26,27 @@ use crate::hooks::{HookModule, register};
 use crate::winapi::IntoHinstance;
 
 pub mod analysis;
+pub mod cache;
+pub mod callconv;
+pub mod cfg;
 pub mod config;
+pub mod diff;
+pub mod disasm;
 pub mod errors;
+pub mod export;
 pub mod hooks;
+pub mod instances;
 pub mod memory;
 pub mod overlay;
 pub mod pattern;
+pub mod pe;
+pub mod strings;
+pub mod structure;
+pub mod tools;
+pub mod types;
 pub mod vtable;
 pub mod winapi;
+pub mod xref;
 
 pub use crate::errors::{Error, Result};
 pub use crate::overlay::{AppUi, OverlayBuilder};
@@ -42,11 +55,14 @@ pub use egui;
 pub use ilhook::x64::Registers;
 
 use core::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
 
 pub(crate) static SHUTDOWN: AtomicBool = AtomicBool::new(false);
 static RUNNING: AtomicBool = AtomicBool::new(false);
+/// Starts hook modules whose DLL loads after startup.
+static MODULE_WATCHER: Mutex<Option<hooks::ModuleWatcher>> = Mutex::new(None);
 
 fn init_logging() {
     use simplelog::{ConfigBuilder, LevelFilter, WriteLogger};
@@ -75,6 +91,7 @@ fn init_logging() {
 
 fn stop_hooks() {
     log::info!("stopping hooks");
+    drop(MODULE_WATCHER.lock().unwrap().take());
     crate::hooks::stop::<crate::config::Config>();
 }
 
@@ -105,7 +122,10 @@ fn start_overlay() {
     thread::spawn(|| {
         let cfg = crate::config::Config::default();
         log::debug!("overlay thread starting");
-        struct Starter;
+        struct Starter {
+            #[cfg(feature = "tools-window")]
+            tools: crate::tools::ToolsWindow,
+        }
         impl AppUi for Starter {
             fn ui(&mut self, ctx: &egui::Context) {
                 if SHUTDOWN.load(Ordering::SeqCst) {
@@ -121,9 +141,15 @@ fn start_overlay() {
                         ui.label("Injected overlay running");
                         ui.label("Press F10 to quit");
                     });
+
+                #[cfg(feature = "tools-window")]
+                self.tools.ui(ctx);
             }
         }
-        if let Err(e) = cfg.overlay_builder().run(Starter) {
+        if let Err(e) = cfg.overlay_builder().run(Starter {
+            #[cfg(feature = "tools-window")]
+            tools: crate::tools::ToolsWindow::new(),
+        }) {
             log::error!("overlay error: {e}");
         }
     });
@@ -165,6 +191,12 @@ fn install_hooks() {
             a + b
         }
 
+        type ScaledFunction = unsafe extern "win64" fn(usize, f32) -> i32;
+
+        unsafe extern "win64" fn example_scaled_function(value: usize, factor: f32) -> i32 {
+            (value as f32 * factor) as i32
+        }
+
         impl HookModule<crate::config::Config> for ExampleModule {
             fn name(&self) -> &'static str {
                 "ExampleModule"
@@ -176,9 +208,21 @@ fn install_hooks() {
             ) -> Result<Vec<hooks::HookGuard>> {
                 let example_hook_0 = unsafe {
                     ctx.install_retn(example_original_function as usize, example_callback, 0)?
-                };
+                }
+                .named("example_original_function");
+
+                let example_hook_1 = unsafe {
+                    ctx.hook::<ScaledFunction, _>(
+                        example_scaled_function as ScaledFunction as usize,
+                        |original, (value, factor)| {
+                            log::info!("typed example called: {value:#x}, {factor}");
+                            original(value, factor * 2.0)
+                        },
+                    )?
+                }
+                .named("example_scaled_function");
 
-                Ok(vec![example_hook_0])
+                Ok(vec![example_hook_0, example_hook_1])
             }
         }
 
@@ -189,6 +233,8 @@ fn install_hooks() {
             unsafe {
                 let result = example_original_function(10, 20);
                 log::info!("test call result: {}", result);
+                let result = example_scaled_function(10, 1.5);
+                log::info!("typed test call result: {}", result);
             }
         });
     }
@@ -213,6 +259,16 @@ fn try_start_system(hinst_dll: isize) -> bool {
                 log::error!("failed to start hooks: {e}");
             } else {
                 log::info!("hooks started");
+                crate::hooks::with_manager::<crate::config::Config, _>(|mgr| {
+                    mgr.log_report();
+                    if let Err(e) = mgr.write_report("hooks.json") {
+                        log::error!("failed to write hooks.json: {e}");
+                    }
+                });
+                let watcher = hooks::ModuleWatcher::start::<crate::config::Config>(
+                    Duration::from_millis(500),
+                );
+                *MODULE_WATCHER.lock().unwrap() = Some(watcher);
             }
 
             start_overlay();
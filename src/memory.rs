@@ -8,18 +8,25 @@ use std::fmt;
 use std::ptr::null_mut;
 
 use windows::Win32::Foundation::{HANDLE, INVALID_HANDLE_VALUE};
-use windows::Win32::System::Diagnostics::Debug::{ReadProcessMemory, WriteProcessMemory};
+use windows::Win32::System::Diagnostics::Debug::{
+    FlushInstructionCache, ReadProcessMemory, WriteProcessMemory,
+};
 use windows::Win32::System::Memory::{
-    MEM_COMMIT, MEM_FREE, MEM_RESERVE, MEMORY_BASIC_INFORMATION, PAGE_EXECUTE, PAGE_EXECUTE_READ,
-    PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_NOACCESS, PAGE_PROTECTION_FLAGS,
-    PAGE_READONLY, PAGE_READWRITE, PAGE_TYPE, PAGE_WRITECOPY, VIRTUAL_ALLOCATION_TYPE,
-    VirtualQueryEx,
+    MEM_COMMIT, MEM_FREE, MEM_IMAGE, MEM_MAPPED, MEM_RESERVE, MEMORY_BASIC_INFORMATION,
+    PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_NOACCESS,
+    PAGE_PROTECTION_FLAGS, PAGE_READONLY, PAGE_READWRITE, PAGE_TYPE, PAGE_WRITECOPY,
+    VIRTUAL_ALLOCATION_TYPE, VirtualProtectEx, VirtualQueryEx,
 };
 use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcess, PROCESS_ALL_ACCESS};
 
+use crate::disasm::{self, Instruction};
 use crate::errors::Error;
 use crate::pattern::{Pattern, PatternScanner};
-use crate::vtable::{VTable, VTableScanner};
+use crate::pe::PeImage;
+use crate::vtable::{self, VTable, VTableScanner};
+
+/// Size of a memory page on x64 Windows.
+const PAGE_SIZE: usize = 0x1000;
 
 /// Memory region information.
 #[derive(Debug, Clone)]
@@ -166,6 +173,15 @@ pub enum MemoryType {
     Private,
 }
 
+impl From<u32> for MemoryType {
+    fn from(region_type: u32) -> Self {
+        match region_type {
+            x if x == MEM_IMAGE.0 => MemoryType::Image,
+            x if x == MEM_MAPPED.0 => MemoryType::Mapped,
+            _ => MemoryType::Private,
+        }
+    }
+}
 
 /// Configuration for memory scanning operations.
 #[derive(Debug, Clone)]
@@ -273,7 +289,7 @@ impl MemoryScanner {
                     size: mbi.RegionSize,
                     protection: MemoryProtection::from(mbi.Protect.0),
                     state: MemoryState::from(mbi.State.0),
-                    region_type: MemoryType::Private, // Simplified
+                    region_type: MemoryType::from(mbi.Type.0),
                 });
             }
 
@@ -286,6 +302,12 @@ impl MemoryScanner {
     /// Reads memory from the target process.
     pub fn read_memory(&self, address: usize, size: usize) -> Result<Vec<u8>, Error> {
         let mut buffer = vec![0u8; size];
+        self.read_memory_into(address, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Reads memory from the target process into an existing buffer.
+    pub fn read_memory_into(&self, address: usize, buffer: &mut [u8]) -> Result<(), Error> {
         let mut bytes_read = 0;
 
         let success = unsafe {
@@ -293,19 +315,19 @@ impl MemoryScanner {
                 self.process_handle,
                 address as *const _,
                 buffer.as_mut_ptr() as *mut _,
-                size,
+                buffer.len(),
                 Some(&mut bytes_read),
             )
         };
 
-        if success.is_err() || bytes_read != size {
+        if success.is_err() || bytes_read != buffer.len() {
             return Err(Error::ReadFailed {
                 address,
                 reason: "ReadProcessMemory failed".to_string(),
             });
         }
 
-        Ok(buffer)
+        Ok(())
     }
 
     /// Writes memory to the target process.
@@ -332,6 +354,156 @@ impl MemoryScanner {
         Ok(())
     }
 
+    /// Writes memory regardless of the protection of the target pages.
+    ///
+    /// Each page in the range is made writable for the write and its previous
+    /// protection is restored afterwards. The instruction cache is flushed when
+    /// the written pages were executable.
+    pub fn write_memory_protected(&self, address: usize, data: &[u8]) -> Result<(), Error> {
+        let mut written = 0;
+        while written < data.len() {
+            let page_address = address + written;
+            let chunk = (PAGE_SIZE - page_address % PAGE_SIZE).min(data.len() - written);
+            let mut old = PAGE_PROTECTION_FLAGS(0);
+            unsafe {
+                VirtualProtectEx(
+                    self.process_handle,
+                    page_address as *const _,
+                    chunk,
+                    PAGE_EXECUTE_READWRITE,
+                    &mut old,
+                )
+            }
+            .map_err(|e| Error::WriteFailed {
+                address: page_address,
+                reason: format!("VirtualProtectEx failed: {e}"),
+            })?;
+
+            let result = self.write_memory(page_address, &data[written..written + chunk]);
+
+            let mut restored = PAGE_PROTECTION_FLAGS(0);
+            let _ = unsafe {
+                VirtualProtectEx(
+                    self.process_handle,
+                    page_address as *const _,
+                    chunk,
+                    old,
+                    &mut restored,
+                )
+            };
+            let executable =
+                PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY;
+            if old.0 & executable.0 != 0 {
+                let _ = unsafe {
+                    FlushInstructionCache(
+                        self.process_handle,
+                        Some(page_address as *const _),
+                        chunk,
+                    )
+                };
+            }
+            result?;
+            written += chunk;
+        }
+        Ok(())
+    }
+
+    /// Reads a pointer-sized value from the target process.
+    pub fn read_pointer(&self, address: usize) -> Result<usize, Error> {
+        let data = self.read_memory(address, size_of::<usize>())?;
+        Ok(usize::from_le_bytes(
+            data.try_into().expect("pointer-sized read"),
+        ))
+    }
+
+    /// Reads the MSVC RTTI class name of the vtable at `vtable_address`.
+    ///
+    /// The complete object locator sits in the slot before the vtable. On x64
+    /// it records its own RVA, which gives the module base needed to resolve
+    /// the type descriptor RVA.
+    pub fn read_rtti_name(&self, vtable_address: usize) -> Result<String, Error> {
+        let no_rtti =
+            || Error::AnalysisFailed(format!("no RTTI for vtable at 0x{vtable_address:X}"));
+        let slot = vtable_address
+            .checked_sub(size_of::<usize>())
+            .ok_or_else(no_rtti)?;
+        let locator = self.read_pointer(slot)?;
+        let header = self.read_memory(locator, 24)?;
+        let field = |offset: usize| {
+            u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap()) as usize
+        };
+        if field(0) != 1 {
+            return Err(no_rtti());
+        }
+        let base = locator.checked_sub(field(20)).ok_or_else(no_rtti)?;
+        let name_address = base + field(12) + 0x10;
+        let name = self
+            .read_memory(name_address, 256)
+            .or_else(|_| self.read_memory(name_address, 64))?;
+        let end = name.iter().position(|&b| b == 0).ok_or_else(no_rtti)?;
+        let name = std::str::from_utf8(&name[..end]).map_err(|_| no_rtti())?;
+        vtable::demangle_type_descriptor(name).ok_or_else(no_rtti)
+    }
+
+    /// Reads `size` bytes at `address` and decodes them as instructions.
+    ///
+    /// Decoding stops at the end of the buffer or at the first byte sequence
+    /// that is not a valid instruction.
+    pub fn disassemble(&self, address: usize, size: usize) -> Result<Vec<Instruction>, Error> {
+        let data = self.read_memory(address, size)?;
+        Ok(disasm::instructions(&data, address).collect())
+    }
+
+    /// Reads a loaded module image in its mapped layout.
+    ///
+    /// The headers and each section are read separately, and a section that
+    /// fails to read is retried page by page, so pages that cannot be read
+    /// leave zeroes in the buffer instead of failing the whole image.
+    pub fn read_image(&self, base: usize) -> Result<Vec<u8>, Error> {
+        let header = self.read_memory(base, 0x1000)?;
+        let pe = PeImage::parse(&header, base)?;
+        let mut image = vec![0u8; pe.size_of_image() as usize];
+        let header_len = header.len().min(image.len());
+        image[..header_len].copy_from_slice(&header[..header_len]);
+
+        for section in pe.sections() {
+            let start = section.virtual_address as usize;
+            let end = (start + section.virtual_size as usize).min(image.len());
+            if start >= end {
+                continue;
+            }
+            if let Ok(data) = self.read_memory(base + start, end - start) {
+                image[start..end].copy_from_slice(&data);
+                continue;
+            }
+            let mut page = start;
+            while page < end {
+                let next = ((page / PAGE_SIZE + 1) * PAGE_SIZE).min(end);
+                if let Ok(data) = self.read_memory(base + page, next - page) {
+                    image[page..next].copy_from_slice(&data);
+                }
+                page = next;
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Finds the base addresses of the module images mapped in the process.
+    pub fn find_modules(&self) -> Result<Vec<usize>, Error> {
+        let mut modules = Vec::new();
+        for region in self.enumerate_regions()? {
+            if region.region_type == MemoryType::Image
+                && self
+                    .read_memory(region.base_address, 2)
+                    .is_ok_and(|magic| magic == b"MZ")
+            {
+                modules.push(region.base_address);
+            }
+        }
+        Ok(modules)
+    }
+
     /// Scans all suitable memory regions for a pattern.
     pub fn scan_pattern(&self, pattern_str: &str) -> Result<Vec<ScanResult>, Error> {
         let regions = self.enumerate_regions()?;
@@ -0,0 +1,1074 @@
+//! Portable Executable parsing for loaded x64 module images.
+//!
+//! Images are parsed in their mapped layout, so every RVA is a direct offset
+//! into the byte buffer. Besides headers, sections and data directories, the
+//! exception directory (`.pdata`) is decoded into exact function boundaries
+//! with their unwind information: stack frame size, saved registers, frame
+//! pointer and chained fragments. Leaf functions, which have no `.pdata`
+//! entry, are found by recursive descent from the known entries.
+
+use std::collections::{BTreeSet, HashSet};
+use std::ops::Range;
+
+use crate::disasm::{self, Flow, Register};
+use crate::errors::Error;
+
+/// Data directory index of the export table.
+pub const DIRECTORY_EXPORT: usize = 0;
+/// Data directory index of the import table.
+pub const DIRECTORY_IMPORT: usize = 1;
+/// Data directory index of the exception table (`.pdata`).
+pub const DIRECTORY_EXCEPTION: usize = 3;
+
+const PE32_PLUS_MAGIC: u16 = 0x20B;
+const FILE_DLL: u16 = 0x2000;
+const SECTION_HEADER_SIZE: usize = 40;
+const RUNTIME_FUNCTION_SIZE: usize = 12;
+const IMPORT_DESCRIPTOR_SIZE: usize = 20;
+const ORDINAL_FLAG: u64 = 1 << 63;
+const MAX_CHAIN_DEPTH: usize = 32;
+const MAX_LEAF_SPAN: usize = 0x4000;
+const MAX_TRACED_INSTRUCTIONS: usize = 0x4000;
+
+const SCN_CNT_CODE: u32 = 0x0000_0020;
+const SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// Unwind info flag: the function has an exception handler.
+pub const UNW_FLAG_EHANDLER: u8 = 0x1;
+/// Unwind info flag: the function has a termination handler.
+pub const UNW_FLAG_UHANDLER: u8 = 0x2;
+/// Unwind info flag: the unwind info continues in a parent entry.
+pub const UNW_FLAG_CHAININFO: u8 = 0x4;
+
+/// An entry of the import table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// Name of the imported DLL as written in the image.
+    pub dll: String,
+    /// Imported function name, or `None` for imports by ordinal.
+    pub name: Option<String>,
+    pub ordinal: Option<u16>,
+    /// RVA of the Import Address Table slot the loader fills in.
+    pub slot: u32,
+}
+
+/// An entry of the export table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub name: Option<String>,
+    /// Biased ordinal, as passed to `GetProcAddress`.
+    pub ordinal: u16,
+    /// RVA of the exported function, or of the forwarder string.
+    pub rva: u32,
+    /// RVA of the Export Address Table slot holding [`rva`](Self::rva).
+    pub slot: u32,
+    /// `dll.function` for exports forwarded to another module.
+    pub forwarder: Option<String>,
+}
+
+/// A section header of a loaded image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub virtual_address: u32,
+    pub virtual_size: u32,
+    pub characteristics: u32,
+}
+
+impl Section {
+    /// Returns true if the section contains executable code.
+    pub fn is_executable(&self) -> bool {
+        self.characteristics & (SCN_MEM_EXECUTE | SCN_CNT_CODE) != 0
+    }
+
+    /// Returns true if the section is writable.
+    pub fn is_writable(&self) -> bool {
+        self.characteristics & SCN_MEM_WRITE != 0
+    }
+
+    /// Returns the RVA range covered by the section.
+    pub fn rva_range(&self) -> Range<u32> {
+        self.virtual_address..self.virtual_address.saturating_add(self.virtual_size)
+    }
+}
+
+/// A data directory entry of the optional header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataDirectory {
+    pub rva: u32,
+    pub size: u32,
+}
+
+/// A `RUNTIME_FUNCTION` entry of the exception directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeFunction {
+    pub begin: u32,
+    pub end: u32,
+    pub unwind_info: u32,
+}
+
+/// A decoded unwind operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnwindOperation {
+    /// `push reg` of a non-volatile register.
+    PushNonVolatile(Register),
+    /// Fixed stack allocation (`sub rsp, size`).
+    Alloc(u32),
+    /// Establishes the frame pointer register.
+    SetFramePointer,
+    /// `mov [rsp+offset], reg` of a non-volatile register.
+    SaveNonVolatile { register: Register, offset: u32 },
+    /// `movaps [rsp+offset], xmm` of a non-volatile XMM register.
+    SaveXmm128 { register: Register, offset: u32 },
+    /// Epilog description (version 2 unwind info).
+    Epilog,
+    /// Hardware exception frame pushed by the processor.
+    PushMachineFrame { error_code: bool },
+}
+
+/// An unwind code with the prolog offset of the instruction it describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnwindCode {
+    pub prolog_offset: u8,
+    pub operation: UnwindOperation,
+}
+
+/// A decoded `UNWIND_INFO` record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnwindInfo {
+    pub version: u8,
+    pub flags: u8,
+    pub prolog_size: u8,
+    pub frame_register: Option<Register>,
+    /// Offset of the frame pointer from RSP, already scaled by 16.
+    pub frame_offset: u32,
+    pub codes: Vec<UnwindCode>,
+    /// RVA of the language-specific handler.
+    pub handler: Option<u32>,
+    /// Parent entry when the unwind info is chained.
+    pub chained: Option<RuntimeFunction>,
+}
+
+impl UnwindInfo {
+    /// Returns the bytes the prolog subtracts from RSP, excluding the return address.
+    pub fn stack_frame_size(&self) -> usize {
+        self.codes
+            .iter()
+            .map(|code| match code.operation {
+                UnwindOperation::PushNonVolatile(_) => 8,
+                UnwindOperation::Alloc(size) => size as usize,
+                UnwindOperation::PushMachineFrame { error_code } => {
+                    if error_code {
+                        48
+                    } else {
+                        40
+                    }
+                }
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Returns the non-volatile registers preserved by the prolog, in prolog order.
+    pub fn saved_registers(&self) -> Vec<Register> {
+        self.codes
+            .iter()
+            .rev()
+            .filter_map(|code| match code.operation {
+                UnwindOperation::PushNonVolatile(register)
+                | UnwindOperation::SaveNonVolatile { register, .. }
+                | UnwindOperation::SaveXmm128 { register, .. } => Some(register),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// A function boundary taken from the exception directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionEntry {
+    pub address: usize,
+    pub size: usize,
+    pub unwind: UnwindInfo,
+    /// Start of the primary function when this entry is a chained fragment.
+    pub parent: Option<usize>,
+    /// Stack frame size including every chained parent.
+    pub frame_size: usize,
+    /// Saved registers including every chained parent.
+    pub saved_registers: Vec<Register>,
+}
+
+impl FunctionEntry {
+    /// Returns the address range covered by the entry.
+    pub fn range(&self) -> Range<usize> {
+        self.address..self.address + self.size
+    }
+}
+
+/// A function without exception data, found by recursive descent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeafFunction {
+    pub address: usize,
+    pub size: usize,
+}
+
+/// A parsed view over a mapped PE32+ image.
+#[derive(Debug, Clone, Copy)]
+pub struct PeImage<'a> {
+    data: &'a [u8],
+    base: usize,
+    nt_offset: usize,
+}
+
+impl<'a> PeImage<'a> {
+    /// Parses the headers of an image mapped at `base`.
+    ///
+    /// `data` holds the image in its loaded layout starting at the DOS header.
+    pub fn parse(data: &'a [u8], base: usize) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::InvalidImage(reason.to_string());
+        if data.get(..2) != Some(b"MZ") {
+            return Err(invalid("missing DOS signature"));
+        }
+        let nt_offset =
+            read_u32(data, 0x3C).ok_or_else(|| invalid("truncated DOS header"))? as usize;
+        if data.get(nt_offset..nt_offset + 4) != Some(b"PE\0\0") {
+            return Err(invalid("missing NT signature"));
+        }
+        let image = Self {
+            data,
+            base,
+            nt_offset,
+        };
+        if read_u16(data, image.optional_header()) != Some(PE32_PLUS_MAGIC) {
+            return Err(invalid("not a PE32+ image"));
+        }
+        Ok(image)
+    }
+
+    /// Returns the address the image is mapped at.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Returns the raw image bytes.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the link timestamp from the file header.
+    pub fn timestamp(&self) -> u32 {
+        read_u32(self.data, self.nt_offset + 8).unwrap_or(0)
+    }
+
+    /// Returns true if the file header marks the image as a DLL.
+    pub fn is_dll(&self) -> bool {
+        read_u16(self.data, self.nt_offset + 22).is_some_and(|flags| flags & FILE_DLL != 0)
+    }
+
+    /// Returns `SizeOfImage` from the optional header.
+    pub fn size_of_image(&self) -> u32 {
+        read_u32(self.data, self.optional_header() + 56).unwrap_or(0)
+    }
+
+    /// Returns the absolute entry point address, if the image has one.
+    pub fn entry_point(&self) -> Option<usize> {
+        match read_u32(self.data, self.optional_header() + 16)? {
+            0 => None,
+            rva => Some(self.base + rva as usize),
+        }
+    }
+
+    /// Returns the data directory at `index`, if present and non-empty.
+    pub fn data_directory(&self, index: usize) -> Option<DataDirectory> {
+        let optional = self.optional_header();
+        let count = read_u32(self.data, optional + 108)? as usize;
+        if index >= count {
+            return None;
+        }
+        let offset = optional + 112 + index * 8;
+        let directory = DataDirectory {
+            rva: read_u32(self.data, offset)?,
+            size: read_u32(self.data, offset + 4)?,
+        };
+        (directory.rva != 0 && directory.size != 0).then_some(directory)
+    }
+
+    /// Returns the section headers.
+    pub fn sections(&self) -> Vec<Section> {
+        let count = read_u16(self.data, self.nt_offset + 6).unwrap_or(0) as usize;
+        let optional_size = read_u16(self.data, self.nt_offset + 20).unwrap_or(0) as usize;
+        let table = self.optional_header() + optional_size;
+        (0..count)
+            .map_while(|i| {
+                let offset = table + i * SECTION_HEADER_SIZE;
+                let name = self.data.get(offset..offset + 8)?;
+                let end = name.iter().position(|&b| b == 0).unwrap_or(8);
+                Some(Section {
+                    name: String::from_utf8_lossy(&name[..end]).into_owned(),
+                    virtual_size: read_u32(self.data, offset + 8)?,
+                    virtual_address: read_u32(self.data, offset + 12)?,
+                    characteristics: read_u32(self.data, offset + 36)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the section containing `rva`.
+    pub fn section_for_rva(&self, rva: u32) -> Option<Section> {
+        self.sections()
+            .into_iter()
+            .find(|section| section.rva_range().contains(&rva))
+    }
+
+    /// Returns the bytes from `rva` to the end of the image buffer.
+    pub fn slice_from(&self, rva: u32) -> Option<&'a [u8]> {
+        self.data.get(rva as usize..)
+    }
+
+    /// Reads the import table.
+    ///
+    /// Function names come from the import lookup table, so they are still
+    /// available after the loader has bound the address table. Malformed
+    /// descriptors end the walk.
+    pub fn imports(&self) -> Vec<Import> {
+        let Some(directory) = self.data_directory(DIRECTORY_IMPORT) else {
+            return Vec::new();
+        };
+        let mut imports = Vec::new();
+        let mut descriptor = directory.rva as usize;
+        while let (Some(lookup), Some(name), Some(first_thunk)) = (
+            read_u32(self.data, descriptor),
+            read_u32(self.data, descriptor + 12),
+            read_u32(self.data, descriptor + 16),
+        ) {
+            if name == 0 || first_thunk == 0 {
+                break;
+            }
+            let Some(dll) = read_c_string(self.data, name as usize) else {
+                break;
+            };
+            // Without a lookup table the names only exist until binding.
+            let lookup = if lookup != 0 { lookup } else { first_thunk };
+            for index in 0.. {
+                let Some(thunk) = read_u64(self.data, lookup as usize + index * 8) else {
+                    break;
+                };
+                if thunk == 0 {
+                    break;
+                }
+                let (name, ordinal) = if thunk & ORDINAL_FLAG != 0 {
+                    (None, Some(thunk as u16))
+                } else {
+                    // IMAGE_IMPORT_BY_NAME: a u16 hint followed by the name.
+                    (read_c_string(self.data, (thunk as u32) as usize + 2), None)
+                };
+                imports.push(Import {
+                    dll: dll.clone(),
+                    name,
+                    ordinal,
+                    slot: first_thunk + index as u32 * 8,
+                });
+            }
+            descriptor += IMPORT_DESCRIPTOR_SIZE;
+        }
+        imports
+    }
+
+    /// Finds the import of `function` from `dll`. DLL names compare
+    /// case-insensitively, function names exactly.
+    pub fn find_import(&self, dll: &str, function: &str) -> Option<Import> {
+        self.imports().into_iter().find(|import| {
+            import.dll.eq_ignore_ascii_case(dll) && import.name.as_deref() == Some(function)
+        })
+    }
+
+    /// Reads the export table.
+    pub fn exports(&self) -> Vec<Export> {
+        let Some(directory) = self.data_directory(DIRECTORY_EXPORT) else {
+            return Vec::new();
+        };
+        let table = directory.rva as usize;
+        let (Some(ordinal_base), Some(count), Some(name_count), Some(functions)) = (
+            read_u32(self.data, table + 16),
+            read_u32(self.data, table + 20),
+            read_u32(self.data, table + 24),
+            read_u32(self.data, table + 28),
+        ) else {
+            return Vec::new();
+        };
+        let names = read_u32(self.data, table + 32).unwrap_or(0) as usize;
+        let ordinals = read_u32(self.data, table + 36).unwrap_or(0) as usize;
+
+        let mut exports: Vec<Export> = (0..count)
+            .map_while(|index| {
+                let slot = functions + index * 4;
+                let rva = read_u32(self.data, slot as usize)?;
+                Some(Export {
+                    name: None,
+                    ordinal: (ordinal_base + index) as u16,
+                    rva,
+                    slot,
+                    forwarder: (directory.rva..directory.rva + directory.size)
+                        .contains(&rva)
+                        .then(|| read_c_string(self.data, rva as usize))
+                        .flatten(),
+                })
+            })
+            .collect();
+        for index in 0..name_count as usize {
+            let (Some(name), Some(function)) = (
+                read_u32(self.data, names + index * 4),
+                read_u16(self.data, ordinals + index * 2),
+            ) else {
+                break;
+            };
+            if let Some(export) = exports.get_mut(function as usize) {
+                export.name = read_c_string(self.data, name as usize);
+            }
+        }
+        exports.retain(|export| export.rva != 0);
+        exports
+    }
+
+    /// Finds the export named `function`.
+    pub fn find_export(&self, function: &str) -> Option<Export> {
+        self.exports()
+            .into_iter()
+            .find(|export| export.name.as_deref() == Some(function))
+    }
+
+    /// Reads the `RUNTIME_FUNCTION` table of the exception directory.
+    pub fn runtime_functions(&self) -> Vec<RuntimeFunction> {
+        let Some(directory) = self.data_directory(DIRECTORY_EXCEPTION) else {
+            return Vec::new();
+        };
+        let start = directory.rva as usize;
+        (0..directory.size as usize / RUNTIME_FUNCTION_SIZE)
+            .map_while(|i| self.runtime_function_at(start + i * RUNTIME_FUNCTION_SIZE))
+            .filter(|function| function.begin < function.end)
+            .collect()
+    }
+
+    /// Decodes the `UNWIND_INFO` record at `rva`.
+    pub fn unwind_info(&self, rva: u32) -> Result<UnwindInfo, Error> {
+        let offset = rva as usize;
+        let truncated = || Error::InvalidImage(format!("truncated unwind info at RVA 0x{rva:X}"));
+        let header = self.data.get(offset..offset + 4).ok_or_else(truncated)?;
+        let version = header[0] & 0x7;
+        let flags = header[0] >> 3;
+        let count = header[2] as usize;
+        let frame_register = header[3] & 0xF;
+        if version != 1 && version != 2 {
+            return Err(Error::InvalidImage(format!(
+                "unsupported unwind version {version} at RVA 0x{rva:X}"
+            )));
+        }
+
+        let slots: Vec<u16> = (0..count)
+            .map(|i| read_u16(self.data, offset + 4 + i * 2))
+            .collect::<Option<_>>()
+            .ok_or_else(truncated)?;
+        let codes = decode_unwind_codes(&slots, version).ok_or_else(|| {
+            Error::InvalidImage(format!("malformed unwind codes at RVA 0x{rva:X}"))
+        })?;
+
+        let trailer = offset + 4 + 2 * ((count + 1) & !1);
+        let mut info = UnwindInfo {
+            version,
+            flags,
+            prolog_size: header[1],
+            frame_register: (frame_register != 0).then_some(Register::Gpr(frame_register)),
+            frame_offset: (header[3] >> 4) as u32 * 16,
+            codes,
+            handler: None,
+            chained: None,
+        };
+        if flags & UNW_FLAG_CHAININFO != 0 {
+            info.chained = Some(self.runtime_function_at(trailer).ok_or_else(truncated)?);
+        } else if flags & (UNW_FLAG_EHANDLER | UNW_FLAG_UHANDLER) != 0 {
+            info.handler = Some(read_u32(self.data, trailer).ok_or_else(truncated)?);
+        }
+        Ok(info)
+    }
+
+    /// Resolves every exception directory entry into a function boundary.
+    ///
+    /// Chained fragments keep their own range and report the primary function
+    /// they belong to; their frame size and saved registers include the
+    /// parents' prologs. Entries whose unwind info cannot be decoded are skipped.
+    pub fn function_entries(&self) -> Vec<FunctionEntry> {
+        self.runtime_functions()
+            .into_iter()
+            .filter_map(|function| self.resolve_entry(function))
+            .collect()
+    }
+
+    /// Finds functions without exception data by recursive descent.
+    ///
+    /// Every entry and the image entry point are traced; direct call and tail
+    /// jump targets in executable sections that are not covered by an entry
+    /// become leaf functions, which are traced in turn.
+    pub fn find_leaf_functions(&self, entries: &[FunctionEntry]) -> Vec<LeafFunction> {
+        let mut covered: Vec<Range<usize>> = entries.iter().map(FunctionEntry::range).collect();
+        covered.sort_unstable_by_key(|range| range.start);
+        let executable: Vec<Range<usize>> = self
+            .sections()
+            .iter()
+            .filter(|section| section.is_executable())
+            .map(|section| {
+                let range = section.rva_range();
+                self.base + range.start as usize..self.base + range.end as usize
+            })
+            .collect();
+        let is_covered = |address: usize| {
+            let index = covered.partition_point(|range| range.start <= address);
+            index > 0 && covered[index - 1].contains(&address)
+        };
+        let is_code = |address: usize| executable.iter().any(|range| range.contains(&address));
+
+        let mut known: HashSet<usize> = entries.iter().map(|entry| entry.address).collect();
+        let mut worklist: Vec<(usize, Range<usize>)> = entries
+            .iter()
+            .map(|entry| (entry.address, entry.range()))
+            .collect();
+        if let Some(entry) = self.entry_point()
+            && !is_covered(entry)
+            && known.insert(entry)
+        {
+            worklist.push((entry, entry..entry + MAX_LEAF_SPAN));
+        }
+
+        let mut leaves = Vec::new();
+        let mut pending_leaves: BTreeSet<usize> = worklist
+            .iter()
+            .filter(|(address, _)| !is_covered(*address))
+            .map(|(address, _)| *address)
+            .collect();
+        while let Some((start, limit)) = worklist.pop() {
+            let (end, calls) = self.trace(start, limit, &is_covered);
+            if pending_leaves.remove(&start) && end > start {
+                leaves.push(LeafFunction {
+                    address: start,
+                    size: end - start,
+                });
+            }
+            for target in calls {
+                if is_code(target) && !is_covered(target) && known.insert(target) {
+                    pending_leaves.insert(target);
+                    worklist.push((target, target..target + MAX_LEAF_SPAN));
+                }
+            }
+        }
+        leaves.sort_unstable_by_key(|leaf| leaf.address);
+        leaves
+    }
+
+    fn optional_header(&self) -> usize {
+        self.nt_offset + 24
+    }
+
+    fn runtime_function_at(&self, offset: usize) -> Option<RuntimeFunction> {
+        Some(RuntimeFunction {
+            begin: read_u32(self.data, offset)?,
+            end: read_u32(self.data, offset + 4)?,
+            unwind_info: read_u32(self.data, offset + 8)?,
+        })
+    }
+
+    fn resolve_entry(&self, function: RuntimeFunction) -> Option<FunctionEntry> {
+        // An odd unwind RVA points at another RUNTIME_FUNCTION instead of UNWIND_INFO.
+        let mut current = function;
+        for _ in 0..MAX_CHAIN_DEPTH {
+            if current.unwind_info & 1 == 0 {
+                break;
+            }
+            current = self.runtime_function_at((current.unwind_info & !1) as usize)?;
+        }
+        let unwind = self.unwind_info(current.unwind_info).ok()?;
+
+        let mut frame_size = unwind.stack_frame_size();
+        let mut saved_registers = unwind.saved_registers();
+        let mut parent = None;
+        let mut next = unwind.chained;
+        for _ in 0..MAX_CHAIN_DEPTH {
+            let Some(chained) = next else { break };
+            let info = self.unwind_info(chained.unwind_info).ok()?;
+            frame_size += info.stack_frame_size();
+            let mut registers = info.saved_registers();
+            registers.append(&mut saved_registers);
+            saved_registers = registers;
+            parent = Some(self.base + chained.begin as usize);
+            next = info.chained;
+        }
+
+        Some(FunctionEntry {
+            address: self.base + function.begin as usize,
+            size: (function.end - function.begin) as usize,
+            unwind,
+            parent,
+            frame_size,
+            saved_registers,
+        })
+    }
+
+    /// Follows control flow from `start` within `limit`.
+    ///
+    /// Returns the end of the furthest decoded instruction and the targets of
+    /// direct calls and of jumps leaving the limit (tail calls).
+    fn trace(
+        &self,
+        start: usize,
+        limit: Range<usize>,
+        is_covered: &impl Fn(usize) -> bool,
+    ) -> (usize, Vec<usize>) {
+        let image_end = self.base + self.data.len();
+        let limit = limit.start.max(self.base)..limit.end.min(image_end);
+        let leaf = !is_covered(start);
+        let mut blocks = vec![start];
+        let mut visited = HashSet::new();
+        let mut calls = Vec::new();
+        let mut end = start;
+        let mut budget = MAX_TRACED_INSTRUCTIONS;
+
+        while let Some(block) = blocks.pop() {
+            if !visited.insert(block) {
+                continue;
+            }
+            let mut address = block;
+            while budget > 0 && limit.contains(&address) {
+                if leaf && address != start && is_covered(address) {
+                    break;
+                }
+                let Ok(insn) = disasm::decode(&self.data[address - self.base..], address) else {
+                    break;
+                };
+                budget -= 1;
+                end = end.max(insn.next_address());
+                let target = insn.branch_target();
+                match insn.flow() {
+                    Flow::Call => calls.extend(target),
+                    Flow::ConditionalJump | Flow::Jump => {
+                        if let Some(target) = target {
+                            if limit.contains(&target) && !(leaf && is_covered(target)) {
+                                blocks.push(target);
+                            } else {
+                                calls.push(target);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                if matches!(
+                    insn.flow(),
+                    Flow::Jump | Flow::IndirectJump | Flow::Return | Flow::Trap
+                ) {
+                    break;
+                }
+                address = insn.next_address();
+            }
+        }
+        (end, calls)
+    }
+}
+
+/// Returns the start of the function containing `address`.
+///
+/// Chained fragments resolve to their primary function.
+pub fn containing_function(
+    entries: &[FunctionEntry],
+    leaves: &[LeafFunction],
+    address: usize,
+) -> Option<usize> {
+    if let Some(entry) = entries.iter().find(|entry| entry.range().contains(&address)) {
+        return Some(entry.parent.unwrap_or(entry.address));
+    }
+    leaves
+        .iter()
+        .find(|leaf| (leaf.address..leaf.address + leaf.size).contains(&address))
+        .map(|leaf| leaf.address)
+}
+
+/// Decodes the unwind code slots of an `UNWIND_INFO` record.
+fn decode_unwind_codes(slots: &[u16], version: u8) -> Option<Vec<UnwindCode>> {
+    let mut codes = Vec::new();
+    let mut i = 0;
+    while i < slots.len() {
+        let slot = slots[i];
+        let prolog_offset = (slot & 0xFF) as u8;
+        let op = ((slot >> 8) & 0xF) as u8;
+        let info = (slot >> 12) as u8;
+        let gpr = Register::Gpr(info);
+        let xmm = Register::Xmm(info);
+        let next = |n: usize| slots.get(i + n).map(|&s| s as u32);
+        let (operation, used) = match op {
+            0 => (UnwindOperation::PushNonVolatile(gpr), 1),
+            1 if info == 0 => (UnwindOperation::Alloc(next(1)? * 8), 2),
+            1 => (UnwindOperation::Alloc(next(1)? | (next(2)? << 16)), 3),
+            2 => (UnwindOperation::Alloc(info as u32 * 8 + 8), 1),
+            3 => (UnwindOperation::SetFramePointer, 1),
+            4 => (
+                UnwindOperation::SaveNonVolatile {
+                    register: gpr,
+                    offset: next(1)? * 8,
+                },
+                2,
+            ),
+            5 => (
+                UnwindOperation::SaveNonVolatile {
+                    register: gpr,
+                    offset: next(1)? | (next(2)? << 16),
+                },
+                3,
+            ),
+            6 if version >= 2 => (UnwindOperation::Epilog, 2),
+            8 => (
+                UnwindOperation::SaveXmm128 {
+                    register: xmm,
+                    offset: next(1)? * 16,
+                },
+                2,
+            ),
+            9 => (
+                UnwindOperation::SaveXmm128 {
+                    register: xmm,
+                    offset: next(1)? | (next(2)? << 16),
+                },
+                3,
+            ),
+            10 => (
+                UnwindOperation::PushMachineFrame {
+                    error_code: info != 0,
+                },
+                1,
+            ),
+            _ => return None,
+        };
+        codes.push(UnwindCode {
+            prolog_offset,
+            operation,
+        });
+        i += used;
+    }
+    Some(codes)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_c_string(data: &[u8], offset: usize) -> Option<String> {
+    let bytes = data.get(offset..)?;
+    let end = bytes.iter().position(|&b| b == 0)?;
+    Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+#[cfg(test)]
+pub(crate) mod fixtures {
+    //! Mapped test images with `.text` at 0x1000, `.pdata` at 0x2000 and `.data` at 0x3000.
+
+    use super::*;
+
+    pub(crate) const BASE: usize = 0x1_4000_0000;
+    pub(crate) const TEXT: usize = 0x1000;
+    pub(crate) const PDATA: usize = 0x2000;
+    pub(crate) const DATA: usize = 0x3000;
+    pub(crate) const IMAGE_SIZE: usize = 0x4000;
+
+    pub(crate) fn put_u16(image: &mut [u8], offset: usize, value: u16) {
+        image[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn put_u32(image: &mut [u8], offset: usize, value: u32) {
+        image[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn put_u64(image: &mut [u8], offset: usize, value: u64) {
+        image[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Address the loader bound the first import of a fixture image to; later
+    /// imports follow at 0x10 byte steps.
+    pub(crate) const BOUND: u64 = 0x7FF8_0000_1000;
+
+    fn put_directory(image: &mut [u8], index: usize, rva: usize, size: usize) {
+        let directory = 0x98 + 112 + index * 8;
+        put_u32(image, directory, rva as u32);
+        put_u32(image, directory + 4, size as u32);
+    }
+
+    fn put_string(image: &mut [u8], offset: usize, value: &str) {
+        image[offset..offset + value.len()].copy_from_slice(value.as_bytes());
+        image[offset + value.len()] = 0;
+    }
+
+    /// Writes a bound import table for `dlls` to the first half of `.data`.
+    pub(crate) fn put_imports(image: &mut [u8], dlls: &[(&str, &[&str])]) {
+        let mut cursor = DATA + 0x100;
+        let mut alloc = |len: usize| {
+            let offset = cursor;
+            cursor += (len + 7) & !7;
+            offset
+        };
+        let mut bound = BOUND;
+        for (i, (dll, functions)) in dlls.iter().enumerate() {
+            let name = alloc(dll.len() + 1);
+            put_string(image, name, dll);
+            let lookup = alloc((functions.len() + 1) * 8);
+            let address_table = alloc((functions.len() + 1) * 8);
+            for (j, function) in functions.iter().enumerate() {
+                let by_name = alloc(function.len() + 3);
+                put_string(image, by_name + 2, function);
+                put_u64(image, lookup + j * 8, by_name as u64);
+                put_u64(image, address_table + j * 8, bound);
+                bound += 0x10;
+            }
+            let descriptor = DATA + i * 20;
+            put_u32(image, descriptor, lookup as u32);
+            put_u32(image, descriptor + 12, name as u32);
+            put_u32(image, descriptor + 16, address_table as u32);
+        }
+        put_directory(image, DIRECTORY_IMPORT, DATA, (dlls.len() + 1) * 20);
+    }
+
+    /// Writes an export table named `module` to the second half of `.data`,
+    /// with ordinals starting at 1.
+    pub(crate) fn put_exports(image: &mut [u8], module: &str, functions: &[(&str, u32)]) {
+        let table = DATA + 0x800;
+        let addresses = table + 0x40;
+        let names = addresses + functions.len() * 4;
+        let ordinals = names + functions.len() * 4;
+        let mut string = ordinals + functions.len() * 2;
+        put_string(image, string, module);
+        put_u32(image, table + 12, string as u32);
+        string += module.len() + 1;
+        put_u32(image, table + 16, 1);
+        put_u32(image, table + 20, functions.len() as u32);
+        put_u32(image, table + 24, functions.len() as u32);
+        put_u32(image, table + 28, addresses as u32);
+        put_u32(image, table + 32, names as u32);
+        put_u32(image, table + 36, ordinals as u32);
+        for (i, (name, rva)) in functions.iter().enumerate() {
+            put_u32(image, addresses + i * 4, *rva);
+            put_u32(image, names + i * 4, string as u32);
+            put_u16(image, ordinals + i * 2, i as u16);
+            put_string(image, string, name);
+            string += name.len() + 1;
+        }
+        put_directory(image, DIRECTORY_EXPORT, table, string - table);
+    }
+
+    /// Builds an image with `code` at the start of `.text` and the given exception data.
+    pub(crate) fn build_image(
+        code: &[u8],
+        pdata: &[RuntimeFunction],
+        unwind: &[(u32, &[u8])],
+    ) -> Vec<u8> {
+        let mut image = vec![0u8; IMAGE_SIZE];
+        image[..2].copy_from_slice(b"MZ");
+        put_u32(&mut image, 0x3C, 0x80);
+        image[0x80..0x84].copy_from_slice(b"PE\0\0");
+        put_u16(&mut image, 0x84, 0x8664);
+        put_u16(&mut image, 0x86, 3);
+        put_u32(&mut image, 0x88, 0x1234_5678);
+        put_u16(&mut image, 0x94, 0xF0);
+        let optional = 0x98;
+        put_u16(&mut image, optional, PE32_PLUS_MAGIC);
+        put_u32(&mut image, optional + 16, TEXT as u32);
+        put_u32(&mut image, optional + 56, IMAGE_SIZE as u32);
+        put_u32(&mut image, optional + 108, 16);
+        let exception = optional + 112 + DIRECTORY_EXCEPTION * 8;
+        put_u32(&mut image, exception, PDATA as u32);
+        put_u32(&mut image, exception + 4, (pdata.len() * 12) as u32);
+
+        let sections = optional + 0xF0;
+        let headers: [(&[u8], usize, u32); 3] = [
+            (b".text", TEXT, SCN_CNT_CODE | SCN_MEM_EXECUTE),
+            (b".pdata", PDATA, 0x4000_0000),
+            (b".data", DATA, 0x4000_0000 | SCN_MEM_WRITE),
+        ];
+        for (i, (name, rva, characteristics)) in headers.into_iter().enumerate() {
+            let header = sections + i * SECTION_HEADER_SIZE;
+            image[header..header + name.len()].copy_from_slice(name);
+            put_u32(&mut image, header + 8, 0x1000);
+            put_u32(&mut image, header + 12, rva as u32);
+            put_u32(&mut image, header + 36, characteristics);
+        }
+
+        image[TEXT..TEXT + code.len()].copy_from_slice(code);
+        for (i, function) in pdata.iter().enumerate() {
+            let offset = PDATA + i * 12;
+            put_u32(&mut image, offset, function.begin);
+            put_u32(&mut image, offset + 4, function.end);
+            put_u32(&mut image, offset + 8, function.unwind_info);
+        }
+        for (rva, bytes) in unwind {
+            let offset = *rva as usize;
+            image[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::{BASE, BOUND, build_image, put_exports, put_imports};
+    use super::*;
+
+    #[test]
+    fn test_parse_headers() {
+        let image = build_image(&[0xC3], &[], &[]);
+        let pe = PeImage::parse(&image, BASE).unwrap();
+        assert_eq!(pe.timestamp(), 0x1234_5678);
+        assert_eq!(pe.size_of_image(), 0x4000);
+        assert_eq!(pe.entry_point(), Some(BASE + 0x1000));
+        assert!(!pe.is_dll());
+        let sections = pe.sections();
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[0].name, ".text");
+        assert!(sections[0].is_executable());
+        assert!(!sections[1].is_executable());
+        assert!(sections[2].is_writable());
+        assert_eq!(pe.section_for_rva(0x2004).unwrap().name, ".pdata");
+        assert!(pe.data_directory(DIRECTORY_IMPORT).is_none());
+        assert!(PeImage::parse(b"MZ", BASE).is_err());
+    }
+
+    #[test]
+    fn test_imports_and_exports() {
+        let mut image = build_image(&[0xC3], &[], &[]);
+        put_imports(
+            &mut image,
+            &[
+                ("KERNEL32.dll", &["CreateFileW", "CloseHandle"]),
+                ("d3d11.dll", &["D3D11CreateDevice"]),
+            ],
+        );
+        put_exports(
+            &mut image,
+            "game.exe",
+            &[("Tick", 0x1000), ("Render", 0x1040)],
+        );
+        let pe = PeImage::parse(&image, BASE).unwrap();
+
+        let imports = pe.imports();
+        assert_eq!(imports.len(), 3);
+        assert_eq!(imports[1].name.as_deref(), Some("CloseHandle"));
+        assert_eq!(imports[2].dll, "d3d11.dll");
+        let import = pe.find_import("kernel32.dll", "CreateFileW").unwrap();
+        assert_eq!(read_u64(&image, import.slot as usize), Some(BOUND));
+        assert!(pe.find_import("user32.dll", "CreateFileW").is_none());
+
+        let exports = pe.exports();
+        assert_eq!(exports.len(), 2);
+        assert_eq!(exports[0].ordinal, 1);
+        let render = pe.find_export("Render").unwrap();
+        assert_eq!((render.ordinal, render.rva), (2, 0x1040));
+        assert_eq!(read_u32(&image, render.slot as usize), Some(0x1040));
+        assert_eq!(render.forwarder, None);
+    }
+
+    #[test]
+    fn test_unwind_codes_and_chains() {
+        // push rbx; push rdi; sub rsp, 0x128; movaps [rsp+0x20], xmm6
+        let primary: &[u8] = &[
+            0x01, 0x10, 0x06, 0x00, // version 1, prolog 0x10, 6 slots
+            0x10, 0x68, 0x02, 0x00, // save xmm6 at 0x20
+            0x09, 0x01, 0x25, 0x00, // alloc large 0x128
+            0x02, 0x70, // push rdi
+            0x01, 0x30, // push rbx
+        ];
+        // Fragment chained to the primary function, with its own small alloc.
+        let mut fragment = vec![0x01 | (UNW_FLAG_CHAININFO << 3), 0x04, 0x01, 0x00];
+        fragment.extend_from_slice(&[0x04, 0x22, 0x00, 0x00]);
+        fragment.extend_from_slice(&0x1000u32.to_le_bytes());
+        fragment.extend_from_slice(&0x1040u32.to_le_bytes());
+        fragment.extend_from_slice(&0x2800u32.to_le_bytes());
+
+        let pdata = [
+            RuntimeFunction {
+                begin: 0x1000,
+                end: 0x1040,
+                unwind_info: 0x2800,
+            },
+            RuntimeFunction {
+                begin: 0x1100,
+                end: 0x1120,
+                unwind_info: 0x2840,
+            },
+        ];
+        let image = build_image(&[], &pdata, &[(0x2800, primary), (0x2840, &fragment)]);
+        let pe = PeImage::parse(&image, BASE).unwrap();
+        assert_eq!(pe.runtime_functions(), pdata);
+
+        let info = pe.unwind_info(0x2800).unwrap();
+        assert_eq!(info.prolog_size, 0x10);
+        assert_eq!(info.codes.len(), 4);
+        assert_eq!(info.stack_frame_size(), 0x138);
+        assert_eq!(
+            info.saved_registers(),
+            vec![Register::RBX, Register::RDI, Register::Xmm(6)]
+        );
+
+        let entries = pe.function_entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].address, BASE + 0x1000);
+        assert_eq!(entries[0].size, 0x40);
+        assert_eq!(entries[0].parent, None);
+        assert_eq!(entries[1].parent, Some(BASE + 0x1000));
+        assert_eq!(entries[1].frame_size, 0x138 + 0x18);
+        assert_eq!(entries[1].saved_registers.len(), 3);
+    }
+
+    #[test]
+    fn test_find_leaf_functions() {
+        let mut code = vec![0xCC; 0x60];
+        // 0x1000: sub rsp, 0x28; call 0x1020; add rsp, 0x28; jmp 0x1040
+        code[..4].copy_from_slice(&[0x48, 0x83, 0xEC, 0x28]);
+        code[4..9].copy_from_slice(&[0xE8, 0x17, 0x00, 0x00, 0x00]);
+        code[9..13].copy_from_slice(&[0x48, 0x83, 0xC4, 0x28]);
+        code[13..18].copy_from_slice(&[0xE9, 0x2E, 0x00, 0x00, 0x00]);
+        // 0x1020: test ecx, ecx; je +3; mov eax, ecx; ret
+        code[0x20..0x27].copy_from_slice(&[0x85, 0xC9, 0x74, 0x03, 0x89, 0xC8, 0xC3]);
+        code[0x27..0x2A].copy_from_slice(&[0x31, 0xC0, 0xC3]);
+        // 0x1040: xor eax, eax; ret
+        code[0x40..0x43].copy_from_slice(&[0x31, 0xC0, 0xC3]);
+
+        let unwind: &[u8] = &[0x01, 0x04, 0x01, 0x00, 0x04, 0x42, 0x00, 0x00];
+        let pdata = [RuntimeFunction {
+            begin: 0x1000,
+            end: 0x1012,
+            unwind_info: 0x2800,
+        }];
+        let image = build_image(&code, &pdata, &[(0x2800, unwind)]);
+        let pe = PeImage::parse(&image, BASE).unwrap();
+        let entries = pe.function_entries();
+        assert_eq!(entries[0].frame_size, 0x28);
+
+        let leaves = pe.find_leaf_functions(&entries);
+        assert_eq!(
+            leaves,
+            vec![
+                LeafFunction {
+                    address: BASE + 0x1020,
+                    size: 0x0A,
+                },
+                LeafFunction {
+                    address: BASE + 0x1040,
+                    size: 3,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! String extraction and string-to-code linking.
+//!
+//! Strings are extracted from data sections in ASCII, UTF-8 and UTF-16LE and
+//! linked to the instructions that load or take their address, which makes a
+//! unique string literal the quickest way back to the function using it after
+//! the binary has been patched.
+
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{StringEncoding, StringReference};
+use crate::pe::PeImage;
+use crate::xref::{XrefIndex, XrefKind};
+
+/// Configuration for string extraction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StringScanConfig {
+    /// Encodings to extract.
+    pub encodings: Vec<StringEncoding>,
+    /// Minimum string length in characters.
+    pub min_length: usize,
+    /// Maximum string length in characters; longer runs are skipped.
+    pub max_length: usize,
+    /// Whether a string must end with a NUL terminator.
+    pub null_terminated: bool,
+}
+
+impl Default for StringScanConfig {
+    fn default() -> Self {
+        Self {
+            encodings: vec![
+                StringEncoding::Ascii,
+                StringEncoding::Utf8,
+                StringEncoding::Unicode,
+            ],
+            min_length: 4,
+            max_length: 4096,
+            null_terminated: true,
+        }
+    }
+}
+
+impl StringScanConfig {
+    fn accepts(&self, encoding: StringEncoding, length: usize, terminated: bool) -> bool {
+        self.encodings.contains(&encoding)
+            && (self.min_length..=self.max_length).contains(&length)
+            && (terminated || !self.null_terminated)
+    }
+}
+
+/// Extracts the strings in `data`, which is mapped at `base_address`.
+///
+/// Byte strings containing only printable ASCII are reported as
+/// [`StringEncoding::Ascii`], those with multi-byte sequences as
+/// [`StringEncoding::Utf8`]. UTF-16LE strings ([`StringEncoding::Unicode`])
+/// must be 2-byte aligned and are limited to characters below U+0800 and
+/// general punctuation, which keeps pairs of ASCII bytes from being read as
+/// CJK text.
+pub fn extract_strings(
+    data: &[u8],
+    base_address: usize,
+    config: &StringScanConfig,
+) -> Vec<StringReference> {
+    let mut strings = extract_byte_strings(data, base_address, config);
+    if config.encodings.contains(&StringEncoding::Unicode) {
+        strings.extend(extract_utf16_strings(data, base_address, config));
+        strings.sort_by_key(|string| string.address);
+    }
+    strings
+}
+
+/// Finds the addresses of `text` in the non-executable sections of a module image.
+///
+/// A match must be followed by a NUL terminator. Suffix matches inside longer
+/// strings are reported too, since compilers merge identical string tails.
+pub fn find_in_image(image: &PeImage, text: &str, encodings: &[StringEncoding]) -> Vec<usize> {
+    let data = image.data();
+    let mut needles: Vec<(Vec<u8>, usize)> = Vec::new();
+    if encodings.contains(&StringEncoding::Ascii) || encodings.contains(&StringEncoding::Utf8) {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.push(0);
+        needles.push((bytes, 1));
+    }
+    if encodings.contains(&StringEncoding::Unicode) {
+        let mut bytes: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        bytes.extend_from_slice(&[0, 0]);
+        needles.push((bytes, 2));
+    }
+
+    let mut addresses = Vec::new();
+    for section in image.sections().iter().filter(|s| !s.is_executable()) {
+        let range = section.rva_range();
+        let start = range.start as usize;
+        let end = (range.end as usize).min(data.len());
+        if start >= end {
+            continue;
+        }
+        for (needle, alignment) in &needles {
+            addresses.extend(
+                data[start..end]
+                    .windows(needle.len())
+                    .enumerate()
+                    .filter(|(offset, window)| {
+                        (start + offset).is_multiple_of(*alignment) && window == needle
+                    })
+                    .map(|(offset, _)| image.base() + start + offset),
+            );
+        }
+    }
+    addresses.sort_unstable();
+    addresses.dedup();
+    addresses
+}
+
+/// Returns true if the reference loads or takes the address of its target.
+pub fn is_string_use(kind: XrefKind) -> bool {
+    matches!(kind, XrefKind::AddressOf | XrefKind::Read)
+}
+
+/// Fills `references` of each string with the instructions using its address.
+pub fn link_references(strings: &mut [StringReference], index: &XrefIndex) {
+    for string in strings {
+        string.references = index
+            .xrefs_to(string.address)
+            .filter(|xref| is_string_use(xref.kind))
+            .map(|xref| xref.from)
+            .collect();
+        string.references.sort_unstable();
+        string.references.dedup();
+    }
+}
+
+fn extract_byte_strings(
+    data: &[u8],
+    base_address: usize,
+    config: &StringScanConfig,
+) -> Vec<StringReference> {
+    let utf8 = config.encodings.contains(&StringEncoding::Utf8);
+    let mut strings = Vec::new();
+    let mut start = 0;
+    let mut length = 0;
+    let mut multibyte = false;
+    let mut offset = 0;
+
+    while offset <= data.len() {
+        let byte = data.get(offset).copied();
+        let width = match byte {
+            Some(b) if b.is_ascii_graphic() || b == b' ' || b == b'\t' => 1,
+            Some(b) if utf8 && b >= 0xC2 => utf8_char_width(&data[offset..]),
+            _ => 0,
+        };
+        if width > 0 {
+            if length == 0 {
+                start = offset;
+                multibyte = false;
+            }
+            length += 1;
+            multibyte |= width > 1;
+            offset += width;
+            continue;
+        }
+
+        if length > 0 {
+            let encoding = if multibyte {
+                StringEncoding::Utf8
+            } else {
+                StringEncoding::Ascii
+            };
+            if config.accepts(encoding, length, byte == Some(0)) {
+                strings.push(StringReference {
+                    address: base_address + start,
+                    value: String::from_utf8_lossy(&data[start..offset]).into_owned(),
+                    encoding,
+                    references: Vec::new(),
+                });
+            }
+            length = 0;
+        }
+        offset += 1;
+    }
+    strings
+}
+
+/// Returns the length of a printable multi-byte UTF-8 character at the start of `bytes`.
+fn utf8_char_width(bytes: &[u8]) -> usize {
+    let width = match bytes[0] {
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => return 0,
+    };
+    match bytes.get(..width).and_then(|b| std::str::from_utf8(b).ok()) {
+        Some(s) if s.chars().all(|c| !c.is_control()) => width,
+        _ => 0,
+    }
+}
+
+fn extract_utf16_strings(
+    data: &[u8],
+    base_address: usize,
+    config: &StringScanConfig,
+) -> Vec<StringReference> {
+    let is_text = |unit: u16| matches!(unit, 0x09 | 0x20..=0x7E | 0xA0..=0x07FF | 0x2000..=0x206F);
+    let first = base_address % 2;
+    let mut strings = Vec::new();
+    let mut start = None;
+    let mut offset = first;
+
+    while offset <= data.len() {
+        let unit = data
+            .get(offset..offset + 2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+        if unit.is_some_and(is_text) {
+            start.get_or_insert(offset);
+        } else if let Some(begin) = start.take() {
+            let units: Vec<u16> = data[begin..offset]
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            if config.accepts(StringEncoding::Unicode, units.len(), unit == Some(0)) {
+                strings.push(StringReference {
+                    address: base_address + begin,
+                    value: String::from_utf16_lossy(&units),
+                    encoding: StringEncoding::Unicode,
+                    references: Vec::new(),
+                });
+            }
+        }
+        offset += 2;
+    }
+    strings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::fixtures::{BASE, DATA, TEXT, build_image};
+    use crate::xref::XrefIndex;
+
+    #[test]
+    fn test_extract_encodings() {
+        let mut data = b"PlayerController::Tick\0ab\0Caf\xC3\xA9 cr\xC3\xA8me\0".to_vec();
+        data.resize(0x28, 0);
+        for unit in "Wide name".encode_utf16() {
+            data.extend_from_slice(&unit.to_le_bytes());
+        }
+        data.extend_from_slice(&[0, 0, b'u', b'n', b't', b'e', b'r', b'm']);
+
+        let strings = extract_strings(&data, 0x1000, &StringScanConfig::default());
+        let found: Vec<(&str, StringEncoding)> = strings
+            .iter()
+            .map(|s| (s.value.as_str(), s.encoding))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("PlayerController::Tick", StringEncoding::Ascii),
+                ("Café crème", StringEncoding::Utf8),
+                ("Wide name", StringEncoding::Unicode),
+            ]
+        );
+        assert_eq!(strings[2].address, 0x1028);
+
+        let config = StringScanConfig {
+            encodings: vec![StringEncoding::Ascii],
+            min_length: 2,
+            null_terminated: false,
+            ..Default::default()
+        };
+        let ascii: Vec<String> = extract_strings(&data, 0x1000, &config)
+            .into_iter()
+            .map(|s| s.value)
+            .collect();
+        assert_eq!(
+            ascii,
+            vec!["PlayerController::Tick", "ab", "Caf", " cr", "me", "unterm"]
+        );
+    }
+
+    #[test]
+    fn test_find_and_link_references() {
+        let code = [
+            0x48, 0x8D, 0x0D, 0xF9, 0x1F, 0x00, 0x00, // 0x1000: lea rcx, [0x3000]
+            0xC3,
+        ];
+        let mut image = build_image(&code, &[], &[]);
+        image[DATA..DATA + 5].copy_from_slice(b"Tick\0");
+        image[DATA + 0x10..DATA + 0x1B].copy_from_slice(b"Actor::Tick");
+        let pe = PeImage::parse(&image, BASE).unwrap();
+
+        let matches = find_in_image(&pe, "Tick", &[StringEncoding::Ascii]);
+        assert_eq!(matches, vec![BASE + DATA, BASE + DATA + 0x17]);
+        assert!(find_in_image(&pe, "Tick", &[StringEncoding::Unicode]).is_empty());
+
+        let data = &image[DATA..DATA + 0x1000];
+        let mut strings = extract_strings(data, BASE + DATA, &StringScanConfig::default());
+        assert_eq!(strings.len(), 2);
+        link_references(&mut strings, &XrefIndex::build(&pe));
+        assert_eq!(strings[0].references, vec![BASE + TEXT]);
+        assert!(strings[1].references.is_empty());
+    }
+}
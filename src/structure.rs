@@ -0,0 +1,718 @@
+//! Structure recovery from member function disassembly.
+//!
+//! Object layouts are reconstructed by walking the code of constructors and
+//! virtual member functions and collecting every memory access made through the
+//! `this` pointer (`[rcx+disp]` on entry, and any register `rcx` is copied into).
+//! Operand widths give field sizes, SSE moves mark floating point fields and
+//! registers that are later dereferenced mark pointer fields. Constructors are
+//! located by the store of a vtable address into the object, and allocation
+//! sizes are recovered from the `mov ecx, imm32; call operator new` sequence
+//! that precedes a constructor call.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::analysis::{DataType, StructField};
+use crate::disasm::{self, Flow, Instruction, Mnemonic, OpcodeMap};
+
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RDX: u8 = 2;
+const RBX: u8 = 3;
+const RBP: u8 = 5;
+const RSI: u8 = 6;
+const RDI: u8 = 7;
+
+/// Registers clobbered by a call under the Windows x64 ABI.
+const VOLATILE_REGISTERS: [u8; 7] = [0, 1, 2, 8, 9, 10, 11];
+
+/// How a field was accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    /// General purpose register load/store or arithmetic.
+    Integer,
+    /// Scalar or packed SSE access.
+    Float,
+    /// The loaded value was later dereferenced, or an address was stored.
+    Pointer,
+    /// A vtable address was stored at this offset.
+    VTable,
+}
+
+/// A single memory access through the `this` pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldAccess {
+    /// Address of the accessing instruction.
+    pub instruction_address: usize,
+    /// Offset from the start of the object.
+    pub offset: usize,
+    /// Operand width in bytes.
+    pub size: usize,
+    pub kind: AccessKind,
+    pub is_write: bool,
+}
+
+/// A vtable address written into an object by a constructor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VTableStore {
+    pub instruction_address: usize,
+    pub offset: usize,
+    pub vtable_address: usize,
+}
+
+/// Accesses collected from a single function.
+#[derive(Debug, Clone, Default)]
+pub struct FunctionAccesses {
+    pub accesses: Vec<FieldAccess>,
+    pub vtable_stores: Vec<VTableStore>,
+}
+
+/// Layout recovered for the class owning a vtable.
+#[derive(Debug, Clone)]
+pub struct ObjectLayout {
+    pub vtable_address: usize,
+    /// Size passed to `operator new` before a constructor call, if found.
+    pub allocation_size: Option<usize>,
+    pub constructors: Vec<usize>,
+    pub accesses: Vec<FieldAccess>,
+}
+
+impl ObjectLayout {
+    /// Creates an empty layout for the given vtable.
+    pub fn new(vtable_address: usize) -> Self {
+        Self {
+            vtable_address,
+            allocation_size: None,
+            constructors: Vec::new(),
+            accesses: Vec::new(),
+        }
+    }
+
+    /// Returns the best size estimate for the object.
+    ///
+    /// The allocation size wins when known; otherwise the end of the furthest
+    /// accessed field is rounded up to pointer alignment.
+    pub fn estimated_size(&self) -> usize {
+        let ptr_size = std::mem::size_of::<usize>();
+        if let Some(size) = self.allocation_size {
+            return size;
+        }
+        let end = self
+            .accesses
+            .iter()
+            .map(|a| a.offset + a.size)
+            .max()
+            .unwrap_or(ptr_size)
+            .max(ptr_size);
+        end.next_multiple_of(ptr_size)
+    }
+
+    /// Merges the collected accesses into non-overlapping fields.
+    ///
+    /// Accesses at the same offset are combined, preferring the widest operand
+    /// and the most specific kind (vtable, then pointer, then float). Fields
+    /// that would overlap a previous field are dropped.
+    pub fn fields(&self) -> Vec<StructField> {
+        let mut merged: BTreeMap<usize, (usize, AccessKind)> = BTreeMap::new();
+        for access in &self.accesses {
+            let entry = merged
+                .entry(access.offset)
+                .or_insert((access.size, access.kind));
+            entry.0 = entry.0.max(access.size);
+            if kind_rank(access.kind) > kind_rank(entry.1) {
+                entry.1 = access.kind;
+            }
+        }
+
+        let mut fields = Vec::new();
+        let mut next_free = 0;
+        for (offset, (size, kind)) in merged {
+            if offset < next_free {
+                continue;
+            }
+            let name = if kind == AccessKind::VTable {
+                if offset == 0 {
+                    "vtable".to_string()
+                } else {
+                    format!("vtable_{:X}", offset)
+                }
+            } else {
+                format!("field_{:X}", offset)
+            };
+            fields.push(StructField {
+                name,
+                offset,
+                data_type: guess_data_type(size, kind),
+                size,
+                bit_field: None,
+            });
+            next_free = offset + size;
+        }
+        fields
+    }
+}
+
+fn kind_rank(kind: AccessKind) -> u8 {
+    match kind {
+        AccessKind::Integer => 0,
+        AccessKind::Float => 1,
+        AccessKind::Pointer => 2,
+        AccessKind::VTable => 3,
+    }
+}
+
+/// Guesses a `DataType` from an operand width and access kind.
+pub fn guess_data_type(size: usize, kind: AccessKind) -> DataType {
+    match (kind, size) {
+        (AccessKind::VTable, _) | (AccessKind::Pointer, _) => {
+            DataType::Pointer(Box::new(DataType::Void))
+        }
+        (AccessKind::Float, 4) => DataType::Float32,
+        (AccessKind::Float, 8) => DataType::Float64,
+        (AccessKind::Float, 16) => DataType::Array(Box::new(DataType::Float32), 4),
+        (_, 1) => DataType::UInt8,
+        (_, 2) => DataType::UInt16,
+        (_, 4) => DataType::Int32,
+        (_, 8) => DataType::Int64,
+        (_, n) => DataType::Array(Box::new(DataType::UInt8), n),
+    }
+}
+
+/// Linear-sweep collector of `this`-relative accesses.
+///
+/// Instructions are decoded from the function entry until the first return,
+/// trap or undecodable byte sequence.
+pub struct AccessCollector {
+    /// Maximum number of bytes to sweep per function.
+    pub max_bytes: usize,
+}
+
+impl Default for AccessCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AccessCollector {
+    pub fn new() -> Self {
+        Self { max_bytes: 0x800 }
+    }
+
+    /// Collects the `this`-relative accesses of the function starting at `code[0]`.
+    ///
+    /// `address` is the runtime address of `code[0]`; it is used to resolve
+    /// RIP-relative operands and is reported in every access.
+    pub fn collect(&self, code: &[u8], address: usize) -> FunctionAccesses {
+        let mut result = FunctionAccesses::default();
+        let mut regs = RegisterState::new();
+        let mut pointer_offsets: HashSet<usize> = HashSet::new();
+
+        let code = &code[..code.len().min(self.max_bytes)];
+        for insn in disasm::instructions(code, address) {
+            match insn.flow() {
+                Flow::Return | Flow::Trap => break,
+                Flow::Call => {
+                    for reg in VOLATILE_REGISTERS {
+                        regs.clobber(reg);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            if insn.map == OpcodeMap::Primary && matches!(insn.opcode, 0x58..=0x5F | 0xB0..=0xBF) {
+                regs.clobber((insn.opcode & 7) | ((insn.rex & 1) << 3));
+                continue;
+            }
+
+            let (Some(access), Some(modrm)) = (classify(&insn), insn.modrm) else {
+                for reg in unmodelled_writes(&insn) {
+                    regs.clobber(reg);
+                }
+                continue;
+            };
+
+            let Some(mem) = insn.memory else {
+                // Register-to-register form: `mov r64, r64` propagates `this`.
+                if insn.mnemonic == Mnemonic::Mov && matches!(insn.opcode, 0x89 | 0x8B) {
+                    let (dst, src) = if insn.opcode == 0x8B {
+                        (modrm.reg, modrm.rm)
+                    } else {
+                        (modrm.rm, modrm.reg)
+                    };
+                    regs.copy(dst, src, insn.is_wide());
+                } else {
+                    match access.dest {
+                        Some(Dest::Reg) => regs.clobber(modrm.reg),
+                        Some(Dest::Rm) => regs.clobber(modrm.rm),
+                        None => {}
+                    }
+                }
+                continue;
+            };
+
+            // A dereference of a value loaded from the object marks a pointer field.
+            if let Some(base) = mem.base
+                && let Some(&offset) = regs.loaded_from.get(&base)
+            {
+                pointer_offsets.insert(offset);
+            }
+
+            if insn.mnemonic == Mnemonic::Lea {
+                regs.clobber(modrm.reg);
+                if let Some(target) = insn.rip_target() {
+                    regs.rip_values.insert(modrm.reg, target);
+                }
+                continue;
+            }
+
+            let this_based = mem.base.is_some_and(|b| regs.this_regs.contains(&b))
+                && mem.index.is_none()
+                && mem.displacement >= 0;
+            if this_based {
+                let offset = mem.displacement as usize;
+                let mut kind = access.kind;
+                if access.is_write
+                    && insn.opcode == 0x89
+                    && access.size == 8
+                    && let Some(&target) = regs.rip_values.get(&modrm.reg)
+                {
+                    kind = AccessKind::VTable;
+                    result.vtable_stores.push(VTableStore {
+                        instruction_address: insn.address,
+                        offset,
+                        vtable_address: target,
+                    });
+                }
+                result.accesses.push(FieldAccess {
+                    instruction_address: insn.address,
+                    offset,
+                    size: access.size,
+                    kind,
+                    is_write: access.is_write,
+                });
+                if access.dest == Some(Dest::Reg) {
+                    regs.clobber(modrm.reg);
+                    if access.size == 8 && kind == AccessKind::Integer {
+                        regs.loaded_from.insert(modrm.reg, offset);
+                    }
+                }
+            } else if access.dest == Some(Dest::Reg) {
+                regs.clobber(modrm.reg);
+            }
+        }
+
+        for access in &mut result.accesses {
+            if access.kind == AccessKind::Integer && pointer_offsets.contains(&access.offset) {
+                access.kind = AccessKind::Pointer;
+            }
+        }
+        result
+    }
+}
+
+/// What the collector knows about each general purpose register.
+struct RegisterState {
+    /// Registers holding the `this` pointer.
+    this_regs: HashSet<u8>,
+    /// Registers holding a value loaded from `[this+offset]`.
+    loaded_from: HashMap<u8, usize>,
+    /// Registers holding an absolute address produced by `lea reg, [rip+disp]`.
+    rip_values: HashMap<u8, usize>,
+}
+
+impl RegisterState {
+    fn new() -> Self {
+        Self {
+            this_regs: HashSet::from([RCX]),
+            loaded_from: HashMap::new(),
+            rip_values: HashMap::new(),
+        }
+    }
+
+    /// Forgets everything about a register that was overwritten.
+    fn clobber(&mut self, reg: u8) {
+        self.this_regs.remove(&reg);
+        self.loaded_from.remove(&reg);
+        self.rip_values.remove(&reg);
+    }
+
+    /// Models `mov dst, src`.
+    fn copy(&mut self, dst: u8, src: u8, wide: bool) {
+        if wide && self.this_regs.contains(&src) {
+            self.this_regs.insert(dst);
+        } else {
+            self.this_regs.remove(&dst);
+        }
+        match self.loaded_from.get(&src).copied() {
+            Some(offset) => self.loaded_from.insert(dst, offset),
+            None => self.loaded_from.remove(&dst),
+        };
+        match self.rip_values.get(&src).copied() {
+            Some(value) => self.rip_values.insert(dst, value),
+            None => self.rip_values.remove(&dst),
+        };
+    }
+}
+
+/// Where the result of a recognised instruction goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dest {
+    /// Written to the general purpose register in the `ModRM.reg` field.
+    Reg,
+    /// Written to the `ModRM.rm` operand.
+    Rm,
+}
+
+/// Memory access performed by an instruction's `ModRM` operand.
+#[derive(Debug, Clone, Copy)]
+struct Access {
+    size: usize,
+    kind: AccessKind,
+    is_write: bool,
+    dest: Option<Dest>,
+}
+
+/// Returns true if the instruction stores to its `ModRM` memory operand.
+pub(crate) fn is_memory_write(insn: &Instruction) -> bool {
+    insn.memory.is_some() && classify(insn).is_some_and(|access| access.is_write)
+}
+
+/// Classifies the `ModRM` operand access of a decoded instruction.
+///
+/// Returns `None` for instructions whose operand width or direction is not
+/// modelled; those are skipped by the collector.
+fn classify(insn: &Instruction) -> Option<Access> {
+    let gpr = insn.operand_size() as usize;
+    let op = insn.opcode;
+    let int = |size, is_write, dest| Access {
+        size,
+        kind: AccessKind::Integer,
+        is_write,
+        dest,
+    };
+    let float = |size, is_write| Access {
+        size,
+        kind: AccessKind::Float,
+        is_write,
+        dest: None,
+    };
+    let sse_prefix = match insn.vex {
+        Some(v) => [None, Some(0x66), Some(0xF3), Some(0xF2)][v.pp as usize],
+        None => insn.prefixes.mandatory,
+    };
+    let vector = if insn.vex.is_some_and(|v| v.length > 0) {
+        32
+    } else {
+        16
+    };
+    let scalar = match sse_prefix {
+        Some(0xF3) => 4,
+        Some(0xF2) => 8,
+        _ => vector,
+    };
+    let is_cmp = insn.modrm.is_some_and(|m| m.reg & 7 == 7);
+
+    Some(match insn.mnemonic {
+        Mnemonic::Mov => match op {
+            0x88 => int(1, true, Some(Dest::Rm)),
+            0x89 | 0xC7 => int(gpr, true, Some(Dest::Rm)),
+            0xC6 => int(1, true, Some(Dest::Rm)),
+            0x8A => int(1, false, Some(Dest::Reg)),
+            0x8B => int(gpr, false, Some(Dest::Reg)),
+            _ => return None,
+        },
+        Mnemonic::Lea => int(gpr, false, Some(Dest::Reg)),
+        Mnemonic::Movzx | Mnemonic::Movsx => {
+            let size = if matches!(op, 0xB6 | 0xBE) { 1 } else { 2 };
+            int(size, false, Some(Dest::Reg))
+        }
+        Mnemonic::Movsxd => int(4, false, Some(Dest::Reg)),
+        Mnemonic::Add
+        | Mnemonic::Or
+        | Mnemonic::Adc
+        | Mnemonic::Sbb
+        | Mnemonic::And
+        | Mnemonic::Sub
+        | Mnemonic::Xor
+        | Mnemonic::Cmp
+            if insn.modrm.is_some() =>
+        {
+            let byte = matches!(op, 0x80) || (op < 0x40 && op & 1 == 0);
+            let size = if byte { 1 } else { gpr };
+            if insn.mnemonic == Mnemonic::Cmp || (op >= 0x80 && is_cmp) {
+                int(size, false, None)
+            } else if op < 0x40 && op & 2 != 0 {
+                int(size, false, Some(Dest::Reg))
+            } else {
+                int(size, true, Some(Dest::Rm))
+            }
+        }
+        Mnemonic::Test if insn.modrm.is_some() => {
+            let byte = matches!(op, 0x84 | 0xF6);
+            int(if byte { 1 } else { gpr }, false, None)
+        }
+        Mnemonic::Inc | Mnemonic::Dec | Mnemonic::Neg | Mnemonic::Not => {
+            let byte = matches!(op, 0xF6 | 0xFE);
+            int(if byte { 1 } else { gpr }, true, Some(Dest::Rm))
+        }
+        Mnemonic::Call | Mnemonic::Jmp if insn.modrm.is_some() => Access {
+            size: 8,
+            kind: AccessKind::Pointer,
+            is_write: false,
+            dest: None,
+        },
+        Mnemonic::Movss | Mnemonic::Movsd => float(scalar, op == 0x11),
+        Mnemonic::Movups | Mnemonic::Movupd | Mnemonic::Movaps | Mnemonic::Movapd => {
+            float(vector, matches!(op, 0x11 | 0x29))
+        }
+        Mnemonic::Movdqa | Mnemonic::Movdqu => int(vector, op == 0x7F, None),
+        Mnemonic::Movd | Mnemonic::Movq => {
+            let size = if insn.mnemonic == Mnemonic::Movd {
+                4
+            } else {
+                8
+            };
+            let is_write = op == 0xD6 || (op == 0x7E && sse_prefix != Some(0xF3));
+            // `movd/movq r/m, xmm` writes a general purpose register in its
+            // register form.
+            let dest = (op == 0x7E && is_write).then_some(Dest::Rm);
+            int(size, is_write, dest)
+        }
+        Mnemonic::SseCompare if op != 0xC2 => {
+            float(if sse_prefix == Some(0x66) { 8 } else { 4 }, false)
+        }
+        Mnemonic::SseAdd
+        | Mnemonic::SseSub
+        | Mnemonic::SseMul
+        | Mnemonic::SseDiv
+        | Mnemonic::SseMin
+        | Mnemonic::SseMax
+        | Mnemonic::SseSqrt
+        | Mnemonic::SseCompare => float(scalar, false),
+        Mnemonic::SseConvert if op == 0x2A => int(gpr, false, None),
+        // cvt(t)ss2si and cvt(t)sd2si write a general purpose register.
+        Mnemonic::SseConvert if matches!(op, 0x2C | 0x2D) => Access {
+            size: scalar,
+            kind: AccessKind::Float,
+            is_write: false,
+            dest: Some(Dest::Reg),
+        },
+        Mnemonic::SseConvert if scalar != vector => float(scalar, false),
+        _ => return None,
+    })
+}
+
+/// General purpose registers written by an instruction that [`classify`]
+/// does not model, whose tracked meaning is lost.
+fn unmodelled_writes(insn: &Instruction) -> Vec<u8> {
+    let reg = insn.modrm.map(|m| m.reg);
+    let rm = insn.modrm.filter(|m| m.mode == 3).map(|m| m.rm);
+    let opcode_reg = (insn.opcode & 7) | ((insn.rex & 1) << 3);
+    match insn.mnemonic {
+        Mnemonic::Mul | Mnemonic::Div | Mnemonic::Idiv => vec![RAX, RDX],
+        Mnemonic::Imul if matches!(insn.opcode, 0xF6 | 0xF7) => vec![RAX, RDX],
+        Mnemonic::Imul | Mnemonic::Bsf | Mnemonic::Bsr | Mnemonic::Cmov => {
+            reg.into_iter().collect()
+        }
+        Mnemonic::Rol
+        | Mnemonic::Ror
+        | Mnemonic::Rcl
+        | Mnemonic::Rcr
+        | Mnemonic::Shl
+        | Mnemonic::Shr
+        | Mnemonic::Sar
+        | Mnemonic::Shld
+        | Mnemonic::Shrd
+        | Mnemonic::Set => rm.into_iter().collect(),
+        Mnemonic::Xchg if insn.modrm.is_none() => vec![RAX, opcode_reg],
+        Mnemonic::Xchg | Mnemonic::Xadd => reg.into_iter().chain(rm).collect(),
+        Mnemonic::Cmpxchg => rm.into_iter().chain([RAX]).collect(),
+        Mnemonic::Bswap => vec![opcode_reg],
+        Mnemonic::Cdqe => vec![RAX],
+        Mnemonic::Cqo => vec![RDX],
+        Mnemonic::Enter | Mnemonic::Leave => vec![RBP],
+        Mnemonic::Cpuid => vec![RAX, RBX, RCX, RDX],
+        Mnemonic::Rdtsc => vec![RAX, RDX],
+        Mnemonic::Movs | Mnemonic::Cmps | Mnemonic::Stos | Mnemonic::Lods | Mnemonic::Scas => {
+            vec![RAX, RCX, RSI, RDI]
+        }
+        // The accumulator forms with an immediate operand.
+        Mnemonic::Add
+        | Mnemonic::Or
+        | Mnemonic::Adc
+        | Mnemonic::Sbb
+        | Mnemonic::And
+        | Mnemonic::Sub
+        | Mnemonic::Xor
+            if insn.modrm.is_none() =>
+        {
+            vec![RAX]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Finds the start of the function containing `offset` by walking back to
+/// the nearest `int3`/`ret` padding boundary.
+pub fn find_function_start(code: &[u8], offset: usize, max_distance: usize) -> Option<usize> {
+    let lower = offset.saturating_sub(max_distance);
+    let mut i = offset;
+    while i > lower {
+        if matches!(code[i - 1], 0xCC | 0xC3) {
+            return Some(i);
+        }
+        i -= 1;
+    }
+    None
+}
+
+/// Finds offsets of `lea reg, [rip+disp]` instructions that resolve to `target`.
+pub fn find_rip_lea_references(code: &[u8], base_address: usize, target: usize) -> Vec<usize> {
+    let mut results = Vec::new();
+    if code.len() < 7 {
+        return results;
+    }
+    for i in 0..=code.len() - 7 {
+        if !matches!(code[i], 0x48 | 0x4C) || code[i + 1] != 0x8D || code[i + 2] & 0xC7 != 0x05 {
+            continue;
+        }
+        let disp = i32::from_le_bytes([code[i + 3], code[i + 4], code[i + 5], code[i + 6]]);
+        let next_ip = base_address + i + 7;
+        if next_ip.wrapping_add_signed(disp as isize) == target {
+            results.push(i);
+        }
+    }
+    results
+}
+
+/// Finds offsets of `call rel32` instructions whose target is `target`.
+pub fn find_call_references(code: &[u8], base_address: usize, target: usize) -> Vec<usize> {
+    let mut results = Vec::new();
+    if code.len() < 5 {
+        return results;
+    }
+    for i in 0..=code.len() - 5 {
+        if code[i] != 0xE8 {
+            continue;
+        }
+        let rel = i32::from_le_bytes([code[i + 1], code[i + 2], code[i + 3], code[i + 4]]);
+        if (base_address + i + 5).wrapping_add_signed(rel as isize) == target {
+            results.push(i);
+        }
+    }
+    results
+}
+
+/// Recovers the allocation size for a constructor call at `call_offset`.
+///
+/// Looks back up to `window` bytes for `mov ecx, imm32` followed by a
+/// `call rel32` (the allocator) that precedes the constructor call.
+pub fn find_allocation_size(code: &[u8], call_offset: usize, window: usize) -> Option<usize> {
+    let lower = call_offset.saturating_sub(window);
+    let mut i = call_offset;
+    let mut allocator_call = None;
+    while i > lower {
+        i -= 1;
+        if allocator_call.is_none() {
+            if code[i] == 0xE8 && i + 5 <= call_offset {
+                allocator_call = Some(i);
+            }
+            continue;
+        }
+        if code[i] == 0xB9
+            && i + 5 <= allocator_call?
+            && (i == 0 || !matches!(code[i - 1], 0x40..=0x4F))
+        {
+            let size = u32::from_le_bytes(code[i + 1..i + 5].try_into().ok()?) as usize;
+            if size > 0 && size < 0x100000 {
+                return Some(size);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_this_accesses() {
+        let code = [
+            0x48, 0x89, 0xCB, // mov rbx, rcx
+            0x8B, 0x41, 0x10, // mov eax, [rcx+0x10]
+            0xF3, 0x0F, 0x10, 0x43, 0x18, // movss xmm0, [rbx+0x18]
+            0x48, 0x8B, 0x43, 0x20, // mov rax, [rbx+0x20]
+            0x8B, 0x40, 0x08, // mov eax, [rax+8]
+            0xC6, 0x83, 0x00, 0x01, 0x00, 0x00, 0x01, // mov byte [rbx+0x100], 1
+            0xC3,
+        ];
+        let result = AccessCollector::new().collect(&code, 0x1000);
+        let summary: Vec<_> = result
+            .accesses
+            .iter()
+            .map(|a| (a.offset, a.size, a.kind, a.is_write))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0x10, 4, AccessKind::Integer, false),
+                (0x18, 4, AccessKind::Float, false),
+                (0x20, 8, AccessKind::Pointer, false),
+                (0x100, 1, AccessKind::Integer, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unmodelled_writes_forget_registers() {
+        let code = [
+            0x48, 0x8B, 0x41, 0x08, // mov rax, [rcx+8]
+            0x48, 0xC1, 0xE0, 0x03, // shl rax, 3
+            0x8B, 0x10, // mov edx, [rax]
+            0x48, 0x89, 0xCB, // mov rbx, rcx
+            0x48, 0x87, 0xD3, // xchg rbx, rdx
+            0x8B, 0x43, 0x18, // mov eax, [rbx+0x18]
+            0x48, 0x0F, 0xAF, 0xCA, // imul rcx, rdx
+            0x8B, 0x41, 0x10, // mov eax, [rcx+0x10]
+            0xC3,
+        ];
+        let result = AccessCollector::new().collect(&code, 0x1000);
+        let summary: Vec<_> = result.accesses.iter().map(|a| (a.offset, a.kind)).collect();
+        assert_eq!(summary, vec![(8, AccessKind::Integer)]);
+    }
+
+    #[test]
+    fn test_vtable_store_and_fields() {
+        // lea rax, [rip+0x100]; mov [rcx], rax; mov dword [rcx+8], 0; ret
+        let code = [
+            0x48, 0x8D, 0x05, 0x00, 0x01, 0x00, 0x00, 0x48, 0x89, 0x01, 0xC7, 0x41, 0x08, 0x00,
+            0x00, 0x00, 0x00, 0xC3,
+        ];
+        let result = AccessCollector::new().collect(&code, 0x1000);
+        assert_eq!(result.vtable_stores.len(), 1);
+        assert_eq!(result.vtable_stores[0].vtable_address, 0x1107);
+        assert_eq!(result.vtable_stores[0].offset, 0);
+
+        let mut layout = ObjectLayout::new(0x1107);
+        layout.accesses = result.accesses;
+        let fields = layout.fields();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].name, "vtable");
+        assert_eq!(fields[1].offset, 8);
+        assert_eq!(fields[1].data_type, DataType::Int32);
+        assert_eq!(layout.estimated_size(), 16);
+    }
+
+    #[test]
+    fn test_allocation_size() {
+        let code = [
+            0xB9, 0x48, 0x00, 0x00, 0x00, // mov ecx, 0x48
+            0xE8, 0x00, 0x00, 0x00, 0x00, // call operator new
+            0x48, 0x89, 0xC1, // mov rcx, rax
+            0xE8, 0x00, 0x00, 0x00, 0x00, // call ctor
+        ];
+        assert_eq!(find_allocation_size(&code, 13, 0x40), Some(0x48));
+        assert_eq!(find_call_references(&code, 0x1000, 0x1012), vec![13]);
+    }
+}
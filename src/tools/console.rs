@@ -0,0 +1,449 @@
+//! Built-in tools window for the injected runtime.
+//!
+//! Signature scanning, background analysis with a browser for its results,
+//! the [`HexView`], the [`StructInspector`] and the [`TraceView`] are combined
+//! behind tabs. The window is compiled with the `tools-window` cargo feature,
+//! which also adds it to the overlay started by the runtime.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::AppUi;
+use crate::analysis::{AnalysisEngine, AnalysisProgress, AnalysisResult, AnalysisStage};
+use crate::memory::{MemoryScanner, ScanResult};
+use crate::pattern::Pattern;
+
+use super::{HexView, StructInspector, TraceView};
+
+/// Scan matches listed in the window; the count shows how many there are in total.
+const MAX_LISTED_MATCHES: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tab {
+    Scanner,
+    Analysis,
+    Memory,
+    Inspector,
+    Trace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Browse {
+    Functions,
+    VTables,
+    Strings,
+}
+
+/// Results sent back by the background threads.
+enum Message {
+    Scan(Result<Vec<ScanResult>, String>),
+    Progress(AnalysisProgress),
+    Analysis(Result<Box<AnalysisResult>, String>),
+}
+
+/// Rows of the analysis browser matching the current filter.
+struct Filtered {
+    browse: Browse,
+    filter: String,
+    /// Function and string indices, or vtable addresses.
+    rows: Vec<usize>,
+}
+
+/// Tools window with a signature scanner, analysis browser, memory view,
+/// structure inspector and trace log.
+pub struct ToolsWindow {
+    tab: Tab,
+    pattern: String,
+    scanning: bool,
+    scan_results: Vec<ScanResult>,
+    progress: Option<AnalysisProgress>,
+    analysis: Option<AnalysisResult>,
+    browse: Browse,
+    filter: String,
+    filtered: Option<Filtered>,
+    rtti_names: HashMap<usize, Option<String>>,
+    hex_view: HexView,
+    inspector: StructInspector,
+    trace_view: TraceView,
+    status: String,
+    sender: Sender<Message>,
+    receiver: Receiver<Message>,
+}
+
+impl Default for ToolsWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ToolsWindow {
+    /// Creates the window showing the scanner tab.
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            tab: Tab::Scanner,
+            pattern: String::new(),
+            scanning: false,
+            scan_results: Vec::new(),
+            progress: None,
+            analysis: None,
+            browse: Browse::Functions,
+            filter: String::new(),
+            filtered: None,
+            rtti_names: HashMap::new(),
+            hex_view: HexView::default(),
+            inspector: StructInspector::new(),
+            trace_view: TraceView::new(),
+            status: String::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Returns the result of the last completed analysis.
+    pub fn analysis(&self) -> Option<&AnalysisResult> {
+        self.analysis.as_ref()
+    }
+
+    fn analysis_running(&self) -> bool {
+        self.progress.is_some()
+    }
+
+    /// Takes the messages sent by the background threads since the last frame.
+    fn poll(&mut self) {
+        while let Ok(message) = self.receiver.try_recv() {
+            match message {
+                Message::Scan(result) => {
+                    self.scanning = false;
+                    match result {
+                        Ok(results) => {
+                            self.status = format!("{} matches", results.len());
+                            self.scan_results = results;
+                        }
+                        Err(error) => self.status = format!("scan failed: {error}"),
+                    }
+                }
+                Message::Progress(progress) => self.progress = Some(progress),
+                Message::Analysis(result) => {
+                    self.progress = None;
+                    match result {
+                        Ok(result) => {
+                            self.status = format!(
+                                "analysis finished in {}ms",
+                                result.statistics.analysis_time_ms
+                            );
+                            self.analysis = Some(*result);
+                            self.filtered = None;
+                        }
+                        Err(error) => self.status = format!("analysis failed: {error}"),
+                    }
+                }
+            }
+        }
+    }
+
+    fn start_scan(&mut self) {
+        let pattern = self.pattern.trim().to_string();
+        if let Err(error) = Pattern::new(&pattern) {
+            self.status = error.to_string();
+            return;
+        }
+        self.scanning = true;
+        self.status = format!("scanning for {pattern}");
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            let result = MemoryScanner::new()
+                .and_then(|scanner| scanner.scan_pattern(&pattern))
+                .map_err(|error| error.to_string());
+            let _ = sender.send(Message::Scan(result));
+        });
+    }
+
+    fn start_analysis(&mut self) {
+        self.progress = Some(AnalysisProgress {
+            stage: AnalysisStage::Modules,
+            completed: 0,
+            total: 1,
+        });
+        self.status.clear();
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            let progress = sender.clone();
+            let result = MemoryScanner::new()
+                .and_then(|scanner| {
+                    AnalysisEngine::new(scanner)
+                        .with_progress(move |update| {
+                            let _ = progress.send(Message::Progress(update));
+                        })
+                        .analyze()
+                })
+                .map(Box::new)
+                .map_err(|error| error.to_string());
+            let _ = sender.send(Message::Analysis(result));
+        });
+    }
+
+    fn show(&mut self, ui: &mut egui::Ui, scanner: &MemoryScanner) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.tab, Tab::Scanner, "Scanner");
+            ui.selectable_value(&mut self.tab, Tab::Analysis, "Analysis");
+            ui.selectable_value(&mut self.tab, Tab::Memory, "Memory");
+            ui.selectable_value(&mut self.tab, Tab::Inspector, "Inspector");
+            ui.selectable_value(&mut self.tab, Tab::Trace, "Trace");
+        });
+        ui.separator();
+
+        let mut open_address = None;
+        match self.tab {
+            Tab::Scanner => self.scanner_tab(ui, &mut open_address),
+            Tab::Analysis => self.analysis_tab(ui, scanner, &mut open_address),
+            Tab::Memory => self.hex_view.show(ui, scanner),
+            Tab::Inspector => self.inspector.show(ui, scanner),
+            Tab::Trace => self.trace_view.show(ui),
+        }
+        if let Some(address) = open_address {
+            self.hex_view.go_to(address);
+            self.tab = Tab::Memory;
+        }
+
+        if !self.status.is_empty() {
+            ui.separator();
+            ui.label(&self.status);
+        }
+    }
+
+    fn scanner_tab(&mut self, ui: &mut egui::Ui, open_address: &mut Option<usize>) {
+        ui.horizontal(|ui| {
+            ui.label("Pattern");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.pattern)
+                    .hint_text("48 8B 05 ?? ?? ?? ?? 48 85 C0")
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(320.0),
+            );
+            let entered =
+                response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+            let scan = ui.add_enabled(!self.scanning, egui::Button::new("Scan"));
+            if (scan.clicked() || entered) && !self.scanning {
+                self.start_scan();
+            }
+            if self.scanning {
+                ui.spinner();
+            }
+        });
+
+        if self.scan_results.len() > MAX_LISTED_MATCHES {
+            ui.label(format!(
+                "showing the first {MAX_LISTED_MATCHES} of {} matches",
+                self.scan_results.len()
+            ));
+        }
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (index, result) in self
+                .scan_results
+                .iter()
+                .take(MAX_LISTED_MATCHES)
+                .enumerate()
+            {
+                let title = format!(
+                    "0x{:X}  ({} region at 0x{:X})",
+                    result.address, result.region.protection, result.region.base_address
+                );
+                egui::CollapsingHeader::new(title)
+                    .id_salt(("scan_result", index))
+                    .show(ui, |ui| {
+                        ui.monospace(result.hexdump());
+                        if ui.button("Open in memory view").clicked() {
+                            *open_address = Some(result.address);
+                        }
+                    });
+            }
+        });
+    }
+
+    fn analysis_tab(
+        &mut self,
+        ui: &mut egui::Ui,
+        scanner: &MemoryScanner,
+        open_address: &mut Option<usize>,
+    ) {
+        ui.horizontal(|ui| {
+            let running = self.analysis_running();
+            if ui
+                .add_enabled(!running, egui::Button::new("Analyze"))
+                .clicked()
+            {
+                self.start_analysis();
+            }
+            if let Some(progress) = self.progress {
+                ui.add(
+                    egui::ProgressBar::new(progress.fraction())
+                        .desired_width(240.0)
+                        .text(format!(
+                            "{:?} {}/{}",
+                            progress.stage, progress.completed, progress.total
+                        )),
+                );
+            }
+        });
+
+        let Some(analysis) = &self.analysis else {
+            ui.label("No analysis results yet.");
+            return;
+        };
+        ui.label(analysis.statistics.to_string().trim_end());
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.browse, Browse::Functions, "Functions");
+            ui.selectable_value(&mut self.browse, Browse::VTables, "VTables");
+            ui.selectable_value(&mut self.browse, Browse::Strings, "Strings");
+            ui.label("Filter");
+            ui.add(egui::TextEdit::singleline(&mut self.filter).desired_width(200.0));
+        });
+        ui.separator();
+
+        let stale = self.filtered.as_ref().is_none_or(|filtered| {
+            filtered.browse != self.browse || filtered.filter != self.filter
+        });
+        if stale {
+            self.filtered = Some(Filtered {
+                browse: self.browse,
+                filter: self.filter.clone(),
+                rows: filter_rows(analysis, self.browse, &self.filter),
+            });
+        }
+        let rows = self
+            .filtered
+            .as_ref()
+            .map_or(&[][..], |filtered| &filtered.rows);
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace) + 4.0;
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .show_rows(ui, row_height, rows.len(), |ui, range| {
+                for &row in &rows[range] {
+                    ui.horizontal(|ui| {
+                        let (address, text) = match self.browse {
+                            Browse::Functions => function_row(analysis, row),
+                            Browse::VTables => {
+                                let name = self
+                                    .rtti_names
+                                    .entry(row)
+                                    .or_insert_with(|| scanner.read_rtti_name(row).ok());
+                                vtable_row(analysis, row, name.as_deref())
+                            }
+                            Browse::Strings => string_row(analysis, row),
+                        };
+                        if ui.small_button("Go").clicked() {
+                            *open_address = Some(address);
+                        }
+                        ui.monospace(text);
+                    });
+                }
+            });
+    }
+}
+
+/// Returns the browser rows matching `filter`, which matches names and values
+/// case-insensitively and addresses by hex digits.
+fn filter_rows(analysis: &AnalysisResult, browse: Browse, filter: &str) -> Vec<usize> {
+    let filter = filter.trim().to_lowercase();
+    let matches = |address: usize, text: &str| {
+        filter.is_empty()
+            || text.to_lowercase().contains(&filter)
+            || format!("{address:x}").contains(filter.trim_start_matches("0x"))
+    };
+    match browse {
+        Browse::Functions => analysis
+            .functions
+            .iter()
+            .enumerate()
+            .filter(|(_, function)| {
+                matches(function.address, function.name.as_deref().unwrap_or(""))
+            })
+            .map(|(index, _)| index)
+            .collect(),
+        Browse::VTables => {
+            let mut vtables: Vec<usize> = analysis
+                .class_hierarchy
+                .get_all_classes()
+                .filter(|class| matches(class.vtable_address, &class.name))
+                .map(|class| class.vtable_address)
+                .collect();
+            vtables.sort_unstable();
+            vtables
+        }
+        Browse::Strings => analysis
+            .string_references
+            .iter()
+            .enumerate()
+            .filter(|(_, string)| matches(string.address, &string.value))
+            .map(|(index, _)| index)
+            .collect(),
+    }
+}
+
+fn function_row(analysis: &AnalysisResult, index: usize) -> (usize, String) {
+    let function = &analysis.functions[index];
+    let name = function
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("sub_{:X}", function.address));
+    let size = function
+        .size
+        .map_or_else(|| "?".to_string(), |size| format!("0x{size:X}"));
+    let text = format!(
+        "{:016X}  {name:<32} size {size:<8} {:?} params {} xrefs {}",
+        function.address,
+        function.calling_convention,
+        function.parameters.len(),
+        function.xrefs_to.len()
+    );
+    (function.address, text)
+}
+
+fn vtable_row(
+    analysis: &AnalysisResult,
+    vtable: usize,
+    rtti_name: Option<&str>,
+) -> (usize, String) {
+    let class = analysis.class_hierarchy.get_class(vtable);
+    let name = rtti_name
+        .or(class.map(|class| class.name.as_str()))
+        .unwrap_or("?");
+    let functions = class.map_or(0, |class| class.functions.len());
+    (
+        vtable,
+        format!("{vtable:016X}  {name:<40} {functions} functions"),
+    )
+}
+
+fn string_row(analysis: &AnalysisResult, index: usize) -> (usize, String) {
+    let string = &analysis.string_references[index];
+    let mut value: String = string.value.chars().take(80).collect();
+    if value.len() < string.value.len() {
+        value.push_str("...");
+    }
+    let text = format!(
+        "{:016X}  {:?} {value:?} refs {}",
+        string.address,
+        string.encoding,
+        string.references.len()
+    );
+    (string.address, text)
+}
+
+impl AppUi for ToolsWindow {
+    fn ui(&mut self, ctx: &egui::Context) {
+        self.poll();
+        egui::Window::new("Tools")
+            .default_size([760.0, 560.0])
+            .show(ctx, |ui| match MemoryScanner::new() {
+                Ok(scanner) => self.show(ui, &scanner),
+                Err(error) => {
+                    ui.label(format!("memory scanner unavailable: {error}"));
+                }
+            });
+    }
+}
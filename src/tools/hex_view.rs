@@ -0,0 +1,490 @@
+//! Hex and ASCII memory viewer with in-place editing.
+//!
+//! The view re-reads its address range every frame and highlights bytes that
+//! changed. A selection is interpreted as integers, floats, a pointer and a
+//! RIP-relative target, and typed hex digits or pasted byte strings are
+//! written back through [`MemoryScanner::write_memory_protected`].
+
+use crate::AppUi;
+use crate::disasm;
+use crate::errors::{Error, Result};
+use crate::memory::MemoryScanner;
+use crate::winapi;
+
+use super::{Snapshot, evaluate_address, read_bytes};
+
+/// Bytes shown per row.
+const COLUMNS: usize = 16;
+
+/// One way of reading the bytes at the selection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interpretation {
+    pub label: &'static str,
+    pub value: String,
+    /// Address the value points to, for values that can be followed.
+    pub target: Option<usize>,
+}
+
+/// Interprets the bytes at `address` as each supported data type.
+///
+/// Types wider than `bytes` are skipped. `rel32` treats the first four bytes as
+/// a displacement from the end of the field, and `RIP target` decodes an
+/// instruction at `address` and reports its branch or RIP-relative target.
+pub fn interpret(bytes: &[u8], address: usize) -> Vec<Interpretation> {
+    fn take<const N: usize>(bytes: &[u8]) -> Option<[u8; N]> {
+        bytes.get(..N)?.try_into().ok()
+    }
+
+    let mut values = Vec::new();
+    let mut push = |label, value, target| {
+        values.push(Interpretation {
+            label,
+            value,
+            target,
+        })
+    };
+
+    if let Some([byte]) = take::<1>(bytes) {
+        push("i8", (byte as i8).to_string(), None);
+        push("u8", format!("{byte} (0x{byte:X})"), None);
+    }
+    if let Some(raw) = take::<2>(bytes) {
+        push("i16", i16::from_le_bytes(raw).to_string(), None);
+        let value = u16::from_le_bytes(raw);
+        push("u16", format!("{value} (0x{value:X})"), None);
+    }
+    if let Some(raw) = take::<4>(bytes) {
+        push("i32", i32::from_le_bytes(raw).to_string(), None);
+        let value = u32::from_le_bytes(raw);
+        push("u32", format!("{value} (0x{value:X})"), None);
+    }
+    if let Some(raw) = take::<8>(bytes) {
+        push("i64", i64::from_le_bytes(raw).to_string(), None);
+        let value = u64::from_le_bytes(raw);
+        push("u64", format!("{value} (0x{value:X})"), None);
+    }
+    if let Some(raw) = take::<4>(bytes) {
+        push("f32", f32::from_le_bytes(raw).to_string(), None);
+    }
+    if let Some(raw) = take::<8>(bytes) {
+        push("f64", f64::from_le_bytes(raw).to_string(), None);
+        let pointer = u64::from_le_bytes(raw) as usize;
+        push("pointer", format!("0x{pointer:X}"), Some(pointer));
+    }
+    if let Some(raw) = take::<4>(bytes) {
+        let target = (address + 4).wrapping_add_signed(i32::from_le_bytes(raw) as isize);
+        push("rel32", format!("0x{target:X}"), Some(target));
+    }
+    if let Ok(instruction) = disasm::decode(bytes, address)
+        && let Some(target) = instruction
+            .rip_target()
+            .or_else(|| instruction.branch_target())
+    {
+        let value = format!(
+            "0x{target:X} ({:?}, {} bytes)",
+            instruction.mnemonic, instruction.length
+        );
+        push("RIP target", value, Some(target));
+    }
+    values
+}
+
+/// Parses a string of hex byte pairs such as `"90 90 CC"` or `"9090cc"`.
+pub fn parse_hex_bytes(text: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err(Error::InvalidHex(text.to_string()));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| Error::InvalidHex(text.to_string()))
+        })
+        .collect()
+}
+
+/// A byte range selected in the view, from `anchor` to `cursor` inclusive.
+#[derive(Debug, Clone, Copy)]
+struct Selection {
+    anchor: usize,
+    cursor: usize,
+}
+
+impl Selection {
+    fn at(address: usize) -> Self {
+        Self {
+            anchor: address,
+            cursor: address,
+        }
+    }
+
+    fn start(self) -> usize {
+        self.anchor.min(self.cursor)
+    }
+
+    fn end(self) -> usize {
+        self.anchor.max(self.cursor) + 1
+    }
+
+    fn contains(self, address: usize) -> bool {
+        (self.start()..self.end()).contains(&address)
+    }
+}
+
+/// Hex and ASCII memory view.
+///
+/// Use [`HexView::show`] to embed the view in another window, or pass it to
+/// the overlay directly, which shows it in its own window reading the
+/// current process.
+pub struct HexView {
+    address: usize,
+    rows: usize,
+    expression: String,
+    history: Vec<usize>,
+    selection: Option<Selection>,
+    high_nibble: Option<u8>,
+    snapshot: Snapshot,
+    patch: String,
+    status: String,
+}
+
+impl Default for HexView {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl HexView {
+    /// Creates a view of 16 rows starting at `address`.
+    pub fn new(address: usize) -> Self {
+        Self {
+            address,
+            rows: 16,
+            expression: String::new(),
+            history: Vec::new(),
+            selection: None,
+            high_nibble: None,
+            snapshot: Snapshot::default(),
+            patch: String::new(),
+            status: String::new(),
+        }
+    }
+
+    /// Sets the number of rows shown.
+    pub fn with_rows(mut self, rows: usize) -> Self {
+        self.rows = rows.max(1);
+        self
+    }
+
+    /// Returns the first address shown.
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// Returns the selected address range.
+    pub fn selection(&self) -> Option<std::ops::Range<usize>> {
+        self.selection
+            .map(|selection| selection.start()..selection.end())
+    }
+
+    /// Moves the view to `address`, remembering the current one for "Back".
+    pub fn go_to(&mut self, address: usize) {
+        if address != self.address {
+            self.history.push(self.address);
+        }
+        self.address = address;
+        self.selection = Some(Selection::at(address));
+        self.high_nibble = None;
+    }
+
+    fn size(&self) -> usize {
+        self.rows * COLUMNS
+    }
+
+    /// Draws the view, reading and writing memory through `scanner`.
+    pub fn show(&mut self, ui: &mut egui::Ui, scanner: &MemoryScanner) {
+        self.toolbar(ui, scanner);
+        self.handle_keys(ui, scanner);
+
+        let now = ui.input(|input| input.time);
+        self.snapshot.update(
+            self.address,
+            read_bytes(scanner, self.address, self.size()),
+            now,
+        );
+
+        let grid = ui.scope(|ui| self.grid(ui, now)).response;
+        if grid.hovered() {
+            let scroll = ui.input(|input| input.raw_scroll_delta.y);
+            if scroll > 0.0 {
+                self.address = self.address.wrapping_sub(COLUMNS);
+            } else if scroll < 0.0 {
+                self.address = self.address.wrapping_add(COLUMNS);
+            }
+        }
+
+        ui.separator();
+        self.selection_panel(ui, scanner);
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+    }
+
+    fn toolbar(&mut self, ui: &mut egui::Ui, scanner: &MemoryScanner) {
+        ui.horizontal(|ui| {
+            ui.label("Go to");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.expression)
+                    .hint_text("[game.exe+1234]+10")
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(220.0),
+            );
+            let entered =
+                response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+            if ui.button("Go").clicked() || entered {
+                let address = evaluate_address(&self.expression, winapi::module_base, |address| {
+                    scanner.read_pointer(address).ok()
+                });
+                match address {
+                    Ok(address) => {
+                        self.go_to(address);
+                        self.status.clear();
+                    }
+                    Err(error) => self.status = error.to_string(),
+                }
+            }
+            if ui
+                .add_enabled(!self.history.is_empty(), egui::Button::new("Back"))
+                .clicked()
+                && let Some(previous) = self.history.pop()
+            {
+                self.address = previous;
+                self.selection = None;
+            }
+            let page = self.size();
+            if ui.button("Page up").clicked() {
+                self.address = self.address.wrapping_sub(page);
+            }
+            if ui.button("Page down").clicked() {
+                self.address = self.address.wrapping_add(page);
+            }
+            ui.label("Rows");
+            ui.add(egui::DragValue::new(&mut self.rows).range(1..=64));
+        });
+    }
+
+    fn grid(&mut self, ui: &mut egui::Ui, now: f64) {
+        let selected_background = ui.visuals().selection.bg_fill;
+        egui::Grid::new("hex_view_rows")
+            .spacing([4.0, 2.0])
+            .show(ui, |ui| {
+                for row in 0..self.rows {
+                    let row_offset = row * COLUMNS;
+                    ui.monospace(format!("{:016X}", self.address.wrapping_add(row_offset)));
+                    let mut ascii = String::with_capacity(COLUMNS);
+                    for column in 0..COLUMNS {
+                        let offset = row_offset + column;
+                        let address = self.address.wrapping_add(offset);
+                        let byte = self.snapshot.byte(offset);
+                        let mut text = match byte {
+                            Some(byte) => egui::RichText::new(format!("{byte:02X}")),
+                            None => egui::RichText::new("??").weak(),
+                        }
+                        .monospace();
+                        if self.snapshot.changed(offset..offset + 1, now) {
+                            text = text.color(egui::Color32::from_rgb(255, 120, 80));
+                        }
+                        if self
+                            .selection
+                            .is_some_and(|selection| selection.contains(address))
+                        {
+                            text = text.background_color(selected_background);
+                        }
+                        let response = ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+                        if response.clicked() {
+                            let extend = ui.input(|input| input.modifiers.shift);
+                            self.selection = match self.selection {
+                                Some(selection) if extend => Some(Selection {
+                                    anchor: selection.anchor,
+                                    cursor: address,
+                                }),
+                                _ => Some(Selection::at(address)),
+                            };
+                            self.high_nibble = None;
+                        }
+                        ascii.push(match byte {
+                            Some(byte) if byte.is_ascii_graphic() || byte == b' ' => byte as char,
+                            _ => '.',
+                        });
+                    }
+                    ui.monospace(ascii);
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// Handles hex digit entry and cursor movement while no text field has focus.
+    fn handle_keys(&mut self, ui: &mut egui::Ui, scanner: &MemoryScanner) {
+        let Some(selection) = self.selection else {
+            return;
+        };
+        if ui.memory(|memory| memory.focused().is_some()) {
+            return;
+        }
+
+        let (text, moved) = ui.input(|input| {
+            let text: String = input
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    egui::Event::Text(text) => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect();
+            let moved = if input.key_pressed(egui::Key::ArrowLeft) {
+                Some(-1)
+            } else if input.key_pressed(egui::Key::ArrowRight) {
+                Some(1)
+            } else if input.key_pressed(egui::Key::ArrowUp) {
+                Some(-(COLUMNS as isize))
+            } else if input.key_pressed(egui::Key::ArrowDown) {
+                Some(COLUMNS as isize)
+            } else {
+                None
+            };
+            (text, moved)
+        });
+
+        let mut cursor = selection.cursor;
+        if let Some(delta) = moved {
+            cursor = cursor.wrapping_add_signed(delta);
+            self.high_nibble = None;
+        }
+        for digit in text.chars().filter_map(|c| c.to_digit(16)) {
+            let digit = digit as u8;
+            match self.high_nibble.take() {
+                None => self.high_nibble = Some(digit),
+                Some(high) => match scanner.write_memory_protected(cursor, &[high << 4 | digit]) {
+                    Ok(()) => cursor = cursor.wrapping_add(1),
+                    Err(error) => self.status = error.to_string(),
+                },
+            }
+        }
+        if cursor != selection.cursor {
+            self.selection = Some(Selection::at(cursor));
+        }
+
+        if cursor < self.address {
+            self.address = self.address.wrapping_sub(COLUMNS);
+        } else if cursor >= self.address.wrapping_add(self.size()) {
+            self.address = self.address.wrapping_add(COLUMNS);
+        }
+    }
+
+    fn selection_panel(&mut self, ui: &mut egui::Ui, scanner: &MemoryScanner) {
+        let Some(selection) = self.selection else {
+            ui.label("Click a byte to select it, shift-click to extend the selection.");
+            return;
+        };
+        let start = selection.start();
+        ui.monospace(format!(
+            "Selection 0x{start:X}, {} bytes{}",
+            selection.end() - start,
+            if self.high_nibble.is_some() {
+                ", typing"
+            } else {
+                ""
+            }
+        ));
+
+        let bytes = scanner
+            .read_memory(start, 16)
+            .or_else(|_| scanner.read_memory(start, 8));
+        let mut follow = None;
+        if let Ok(bytes) = bytes {
+            egui::Grid::new("hex_view_interpretation")
+                .striped(true)
+                .show(ui, |ui| {
+                    for value in interpret(&bytes, start) {
+                        ui.label(value.label);
+                        ui.monospace(&value.value);
+                        if let Some(target) = value.target
+                            && ui.small_button("Follow").clicked()
+                        {
+                            follow = Some(target);
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+        if let Some(target) = follow {
+            self.go_to(target);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Write");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.patch)
+                    .hint_text("90 90")
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(220.0),
+            );
+            if ui.button("Apply").clicked() {
+                self.status = match parse_hex_bytes(&self.patch)
+                    .and_then(|bytes| scanner.write_memory_protected(start, &bytes).map(|_| bytes))
+                {
+                    Ok(bytes) => format!("wrote {} bytes at 0x{start:X}", bytes.len()),
+                    Err(error) => error.to_string(),
+                };
+            }
+        });
+    }
+}
+
+impl AppUi for HexView {
+    fn ui(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Memory")
+            .default_size([720.0, 520.0])
+            .show(ctx, |ui| match MemoryScanner::new() {
+                Ok(scanner) => self.show(ui, &scanner),
+                Err(error) => {
+                    ui.label(format!("memory scanner unavailable: {error}"));
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpret_rip_relative_load() {
+        // mov rax, [rip+0x10]; nop
+        let bytes = [0x48, 0x8B, 0x05, 0x10, 0x00, 0x00, 0x00, 0x90];
+        let values = interpret(&bytes, 0x1000);
+        let value = |label| values.iter().find(|value| value.label == label).cloned();
+
+        assert_eq!(value("i8").unwrap().value, "72");
+        assert_eq!(value("u16").unwrap().value, "35656 (0x8B48)");
+        assert_eq!(value("RIP target").unwrap().target, Some(0x1017));
+        assert_eq!(value("pointer").unwrap().target, Some(0x9000000010058B48));
+        assert!(
+            interpret(&bytes[..2], 0x1000)
+                .iter()
+                .all(|value| value.label != "u32")
+        );
+    }
+
+    #[test]
+    fn test_parse_hex_bytes() {
+        assert_eq!(parse_hex_bytes("90 90 cc").unwrap(), [0x90, 0x90, 0xCC]);
+        assert_eq!(parse_hex_bytes("4889").unwrap(), [0x48, 0x89]);
+        assert!(parse_hex_bytes("9").is_err());
+        assert!(parse_hex_bytes("zz").is_err());
+        assert!(parse_hex_bytes("").is_err());
+    }
+}
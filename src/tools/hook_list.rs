@@ -0,0 +1,115 @@
+//! Hook list panel with per-hook and per-module toggles.
+//!
+//! The panel reads the global [`HookManager`](crate::hooks::HookManager) every
+//! frame, so it reflects hooks that modules enable or disable themselves.
+
+use core::marker::PhantomData;
+
+use crate::AppUi;
+use crate::hooks::{self, HookInfo};
+
+/// Lists the hooks of the global manager for config type `C`.
+pub struct HookList<C> {
+    error: Option<String>,
+    marker: PhantomData<fn() -> C>,
+}
+
+impl<C: Send + Sync + 'static> Default for HookList<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Send + Sync + 'static> HookList<C> {
+    pub fn new() -> Self {
+        Self {
+            error: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Draws the panel into `ui`.
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        let Some((statuses, failures)) =
+            hooks::with_manager::<C, _>(|mgr| (mgr.hook_info(), mgr.failures().to_vec()))
+        else {
+            ui.label("hook manager not initialized");
+            return;
+        };
+        if statuses.is_empty() {
+            ui.label("no hooks installed");
+        }
+
+        let mut modules: Vec<&str> = Vec::new();
+        for status in &statuses {
+            if !modules.contains(&status.module.as_str()) {
+                modules.push(&status.module);
+            }
+        }
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for module in modules {
+                let hooks: Vec<&HookInfo> =
+                    statuses.iter().filter(|s| s.module == module).collect();
+                let enabled = hooks.iter().filter(|s| s.enabled).count();
+
+                ui.horizontal(|ui| {
+                    let mut all = enabled == hooks.len();
+                    let toggleable = hooks.iter().any(|s| s.toggleable);
+                    let response = ui.add_enabled(toggleable, egui::Checkbox::new(&mut all, ""));
+                    if response.changed() {
+                        self.apply(hooks::set_module_enabled::<C>(module, all));
+                    }
+                    ui.strong(module);
+                    ui.weak(format!("{enabled}/{} enabled", hooks.len()));
+                });
+                ui.indent(module, |ui| {
+                    for status in hooks {
+                        ui.horizontal(|ui| {
+                            let mut checked = status.enabled;
+                            let response = ui
+                                .add_enabled(
+                                    status.toggleable,
+                                    egui::Checkbox::new(&mut checked, status.name.as_str()),
+                                )
+                                .on_disabled_hover_text("installed without a way to re-enable it");
+                            if response.changed() {
+                                self.apply(hooks::set_hook_enabled::<C>(
+                                    module,
+                                    &status.name,
+                                    checked,
+                                ));
+                            }
+                            ui.weak(format!("{} calls", status.calls))
+                                .on_hover_text(status.log_line());
+                        });
+                    }
+                });
+            }
+        });
+
+        for failure in &failures {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                format!("{} failed to start: {}", failure.module, failure.error),
+            );
+        }
+
+        if let Some(error) = &self.error {
+            ui.separator();
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
+    }
+
+    fn apply(&mut self, result: crate::errors::Result<()>) {
+        self.error = result.err().map(|error| error.to_string());
+    }
+}
+
+impl<C: Send + Sync + 'static> AppUi for HookList<C> {
+    fn ui(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Hooks")
+            .default_size([320.0, 360.0])
+            .show(ctx, |ui| self.show(ui));
+    }
+}
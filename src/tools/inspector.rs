@@ -0,0 +1,931 @@
+//! ReClass-style live structure inspector.
+//!
+//! Memory at an address or at the end of a pointer chain is shown as rows of
+//! typed fields that are re-read every frame. Fields are retyped, renamed,
+//! inserted and removed in place, pointers can be followed, and the layout
+//! is saved as a [`DiscoveredStruct`] in JSON.
+
+use std::collections::HashMap;
+
+use crate::AppUi;
+use crate::analysis::{DataType, DiscoveredStruct, StructField};
+use crate::errors::{Error, Result};
+use crate::memory::MemoryScanner;
+use crate::winapi;
+
+use super::{Snapshot, read_bytes};
+
+/// How the bytes of a layout field are displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Hex8,
+    Hex16,
+    Hex32,
+    Hex64,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Bool,
+    Float,
+    Double,
+    /// Pointer that can be followed.
+    Pointer,
+    /// Pointer to a vtable, labelled with its RTTI class name.
+    VTable,
+    /// UTF-8 text of the given length in bytes.
+    Text(usize),
+    /// UTF-16 text of the given length in code units.
+    WideText(usize),
+}
+
+impl FieldKind {
+    /// Kinds offered when retyping a field.
+    pub const CHOICES: [FieldKind; 19] = [
+        FieldKind::Hex8,
+        FieldKind::Hex16,
+        FieldKind::Hex32,
+        FieldKind::Hex64,
+        FieldKind::Int8,
+        FieldKind::Int16,
+        FieldKind::Int32,
+        FieldKind::Int64,
+        FieldKind::UInt8,
+        FieldKind::UInt16,
+        FieldKind::UInt32,
+        FieldKind::UInt64,
+        FieldKind::Bool,
+        FieldKind::Float,
+        FieldKind::Double,
+        FieldKind::Pointer,
+        FieldKind::VTable,
+        FieldKind::Text(16),
+        FieldKind::WideText(16),
+    ];
+
+    /// Returns the number of bytes the field covers.
+    pub fn size(self) -> usize {
+        match self {
+            FieldKind::Hex8 | FieldKind::Int8 | FieldKind::UInt8 | FieldKind::Bool => 1,
+            FieldKind::Hex16 | FieldKind::Int16 | FieldKind::UInt16 => 2,
+            FieldKind::Hex32 | FieldKind::Int32 | FieldKind::UInt32 | FieldKind::Float => 4,
+            FieldKind::Hex64
+            | FieldKind::Int64
+            | FieldKind::UInt64
+            | FieldKind::Double
+            | FieldKind::Pointer
+            | FieldKind::VTable => 8,
+            FieldKind::Text(length) => length,
+            FieldKind::WideText(length) => length * 2,
+        }
+    }
+
+    /// Returns the name shown in the type selector.
+    pub fn label(self) -> &'static str {
+        match self {
+            FieldKind::Hex8 => "Hex8",
+            FieldKind::Hex16 => "Hex16",
+            FieldKind::Hex32 => "Hex32",
+            FieldKind::Hex64 => "Hex64",
+            FieldKind::Int8 => "Int8",
+            FieldKind::Int16 => "Int16",
+            FieldKind::Int32 => "Int32",
+            FieldKind::Int64 => "Int64",
+            FieldKind::UInt8 => "UInt8",
+            FieldKind::UInt16 => "UInt16",
+            FieldKind::UInt32 => "UInt32",
+            FieldKind::UInt64 => "UInt64",
+            FieldKind::Bool => "Bool",
+            FieldKind::Float => "Float",
+            FieldKind::Double => "Double",
+            FieldKind::Pointer => "Pointer",
+            FieldKind::VTable => "VTable",
+            FieldKind::Text(_) => "Text",
+            FieldKind::WideText(_) => "WideText",
+        }
+    }
+
+    /// Returns true for untyped filler bytes.
+    pub fn is_hex(self) -> bool {
+        matches!(
+            self,
+            FieldKind::Hex8 | FieldKind::Hex16 | FieldKind::Hex32 | FieldKind::Hex64
+        )
+    }
+
+    /// Formats the value stored in `bytes`, which must hold `self.size()` bytes.
+    pub fn format(self, bytes: &[u8]) -> String {
+        match self {
+            FieldKind::Hex8 | FieldKind::Hex16 | FieldKind::Hex32 | FieldKind::Hex64 => {
+                let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02X}")).collect();
+                let ascii: String = bytes
+                    .iter()
+                    .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                    .collect();
+                format!("{}  {ascii}", hex.join(" "))
+            }
+            FieldKind::Int8 => (bytes[0] as i8).to_string(),
+            FieldKind::Int16 => i16::from_le_bytes(array(bytes)).to_string(),
+            FieldKind::Int32 => i32::from_le_bytes(array(bytes)).to_string(),
+            FieldKind::Int64 => i64::from_le_bytes(array(bytes)).to_string(),
+            FieldKind::UInt8 => format!("{0} (0x{0:X})", bytes[0]),
+            FieldKind::UInt16 => format!("{0} (0x{0:X})", u16::from_le_bytes(array(bytes))),
+            FieldKind::UInt32 => format!("{0} (0x{0:X})", u32::from_le_bytes(array(bytes))),
+            FieldKind::UInt64 => format!("{0} (0x{0:X})", u64::from_le_bytes(array(bytes))),
+            FieldKind::Bool => (bytes[0] != 0).to_string(),
+            FieldKind::Float => format!("{:.4}", f32::from_le_bytes(array(bytes))),
+            FieldKind::Double => format!("{:.4}", f64::from_le_bytes(array(bytes))),
+            FieldKind::Pointer | FieldKind::VTable => {
+                format!("0x{:X}", u64::from_le_bytes(array(bytes)))
+            }
+            FieldKind::Text(_) => {
+                let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                format!("{:?}", String::from_utf8_lossy(&bytes[..end]))
+            }
+            FieldKind::WideText(_) => {
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                    .take_while(|&unit| unit != 0)
+                    .collect();
+                format!("{:?}", String::from_utf16_lossy(&units))
+            }
+        }
+    }
+
+    /// Returns the pointer stored in `bytes` for kinds that can be followed.
+    fn target(self, bytes: &[u8]) -> Option<usize> {
+        match self {
+            FieldKind::Pointer | FieldKind::VTable => {
+                Some(u64::from_le_bytes(array(bytes)) as usize).filter(|&target| target != 0)
+            }
+            _ => None,
+        }
+    }
+
+    /// Hex fields covering `size` bytes, widest first.
+    fn hex_fill(mut size: usize) -> Vec<FieldKind> {
+        let mut kinds = Vec::new();
+        for kind in [
+            FieldKind::Hex64,
+            FieldKind::Hex32,
+            FieldKind::Hex16,
+            FieldKind::Hex8,
+        ] {
+            while size >= kind.size() {
+                kinds.push(kind);
+                size -= kind.size();
+            }
+        }
+        kinds
+    }
+
+    fn data_type(self) -> Option<DataType> {
+        Some(match self {
+            FieldKind::Hex8 | FieldKind::Hex16 | FieldKind::Hex32 | FieldKind::Hex64 => {
+                return None;
+            }
+            FieldKind::Int8 => DataType::Int8,
+            FieldKind::Int16 => DataType::Int16,
+            FieldKind::Int32 => DataType::Int32,
+            FieldKind::Int64 => DataType::Int64,
+            FieldKind::UInt8 => DataType::UInt8,
+            FieldKind::UInt16 => DataType::UInt16,
+            FieldKind::UInt32 => DataType::UInt32,
+            FieldKind::UInt64 => DataType::UInt64,
+            FieldKind::Bool => DataType::Bool,
+            FieldKind::Float => DataType::Float32,
+            FieldKind::Double => DataType::Float64,
+            FieldKind::Pointer | FieldKind::VTable => DataType::Pointer(Box::new(DataType::Void)),
+            FieldKind::Text(length) => DataType::Array(Box::new(DataType::Int8), length),
+            FieldKind::WideText(length) => DataType::Array(Box::new(DataType::UInt16), length),
+        })
+    }
+
+    fn from_data_type(data_type: &DataType) -> Option<FieldKind> {
+        Some(match data_type {
+            DataType::Int8 => FieldKind::Int8,
+            DataType::Int16 => FieldKind::Int16,
+            DataType::Int32 => FieldKind::Int32,
+            DataType::Int64 => FieldKind::Int64,
+            DataType::UInt8 => FieldKind::UInt8,
+            DataType::UInt16 => FieldKind::UInt16,
+            DataType::UInt32 => FieldKind::UInt32,
+            DataType::UInt64 => FieldKind::UInt64,
+            DataType::Bool => FieldKind::Bool,
+            DataType::Float32 => FieldKind::Float,
+            DataType::Float64 => FieldKind::Double,
+            DataType::Pointer(_) => FieldKind::Pointer,
+            DataType::Array(element, length) => match **element {
+                DataType::Int8 | DataType::UInt8 => FieldKind::Text(*length),
+                DataType::Int16 | DataType::UInt16 => FieldKind::WideText(*length),
+                _ => return None,
+            },
+            _ => return None,
+        })
+    }
+}
+
+fn array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    bytes[..N].try_into().expect("field bytes")
+}
+
+/// A field of a [`StructLayout`]. Hex filler fields have no name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutField {
+    pub name: String,
+    pub kind: FieldKind,
+}
+
+impl LayoutField {
+    fn hex(kind: FieldKind) -> Self {
+        Self {
+            name: String::new(),
+            kind,
+        }
+    }
+}
+
+/// A structure layout whose fields are laid out back to back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructLayout {
+    pub name: String,
+    pub fields: Vec<LayoutField>,
+}
+
+impl StructLayout {
+    /// Creates a layout of `size` bytes shown as hex.
+    pub fn new(name: impl Into<String>, size: usize) -> Self {
+        Self {
+            name: name.into(),
+            fields: FieldKind::hex_fill(size)
+                .into_iter()
+                .map(LayoutField::hex)
+                .collect(),
+        }
+    }
+
+    /// Returns the total size in bytes.
+    pub fn size(&self) -> usize {
+        self.fields.iter().map(|field| field.kind.size()).sum()
+    }
+
+    /// Returns the offset of the field at `index`.
+    pub fn offset_of(&self, index: usize) -> usize {
+        self.fields[..index]
+            .iter()
+            .map(|field| field.kind.size())
+            .sum()
+    }
+
+    /// Changes the kind of the field at `index` without moving the fields after it.
+    ///
+    /// A narrower kind leaves hex filler behind it. A wider kind consumes the
+    /// following fields, splitting the last one it overlaps, and grows the
+    /// layout when it runs past the end.
+    pub fn set_kind(&mut self, index: usize, kind: FieldKind) {
+        let offset = self.offset_of(index);
+        let old_size = self.fields[index].kind.size();
+        let field = &mut self.fields[index];
+        field.kind = kind;
+        if kind.is_hex() {
+            field.name.clear();
+        } else if field.name.is_empty() {
+            field.name = match kind {
+                FieldKind::VTable => "vtable".to_string(),
+                _ => format!("field_{offset:X}"),
+            };
+        }
+
+        if kind.size() < old_size {
+            let filler = FieldKind::hex_fill(old_size - kind.size());
+            self.insert_hex(index + 1, filler);
+            return;
+        }
+        let mut needed = kind.size() - old_size;
+        while needed > 0 && index + 1 < self.fields.len() {
+            let next = self.fields.remove(index + 1).kind.size();
+            if next > needed {
+                self.insert_hex(index + 1, FieldKind::hex_fill(next - needed));
+                break;
+            }
+            needed -= next;
+        }
+    }
+
+    /// Inserts `size` bytes of hex before the field at `index`.
+    pub fn insert(&mut self, index: usize, size: usize) {
+        self.insert_hex(index, FieldKind::hex_fill(size));
+    }
+
+    /// Appends `size` bytes of hex.
+    pub fn extend(&mut self, size: usize) {
+        self.insert_hex(self.fields.len(), FieldKind::hex_fill(size));
+    }
+
+    /// Removes the field at `index`, moving the fields after it down.
+    pub fn remove(&mut self, index: usize) {
+        self.fields.remove(index);
+    }
+
+    fn insert_hex(&mut self, index: usize, kinds: Vec<FieldKind>) {
+        self.fields
+            .splice(index..index, kinds.into_iter().map(LayoutField::hex));
+    }
+
+    /// Converts the typed fields into a structure definition.
+    ///
+    /// Hex filler becomes padding, and the first vtable field sets `vtable_offset`.
+    pub fn to_struct(&self) -> DiscoveredStruct {
+        let mut fields = Vec::new();
+        let mut vtable_offset = None;
+        let mut offset = 0;
+        for field in &self.fields {
+            if let Some(data_type) = field.kind.data_type() {
+                if field.kind == FieldKind::VTable && vtable_offset.is_none() {
+                    vtable_offset = Some(offset);
+                }
+                fields.push(StructField {
+                    name: field.name.clone(),
+                    offset,
+                    data_type,
+                    size: field.kind.size(),
+                    bit_field: None,
+                });
+            }
+            offset += field.kind.size();
+        }
+
+        DiscoveredStruct {
+            name: self.name.clone(),
+            size: offset,
+            fields,
+            vtable_offset,
+            base_classes: Vec::new(),
+            confidence: 1.0,
+        }
+    }
+
+    /// Builds a layout from a structure definition.
+    ///
+    /// Fields the inspector cannot display, such as nested structures and
+    /// bitfields, are shown as hex. Overlapping fields keep the first one.
+    pub fn from_struct(definition: &DiscoveredStruct) -> Self {
+        let mut entries: Vec<(usize, usize, &str, Option<FieldKind>)> = definition
+            .fields
+            .iter()
+            .map(|field| {
+                let kind = if Some(field.offset) == definition.vtable_offset {
+                    Some(FieldKind::VTable)
+                } else if field.bit_field.is_some() {
+                    None
+                } else {
+                    FieldKind::from_data_type(&field.data_type)
+                        .filter(|kind| kind.size() == field.size)
+                };
+                (field.offset, field.size, field.name.as_str(), kind)
+            })
+            .collect();
+        if let Some(offset) = definition.vtable_offset
+            && !entries.iter().any(|entry| entry.0 == offset)
+        {
+            entries.push((
+                offset,
+                FieldKind::VTable.size(),
+                "vtable",
+                Some(FieldKind::VTable),
+            ));
+        }
+        entries.sort_by_key(|entry| entry.0);
+
+        let mut layout = Self::new(definition.name.clone(), 0);
+        for (offset, size, name, kind) in entries {
+            let end = layout.size();
+            if offset < end {
+                continue;
+            }
+            layout.extend(offset - end);
+            match kind {
+                Some(kind) => layout.fields.push(LayoutField {
+                    name: name.to_string(),
+                    kind,
+                }),
+                None => layout.extend(size),
+            }
+        }
+        let end = layout.size();
+        layout.extend(definition.size.saturating_sub(end));
+        layout
+    }
+}
+
+/// Evaluates a ReClass-style address expression.
+///
+/// Numbers are hexadecimal with an optional `0x` prefix. Module names such as
+/// `game.exe`, or any name written as `<name>`, resolve through `module`, and
+/// `[expr]` reads the pointer stored at `expr` through `read_pointer`. Terms
+/// combine with `+`, `-`, `*` and parentheses, so `[[game.exe+1234]+10]+8`
+/// walks a two-level pointer chain.
+pub fn evaluate_address(
+    expression: &str,
+    module: impl Fn(&str) -> Option<usize>,
+    read_pointer: impl Fn(usize) -> Option<usize>,
+) -> Result<usize> {
+    let mut parser = Expression {
+        input: expression.as_bytes(),
+        position: 0,
+        module: &module,
+        read_pointer: &read_pointer,
+    };
+    let value = parser.sum()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err(parser.error(format!("unexpected '{}'", c as char))),
+    }
+}
+
+struct Expression<'a> {
+    input: &'a [u8],
+    position: usize,
+    module: &'a dyn Fn(&str) -> Option<usize>,
+    read_pointer: &'a dyn Fn(usize) -> Option<usize>,
+}
+
+impl Expression<'_> {
+    fn peek(&mut self) -> Option<u8> {
+        while self
+            .input
+            .get(self.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.position += 1;
+        }
+        self.input.get(self.position).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: u8) -> Result<()> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", c as char)))
+        }
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::InvalidExpression(format!("{message} at column {}", self.position + 1))
+    }
+
+    fn sum(&mut self) -> Result<usize> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat(b'+') {
+                value = value.wrapping_add(self.product()?);
+            } else if self.eat(b'-') {
+                value = value.wrapping_sub(self.product()?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<usize> {
+        let mut value = self.operand()?;
+        while self.eat(b'*') {
+            value = value.wrapping_mul(self.operand()?);
+        }
+        Ok(value)
+    }
+
+    fn operand(&mut self) -> Result<usize> {
+        match self.peek() {
+            Some(b'[') => {
+                self.position += 1;
+                let address = self.sum()?;
+                self.expect(b']')?;
+                (self.read_pointer)(address)
+                    .ok_or_else(|| self.error(format!("cannot read pointer at 0x{address:X}")))
+            }
+            Some(b'(') => {
+                self.position += 1;
+                let value = self.sum()?;
+                self.expect(b')')?;
+                Ok(value)
+            }
+            Some(b'<') => {
+                self.position += 1;
+                let start = self.position;
+                while self.input.get(self.position).is_some_and(|&c| c != b'>') {
+                    self.position += 1;
+                }
+                let name = String::from_utf8_lossy(&self.input[start..self.position]).into_owned();
+                self.expect(b'>')?;
+                self.resolve_module(name.trim())
+            }
+            Some(c) if c.is_ascii_alphanumeric() || c == b'_' || c == b'.' => {
+                let start = self.position;
+                while self
+                    .input
+                    .get(self.position)
+                    .is_some_and(|&c| c.is_ascii_alphanumeric() || c == b'_' || c == b'.')
+                {
+                    self.position += 1;
+                }
+                let word =
+                    std::str::from_utf8(&self.input[start..self.position]).unwrap_or_default();
+                let digits = word
+                    .strip_prefix("0x")
+                    .or_else(|| word.strip_prefix("0X"))
+                    .unwrap_or(word);
+                if !digits.is_empty() && digits.bytes().all(|c| c.is_ascii_hexdigit()) {
+                    usize::from_str_radix(digits, 16)
+                        .map_err(|_| self.error(format!("number {word} is too large")))
+                } else {
+                    self.resolve_module(word)
+                }
+            }
+            Some(c) => Err(self.error(format!("unexpected '{}'", c as char))),
+            None => Err(self.error("unexpected end of expression".to_string())),
+        }
+    }
+
+    fn resolve_module(&self, name: &str) -> Result<usize> {
+        (self.module)(name).ok_or_else(|| self.error(format!("unknown module {name}")))
+    }
+}
+
+/// A pending layout edit collected while drawing the rows.
+enum Edit {
+    SetKind(usize, FieldKind),
+    Insert(usize),
+    Remove(usize),
+    Follow(usize),
+}
+
+/// A field row resolved against the current memory contents.
+struct Row {
+    offset: usize,
+    value: String,
+    changed: bool,
+    target: Option<usize>,
+}
+
+/// Live structure inspector panel.
+///
+/// Use [`StructInspector::show`] to embed the panel in another window, or
+/// pass the inspector to the overlay directly, which shows it in its own
+/// window reading the current process.
+pub struct StructInspector {
+    expression: String,
+    history: Vec<String>,
+    layout: StructLayout,
+    snapshot: Snapshot,
+    rtti_names: HashMap<usize, Option<String>>,
+    path: String,
+    status: String,
+}
+
+impl Default for StructInspector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StructInspector {
+    /// Creates an inspector with a 0x80 byte hex layout and no address.
+    pub fn new() -> Self {
+        Self {
+            expression: String::new(),
+            history: Vec::new(),
+            layout: StructLayout::new("Class", 0x80),
+            snapshot: Snapshot::default(),
+            rtti_names: HashMap::new(),
+            path: "layout.json".to_string(),
+            status: String::new(),
+        }
+    }
+
+    /// Replaces the layout being inspected.
+    pub fn with_layout(mut self, layout: StructLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Sets the address expression, see [`evaluate_address`].
+    pub fn with_expression(mut self, expression: impl Into<String>) -> Self {
+        self.expression = expression.into();
+        self
+    }
+
+    /// Returns the current layout.
+    pub fn layout(&self) -> &StructLayout {
+        &self.layout
+    }
+
+    /// Writes the layout to `path` as a JSON [`DiscoveredStruct`].
+    pub fn save(&self, path: &str) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(&self.layout.to_struct()).map_err(std::io::Error::from)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Replaces the layout with a JSON [`DiscoveredStruct`] read from `path`.
+    pub fn load(&mut self, path: &str) -> Result<()> {
+        let data = std::fs::read(path)?;
+        let definition: DiscoveredStruct =
+            serde_json::from_slice(&data).map_err(std::io::Error::from)?;
+        self.layout = StructLayout::from_struct(&definition);
+        Ok(())
+    }
+
+    /// Draws the panel, reading memory through `scanner`.
+    pub fn show(&mut self, ui: &mut egui::Ui, scanner: &MemoryScanner) {
+        self.toolbar(ui);
+
+        let address = evaluate_address(&self.expression, winapi::module_base, |address| {
+            scanner.read_pointer(address).ok()
+        });
+        let now = ui.input(|input| input.time);
+        match &address {
+            Ok(address) => {
+                ui.monospace(format!("0x{address:X}"));
+                let bytes = read_bytes(scanner, *address, self.layout.size());
+                self.snapshot.update(*address, bytes, now);
+            }
+            Err(error) => {
+                if !self.expression.trim().is_empty() {
+                    ui.colored_label(egui::Color32::LIGHT_RED, error.to_string());
+                }
+                self.snapshot.clear();
+            }
+        }
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+        ui.separator();
+
+        let rows = self.rows(scanner, now);
+        let mut edit = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("struct_inspector_rows")
+                .striped(true)
+                .num_columns(5)
+                .show(ui, |ui| {
+                    for (index, row) in rows.iter().enumerate() {
+                        if let Some(change) = self.row(ui, index, row) {
+                            edit = Some(change);
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+
+        match edit {
+            Some(Edit::SetKind(index, kind)) => self.layout.set_kind(index, kind),
+            Some(Edit::Insert(index)) => self.layout.insert(index, 8),
+            Some(Edit::Remove(index)) => self.layout.remove(index),
+            Some(Edit::Follow(target)) => {
+                self.history.push(std::mem::replace(
+                    &mut self.expression,
+                    format!("0x{target:X}"),
+                ));
+            }
+            None => {}
+        }
+    }
+
+    fn toolbar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Address");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.expression)
+                    .hint_text("[game.exe+1234]+10")
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(260.0),
+            );
+            if ui
+                .add_enabled(!self.history.is_empty(), egui::Button::new("Back"))
+                .clicked()
+                && let Some(previous) = self.history.pop()
+            {
+                self.expression = previous;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Class");
+            ui.add(egui::TextEdit::singleline(&mut self.layout.name).desired_width(140.0));
+            ui.label(format!("0x{:X} bytes", self.layout.size()));
+            if ui.button("+8").clicked() {
+                self.layout.extend(8);
+            }
+            if ui.button("+64").clicked() {
+                self.layout.extend(64);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.path).desired_width(200.0));
+            if ui.button("Save").clicked() {
+                self.status = match self.save(&self.path) {
+                    Ok(()) => format!("saved {}", self.path),
+                    Err(error) => format!("save failed: {error}"),
+                };
+            }
+            if ui.button("Load").clicked() {
+                let path = self.path.clone();
+                self.status = match self.load(&path) {
+                    Ok(()) => format!("loaded {path}"),
+                    Err(error) => format!("load failed: {error}"),
+                };
+            }
+        });
+    }
+
+    fn rows(&mut self, scanner: &MemoryScanner, now: f64) -> Vec<Row> {
+        let mut offset = 0;
+        let mut rows = Vec::with_capacity(self.layout.fields.len());
+        for field in &self.layout.fields {
+            let end = offset + field.kind.size();
+            let bytes = self.snapshot.read(offset..end);
+            let mut value = match &bytes {
+                Some(bytes) => field.kind.format(bytes),
+                None => "??".to_string(),
+            };
+            let target = bytes.as_deref().and_then(|bytes| field.kind.target(bytes));
+            if field.kind == FieldKind::VTable
+                && let Some(vtable) = target
+            {
+                let name = self
+                    .rtti_names
+                    .entry(vtable)
+                    .or_insert_with(|| scanner.read_rtti_name(vtable).ok());
+                if let Some(name) = name {
+                    value = format!("{value} {name}");
+                }
+            }
+            let changed = self.snapshot.changed(offset..end, now);
+            rows.push(Row {
+                offset,
+                value,
+                changed,
+                target,
+            });
+            offset = end;
+        }
+        rows
+    }
+
+    fn row(&mut self, ui: &mut egui::Ui, index: usize, row: &Row) -> Option<Edit> {
+        let mut edit = None;
+        let field = &mut self.layout.fields[index];
+
+        ui.monospace(format!("+{:04X}", row.offset));
+        match self.snapshot.address() {
+            Some(address) => ui.monospace(format!("{:X}", address.wrapping_add(row.offset))),
+            None => ui.monospace("-"),
+        };
+
+        ui.horizontal(|ui| {
+            let mut selected = field.kind;
+            egui::ComboBox::from_id_salt(("struct_inspector_kind", index))
+                .width(80.0)
+                .selected_text(field.kind.label())
+                .show_ui(ui, |ui| {
+                    for choice in FieldKind::CHOICES {
+                        ui.selectable_value(&mut selected, choice, choice.label());
+                    }
+                });
+            if let FieldKind::Text(length) | FieldKind::WideText(length) = selected {
+                let mut new_length = length;
+                ui.add(egui::DragValue::new(&mut new_length).range(1..=4096));
+                selected = match selected {
+                    FieldKind::Text(_) => FieldKind::Text(new_length),
+                    _ => FieldKind::WideText(new_length),
+                };
+            }
+            if selected != field.kind {
+                edit = Some(Edit::SetKind(index, selected));
+            }
+        });
+
+        if field.kind.is_hex() {
+            ui.label("");
+        } else {
+            ui.add(egui::TextEdit::singleline(&mut field.name).desired_width(120.0));
+        }
+
+        let mut value = egui::RichText::new(&row.value).monospace();
+        if row.changed {
+            value = value.color(egui::Color32::from_rgb(255, 120, 80));
+        }
+        ui.horizontal(|ui| {
+            ui.label(value);
+            if let Some(target) = row.target
+                && ui
+                    .small_button("->")
+                    .on_hover_text("Follow pointer")
+                    .clicked()
+            {
+                edit = Some(Edit::Follow(target));
+            }
+            if ui
+                .small_button("+")
+                .on_hover_text("Insert 8 bytes before")
+                .clicked()
+            {
+                edit = Some(Edit::Insert(index));
+            }
+            if ui.small_button("x").on_hover_text("Remove field").clicked() {
+                edit = Some(Edit::Remove(index));
+            }
+        });
+
+        edit
+    }
+}
+
+impl AppUi for StructInspector {
+    fn ui(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Structure Inspector")
+            .default_size([640.0, 480.0])
+            .show(ctx, |ui| match MemoryScanner::new() {
+                Ok(scanner) => self.show(ui, &scanner),
+                Err(error) => {
+                    ui.label(format!("memory scanner unavailable: {error}"));
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_pointer_chain() {
+        let memory: HashMap<usize, usize> = [(0x1010, 0x5000), (0x5008, 0x9000)].into();
+        let module = |name: &str| (name == "game.exe").then_some(0x1000);
+        let read = |address: usize| memory.get(&address).copied();
+
+        assert_eq!(
+            evaluate_address("[[game.exe+10]+8]+4", module, read).unwrap(),
+            0x9004
+        );
+        assert_eq!(
+            evaluate_address("<game.exe> + 0x20 * 2", module, read).unwrap(),
+            0x1040
+        );
+        assert!(evaluate_address("[game.exe+10", module, read).is_err());
+        assert!(evaluate_address("client.dll+10", module, read).is_err());
+        assert!(evaluate_address("[20]", module, read).is_err());
+    }
+
+    #[test]
+    fn test_set_kind_splits_and_merges() {
+        let mut layout = StructLayout::new("Player", 0x10);
+        layout.set_kind(0, FieldKind::Int32);
+        let kinds: Vec<FieldKind> = layout.fields.iter().map(|field| field.kind).collect();
+        assert_eq!(
+            kinds,
+            [FieldKind::Int32, FieldKind::Hex32, FieldKind::Hex64]
+        );
+        assert_eq!(layout.fields[0].name, "field_0");
+
+        layout.set_kind(1, FieldKind::Hex8);
+        layout.set_kind(0, FieldKind::Double);
+        let kinds: Vec<FieldKind> = layout.fields.iter().map(|field| field.kind).collect();
+        assert_eq!(kinds, [FieldKind::Double, FieldKind::Hex64]);
+        assert_eq!(layout.size(), 0x10);
+
+        layout.set_kind(1, FieldKind::Text(12));
+        assert_eq!(layout.size(), 0x14);
+    }
+
+    #[test]
+    fn test_struct_round_trip() {
+        let mut layout = StructLayout::new("Player", 0x20);
+        layout.set_kind(0, FieldKind::VTable);
+        layout.set_kind(1, FieldKind::Float);
+        layout.fields[1].name = "health".to_string();
+        layout.set_kind(3, FieldKind::Pointer);
+
+        let definition = layout.to_struct();
+        assert_eq!(definition.size, 0x20);
+        assert_eq!(definition.vtable_offset, Some(0));
+        let offsets: Vec<usize> = definition.fields.iter().map(|field| field.offset).collect();
+        assert_eq!(offsets, [0, 8, 0x10]);
+
+        assert_eq!(StructLayout::from_struct(&definition), layout);
+    }
+}
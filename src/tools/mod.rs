@@ -0,0 +1,102 @@
+//! Interactive reverse engineering tools that run inside the overlay.
+//!
+//! Each tool is an embeddable egui panel that reads the live process through
+//! [`MemoryScanner`], and also implements [`AppUi`](crate::AppUi) so it can be
+//! passed straight to the overlay.
+
+use std::ops::Range;
+
+use crate::memory::MemoryScanner;
+
+#[cfg(feature = "tools-window")]
+pub mod console;
+pub mod hex_view;
+pub mod hook_list;
+pub mod inspector;
+pub mod trace_view;
+
+#[cfg(feature = "tools-window")]
+pub use console::ToolsWindow;
+pub use hex_view::{HexView, Interpretation, interpret, parse_hex_bytes};
+pub use hook_list::HookList;
+pub use inspector::{FieldKind, LayoutField, StructInspector, StructLayout, evaluate_address};
+pub use trace_view::TraceView;
+
+/// How long a changed byte stays highlighted, in seconds.
+const HIGHLIGHT_SECONDS: f64 = 1.0;
+
+/// Reads are split at page boundaries so one bad page only hides its own bytes.
+const PAGE_SIZE: usize = 0x1000;
+
+/// Reads `size` bytes at `address`. Bytes on unreadable pages are `None`.
+pub(crate) fn read_bytes(scanner: &MemoryScanner, address: usize, size: usize) -> Vec<Option<u8>> {
+    let mut data = Vec::with_capacity(size);
+    let mut cursor = address;
+    while data.len() < size {
+        let chunk = (PAGE_SIZE - cursor % PAGE_SIZE).min(size - data.len());
+        match scanner.read_memory(cursor, chunk) {
+            Ok(bytes) => data.extend(bytes.into_iter().map(Some)),
+            Err(_) => data.extend(std::iter::repeat_n(None, chunk)),
+        }
+        cursor = cursor.wrapping_add(chunk);
+    }
+    data
+}
+
+/// The bytes shown in the last frame and when each of them last changed.
+#[derive(Debug, Default)]
+pub(crate) struct Snapshot {
+    address: Option<usize>,
+    bytes: Vec<Option<u8>>,
+    changed_at: Vec<f64>,
+}
+
+impl Snapshot {
+    /// Replaces the bytes with a fresh read of `address` taken at time `now`.
+    ///
+    /// Change times are kept only while the address stays the same.
+    pub(crate) fn update(&mut self, address: usize, bytes: Vec<Option<u8>>, now: f64) {
+        if self.address != Some(address) {
+            self.bytes.clear();
+            self.changed_at.clear();
+        }
+        self.changed_at.resize(bytes.len(), f64::NEG_INFINITY);
+        for (index, byte) in bytes.iter().enumerate() {
+            if self
+                .bytes
+                .get(index)
+                .is_some_and(|previous| previous != byte)
+            {
+                self.changed_at[index] = now;
+            }
+        }
+        self.address = Some(address);
+        self.bytes = bytes;
+    }
+
+    /// Forgets the current address and bytes.
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(crate) fn address(&self) -> Option<usize> {
+        self.address
+    }
+
+    /// Returns the byte at `offset`, or `None` when it could not be read.
+    pub(crate) fn byte(&self, offset: usize) -> Option<u8> {
+        self.bytes.get(offset).copied().flatten()
+    }
+
+    /// Returns the bytes in `range` when all of them could be read.
+    pub(crate) fn read(&self, range: Range<usize>) -> Option<Vec<u8>> {
+        self.bytes.get(range)?.iter().copied().collect()
+    }
+
+    /// Returns true if any byte in `range` changed within the highlight period.
+    pub(crate) fn changed(&self, range: Range<usize>, now: f64) -> bool {
+        self.changed_at
+            .get(range)
+            .is_some_and(|times| times.iter().any(|&time| now - time < HIGHLIGHT_SECONDS))
+    }
+}
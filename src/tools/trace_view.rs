@@ -0,0 +1,220 @@
+//! Trace log panel for [`TraceBuffer`] records.
+//!
+//! Shows the newest records of a buffer, by default the global one, with a
+//! text filter, pausing, clearing and dumping the buffer to a file.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::AppUi;
+use crate::hooks::TraceBuffer;
+
+/// Records shown at most; older matches stay in the buffer and in dumps.
+const MAX_SHOWN_RECORDS: usize = 1000;
+
+/// Lists the records of a [`TraceBuffer`].
+///
+/// Records are formatted once, when they are first seen, and the list is
+/// only refreshed when the buffer records something new or the filter
+/// changes.
+pub struct TraceView {
+    buffer: Arc<TraceBuffer>,
+    filter: String,
+    paused: bool,
+    /// Lines of the records seen so far, oldest first, and whether they
+    /// match `matched_filter`.
+    lines: VecDeque<(String, bool)>,
+    /// Indices into `lines` of the newest matching lines.
+    shown: Vec<usize>,
+    /// Number of matching lines.
+    matches: usize,
+    /// [`TraceBuffer::total`] when `lines` was last refreshed.
+    seen: u64,
+    matched_filter: String,
+    dump_path: String,
+    status: String,
+}
+
+impl Default for TraceView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceView {
+    /// Creates a view of [`TraceBuffer::global`].
+    pub fn new() -> Self {
+        Self::with_buffer(TraceBuffer::global())
+    }
+
+    pub fn with_buffer(buffer: Arc<TraceBuffer>) -> Self {
+        Self {
+            buffer,
+            filter: String::new(),
+            paused: false,
+            lines: VecDeque::new(),
+            shown: Vec::new(),
+            matches: 0,
+            seen: 0,
+            matched_filter: String::new(),
+            dump_path: "trace.log".to_string(),
+            status: String::new(),
+        }
+    }
+
+    /// Formats the records added since the last refresh and rematches the
+    /// lines if the filter changed.
+    fn refresh(&mut self) {
+        let mut changed = false;
+        let total = self.buffer.total();
+        if !self.paused && total != self.seen {
+            let records = self.buffer.since(self.seen);
+            self.seen = records.last().map_or(total, |record| record.sequence + 1);
+            for record in &records {
+                let line = record.log_line();
+                let matches = matches_filter(&line, &self.matched_filter);
+                self.lines.push_back((line, matches));
+            }
+            let excess = self.lines.len().saturating_sub(self.buffer.capacity());
+            self.lines.drain(..excess);
+            changed = true;
+        }
+        if self.filter != self.matched_filter {
+            self.matched_filter = self.filter.clone();
+            for (line, matches) in &mut self.lines {
+                *matches = matches_filter(line, &self.matched_filter);
+            }
+            changed = true;
+        }
+        if changed {
+            let matching: Vec<usize> = (0..self.lines.len())
+                .filter(|&index| self.lines[index].1)
+                .collect();
+            self.matches = matching.len();
+            let skipped = matching.len().saturating_sub(MAX_SHOWN_RECORDS);
+            self.shown = matching[skipped..].to_vec();
+        }
+    }
+
+    /// Draws the panel into `ui`.
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.paused, "Pause");
+            if ui.button("Clear").clicked() {
+                self.buffer.clear();
+                self.lines.clear();
+                self.shown.clear();
+                self.matches = 0;
+                self.seen = self.buffer.total();
+            }
+            ui.label("Filter");
+            ui.add(egui::TextEdit::singleline(&mut self.filter).desired_width(200.0));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Dump to");
+            ui.add(egui::TextEdit::singleline(&mut self.dump_path).desired_width(240.0));
+            if ui.button("Dump").clicked() {
+                self.status = match self.buffer.dump(&self.dump_path) {
+                    Ok(()) => format!(
+                        "{} records written to {}",
+                        self.buffer.len(),
+                        self.dump_path
+                    ),
+                    Err(error) => format!("dump failed: {error}"),
+                };
+            }
+        });
+
+        self.refresh();
+        ui.weak(format!(
+            "{} of {} buffered records match, {} recorded in total",
+            self.matches,
+            self.lines.len(),
+            self.buffer.total()
+        ));
+        ui.separator();
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace) + 2.0;
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, self.shown.len(), |ui, range| {
+                for &index in &self.shown[range] {
+                    ui.monospace(&self.lines[index].0);
+                }
+            });
+
+        if !self.status.is_empty() {
+            ui.separator();
+            ui.label(&self.status);
+        }
+    }
+}
+
+/// Matches every whitespace-separated term of `filter` case-insensitively.
+fn matches_filter(line: &str, filter: &str) -> bool {
+    let line = line.to_lowercase();
+    filter
+        .split_whitespace()
+        .all(|term| line.contains(&term.to_lowercase()))
+}
+
+impl AppUi for TraceView {
+    fn ui(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Trace")
+            .default_size([640.0, 400.0])
+            .show(ctx, |ui| self.show(ui));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::TraceRecord;
+
+    #[test]
+    fn test_matches_filter() {
+        let line =
+            "#3 1700000000000 tid=42 CreateFileW(\"C:\\\\game.pak\", 0x80000000) caller=0x7ff6";
+        assert!(matches_filter(line, ""));
+        assert!(matches_filter(line, "createfile GAME.pak"));
+        assert!(matches_filter(line, "tid=42"));
+        assert!(!matches_filter(line, "createfile tid=7"));
+    }
+
+    #[test]
+    fn test_refresh_formats_new_records() {
+        let buffer = Arc::new(TraceBuffer::new(2));
+        let push = |trace: &str| {
+            buffer.push(TraceRecord {
+                sequence: 0,
+                timestamp: 0,
+                trace: trace.to_string(),
+                address: 0,
+                caller: 0,
+                thread_id: 1,
+                args: Vec::new(),
+                ret: None,
+            })
+        };
+        let mut view = TraceView::with_buffer(buffer.clone());
+        push("Open");
+        push("Read");
+        view.refresh();
+        assert_eq!((view.matches, view.lines.len()), (2, 2));
+
+        view.filter = "read".to_string();
+        push("Read");
+        view.refresh();
+        assert_eq!((view.matches, view.lines.len()), (2, 2));
+        assert!(view.lines[view.shown[0]].0.contains("#1 "));
+
+        view.paused = true;
+        push("Close");
+        view.refresh();
+        assert_eq!(view.seen, 3);
+        view.paused = false;
+        view.refresh();
+        assert_eq!((view.matches, view.seen), (1, 4));
+    }
+}
@@ -0,0 +1,1884 @@
+//! Type library with C layout rules.
+//!
+//! Named structures, classes, unions, enums and typedefs are kept in a
+//! [`TypeLibrary`], which computes sizes and alignments with the MSVC x64
+//! rules, including bitfields, base classes, vtable pointers and
+//! `#pragma pack`. Types come from analysis results or from simple C/C++
+//! header declarations. They can be read back as [`DiscoveredStruct`]s or
+//! emitted as `#[repr(C)]` Rust definitions for mod code.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{DataType, DiscoveredStruct, StructField};
+use crate::errors::Error;
+
+const POINTER_SIZE: usize = 8;
+/// Deepest chain of typedefs followed before assuming a cycle.
+const MAX_ALIAS_DEPTH: usize = 32;
+
+/// Bit range of a bitfield within its storage unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BitField {
+    /// Position of the lowest bit.
+    pub bit_offset: u8,
+    pub width: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompositeKind {
+    Struct,
+    Class,
+    Union,
+}
+
+/// A member of a composite type before layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    /// Empty for anonymous structs and unions, whose fields are merged into
+    /// the enclosing type.
+    pub name: String,
+    pub data_type: DataType,
+    /// Width in bits for bitfields.
+    pub bit_width: Option<u8>,
+}
+
+impl Member {
+    pub fn new(name: &str, data_type: DataType) -> Self {
+        Self {
+            name: name.to_string(),
+            data_type,
+            bit_width: None,
+        }
+    }
+
+    pub fn bits(name: &str, data_type: DataType, width: u8) -> Self {
+        Self {
+            name: name.to_string(),
+            data_type,
+            bit_width: Some(width),
+        }
+    }
+}
+
+/// A structure, class or union to lay out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompositeDefinition {
+    pub name: String,
+    pub kind: CompositeKind,
+    /// Base classes, laid out in order before the members.
+    pub bases: Vec<String>,
+    pub members: Vec<Member>,
+    /// True if the type declares virtual functions.
+    pub polymorphic: bool,
+    /// Maximum member alignment set with `#pragma pack`.
+    pub pack: Option<usize>,
+}
+
+impl CompositeDefinition {
+    pub fn new(name: &str, kind: CompositeKind, members: Vec<Member>) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            bases: Vec::new(),
+            members,
+            polymorphic: false,
+            pack: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnumDefinition {
+    pub underlying: DataType,
+    pub variants: Vec<(String, i64)>,
+}
+
+/// A named type in the library.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TypeDefinition {
+    Composite {
+        kind: CompositeKind,
+        layout: DiscoveredStruct,
+        alignment: usize,
+    },
+    Enum(EnumDefinition),
+    /// A typedef.
+    Alias(DataType),
+}
+
+/// Registry of named types.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TypeLibrary {
+    types: BTreeMap<String, TypeDefinition>,
+}
+
+impl TypeLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a library from recovered structures.
+    pub fn from_structures(structures: &[DiscoveredStruct]) -> Self {
+        let mut library = Self::new();
+        for structure in structures {
+            library.add_structure(structure.clone());
+        }
+        library
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TypeDefinition> {
+        self.types.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.types.keys().map(String::as_str)
+    }
+
+    /// Returns the layout of a structure, class or union.
+    pub fn structure(&self, name: &str) -> Option<&DiscoveredStruct> {
+        match self.types.get(name)? {
+            TypeDefinition::Composite { layout, .. } => Some(layout),
+            _ => None,
+        }
+    }
+
+    /// Returns the layouts of every structure, class and union.
+    pub fn structures(&self) -> Vec<&DiscoveredStruct> {
+        self.types
+            .keys()
+            .filter_map(|name| self.structure(name))
+            .collect()
+    }
+
+    /// Adds a structure with a known layout, e.g. one recovered by analysis.
+    ///
+    /// Its alignment is that of its most aligned field.
+    pub fn add_structure(&mut self, structure: DiscoveredStruct) {
+        let alignment = structure
+            .fields
+            .iter()
+            .map(|field| self.align_of(&field.data_type).unwrap_or(1))
+            .max()
+            .unwrap_or(1);
+        let kind = if structure.vtable_offset.is_some() {
+            CompositeKind::Class
+        } else {
+            CompositeKind::Struct
+        };
+        self.types.insert(
+            structure.name.clone(),
+            TypeDefinition::Composite {
+                kind,
+                layout: structure,
+                alignment,
+            },
+        );
+    }
+
+    pub fn add_enum(&mut self, name: &str, definition: EnumDefinition) {
+        self.types
+            .insert(name.to_string(), TypeDefinition::Enum(definition));
+    }
+
+    pub fn add_alias(&mut self, name: &str, data_type: DataType) {
+        self.types
+            .insert(name.to_string(), TypeDefinition::Alias(data_type));
+    }
+
+    /// Size of `data_type` in bytes.
+    pub fn size_of(&self, data_type: &DataType) -> Result<usize, Error> {
+        self.measure(data_type, 0).map(|(size, _)| size)
+    }
+
+    /// Alignment of `data_type` in bytes.
+    pub fn align_of(&self, data_type: &DataType) -> Result<usize, Error> {
+        self.measure(data_type, 0).map(|(_, alignment)| alignment)
+    }
+
+    fn measure(&self, data_type: &DataType, depth: usize) -> Result<(usize, usize), Error> {
+        let primitive = |size| Ok((size, size));
+        match data_type {
+            DataType::Unknown | DataType::Void => Err(Error::InvalidType {
+                name: format!("{data_type:?}"),
+                reason: "has no size".to_string(),
+            }),
+            DataType::Bool | DataType::Int8 | DataType::UInt8 => primitive(1),
+            DataType::Int16 | DataType::UInt16 => primitive(2),
+            DataType::Int32 | DataType::UInt32 | DataType::Float32 => primitive(4),
+            DataType::Int64 | DataType::UInt64 | DataType::Float64 => primitive(8),
+            DataType::Pointer(_) => primitive(POINTER_SIZE),
+            DataType::Array(inner, count) => {
+                let (size, alignment) = self.measure(inner, depth)?;
+                Ok((size * count, alignment))
+            }
+            DataType::Struct(name)
+            | DataType::Class(name)
+            | DataType::Union(name)
+            | DataType::Enum(name) => {
+                if depth > MAX_ALIAS_DEPTH {
+                    return Err(Error::InvalidType {
+                        name: name.clone(),
+                        reason: "typedef cycle".to_string(),
+                    });
+                }
+                match self.types.get(name) {
+                    Some(TypeDefinition::Composite {
+                        layout, alignment, ..
+                    }) => Ok((layout.size, *alignment)),
+                    Some(TypeDefinition::Enum(definition)) => {
+                        self.measure(&definition.underlying, depth + 1)
+                    }
+                    Some(TypeDefinition::Alias(target)) => self.measure(target, depth + 1),
+                    None => Err(Error::UnknownType(name.clone())),
+                }
+            }
+        }
+    }
+
+    /// Lays out `definition` with the MSVC rules and adds it to the library.
+    ///
+    /// Consecutive bitfields share a storage unit while they have the same
+    /// declared size and fit; a zero-width bitfield closes the unit. The
+    /// vtable pointer comes first unless a base class already has one.
+    pub fn define(&mut self, definition: &CompositeDefinition) -> Result<&DiscoveredStruct, Error> {
+        let pack = definition.pack.unwrap_or(usize::MAX).max(1);
+        let union = definition.kind == CompositeKind::Union;
+        let mut fields = Vec::new();
+        let mut cursor = 0;
+        let mut alignment = 1;
+        let mut vtable_offset = None;
+        let mut base_classes = Vec::new();
+
+        let bases = definition
+            .bases
+            .iter()
+            .map(|name| {
+                let base = self
+                    .structure(name)
+                    .ok_or_else(|| Error::UnknownType(name.clone()))?;
+                Ok((base, self.align_of(&DataType::Class(name.clone()))?))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let inherits_vtable = bases.iter().any(|(base, _)| base.vtable_offset.is_some());
+        if definition.polymorphic && !inherits_vtable {
+            fields.push(StructField {
+                name: "vtable".to_string(),
+                offset: 0,
+                data_type: DataType::Pointer(Box::new(DataType::Void)),
+                size: POINTER_SIZE,
+                bit_field: None,
+            });
+            vtable_offset = Some(0);
+            cursor = POINTER_SIZE;
+            alignment = POINTER_SIZE.min(pack);
+        }
+        for (base, base_alignment) in bases {
+            base_classes.push(base.name.clone());
+            if base.fields.is_empty() {
+                continue;
+            }
+            let base_alignment = base_alignment.min(pack);
+            let offset = align_up(cursor, base_alignment);
+            if vtable_offset.is_none() {
+                vtable_offset = base.vtable_offset.map(|o| offset + o);
+            }
+            fields.push(StructField {
+                name: format!("base_{}", base.name),
+                offset,
+                data_type: DataType::Class(base.name.clone()),
+                size: base.size,
+                bit_field: None,
+            });
+            cursor = offset + base.size;
+            alignment = alignment.max(base_alignment);
+        }
+
+        // Offset, declared size and bits used of the open bitfield unit.
+        let mut unit: Option<(usize, usize, usize)> = None;
+        for member in &definition.members {
+            let size = self.size_of(&member.data_type)?;
+            let member_alignment = self.align_of(&member.data_type)?.min(pack);
+            alignment = alignment.max(member_alignment);
+
+            let Some(width) = member.bit_width else {
+                unit = None;
+                let offset = if union {
+                    0
+                } else {
+                    align_up(cursor, member_alignment)
+                };
+                if member.name.is_empty() {
+                    let nested = match &member.data_type {
+                        DataType::Struct(name) | DataType::Class(name) | DataType::Union(name) => {
+                            self.structure(name)
+                        }
+                        _ => None,
+                    };
+                    let nested = nested.ok_or_else(|| Error::InvalidType {
+                        name: definition.name.clone(),
+                        reason: "anonymous member is not a structure or union".to_string(),
+                    })?;
+                    fields.extend(nested.fields.iter().map(|field| StructField {
+                        offset: offset + field.offset,
+                        ..field.clone()
+                    }));
+                } else {
+                    fields.push(StructField {
+                        name: member.name.clone(),
+                        offset,
+                        data_type: member.data_type.clone(),
+                        size,
+                        bit_field: None,
+                    });
+                }
+                cursor = if union {
+                    cursor.max(size)
+                } else {
+                    offset + size
+                };
+                continue;
+            };
+
+            let width = usize::from(width);
+            if width > size * 8 || !is_integral(&member.data_type) {
+                return Err(Error::InvalidType {
+                    name: definition.name.clone(),
+                    reason: format!("invalid bitfield {}", member.name),
+                });
+            }
+            if width == 0 {
+                unit = None;
+                continue;
+            }
+            let (offset, bit_offset) = match unit {
+                Some((offset, unit_size, used))
+                    if unit_size == size && used + width <= size * 8 =>
+                {
+                    (offset, used)
+                }
+                _ => {
+                    let offset = if union {
+                        0
+                    } else {
+                        align_up(cursor, member_alignment)
+                    };
+                    cursor = if union {
+                        cursor.max(size)
+                    } else {
+                        offset + size
+                    };
+                    (offset, 0)
+                }
+            };
+            unit = (!union).then_some((offset, size, bit_offset + width));
+            fields.push(StructField {
+                name: member.name.clone(),
+                offset,
+                data_type: member.data_type.clone(),
+                size,
+                bit_field: Some(BitField {
+                    bit_offset: bit_offset as u8,
+                    width: width as u8,
+                }),
+            });
+        }
+
+        let layout = DiscoveredStruct {
+            name: definition.name.clone(),
+            size: align_up(cursor.max(1), alignment),
+            fields,
+            vtable_offset,
+            base_classes,
+            confidence: 1.0,
+        };
+        self.types.insert(
+            definition.name.clone(),
+            TypeDefinition::Composite {
+                kind: definition.kind,
+                layout,
+                alignment,
+            },
+        );
+        Ok(self.structure(&definition.name).expect("just inserted"))
+    }
+
+    /// Parses structure, class, union, enum and typedef declarations from a
+    /// C/C++ header and adds them to the library.
+    ///
+    /// Member functions are skipped, except that a `virtual` one gives the
+    /// class a vtable pointer. `#pragma pack` is honored, and other
+    /// preprocessor directives, templates and free declarations are ignored.
+    /// Returns the structures defined, in declaration order.
+    pub fn parse_header(&mut self, source: &str) -> Result<Vec<DiscoveredStruct>, Error> {
+        let mut parser = Parser::new(tokenize(source)?, self);
+        parser.declarations(false)?;
+        let defined = std::mem::take(&mut parser.defined);
+        Ok(defined
+            .iter()
+            .filter_map(|name| self.structure(name).cloned())
+            .collect())
+    }
+
+    /// Emits `#[repr(C)]` Rust definitions of every type in the library.
+    ///
+    /// Bitfields become their storage unit with accessor methods, enums become
+    /// newtypes with associated constants, and each structure is followed by a
+    /// compile-time check of its size. Overlapping fields other than union
+    /// members are dropped.
+    pub fn rust_definitions(&self) -> String {
+        let mut out = String::new();
+        for (name, definition) in &self.types {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            match definition {
+                TypeDefinition::Composite {
+                    kind: CompositeKind::Union,
+                    layout,
+                    ..
+                } => self.rust_union(&mut out, layout),
+                TypeDefinition::Composite {
+                    layout, alignment, ..
+                } => self.rust_struct(&mut out, layout, *alignment),
+                TypeDefinition::Enum(definition) => rust_enum(&mut out, name, definition),
+                TypeDefinition::Alias(data_type) => {
+                    let _ = writeln!(
+                        out,
+                        "pub type {} = {};",
+                        rust_identifier(name),
+                        rust_type(data_type)
+                    );
+                }
+            }
+        }
+        out
+    }
+
+    fn rust_struct(&self, out: &mut String, layout: &DiscoveredStruct, alignment: usize) {
+        let mut fields: Vec<&StructField> = layout.fields.iter().filter(|f| f.size > 0).collect();
+        fields.sort_by_key(|field| field.offset);
+
+        // Pack when the recorded offsets do not follow natural alignment.
+        let natural = |field: &StructField| self.align_of(&field.data_type).unwrap_or(1);
+        let aligned = fields
+            .iter()
+            .all(|field| field.offset % natural(field).min(alignment) == 0)
+            && layout.size.is_multiple_of(alignment);
+        let packing = if aligned { alignment } else { 1 };
+        let repr = if fields.iter().all(|field| natural(field) <= packing) {
+            "C".to_string()
+        } else if packing == 1 {
+            "C, packed".to_string()
+        } else {
+            format!("C, packed({packing})")
+        };
+
+        let name = rust_identifier(&layout.name);
+        let mut accessors = String::new();
+        let mut names = Names::default();
+        let _ = writeln!(out, "#[repr({repr})]");
+        out.push_str("#[derive(Clone, Copy)]\n#[allow(non_snake_case, non_camel_case_types)]\n");
+        let _ = writeln!(out, "pub struct {name} {{");
+        let mut next = 0;
+        let mut i = 0;
+        while i < fields.len() {
+            let field = fields[i];
+            i += 1;
+            if field.offset < next {
+                continue;
+            }
+            if field.offset > next {
+                let _ = writeln!(out, "    _pad_{next:X}: [u8; 0x{:X}],", field.offset - next);
+            }
+            if field.bit_field.is_some() {
+                let unit = format!("_bitfield_{:X}", field.offset);
+                let ty = unsigned_type(field.size);
+                let _ = writeln!(out, "    {unit}: {ty}, // 0x{:X}", field.offset);
+                let members = std::iter::once(field).chain(
+                    fields[i..]
+                        .iter()
+                        .copied()
+                        .take_while(|f| f.offset == field.offset && f.bit_field.is_some()),
+                );
+                for member in members {
+                    let bits = member.bit_field.expect("bitfield");
+                    let getter = names.unique(&member.name);
+                    let mask = if usize::from(bits.width) >= field.size * 8 {
+                        format!("{ty}::MAX")
+                    } else {
+                        format!("0x{:X}", (1u64 << bits.width) - 1)
+                    };
+                    let shift = bits.bit_offset;
+                    if !accessors.is_empty() {
+                        accessors.push('\n');
+                    }
+                    let _ = write!(
+                        accessors,
+                        "    pub fn {getter}(&self) -> {ty} {{\n        (self.{unit} >> {shift}) & {mask}\n    }}\n\n    \
+                         pub fn set_{getter}(&mut self, value: {ty}) {{\n        \
+                         self.{unit} = (self.{unit} & !({mask} << {shift})) | ((value & {mask}) << {shift});\n    }}\n"
+                    );
+                }
+            } else {
+                let field_name = names.unique(&field.name);
+                let ty = self.rust_field_type(field);
+                let _ = writeln!(out, "    pub {field_name}: {ty}, // 0x{:X}", field.offset);
+            }
+            next = field.offset + field.size;
+        }
+        if layout.size > next {
+            let _ = writeln!(out, "    _pad_{next:X}: [u8; 0x{:X}],", layout.size - next);
+        }
+        out.push_str("}\n");
+        if !accessors.is_empty() {
+            let _ = write!(
+                out,
+                "\n#[allow(non_snake_case)]\nimpl {name} {{\n{accessors}}}\n"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "\nconst _: () = assert!(core::mem::size_of::<{name}>() == 0x{:X});",
+            layout.size
+        );
+    }
+
+    fn rust_union(&self, out: &mut String, layout: &DiscoveredStruct) {
+        let name = rust_identifier(&layout.name);
+        let mut names = Names::default();
+        out.push_str("#[repr(C)]\n#[derive(Clone, Copy)]\n#[allow(non_snake_case)]\n");
+        let _ = writeln!(out, "pub union {name} {{");
+        // Members of anonymous structures inside the union have no Rust equivalent.
+        for field in layout.fields.iter().filter(|field| field.offset == 0) {
+            let field_name = names.unique(&field.name);
+            let ty = match field.bit_field {
+                Some(_) => unsigned_type(field.size).to_string(),
+                None => self.rust_field_type(field),
+            };
+            let _ = writeln!(out, "    pub {field_name}: {ty},");
+        }
+        out.push_str("}\n");
+        let _ = writeln!(
+            out,
+            "\nconst _: () = assert!(core::mem::size_of::<{name}>() == 0x{:X});",
+            layout.size
+        );
+    }
+
+    /// Rust type of a field, falling back to bytes when the type has no known size.
+    fn rust_field_type(&self, field: &StructField) -> String {
+        match self.size_of(&field.data_type) {
+            Ok(size) if size == field.size => rust_type(&field.data_type),
+            _ => format!("[u8; 0x{:X}]", field.size),
+        }
+    }
+}
+
+fn rust_enum(out: &mut String, name: &str, definition: &EnumDefinition) {
+    let name = rust_identifier(name);
+    let _ = write!(
+        out,
+        "#[repr(transparent)]\n#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]\npub struct {name}(pub {});\n\n\
+         #[allow(non_upper_case_globals)]\nimpl {name} {{\n",
+        rust_type(&definition.underlying)
+    );
+    for (variant, value) in &definition.variants {
+        let _ = writeln!(
+            out,
+            "    pub const {}: Self = Self({value});",
+            rust_identifier(variant)
+        );
+    }
+    out.push_str("}\n");
+}
+
+fn align_up(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
+fn is_integral(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Bool
+            | DataType::Int8
+            | DataType::UInt8
+            | DataType::Int16
+            | DataType::UInt16
+            | DataType::Int32
+            | DataType::UInt32
+            | DataType::Int64
+            | DataType::UInt64
+            | DataType::Enum(_)
+    )
+}
+
+fn unsigned_type(size: usize) -> &'static str {
+    match size {
+        1 => "u8",
+        2 => "u16",
+        4 => "u32",
+        _ => "u64",
+    }
+}
+
+fn rust_type(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Unknown | DataType::Void => "core::ffi::c_void".to_string(),
+        DataType::Bool => "bool".to_string(),
+        DataType::Int8 => "i8".to_string(),
+        DataType::UInt8 => "u8".to_string(),
+        DataType::Int16 => "i16".to_string(),
+        DataType::UInt16 => "u16".to_string(),
+        DataType::Int32 => "i32".to_string(),
+        DataType::UInt32 => "u32".to_string(),
+        DataType::Int64 => "i64".to_string(),
+        DataType::UInt64 => "u64".to_string(),
+        DataType::Float32 => "f32".to_string(),
+        DataType::Float64 => "f64".to_string(),
+        DataType::Pointer(inner) => format!("*mut {}", rust_type(inner)),
+        DataType::Array(inner, count) => format!("[{}; {count}]", rust_type(inner)),
+        DataType::Struct(name)
+        | DataType::Class(name)
+        | DataType::Union(name)
+        | DataType::Enum(name) => rust_identifier(name),
+    }
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "do", "dyn", "else",
+    "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro",
+    "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "self", "Self",
+    "static", "struct", "super", "trait", "true", "try", "type", "typeof", "unsafe", "unsized",
+    "use", "virtual", "where", "while", "yield",
+];
+
+fn rust_identifier(name: &str) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    if RUST_KEYWORDS.contains(&identifier.as_str()) {
+        identifier.push('_');
+    }
+    identifier
+}
+
+/// Field names already used in one generated type.
+#[derive(Default)]
+struct Names(HashMap<String, usize>);
+
+impl Names {
+    fn unique(&mut self, name: &str) -> String {
+        let identifier = rust_identifier(name);
+        let count = self.0.entry(identifier.clone()).or_default();
+        *count += 1;
+        match *count {
+            1 => identifier,
+            n => format!("{identifier}_{n}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Punct(char),
+    /// `::`
+    Scope,
+    /// A preprocessor line without the `#`.
+    Directive(String),
+    /// String literal, whose contents are irrelevant to layout.
+    Str,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, Error> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut line_start = true;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            line_start = true;
+            i += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                if chars[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += 2;
+            continue;
+        }
+        let at_line_start = std::mem::replace(&mut line_start, false);
+        if c == '#' && at_line_start {
+            let start_line = line;
+            let mut text = String::new();
+            i += 1;
+            while i < chars.len() && chars[i] != '\n' {
+                if chars[i] == '\\' && chars.get(i + 1) == Some(&'\n') {
+                    line += 1;
+                    i += 2;
+                    continue;
+                }
+                text.push(chars[i]);
+                i += 1;
+            }
+            tokens.push((Token::Directive(text.trim().to_string()), start_line));
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), line));
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '\'') {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().filter(|&&c| c != '\'').collect();
+            let digits = literal.trim_end_matches(['u', 'U', 'l', 'L']);
+            let value = match digits
+                .strip_prefix("0x")
+                .or_else(|| digits.strip_prefix("0X"))
+            {
+                // Masks and enum values above i64::MAX keep their bit pattern.
+                Some(hex) => u64::from_str_radix(hex, 16).ok().map(|value| value as i64),
+                None => digits
+                    .parse()
+                    .ok()
+                    .or_else(|| digits.parse::<u64>().ok().map(|value| value as i64)),
+            };
+            let value = value.ok_or_else(|| Error::HeaderParse {
+                line,
+                message: format!("invalid number {literal}"),
+            })?;
+            tokens.push((Token::Number(value), line));
+            continue;
+        }
+        if c == '"' || c == '\'' {
+            i += 1;
+            let mut value = 0;
+            while i < chars.len() && chars[i] != c {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                if let Some(&ch) = chars.get(i) {
+                    value = ch as i64;
+                }
+                i += 1;
+            }
+            i += 1;
+            let token = if c == '"' {
+                Token::Str
+            } else {
+                Token::Number(value)
+            };
+            tokens.push((token, line));
+            continue;
+        }
+        if c == ':' && chars.get(i + 1) == Some(&':') {
+            tokens.push((Token::Scope, line));
+            i += 2;
+            continue;
+        }
+        tokens.push((Token::Punct(c), line));
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// Integer and floating-point names of the C library and the Windows headers.
+fn named_primitive(name: &str) -> Option<DataType> {
+    Some(match name {
+        "int8_t" | "CHAR" | "INT8" => DataType::Int8,
+        "uint8_t" | "BYTE" | "UCHAR" | "UINT8" | "BOOLEAN" | "char8_t" => DataType::UInt8,
+        "int16_t" | "SHORT" | "INT16" => DataType::Int16,
+        "uint16_t" | "WORD" | "USHORT" | "UINT16" | "WCHAR" => DataType::UInt16,
+        "int32_t" | "INT" | "INT32" | "LONG" | "BOOL" | "HRESULT" => DataType::Int32,
+        "uint32_t" | "DWORD" | "UINT" | "UINT32" | "ULONG" => DataType::UInt32,
+        "int64_t" | "intptr_t" | "ptrdiff_t" | "ssize_t" | "INT64" | "LONGLONG" | "LONG_PTR"
+        | "INT_PTR" => DataType::Int64,
+        "uint64_t" | "uintptr_t" | "size_t" | "UINT64" | "QWORD" | "ULONGLONG" | "DWORD64"
+        | "ULONG_PTR" | "UINT_PTR" | "DWORD_PTR" | "SIZE_T" => DataType::UInt64,
+        "FLOAT" => DataType::Float32,
+        "DOUBLE" => DataType::Float64,
+        "PVOID" | "LPVOID" | "HANDLE" | "HMODULE" | "HWND" => {
+            DataType::Pointer(Box::new(DataType::Void))
+        }
+        _ => return None,
+    })
+}
+
+/// Words that combine into a builtin type, e.g. `unsigned long long`.
+const PRIMITIVE_WORDS: &[&str] = &[
+    "void", "bool", "char", "wchar_t", "char16_t", "char32_t", "short", "int", "long", "signed",
+    "unsigned", "float", "double", "__int8", "__int16", "__int32", "__int64",
+];
+
+/// Words ignored in front of a type.
+const QUALIFIERS: &[&str] = &[
+    "const",
+    "volatile",
+    "mutable",
+    "inline",
+    "register",
+    "typename",
+    "__unaligned",
+    "__ptr64",
+    "__restrict",
+    "restrict",
+];
+
+fn builtin_type(words: &[String]) -> DataType {
+    let has = |word: &str| words.iter().any(|w| w == word);
+    let unsigned = has("unsigned");
+    let longs = words.iter().filter(|w| *w == "long").count();
+    let integer = |signed_type, unsigned_type| if unsigned { unsigned_type } else { signed_type };
+    if has("void") {
+        DataType::Void
+    } else if has("bool") {
+        DataType::Bool
+    } else if has("float") {
+        DataType::Float32
+    } else if has("double") {
+        DataType::Float64
+    } else if has("wchar_t") || has("char16_t") {
+        DataType::UInt16
+    } else if has("char32_t") {
+        DataType::UInt32
+    } else if has("char") || has("__int8") {
+        integer(DataType::Int8, DataType::UInt8)
+    } else if has("short") || has("__int16") {
+        integer(DataType::Int16, DataType::UInt16)
+    } else if longs >= 2 || has("__int64") {
+        integer(DataType::Int64, DataType::UInt64)
+    } else {
+        // `long` is 32 bits on Windows.
+        integer(DataType::Int32, DataType::UInt32)
+    }
+}
+
+/// Replaces references to the type named `from` with `to`.
+fn renamed(data_type: &DataType, from: &str, to: &str) -> DataType {
+    match data_type {
+        DataType::Pointer(inner) => DataType::Pointer(Box::new(renamed(inner, from, to))),
+        DataType::Array(inner, count) => {
+            DataType::Array(Box::new(renamed(inner, from, to)), *count)
+        }
+        DataType::Struct(name) if name == from => DataType::Struct(to.to_string()),
+        DataType::Class(name) if name == from => DataType::Class(to.to_string()),
+        DataType::Union(name) if name == from => DataType::Union(to.to_string()),
+        other => other.clone(),
+    }
+}
+
+/// One declarator of a declaration.
+struct Declarator {
+    name: String,
+    data_type: DataType,
+    bit_width: Option<u8>,
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    library: &'a mut TypeLibrary,
+    pack: Option<usize>,
+    pack_stack: Vec<Option<usize>>,
+    /// Enumerators usable in array bounds.
+    constants: HashMap<String, i64>,
+    defined: Vec<String>,
+    anonymous: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: Vec<(Token, usize)>, library: &'a mut TypeLibrary) -> Self {
+        let constants = library
+            .types
+            .values()
+            .filter_map(|definition| match definition {
+                TypeDefinition::Enum(definition) => Some(definition.variants.iter().cloned()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        Self {
+            tokens,
+            position: 0,
+            library,
+            pack: None,
+            pack_stack: Vec::new(),
+            constants,
+            defined: Vec::new(),
+            anonymous: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens
+            .get(self.position + offset)
+            .map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(token, _)| token.clone());
+        self.position += 1;
+        token
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        let line = self
+            .tokens
+            .get(self.position.min(self.tokens.len().saturating_sub(1)))
+            .map_or(0, |(_, line)| *line);
+        Error::HeaderParse {
+            line,
+            message: message.into(),
+        }
+    }
+
+    fn is_punct(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punct(c))
+    }
+
+    fn is_ident(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(w)) if w == word)
+    }
+
+    fn eat_punct(&mut self, c: char) -> bool {
+        let matched = self.is_punct(c);
+        if matched {
+            self.position += 1;
+        }
+        matched
+    }
+
+    fn eat_ident(&mut self, word: &str) -> bool {
+        let matched = self.is_ident(word);
+        if matched {
+            self.position += 1;
+        }
+        matched
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), Error> {
+        if self.eat_punct(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{c}'")))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Ident(word)) => {
+                let word = word.clone();
+                self.position += 1;
+                Ok(word)
+            }
+            _ => Err(self.error("expected an identifier")),
+        }
+    }
+
+    /// Reads a possibly qualified name and returns its last component.
+    fn qualified_name(&mut self) -> Result<String, Error> {
+        self.eat_scope();
+        let mut name = self.identifier()?;
+        while self.eat_scope() {
+            name = self.identifier()?;
+        }
+        if self.is_punct('<') {
+            return Err(self.error(format!("templates are not supported ({name})")));
+        }
+        Ok(name)
+    }
+
+    fn eat_scope(&mut self) -> bool {
+        let matched = self.peek() == Some(&Token::Scope);
+        if matched {
+            self.position += 1;
+        }
+        matched
+    }
+
+    /// Skips a balanced `(...)`, `[...]`, `{...}` or `<...>` group starting at the current token.
+    fn skip_group(&mut self) -> Result<(), Error> {
+        let (open, close) = match self.next() {
+            Some(Token::Punct('(')) => ('(', ')'),
+            Some(Token::Punct('[')) => ('[', ']'),
+            Some(Token::Punct('{')) => ('{', '}'),
+            Some(Token::Punct('<')) => ('<', '>'),
+            _ => return Err(self.error("expected a group")),
+        };
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some(Token::Punct(c)) if c == open => depth += 1,
+                Some(Token::Punct(c)) if c == close => depth -= 1,
+                Some(_) => {}
+                None => return Err(self.error(format!("unterminated '{open}'"))),
+            }
+        }
+        Ok(())
+    }
+
+    /// Skips to the end of the current declaration, including a function body.
+    fn skip_statement(&mut self) -> Result<(), Error> {
+        loop {
+            match self.peek() {
+                None => return Ok(()),
+                Some(Token::Punct(';')) => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                Some(Token::Punct('}')) => return Ok(()),
+                Some(Token::Punct('{')) => {
+                    self.skip_group()?;
+                    if !self.is_punct(';') {
+                        return Ok(());
+                    }
+                }
+                Some(Token::Punct('(' | '[')) => self.skip_group()?,
+                Some(_) => self.position += 1,
+            }
+        }
+    }
+
+    /// Skips `__declspec(...)`, `alignas(...)` and `[[...]]` attributes.
+    fn skip_attributes(&mut self) -> Result<(), Error> {
+        loop {
+            if self.is_ident("__declspec") || self.is_ident("alignas") {
+                self.position += 1;
+                self.skip_group()?;
+            } else if self.is_punct('[') && self.peek_at(1) == Some(&Token::Punct('[')) {
+                self.skip_group()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn directive(&mut self, text: &str) {
+        let Some(pragma) = text.strip_prefix("pragma") else {
+            return;
+        };
+        let Some(arguments) = pragma.trim().strip_prefix("pack") else {
+            return;
+        };
+        let arguments: Vec<&str> = arguments
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .split(',')
+            .map(str::trim)
+            .filter(|argument| !argument.is_empty())
+            .collect();
+        let value = |argument: &str| argument.parse::<usize>().ok();
+        match arguments.as_slice() {
+            [] => self.pack = None,
+            ["push", rest @ ..] => {
+                self.pack_stack.push(self.pack);
+                if let Some(n) = rest.iter().find_map(|argument| value(argument)) {
+                    self.pack = Some(n);
+                }
+            }
+            ["pop", ..] => self.pack = self.pack_stack.pop().flatten(),
+            [n] => self.pack = value(n).or(self.pack),
+            _ => {}
+        }
+    }
+
+    /// Parses declarations until the end of input, or a closing brace when `nested`.
+    fn declarations(&mut self, nested: bool) -> Result<(), Error> {
+        while let Some(token) = self.peek().cloned() {
+            match token {
+                Token::Directive(text) => {
+                    self.position += 1;
+                    self.directive(&text);
+                }
+                Token::Punct(';') => self.position += 1,
+                Token::Punct('}') if nested => return Ok(()),
+                Token::Ident(word) => match word.as_str() {
+                    "typedef" => {
+                        self.position += 1;
+                        self.typedef()?;
+                    }
+                    "struct" | "class" | "union" | "enum" => {
+                        self.type_specifier()?;
+                        self.skip_statement()?;
+                    }
+                    "namespace" => {
+                        self.position += 1;
+                        while !self.is_punct('{') && !self.is_punct(';') && self.peek().is_some() {
+                            self.position += 1;
+                        }
+                        if self.eat_punct('{') {
+                            self.declarations(true)?;
+                            self.expect_punct('}')?;
+                        }
+                    }
+                    "extern" if self.peek_at(1) == Some(&Token::Str) => {
+                        self.position += 2;
+                        if self.eat_punct('{') {
+                            self.declarations(true)?;
+                            self.expect_punct('}')?;
+                        }
+                    }
+                    "template" => {
+                        self.position += 1;
+                        self.skip_group()?;
+                        self.skip_statement()?;
+                    }
+                    _ => self.skip_statement()?,
+                },
+                _ => self.skip_statement()?,
+            }
+        }
+        if nested {
+            return Err(self.error("expected '}'"));
+        }
+        Ok(())
+    }
+
+    fn typedef(&mut self) -> Result<(), Error> {
+        let base = self.type_specifier()?;
+        let mut declarators = self.declarators(&base)?;
+        // `typedef struct { ... } Name;` names the structure itself.
+        if let DataType::Struct(anonymous) | DataType::Class(anonymous) | DataType::Union(anonymous) =
+            &base
+            && self.is_anonymous(anonymous)
+            && let Some(name) = declarators
+                .iter()
+                .find(|d| d.data_type == base)
+                .map(|d| d.name.clone())
+        {
+            self.rename(anonymous, &name);
+            for declarator in &mut declarators {
+                declarator.data_type = renamed(&declarator.data_type, anonymous, &name);
+            }
+        }
+        for declarator in declarators {
+            match &declarator.data_type {
+                // `typedef struct Foo Foo;`
+                DataType::Struct(name)
+                | DataType::Class(name)
+                | DataType::Union(name)
+                | DataType::Enum(name)
+                    if *name == declarator.name => {}
+                data_type => self.library.add_alias(&declarator.name, data_type.clone()),
+            }
+        }
+        Ok(())
+    }
+
+    fn is_anonymous(&self, name: &str) -> bool {
+        name.starts_with("__anonymous_")
+    }
+
+    /// Names an anonymous structure after the typedef declaring it.
+    fn rename(&mut self, from: &str, to: &str) {
+        if let Some(TypeDefinition::Composite {
+            kind,
+            mut layout,
+            alignment,
+        }) = self.library.types.remove(from)
+        {
+            layout.name = to.to_string();
+            self.library.types.insert(
+                to.to_string(),
+                TypeDefinition::Composite {
+                    kind,
+                    layout,
+                    alignment,
+                },
+            );
+            if let Some(defined) = self.defined.iter_mut().find(|name| *name == from) {
+                *defined = to.to_string();
+            }
+        }
+    }
+
+    /// Parses a type up to its declarators, defining any structure or enum in it.
+    fn type_specifier(&mut self) -> Result<DataType, Error> {
+        let mut words = Vec::new();
+        loop {
+            self.skip_attributes()?;
+            match self.peek() {
+                Some(Token::Ident(word)) if QUALIFIERS.contains(&word.as_str()) => {
+                    self.position += 1;
+                }
+                Some(Token::Ident(word)) if PRIMITIVE_WORDS.contains(&word.as_str()) => {
+                    words.push(word.clone());
+                    self.position += 1;
+                }
+                _ => break,
+            }
+        }
+        if !words.is_empty() {
+            return Ok(builtin_type(&words));
+        }
+
+        let data_type = match self.peek() {
+            Some(Token::Ident(word)) if matches!(word.as_str(), "struct" | "class" | "union") => {
+                let kind = match word.as_str() {
+                    "struct" => CompositeKind::Struct,
+                    "class" => CompositeKind::Class,
+                    _ => CompositeKind::Union,
+                };
+                self.position += 1;
+                self.composite(kind)?
+            }
+            Some(Token::Ident(word)) if word == "enum" => {
+                self.position += 1;
+                self.enumeration()?
+            }
+            Some(Token::Ident(_) | Token::Scope) => {
+                let name = self.qualified_name()?;
+                self.named_type(name)
+            }
+            _ => return Err(self.error("expected a type")),
+        };
+        while self
+            .peek()
+            .is_some_and(|t| matches!(t, Token::Ident(w) if QUALIFIERS.contains(&w.as_str())))
+        {
+            self.position += 1;
+        }
+        Ok(data_type)
+    }
+
+    /// Resolves a type name; unknown names are assumed to be structures.
+    fn named_type(&self, name: String) -> DataType {
+        if let Some(data_type) = named_primitive(&name) {
+            return data_type;
+        }
+        match self.library.get(&name) {
+            Some(TypeDefinition::Alias(target)) => target.clone(),
+            Some(TypeDefinition::Enum(_)) => DataType::Enum(name),
+            Some(TypeDefinition::Composite {
+                kind: CompositeKind::Union,
+                ..
+            }) => DataType::Union(name),
+            Some(TypeDefinition::Composite {
+                kind: CompositeKind::Class,
+                ..
+            }) => DataType::Class(name),
+            _ => DataType::Struct(name),
+        }
+    }
+
+    fn composite_type(kind: CompositeKind, name: String) -> DataType {
+        match kind {
+            CompositeKind::Struct => DataType::Struct(name),
+            CompositeKind::Class => DataType::Class(name),
+            CompositeKind::Union => DataType::Union(name),
+        }
+    }
+
+    /// Parses a structure, class or union after its keyword.
+    fn composite(&mut self, kind: CompositeKind) -> Result<DataType, Error> {
+        self.skip_attributes()?;
+        let name = if matches!(self.peek(), Some(Token::Ident(_) | Token::Scope)) {
+            Some(self.qualified_name()?)
+        } else {
+            None
+        };
+        self.eat_ident("final");
+        if !self.is_punct('{') && !self.is_punct(':') {
+            let name = name.ok_or_else(|| self.error("expected a name or a body"))?;
+            return Ok(match self.library.get(&name) {
+                Some(TypeDefinition::Composite { kind, .. }) => Self::composite_type(*kind, name),
+                _ => Self::composite_type(kind, name),
+            });
+        }
+        let name = name.unwrap_or_else(|| {
+            self.anonymous += 1;
+            format!("__anonymous_{}", self.anonymous)
+        });
+
+        let mut bases = Vec::new();
+        if self.eat_punct(':') {
+            loop {
+                while ["public", "private", "protected", "virtual"]
+                    .iter()
+                    .any(|w| self.is_ident(w))
+                {
+                    self.position += 1;
+                }
+                bases.push(self.qualified_name()?);
+                if !self.eat_punct(',') {
+                    break;
+                }
+            }
+        }
+        self.expect_punct('{')?;
+        let (members, polymorphic) = self.members()?;
+        self.expect_punct('}')?;
+
+        let definition = CompositeDefinition {
+            name: name.clone(),
+            kind,
+            bases,
+            members,
+            polymorphic,
+            pack: self.pack,
+        };
+        self.library.define(&definition)?;
+        // Anonymous members were merged into this type and are not needed on their own.
+        for member in definition
+            .members
+            .iter()
+            .filter(|member| member.name.is_empty())
+        {
+            if let DataType::Struct(anonymous) | DataType::Union(anonymous) = &member.data_type {
+                self.library.types.remove(anonymous);
+                self.defined.retain(|defined| defined != anonymous);
+            }
+        }
+        self.defined.push(name.clone());
+        Ok(Self::composite_type(kind, name))
+    }
+
+    /// Parses the body of a composite type; the flag is set if it has virtual functions.
+    fn members(&mut self) -> Result<(Vec<Member>, bool), Error> {
+        let mut members = Vec::new();
+        let mut polymorphic = false;
+        loop {
+            match self.peek().cloned() {
+                None | Some(Token::Punct('}')) => return Ok((members, polymorphic)),
+                Some(Token::Punct(';')) => self.position += 1,
+                Some(Token::Directive(text)) => {
+                    self.position += 1;
+                    self.directive(&text);
+                }
+                Some(Token::Ident(word))
+                    if matches!(word.as_str(), "public" | "private" | "protected")
+                        && self.peek_at(1) == Some(&Token::Punct(':')) =>
+                {
+                    self.position += 2;
+                }
+                Some(Token::Ident(word)) if word == "typedef" => {
+                    self.position += 1;
+                    self.typedef()?;
+                }
+                Some(Token::Ident(word))
+                    if matches!(
+                        word.as_str(),
+                        "static" | "friend" | "using" | "static_assert" | "template" | "constexpr"
+                    ) =>
+                {
+                    if word == "template" {
+                        self.position += 1;
+                        self.skip_group()?;
+                    }
+                    self.skip_statement()?;
+                }
+                Some(_) if self.is_function() => {
+                    polymorphic |= self.statement_has("virtual");
+                    self.skip_statement()?;
+                }
+                Some(_) => {
+                    let base = self.type_specifier()?;
+                    if self.eat_punct(';') {
+                        // A nested type declaration, or an anonymous struct or union.
+                        if let DataType::Struct(name) | DataType::Union(name) = &base
+                            && self.is_anonymous(name)
+                        {
+                            members.push(Member::new("", base.clone()));
+                        }
+                        continue;
+                    }
+                    members.extend(
+                        self.declarators(&base)?
+                            .into_iter()
+                            .map(|declarator| Member {
+                                name: declarator.name,
+                                data_type: declarator.data_type,
+                                bit_width: declarator.bit_width,
+                            }),
+                    );
+                }
+            }
+        }
+    }
+
+    /// End of the current statement: the first `;`, `{` or `}` outside parentheses.
+    fn statement_end(&self) -> usize {
+        let mut depth = 0usize;
+        let mut i = self.position;
+        while let Some((token, _)) = self.tokens.get(i) {
+            match token {
+                Token::Punct('(' | '[') => depth += 1,
+                Token::Punct(')' | ']') => depth = depth.saturating_sub(1),
+                Token::Punct(';' | '{' | '}') if depth == 0 => break,
+                _ => {}
+            }
+            i += 1;
+        }
+        i
+    }
+
+    fn statement_has(&self, word: &str) -> bool {
+        self.tokens[self.position..self.statement_end()]
+            .iter()
+            .any(|(token, _)| matches!(token, Token::Ident(w) if w == word))
+    }
+
+    /// True if the statement declares a member function rather than a field.
+    ///
+    /// Function pointer fields are told apart by the `(*` before their name.
+    fn is_function(&self) -> bool {
+        let end = self.statement_end();
+        let statement = &self.tokens[self.position..end];
+        if statement.iter().any(|(token, _)| {
+            matches!(token, Token::Ident(w) if w == "operator") || *token == Token::Punct('~')
+        }) {
+            return true;
+        }
+        let Some(open) = statement
+            .iter()
+            .position(|(token, _)| *token == Token::Punct('('))
+        else {
+            return false;
+        };
+        let pointer = statement[open + 1..].iter().find(
+            |(token, _)| !matches!(token, Token::Ident(w) if w.starts_with("__") || w == "WINAPI"),
+        );
+        !matches!(pointer, Some((Token::Punct('*'), _)))
+    }
+
+    /// Parses an enum after its keyword.
+    fn enumeration(&mut self) -> Result<DataType, Error> {
+        let _ = self.eat_ident("class") || self.eat_ident("struct");
+        self.skip_attributes()?;
+        let name = if matches!(self.peek(), Some(Token::Ident(_) | Token::Scope)) {
+            Some(self.qualified_name()?)
+        } else {
+            None
+        };
+        let underlying = if self.eat_punct(':') {
+            self.type_specifier()?
+        } else {
+            DataType::Int32
+        };
+        if !self.eat_punct('{') {
+            let name = name.ok_or_else(|| self.error("expected an enum name or body"))?;
+            return Ok(DataType::Enum(name));
+        }
+
+        let mut variants = Vec::new();
+        let mut value = 0;
+        while !self.eat_punct('}') {
+            let variant = self.identifier()?;
+            if self.eat_punct('=') {
+                value = self.expression()?;
+            }
+            self.constants.insert(variant.clone(), value);
+            variants.push((variant, value));
+            value += 1;
+            if !self.eat_punct(',') {
+                self.expect_punct('}')?;
+                break;
+            }
+        }
+        match name {
+            Some(name) => {
+                self.library.add_enum(
+                    &name,
+                    EnumDefinition {
+                        underlying,
+                        variants,
+                    },
+                );
+                Ok(DataType::Enum(name))
+            }
+            None => Ok(underlying),
+        }
+    }
+
+    /// Parses comma-separated declarators up to and including the `;`.
+    fn declarators(&mut self, base: &DataType) -> Result<Vec<Declarator>, Error> {
+        let mut declarators = Vec::new();
+        loop {
+            let mut data_type = base.clone();
+            loop {
+                if self.eat_punct('*') || self.eat_punct('&') {
+                    data_type = DataType::Pointer(Box::new(data_type));
+                } else if self.peek().is_some_and(
+                    |t| matches!(t, Token::Ident(w) if QUALIFIERS.contains(&w.as_str())),
+                ) {
+                    self.position += 1;
+                } else {
+                    break;
+                }
+            }
+
+            let name = if self.eat_punct('(') {
+                // Function pointer: `ret (__cdecl *name)(args)`.
+                while matches!(self.peek(), Some(Token::Ident(w)) if w.starts_with("__") || w == "WINAPI")
+                {
+                    self.position += 1;
+                }
+                self.expect_punct('*')?;
+                let name = self.identifier()?;
+                self.expect_punct(')')?;
+                if self.is_punct('(') {
+                    self.skip_group()?;
+                }
+                data_type = DataType::Pointer(Box::new(DataType::Void));
+                name
+            } else {
+                self.identifier()?
+            };
+
+            let mut dimensions = Vec::new();
+            while self.eat_punct('[') {
+                let count = self.expression()?;
+                self.expect_punct(']')?;
+                let count =
+                    usize::try_from(count).map_err(|_| self.error("negative array size"))?;
+                dimensions.push(count);
+            }
+            for count in dimensions.into_iter().rev() {
+                data_type = DataType::Array(Box::new(data_type), count);
+            }
+
+            let bit_width = if self.eat_punct(':') {
+                let width = self.expression()?;
+                Some(u8::try_from(width).map_err(|_| self.error("invalid bitfield width"))?)
+            } else {
+                None
+            };
+
+            // Default member initializers.
+            if self.is_punct('=') || self.is_punct('{') {
+                while !self.is_punct(',') && !self.is_punct(';') {
+                    match self.peek() {
+                        None => return Err(self.error("expected ';'")),
+                        Some(Token::Punct('(' | '{' | '[')) => self.skip_group()?,
+                        Some(_) => self.position += 1,
+                    }
+                }
+            }
+
+            declarators.push(Declarator {
+                name,
+                data_type,
+                bit_width,
+            });
+            if !self.eat_punct(',') {
+                break;
+            }
+        }
+        self.expect_punct(';')?;
+        Ok(declarators)
+    }
+
+    /// Evaluates a constant expression of `+ - * / % << >> | & ^`, enumerators and `sizeof`.
+    fn expression(&mut self) -> Result<i64, Error> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<i64, Error> {
+        let mut left = self.unary()?;
+        loop {
+            let (operator, precedence, width) = match (self.peek(), self.peek_at(1)) {
+                (Some(Token::Punct('<')), Some(Token::Punct('<'))) => ("<<", 4, 2),
+                (Some(Token::Punct('>')), Some(Token::Punct('>'))) => (">>", 4, 2),
+                (Some(Token::Punct('*')), _) => ("*", 6, 1),
+                (Some(Token::Punct('/')), _) => ("/", 6, 1),
+                (Some(Token::Punct('%')), _) => ("%", 6, 1),
+                (Some(Token::Punct('+')), _) => ("+", 5, 1),
+                (Some(Token::Punct('-')), _) => ("-", 5, 1),
+                (Some(Token::Punct('&')), _) => ("&", 3, 1),
+                (Some(Token::Punct('^')), _) => ("^", 2, 1),
+                (Some(Token::Punct('|')), _) => ("|", 1, 1),
+                _ => return Ok(left),
+            };
+            if precedence < min_precedence {
+                return Ok(left);
+            }
+            self.position += width;
+            let right = self.binary(precedence + 1)?;
+            left = match operator {
+                "<<" => left.checked_shl(right as u32),
+                ">>" => left.checked_shr(right as u32),
+                "*" => left.checked_mul(right),
+                "/" => left.checked_div(right),
+                "%" => left.checked_rem(right),
+                "+" => left.checked_add(right),
+                "-" => left.checked_sub(right),
+                "&" => Some(left & right),
+                "^" => Some(left ^ right),
+                _ => Some(left | right),
+            }
+            .ok_or_else(|| self.error("constant overflow"))?;
+        }
+    }
+
+    fn unary(&mut self) -> Result<i64, Error> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Punct('-')) => Ok(self.unary()?.wrapping_neg()),
+            Some(Token::Punct('~')) => Ok(!self.unary()?),
+            Some(Token::Punct('+')) => self.unary(),
+            Some(Token::Punct('(')) => {
+                let value = self.expression()?;
+                self.expect_punct(')')?;
+                Ok(value)
+            }
+            Some(Token::Ident(word)) if word == "sizeof" => {
+                self.expect_punct('(')?;
+                let data_type = self.type_specifier()?;
+                let mut data_type = data_type;
+                while self.eat_punct('*') {
+                    data_type = DataType::Pointer(Box::new(data_type));
+                }
+                self.expect_punct(')')?;
+                Ok(self.library.size_of(&data_type)? as i64)
+            }
+            Some(Token::Ident(word)) => self
+                .constants
+                .get(&word)
+                .copied()
+                .ok_or_else(|| self.error(format!("unknown constant {word}"))),
+            _ => Err(self.error("expected a constant")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"
+// Game types
+#pragma once
+#define MAX_SLOTS 4
+enum class Team : uint8_t { None, Red = 2, Blue };
+enum { SLOT_COUNT = 4 };
+
+typedef struct _Vec3 {
+    float x, y, z;
+} Vec3, *PVec3;
+
+struct Flags {
+    uint32_t alive : 1;
+    uint32_t team : 2;
+    uint8_t visible : 1;
+};
+
+class Entity {
+public:
+    virtual ~Entity();
+    virtual void Tick(float dt) = 0;
+    Vec3 position;
+    Team team;
+    union {
+        int32_t health;
+        float shield;
+    };
+    Entity* owner;
+    void (__cdecl *callback)(Entity*, int);
+};
+
+class Player : public Entity {
+    int get_score() const { return score; }
+    int score;
+    wchar_t name[SLOT_COUNT * 4]; /* UTF-16 */
+    unsigned long long id;
+};
+
+#pragma pack(push, 1)
+struct Packet { uint8_t kind; uint32_t length; };
+#pragma pack(pop)
+"#;
+
+    fn offsets(structure: &DiscoveredStruct) -> Vec<(&str, usize)> {
+        structure
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.offset))
+            .collect()
+    }
+
+    #[test]
+    fn test_layout_rules() {
+        let mut library = TypeLibrary::new();
+        let mut members = vec![
+            Member::new("tag", DataType::Int8),
+            Member::new("value", DataType::Int64),
+            Member::bits("low", DataType::UInt16, 4),
+            Member::bits("high", DataType::UInt16, 12),
+            Member::bits("next", DataType::UInt16, 1),
+            Member::bits("", DataType::UInt16, 0),
+            Member::bits("last", DataType::UInt16, 1),
+        ];
+        let layout = library
+            .define(&CompositeDefinition::new(
+                "Record",
+                CompositeKind::Struct,
+                members.clone(),
+            ))
+            .unwrap();
+        assert_eq!(
+            offsets(layout),
+            [
+                ("tag", 0),
+                ("value", 8),
+                ("low", 0x10),
+                ("high", 0x10),
+                ("next", 0x12),
+                ("last", 0x14)
+            ]
+        );
+        assert_eq!(
+            layout.fields[3].bit_field,
+            Some(BitField {
+                bit_offset: 4,
+                width: 12
+            })
+        );
+        assert_eq!(layout.size, 0x18);
+        assert_eq!(
+            library
+                .align_of(&DataType::Struct("Record".into()))
+                .unwrap(),
+            8
+        );
+
+        let mut packed = CompositeDefinition::new("Packed", CompositeKind::Struct, members.clone());
+        packed.pack = Some(2);
+        assert_eq!(library.define(&packed).unwrap().size, 0x10);
+
+        members.truncate(2);
+        let union = CompositeDefinition::new("Either", CompositeKind::Union, members);
+        assert_eq!(library.define(&union).unwrap().size, 8);
+        assert!(matches!(
+            library.size_of(&DataType::Array(
+                Box::new(DataType::Struct("Missing".into())),
+                2
+            )),
+            Err(Error::UnknownType(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let mut library = TypeLibrary::new();
+        let structures = library.parse_header(HEADER).unwrap();
+        let names: Vec<&str> = structures.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["_Vec3", "Flags", "Entity", "Player", "Packet"]);
+
+        let entity = library.structure("Entity").unwrap();
+        assert_eq!(
+            offsets(entity),
+            [
+                ("vtable", 0),
+                ("position", 8),
+                ("team", 0x14),
+                ("health", 0x18),
+                ("shield", 0x18),
+                ("owner", 0x20),
+                ("callback", 0x28)
+            ]
+        );
+        assert_eq!(entity.fields[2].data_type, DataType::Enum("Team".into()));
+
+        let player = library.structure("Player").unwrap();
+        assert_eq!(player.base_classes, ["Entity"]);
+        assert_eq!(player.vtable_offset, Some(0));
+        assert_eq!(
+            offsets(player),
+            [
+                ("base_Entity", 0),
+                ("score", 0x30),
+                ("name", 0x34),
+                ("id", 0x58)
+            ]
+        );
+        assert_eq!(player.size, 0x60);
+
+        let flags = library.structure("Flags").unwrap();
+        assert_eq!(offsets(flags), [("alive", 0), ("team", 0), ("visible", 4)]);
+        assert_eq!(flags.size, 8);
+        assert_eq!(library.structure("Packet").unwrap().size, 5);
+        assert!(matches!(
+            library.get("PVec3"),
+            Some(TypeDefinition::Alias(DataType::Pointer(_)))
+        ));
+
+        library
+            .parse_header(
+                "enum Mask : uint64_t { All = 0xFFFFFFFFFFFFFFFF, Top = 9223372036854775808ULL };",
+            )
+            .unwrap();
+        let Some(TypeDefinition::Enum(mask)) = library.get("Mask") else {
+            panic!("Mask is not an enum");
+        };
+        assert_eq!(
+            mask.variants,
+            [("All".into(), -1), ("Top".into(), i64::MIN)]
+        );
+
+        let error = library
+            .parse_header("struct Broken {\n    int x\n};")
+            .unwrap_err();
+        assert!(matches!(error, Error::HeaderParse { line: 3, .. }));
+    }
+
+    #[test]
+    fn test_rust_definitions() {
+        let mut library = TypeLibrary::new();
+        library.parse_header(HEADER).unwrap();
+        let rust = library.rust_definitions();
+        for expected in [
+            "pub struct Player {\n    pub base_Entity: Entity, // 0x0\n    pub score: i32, // 0x30\n    pub name: [u16; 16], // 0x34\n    _pad_54: [u8; 0x4],\n    pub id: u64, // 0x58\n}",
+            "const _: () = assert!(core::mem::size_of::<Player>() == 0x60);",
+            "pub callback: *mut core::ffi::c_void, // 0x28",
+            "pub struct Team(pub u8);",
+            "pub const Red: Self = Self(2);",
+            "pub type PVec3 = *mut _Vec3;",
+            "#[repr(C, packed)]\n#[derive(Clone, Copy)]\n#[allow(non_snake_case, non_camel_case_types)]\npub struct Packet {",
+            "    _bitfield_0: u32, // 0x0\n    _bitfield_4: u8, // 0x4\n    _pad_5: [u8; 0x3],",
+            "pub fn team(&self) -> u32 {\n        (self._bitfield_0 >> 1) & 0x3\n    }",
+        ] {
+            assert!(rust.contains(expected), "missing {expected:?} in\n{rust}");
+        }
+    }
+}
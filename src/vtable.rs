@@ -6,11 +6,13 @@
 
 use crate::errors::Result;
 use crate::pattern::{PatternMatch, PatternScanner};
+use crate::structure::ObjectLayout;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 
 /// Represents a virtual function table entry.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct VirtualFunction {
     pub address: usize,
     pub index: usize,
@@ -422,14 +424,21 @@ impl VTableAnalyzer {
             .collect()
     }
 
-    /// Estimates the size of objects based on VTable analysis.
-    pub fn estimate_object_sizes(vtables: &[VTable]) -> HashMap<usize, usize> {
+    /// Estimates the size of objects from layouts recovered by structure analysis.
+    ///
+    /// VTables without a recovered layout only account for the vtable pointer.
+    pub fn estimate_object_sizes(
+        vtables: &[VTable],
+        layouts: &[ObjectLayout],
+    ) -> HashMap<usize, usize> {
         vtables
             .iter()
             .map(|vtable| {
-                // Basic estimation based on function count and known patterns
-                let base_size = std::mem::size_of::<usize>(); // vtable pointer
-                let estimated_size = base_size + (vtable.function_count() * 8); // rough estimate
+                let estimated_size = layouts
+                    .iter()
+                    .find(|layout| layout.vtable_address == vtable.base_address)
+                    .map(|layout| layout.estimated_size())
+                    .unwrap_or(std::mem::size_of::<usize>());
                 (vtable.base_address, estimated_size)
             })
             .collect()
@@ -437,7 +446,7 @@ impl VTableAnalyzer {
 }
 
 /// Represents class information extracted from VTable analysis.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClassInfo {
     pub vtable_address: usize,
     pub name: String,
@@ -447,7 +456,7 @@ pub struct ClassInfo {
 }
 
 /// Represents a complete class hierarchy.
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ClassHierarchy {
     classes: HashMap<usize, ClassInfo>,
 }
@@ -518,6 +527,22 @@ impl ClassHierarchy {
     }
 }
 
+/// Converts an MSVC RTTI type descriptor name into a C++ qualified name.
+///
+/// `.?AVPlayer@game@@` becomes `game::Player`. Names with template or other
+/// encoded components are returned without the `.?AV`/`@@` decoration.
+pub fn demangle_type_descriptor(name: &str) -> Option<String> {
+    let rest = name.strip_prefix(".?A")?;
+    let body = rest.get(1..)?.strip_suffix("@@")?;
+    if body.is_empty() {
+        return None;
+    }
+    if body.contains(['?', '$']) {
+        return Some(body.to_string());
+    }
+    Some(body.split('@').rev().collect::<Vec<_>>().join("::"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -556,4 +581,17 @@ mod tests {
         assert_eq!(scanner.config.min_functions, 3);
         assert_eq!(scanner.config.max_functions, 10);
     }
+
+    #[test]
+    fn test_demangle_type_descriptor() {
+        assert_eq!(
+            demangle_type_descriptor(".?AVPlayer@game@@").as_deref(),
+            Some("game::Player")
+        );
+        assert_eq!(
+            demangle_type_descriptor(".?AUVec3@@").as_deref(),
+            Some("Vec3")
+        );
+        assert_eq!(demangle_type_descriptor("Player"), None);
+    }
 }
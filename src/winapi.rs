@@ -3,11 +3,16 @@
 
 use core::ffi::c_void;
 use std::ptr::null_mut;
-use windows::Win32::Foundation::{HINSTANCE, HMODULE, HWND};
-use windows::Win32::System::LibraryLoader::DisableThreadLibraryCalls;
+use windows::Win32::Foundation::{FreeLibrary, HINSTANCE, HMODULE, HWND};
+use windows::Win32::System::LibraryLoader::{
+    DisableThreadLibraryCalls, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+    GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT, GetModuleFileNameW, GetModuleHandleExW,
+    GetModuleHandleW,
+};
 use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VK_F10, VK_INSERT};
 use windows::Win32::UI::WindowsAndMessaging::{GWL_EXSTYLE, GetWindowLongPtrA};
 use windows::Win32::UI::WindowsAndMessaging::{MB_OK, MessageBoxA};
+use windows::core::PCWSTR;
 
 pub type ThreadFunc = unsafe extern "system" fn(lp_parameter: *mut c_void) -> u32;
 
@@ -85,3 +90,54 @@ pub fn is_insert_pressed() -> bool {
 pub fn is_vk_pressed(vk: i32) -> bool {
     unsafe { (GetAsyncKeyState(vk) as u32 & 0x8000) as i32 != 0 }
 }
+
+/// Returns the base address of a module loaded in the current process.
+pub fn module_base(name: &str) -> Option<usize> {
+    let wide: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+    unsafe { GetModuleHandleW(PCWSTR(wide.as_ptr())) }
+        .ok()
+        .map(|module| module.0 as usize)
+}
+
+/// Returns the file name and base address of the module containing `address`.
+pub fn module_at(address: usize) -> Option<(String, usize)> {
+    let mut module = HMODULE::default();
+    unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            PCWSTR(address as *const u16),
+            &mut module,
+        )
+    }
+    .ok()?;
+    let mut path = [0u16; 260];
+    let length = unsafe { GetModuleFileNameW(Some(module), &mut path) } as usize;
+    let path = String::from_utf16_lossy(&path[..length]);
+    let name = path.rsplit(['\\', '/']).next().unwrap_or(&path).to_string();
+    Some((name, module.0 as usize))
+}
+
+/// Adds a reference to the module mapped at `base`, so it stays loaded until
+/// [`unpin_module`]. Returns false if no module is mapped there.
+pub fn pin_module(base: usize) -> bool {
+    let mut module = HMODULE::default();
+    unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+            PCWSTR(base as *const u16),
+            &mut module,
+        )
+    }
+    .is_ok_and(|()| {
+        if module.0 as usize == base {
+            return true;
+        }
+        let _ = unsafe { FreeLibrary(module) };
+        false
+    })
+}
+
+/// Releases a reference taken by [`pin_module`].
+pub fn unpin_module(base: usize) {
+    let _ = unsafe { FreeLibrary(HMODULE(base as *mut c_void)) };
+}
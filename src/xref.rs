@@ -0,0 +1,336 @@
+//! Cross-reference index over loaded module images.
+//!
+//! Executable sections are decoded with a linear sweep to record direct
+//! calls and jumps (code to code), and RIP-relative loads, stores and address
+//! computations as well as absolute immediates into the image (code to data).
+//! Non-executable sections are scanned for aligned absolute pointers into the
+//! image (data to code or data). The resulting index answers "who calls X",
+//! "who reads global Y" and "what does function F reference".
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::disasm::{self, Flow, Mnemonic};
+use crate::pe::{PeImage, Section};
+use crate::structure;
+
+/// How a reference uses its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum XrefKind {
+    /// Direct call.
+    Call,
+    /// Unconditional direct jump.
+    Jump,
+    /// Conditional direct jump.
+    ConditionalJump,
+    /// RIP-relative memory load.
+    Read,
+    /// RIP-relative memory store.
+    Write,
+    /// RIP-relative address computation (`lea`).
+    AddressOf,
+    /// Absolute pointer stored in a data section.
+    DataPointer,
+}
+
+impl XrefKind {
+    /// Returns true for control flow references.
+    pub fn is_code(&self) -> bool {
+        matches!(
+            self,
+            XrefKind::Call | XrefKind::Jump | XrefKind::ConditionalJump
+        )
+    }
+}
+
+/// A single reference from one address to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Xref {
+    /// Address of the referencing instruction or pointer slot.
+    pub from: usize,
+    /// Referenced address.
+    pub to: usize,
+    pub kind: XrefKind,
+}
+
+/// Queryable set of cross-references.
+///
+/// Serialized as the list of references; the target index is rebuilt on load.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Xref>", into = "Vec<Xref>")]
+pub struct XrefIndex {
+    /// All references, sorted by source address.
+    xrefs: Vec<Xref>,
+    /// Positions in `xrefs` keyed by target address.
+    by_target: HashMap<usize, Vec<usize>>,
+}
+
+impl XrefIndex {
+    /// Creates an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an index from a set of references.
+    pub fn from_xrefs(xrefs: impl IntoIterator<Item = Xref>) -> Self {
+        let mut index = Self::new();
+        index.extend(xrefs);
+        index
+    }
+
+    /// Builds the index for a module image.
+    pub fn build(image: &PeImage) -> Self {
+        let mut xrefs = code_xrefs(image);
+        xrefs.extend(data_xrefs(image));
+        Self::from_xrefs(xrefs)
+    }
+
+    /// Adds references to the index.
+    pub fn extend(&mut self, xrefs: impl IntoIterator<Item = Xref>) {
+        self.xrefs.extend(xrefs);
+        self.xrefs.sort_unstable_by_key(|xref| (xref.from, xref.to));
+        self.xrefs.dedup();
+        self.by_target.clear();
+        for (position, xref) in self.xrefs.iter().enumerate() {
+            self.by_target.entry(xref.to).or_default().push(position);
+        }
+    }
+
+    /// Returns the number of references.
+    pub fn len(&self) -> usize {
+        self.xrefs.len()
+    }
+
+    /// Returns true if the index holds no references.
+    pub fn is_empty(&self) -> bool {
+        self.xrefs.is_empty()
+    }
+
+    /// Iterates over all references in source order.
+    pub fn iter(&self) -> impl Iterator<Item = &Xref> {
+        self.xrefs.iter()
+    }
+
+    /// Returns the references targeting `address`.
+    pub fn xrefs_to(&self, address: usize) -> impl Iterator<Item = &Xref> {
+        self.by_target
+            .get(&address)
+            .into_iter()
+            .flatten()
+            .map(|&position| &self.xrefs[position])
+    }
+
+    /// Returns the references made by the instruction or pointer at `address`.
+    pub fn xrefs_from(&self, address: usize) -> &[Xref] {
+        self.xrefs_in(address..address + 1)
+    }
+
+    /// Returns the references made from anywhere in `range`, e.g. a function body.
+    pub fn xrefs_in(&self, range: Range<usize>) -> &[Xref] {
+        let start = self.xrefs.partition_point(|xref| xref.from < range.start);
+        let end = self.xrefs.partition_point(|xref| xref.from < range.end);
+        &self.xrefs[start..end]
+    }
+
+    /// Returns the addresses of direct calls to `address`.
+    pub fn callers(&self, address: usize) -> Vec<usize> {
+        self.sources(address, |kind| kind == XrefKind::Call)
+    }
+
+    /// Returns the addresses of instructions loading from `address`.
+    pub fn readers(&self, address: usize) -> Vec<usize> {
+        self.sources(address, |kind| kind == XrefKind::Read)
+    }
+
+    /// Returns the addresses of instructions storing to `address`.
+    pub fn writers(&self, address: usize) -> Vec<usize> {
+        self.sources(address, |kind| kind == XrefKind::Write)
+    }
+
+    /// Returns the distinct targets referenced from `range`.
+    pub fn references_in(&self, range: Range<usize>) -> Vec<usize> {
+        let mut targets: Vec<usize> = self.xrefs_in(range).iter().map(|xref| xref.to).collect();
+        targets.sort_unstable();
+        targets.dedup();
+        targets
+    }
+
+    fn sources(&self, address: usize, filter: impl Fn(XrefKind) -> bool) -> Vec<usize> {
+        let mut sources: Vec<usize> = self
+            .xrefs_to(address)
+            .filter(|xref| filter(xref.kind))
+            .map(|xref| xref.from)
+            .collect();
+        sources.sort_unstable();
+        sources
+    }
+}
+
+impl From<Vec<Xref>> for XrefIndex {
+    fn from(xrefs: Vec<Xref>) -> Self {
+        Self::from_xrefs(xrefs)
+    }
+}
+
+impl From<XrefIndex> for Vec<Xref> {
+    fn from(index: XrefIndex) -> Self {
+        index.xrefs
+    }
+}
+
+/// Decodes the executable sections of `image` and records code references.
+///
+/// Undecodable bytes and `int3` padding are skipped one byte at a time so the
+/// sweep resynchronizes after data embedded in code.
+pub fn code_xrefs(image: &PeImage) -> Vec<Xref> {
+    let data = image.data();
+    let bounds = image.base()..image.base() + image.size_of_image() as usize;
+    let mut xrefs = Vec::new();
+    for section in image.sections().iter().filter(|s| s.is_executable()) {
+        let range = section.rva_range();
+        let start = range.start as usize;
+        let end = (range.end as usize).min(data.len());
+        let mut offset = start;
+        while offset < end {
+            if data[offset] == 0xCC {
+                offset += 1;
+                continue;
+            }
+            let address = image.base() + offset;
+            let Ok(insn) = disasm::decode(&data[offset..end], address) else {
+                offset += 1;
+                continue;
+            };
+            offset += insn.length;
+
+            if let Some(to) = insn.branch_target() {
+                let kind = match insn.flow() {
+                    Flow::Call => XrefKind::Call,
+                    Flow::Jump => XrefKind::Jump,
+                    _ => XrefKind::ConditionalJump,
+                };
+                xrefs.push(Xref {
+                    from: address,
+                    to,
+                    kind,
+                });
+            }
+            if matches!(insn.mnemonic, Mnemonic::Mov | Mnemonic::Push)
+                && let Some(to) = insn.immediate.map(|imm| imm as usize)
+                && bounds.contains(&to)
+            {
+                xrefs.push(Xref {
+                    from: address,
+                    to,
+                    kind: XrefKind::AddressOf,
+                });
+            }
+            if let Some(to) = insn.rip_target() {
+                let kind = if insn.mnemonic == Mnemonic::Lea {
+                    XrefKind::AddressOf
+                } else if structure::is_memory_write(&insn) {
+                    XrefKind::Write
+                } else {
+                    XrefKind::Read
+                };
+                xrefs.push(Xref {
+                    from: address,
+                    to,
+                    kind,
+                });
+            }
+        }
+    }
+    xrefs
+}
+
+/// Scans the non-executable sections of `image` for aligned pointers into the image.
+pub fn data_xrefs(image: &PeImage) -> Vec<Xref> {
+    image
+        .sections()
+        .iter()
+        .filter(|s| !s.is_executable())
+        .flat_map(|section| section_data_xrefs(image, section))
+        .collect()
+}
+
+/// Scans one section of `image` for aligned pointers into the image.
+pub fn section_data_xrefs(image: &PeImage, section: &Section) -> Vec<Xref> {
+    let data = image.data();
+    let bounds = image.base()..image.base() + image.size_of_image() as usize;
+    let range = section.rva_range();
+    let start = (range.start as usize + 7) & !7;
+    let end = (range.end as usize).min(data.len());
+    let mut xrefs = Vec::new();
+    for offset in (start..end.saturating_sub(7)).step_by(8) {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&data[offset..offset + 8]);
+        let to = u64::from_le_bytes(bytes) as usize;
+        if bounds.contains(&to) {
+            xrefs.push(Xref {
+                from: image.base() + offset,
+                to,
+                kind: XrefKind::DataPointer,
+            });
+        }
+    }
+    xrefs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::fixtures::{BASE, DATA, TEXT, build_image, put_u64};
+
+    fn sample_index() -> XrefIndex {
+        let code = [
+            0xE8, 0x1B, 0x00, 0x00, 0x00, // 0x1000: call 0x1020
+            0x48, 0x8B, 0x05, 0xF4, 0x1F, 0x00, 0x00, // 0x1005: mov rax, [0x3000]
+            0x89, 0x0D, 0xF6, 0x1F, 0x00, 0x00, // 0x100C: mov [0x3008], ecx
+            0x48, 0x8D, 0x0D, 0xF7, 0x1F, 0x00, 0x00, // 0x1012: lea rcx, [0x3010]
+            0x74, 0x05, // 0x1019: je 0x1020
+            0xC3, // 0x101B: ret
+            0xCC, 0xCC, 0xCC, 0xCC, // padding
+            0x31, 0xC0, 0xC3, // 0x1020: xor eax, eax; ret
+        ];
+        let mut image = build_image(&code, &[], &[]);
+        put_u64(&mut image, DATA + 0x18, (BASE + TEXT + 0x20) as u64);
+        put_u64(&mut image, DATA + 0x20, 0xDEAD_BEEF);
+        XrefIndex::build(&PeImage::parse(&image, BASE).unwrap())
+    }
+
+    #[test]
+    fn test_code_and_data_xrefs() {
+        let index = sample_index();
+        let text = BASE + TEXT;
+        let data = BASE + DATA;
+        assert_eq!(index.callers(text + 0x20), vec![text]);
+        assert_eq!(index.readers(data), vec![text + 0x05]);
+        assert_eq!(index.writers(data + 0x08), vec![text + 0x0C]);
+        assert_eq!(index.xrefs_from(text + 0x12)[0].kind, XrefKind::AddressOf);
+        assert_eq!(
+            index.xrefs_from(text + 0x19)[0].kind,
+            XrefKind::ConditionalJump
+        );
+
+        let to_function: Vec<XrefKind> = index.xrefs_to(text + 0x20).map(|x| x.kind).collect();
+        assert_eq!(to_function.len(), 3);
+        assert!(to_function.contains(&XrefKind::DataPointer));
+        assert_eq!(index.xrefs_to(0xDEAD_BEEF).count(), 0);
+    }
+
+    #[test]
+    fn test_references_in_range() {
+        let index = sample_index();
+        let text = BASE + TEXT;
+        let data = BASE + DATA;
+        assert_eq!(
+            index.references_in(text..text + 0x1C),
+            vec![text + 0x20, data, data + 0x08, data + 0x10]
+        );
+        assert!(index.xrefs_in(text + 0x20..text + 0x23).is_empty());
+        assert!(XrefIndex::new().is_empty());
+    }
+}